@@ -0,0 +1,67 @@
+//! Checks that scripts survive being written back to source, both as printed by `Display` and by the
+//! formatter, so tools editing a parsed script can save it without changing what it means.
+//!
+//! ```sh
+//! cargo run --example round_trip -- song.musical other.musical
+//! ```
+
+use std::process::ExitCode;
+use roorle::syntax::formatter;
+use roorle::syntax::parser::{Script, Token};
+
+
+/// Where `written` stops having the tokens of `original`, if it does
+fn first_difference(original: &[Token], written: &[Token]) -> Option<String> {
+    let differing = original.iter().zip(written.iter()).position(|(a, b)| a != b);
+
+    match differing {
+        Some(pos) => Some(format!("token {} was `{}`, came back as `{}`", pos + 1, original[pos], written[pos])),
+        None if original.len() != written.len() => Some(format!("{} tokens came back as {}", original.len(), written.len())),
+        None => None,
+    }
+}
+
+
+fn check(source: &str) -> Result<(), String> {
+    let script = Script::try_from(source).map_err(|err| format!("doesn't parse: {err:?}"))?;
+
+    let written = [
+        ("display", script.to_string()),
+        ("fmt", formatter::format(source).map_err(|err| format!("doesn't format: {err:?}"))?),
+    ];
+
+    for (how, written) in written {
+        let reparsed = Script::try_from(written.as_str()).map_err(|err| format!("{how} output doesn't parse: {err:?}"))?;
+
+        if let Some(difference) = first_difference(script.get_tokens(), reparsed.get_tokens()) {
+            return Err(format!("{how} output differs, {difference}"));
+        };
+    };
+
+    Ok(())
+}
+
+
+fn main() -> ExitCode {
+    let paths = std::env::args().skip(1).collect::<Vec<_>>();
+    if paths.is_empty() {
+        eprintln!("usage: round_trip <script>...");
+
+        return ExitCode::from(2);
+    };
+
+    let mut failed = false;
+    for path in paths.iter() {
+        let result = std::fs::read_to_string(path).map_err(|err| format!("can't be read: {err}")).and_then(|source| check(&source));
+
+        match result {
+            Ok(()) => println!("ok    {path}"),
+            Err(err) => {
+                println!("FAIL  {path}: {err}");
+                failed = true;
+            },
+        };
+    };
+
+    if failed { ExitCode::FAILURE } else { ExitCode::SUCCESS }
+}
@@ -0,0 +1,51 @@
+/* C interface of roorle, available when the crate is built with `--features ffi`.
+ *
+ * Functions returning an int return ROORLE_OK or one of the other codes below, and leave a message
+ * for roorle_last_error() when they fail. */
+
+#ifndef ROORLE_H
+#define ROORLE_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define ROORLE_OK 0
+#define ROORLE_INVALID_ARGUMENT 1
+#define ROORLE_PARSING_ERROR 2
+#define ROORLE_COMPILING_ERROR 3
+#define ROORLE_BUFFER_TOO_SMALL 4
+#define ROORLE_PANIC 5
+/* The render would last longer than an hour. */
+#define ROORLE_TOO_LONG 6
+
+typedef struct RoorleProgram RoorleProgram;
+
+/* Compiles the UTF-8 `source` into `*program`, which has to be freed with roorle_free(). */
+int roorle_compile(const char *source, RoorleProgram **program);
+
+/* Frees a program from roorle_compile(), NULL is ignored. */
+void roorle_free(RoorleProgram *program);
+
+/* Length of the piece in seconds. */
+double roorle_duration(const RoorleProgram *program);
+
+/* Renders interleaved samples between -1 and 1 into `buffer`, which holds `length` of them.
+ * `*written` is set to the amount of samples the render has, even when it returns
+ * ROORLE_BUFFER_TOO_SMALL, so calling it with a `length` of 0 finds out how big `buffer` has to be. */
+int roorle_render(const RoorleProgram *program, uint32_t sample_rate, uint16_t channels, float *buffer, size_t length, size_t *written);
+
+/* Renders the bytes of a 16-bit WAV file into `buffer`, sized like for roorle_render(). */
+int roorle_render_wav(const RoorleProgram *program, uint32_t sample_rate, uint16_t channels, uint8_t *buffer, size_t length, size_t *written);
+
+/* What the last failing call on this thread went wrong with, valid until the next call. */
+const char *roorle_last_error(void);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
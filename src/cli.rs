@@ -0,0 +1,370 @@
+use std::path::Path;
+use roorle::interpreter::{Format, RenderOptions, SampleSize, DEFAULT_MAX_RENDER_SECONDS};
+
+
+pub const USAGE: &str = "\
+usage: roorle [render] <input> [-o <output>] [options]
+       roorle build [<project>] [-o <output>] [options]
+       roorle play <input> [options]
+       roorle play-midi <input> [--port <name>]
+       roorle play-midi --list
+       roorle stems <input> [-o <prefix>] [options]
+       roorle watch <input> [-o <output>] [options]
+       roorle repl [-o <output>] [options]
+       roorle fmt <input> [-o <output>] [--check]
+       roorle transpose <input> <semitones> [-o <output>]
+       roorle lint <input> [--json]
+       roorle diff <input> <other> [--json]
+       roorle info <input>
+       roorle stats <input> [--json]
+       roorle tempo-map <input> [-o <output>] [--json]
+       roorle chapters <input> [-o <output>]
+       roorle notes <input> [-o <output>]
+       roorle lsp
+       roorle highlight <input> [-o <output>] [--html]
+       roorle import <midi|abc|musicxml|rtttl|mml|wav> <input> [-o <output>]
+
+<input> and <output> can be `-` for stdin and stdout.
+build renders the roorle.toml project in <project> (the current directory by default).
+watch re-renders <input> every time it's saved.
+repl plays what you type (or writes it into <output> without playback support).
+fmt rewrites <input> in place unless given <output>.
+transpose moves every note of <input> by <semitones> (like +3 or -2) and prints the script.
+lint reports likely mistakes in <input> and exits with 1 if it found any.
+diff lists the notes <other> adds, removes or retimes compared to <input>, and exits with 1 if there are any.
+info compiles <input> and describes it (duration, tempo, notes, labels) without rendering.
+stats counts how often every pitch and note length is played in <input>.
+tempo-map lists when the tempo of <input> changes and when every bar starts, as CSV unless given --json.
+chapters lists the marks of <input> and when they're reached, as JSON. Renders to WAV keep them as cue points.
+notes writes every note of <input> as a row of CSV: its start and duration in seconds, frequency, name, line and label.
+lsp speaks the language server protocol over stdin and stdout, for editors.
+highlight prints <input> in terminal colours, or as HTML for an .html <output>.
+import converts a standard MIDI file, an ABC tune, a MusicXML score, an RTTTL ringtone, MML or (experimentally) a recorded melody into a script, printed unless given <output>.
+
+options:
+  -o, --output <path>      where to write the render (defaults to <input> with the format's extension)
+  -f, --format <format>    wav, flac, aiff, raw, midi, rtttl, svg, png, spectrogram, or lrc and srt lyrics (guessed from <output>)
+  -r, --sample-rate <hz>   sample rate of the render (48000)
+  -b, --bits <8|16>        bits per sample (16)
+      --stereo             render two channels, so buses can be panned
+      --gain <gain>        master gain (0.25)
+      --no-soft-clip       hard clip the master instead of saturating it
+      --normalize <dbfs>   scale the render so it peaks at the given level
+      --loudness <lufs>    scale the render to the given integrated loudness
+      --fade-in <seconds>  fade the start of the piece in
+      --fade-out <seconds> fade the end of the piece out
+      --from <seconds>     start rendering at the given time
+      --to <seconds>       stop rendering at the given time
+      --limit <seconds>    refuse renders longer than the given time, 0 for none (3600)
+      --port <name>        MIDI output port for play-midi
+      --list               list MIDI output ports for play-midi
+      --check              only report whether fmt would change <input>
+      --json               print lint findings, diffs, stats, tempo maps and dumps as JSON, one object per line
+      --html               highlight into HTML even without an .html <output>
+      --view               draw a live view of the render in the terminal
+      --sine-table         use a lookup table instead of computing sines
+      --deterministic      render the same samples on every platform, at some cost in speed
+      --seed <n>           start the noise of noise voices differently (0)
+      --stats              report timings, peak level and output size after rendering
+      --strict             make unknown properties, voices and effects errors, like `strict: on`
+      --dump-tokens        print the words the lexer split <input> into
+      --dump-ast           print the statements the parser made of them
+      --dump-ir            print the instructions the compiler produced
+  -h, --help               print this message
+  -V, --version            print the version";
+
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Command {
+    Render,
+    Build,
+    Play,
+    PlayMidi,
+    Stems,
+    Watch,
+    Repl,
+    Lsp,
+    Fmt,
+    Transpose,
+    Lint,
+    Diff,
+    Info,
+    Stats,
+    TempoMap,
+    Chapters,
+    Notes,
+    Highlight,
+    Import,
+    Help,
+    Version,
+}
+
+
+/// Formats `roorle import` can read
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ImportFormat {
+    Midi,
+    Abc,
+    MusicXml,
+    Rtttl,
+    Mml,
+    Wav,
+}
+
+
+impl ImportFormat {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "midi" | "mid" => Some(Self::Midi),
+            "abc" => Some(Self::Abc),
+            "musicxml" | "xml" => Some(Self::MusicXml),
+            "rtttl" | "rtx" => Some(Self::Rtttl),
+            "mml" => Some(Self::Mml),
+            "wav" | "wave" => Some(Self::Wav),
+            _ => None,
+        }
+    }
+}
+
+
+/// Pipeline stages to print while compiling
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Dump {
+    pub tokens: bool,
+    pub ast: bool,
+    pub ir: bool,
+}
+
+
+#[derive(Clone, Debug)]
+pub struct Arguments {
+    pub command: Command,
+    pub input: Option<String>,
+    pub output: Option<String>,
+    pub format: Option<Format>,
+    pub port: Option<String>,
+    pub list_ports: bool,
+    pub check: bool,
+    pub json: bool,
+    pub html: bool,
+    pub dump: Dump,
+    pub stats: bool,
+    pub strict: bool,
+    pub semitones: Option<i32>,
+    pub other: Option<String>,
+    pub importer: Option<ImportFormat>,
+    pub options: RenderOptions,
+}
+
+
+impl Arguments {
+    pub fn format(&self) -> Format {
+        self.format
+            .or_else(|| self.output.as_deref().and_then(Format::from_path))
+            .unwrap_or(Format::Wav)
+    }
+
+    pub fn output_path(&self) -> Option<String> {
+        match (self.output.as_deref(), self.input.as_deref()) {
+            (Some("-"), _) | (None, Some("-")) => None,
+            (Some(output), _) => Some(String::from(output)),
+            (None, Some(input)) => Some(Path::new(input).with_extension(self.format().extension()).to_string_lossy().into_owned()),
+            (None, None) => None,
+        }
+    }
+
+    pub fn read_input(&self) -> std::io::Result<String> {
+        match self.input.as_deref() {
+            None | Some("-") => std::io::read_to_string(std::io::stdin()),
+            Some(path) => std::fs::read_to_string(path),
+        }
+    }
+
+    pub fn read_input_bytes(&self) -> std::io::Result<Vec<u8>> {
+        match self.input.as_deref() {
+            None | Some("-") => {
+                let mut bytes = Vec::new();
+                std::io::Read::read_to_end(&mut std::io::stdin(), &mut bytes)?;
+
+                Ok(bytes)
+            },
+            Some(path) => std::fs::read(path),
+        }
+    }
+}
+
+
+#[derive(Debug)]
+pub enum UsageError {
+    UnknownFlag(String),
+    MissingValue(&'static str),
+    InvalidValue {
+        flag: &'static str,
+        got: String,
+    },
+    UnexpectedArgument(String),
+    MissingInput,
+    ConflictingFlags(&'static str, &'static str),
+}
+
+
+impl std::fmt::Display for UsageError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnknownFlag(flag) => write!(f, "unknown flag `{flag}`"),
+            Self::MissingValue(flag) => write!(f, "`{flag}` needs a value"),
+            Self::InvalidValue { flag, got } => write!(f, "`{got}` isn't a valid value for `{flag}`"),
+            Self::UnexpectedArgument(argument) => write!(f, "unexpected argument `{argument}`"),
+            Self::MissingInput => write!(f, "no input script given"),
+            Self::ConflictingFlags(first, second) => write!(f, "`{first}` and `{second}` can't be used together"),
+        }
+    }
+}
+
+
+fn parse_value<T: std::str::FromStr>(flag: &'static str, value: Option<String>) -> Result<T, UsageError> {
+    let value = value.ok_or(UsageError::MissingValue(flag))?;
+
+    value.parse().map_err(|_| UsageError::InvalidValue { flag, got: value })
+}
+
+
+pub fn parse(arguments: impl IntoIterator<Item = String>) -> Result<Arguments, UsageError> {
+    let mut arguments = arguments.into_iter().peekable();
+
+    let command = match arguments.peek().map(String::as_str) {
+        Some("render") => Some(Command::Render),
+        Some("build") => Some(Command::Build),
+        Some("play") => Some(Command::Play),
+        Some("play-midi") => Some(Command::PlayMidi),
+        Some("stems") => Some(Command::Stems),
+        Some("watch") => Some(Command::Watch),
+        Some("repl") => Some(Command::Repl),
+        Some("lsp") => Some(Command::Lsp),
+        Some("fmt") => Some(Command::Fmt),
+        Some("transpose") => Some(Command::Transpose),
+        Some("lint") => Some(Command::Lint),
+        Some("diff") => Some(Command::Diff),
+        Some("info") => Some(Command::Info),
+        Some("stats") => Some(Command::Stats),
+        Some("tempo-map") => Some(Command::TempoMap),
+        Some("chapters") => Some(Command::Chapters),
+        Some("notes") => Some(Command::Notes),
+        Some("highlight") => Some(Command::Highlight),
+        Some("import") => Some(Command::Import),
+        Some("help") => Some(Command::Help),
+        _ => None,
+    };
+    if command.is_some() {
+        arguments.next();
+    };
+
+    let mut parsed = Arguments {
+        command: command.unwrap_or(Command::Render),
+        input: None,
+        output: None,
+        format: None,
+        port: None,
+        list_ports: false,
+        check: false,
+        json: false,
+        html: false,
+        dump: Dump::default(),
+        stats: false,
+        strict: false,
+        semitones: None,
+        other: None,
+        importer: None,
+        options: RenderOptions { max_render_seconds: Some(DEFAULT_MAX_RENDER_SECONDS), ..RenderOptions::default() },
+    };
+
+    while let Some(argument) = arguments.next() {
+        let options = &mut parsed.options;
+
+        match argument.as_str() {
+            "-h" | "--help" => parsed.command = Command::Help,
+            "-V" | "--version" => parsed.command = Command::Version,
+            "-o" | "--output" => parsed.output = Some(arguments.next().ok_or(UsageError::MissingValue("--output"))?),
+            "-f" | "--format" => {
+                let name = arguments.next().ok_or(UsageError::MissingValue("--format"))?;
+
+                parsed.format = match Format::from_name(&name) {
+                    Some(format) => Some(format),
+                    None => return Err(UsageError::InvalidValue { flag: "--format", got: name }),
+                };
+            },
+            "-r" | "--sample-rate" => options.sample_rate = match parse_value("--sample-rate", arguments.next())? {
+                0 => return Err(UsageError::InvalidValue { flag: "--sample-rate", got: String::from("0") }),
+                rate => rate,
+            },
+            "-b" | "--bits" => options.sample_size = match parse_value("--bits", arguments.next())? {
+                8 => SampleSize::Small,
+                16 => SampleSize::Large,
+                bits => return Err(UsageError::InvalidValue { flag: "--bits", got: format!("{bits}") }),
+            },
+            "--stereo" => options.channels = 2,
+            "--gain" => options.master_gain = parse_value("--gain", arguments.next())?,
+            "--no-soft-clip" => options.soft_clip = false,
+            "--normalize" => options.normalize = Some(parse_value("--normalize", arguments.next())?),
+            "--loudness" => options.loudness = Some(parse_value("--loudness", arguments.next())?),
+            "--fade-in" => options.fade_in = parse_value("--fade-in", arguments.next())?,
+            "--fade-out" => options.fade_out = parse_value("--fade-out", arguments.next())?,
+            "--from" => options.from = parse_value("--from", arguments.next())?,
+            "--to" => options.to = Some(parse_value("--to", arguments.next())?),
+            "--limit" => {
+                let limit: f64 = parse_value("--limit", arguments.next())?;
+
+                options.max_render_seconds = Some(limit).filter(|limit| *limit > 0.0);
+            },
+            "--view" => options.terminal_view = true,
+            "--sine-table" => options.sine_table = true,
+            "--deterministic" => options.deterministic = true,
+            "--seed" => options.seed = parse_value("--seed", arguments.next())?,
+            "--port" => parsed.port = Some(arguments.next().ok_or(UsageError::MissingValue("--port"))?),
+            "--list" => parsed.list_ports = true,
+            "--check" => parsed.check = true,
+            "--json" => parsed.json = true,
+            "--html" => parsed.html = true,
+            "--stats" => parsed.stats = true,
+            "--strict" => parsed.strict = true,
+            "--dump-tokens" => parsed.dump.tokens = true,
+            "--dump-ast" => parsed.dump.ast = true,
+            "--dump-ir" => parsed.dump.ir = true,
+            semitones if parsed.command == Command::Transpose && parsed.input.is_some() && parsed.semitones.is_none() => {
+                parsed.semitones = Some(semitones.parse().map_err(|_| UsageError::InvalidValue { flag: "<semitones>", got: argument.clone() })?);
+            },
+            flag if flag.starts_with('-') && flag != "-" => return Err(UsageError::UnknownFlag(argument)),
+            format if parsed.command == Command::Import && parsed.importer.is_none() => {
+                parsed.importer = Some(ImportFormat::from_name(format).ok_or_else(|| UsageError::InvalidValue { flag: "<format>", got: argument.clone() })?);
+            },
+            _ if parsed.input.is_none() => parsed.input = Some(argument),
+            _ if parsed.command == Command::Diff && parsed.other.is_none() => parsed.other = Some(argument),
+            _ => return Err(UsageError::UnexpectedArgument(argument)),
+        };
+    };
+
+    // both scale the whole render, only one of them can decide by how much
+    if parsed.options.normalize.is_some() && parsed.options.loudness.is_some() {
+        return Err(UsageError::ConflictingFlags("--normalize", "--loudness"));
+    };
+
+    if parsed.command == Command::Transpose && parsed.semitones.is_none() && parsed.input.is_some() {
+        return Err(UsageError::MissingValue("<semitones>"));
+    };
+
+    if parsed.command == Command::Diff && parsed.other.is_none() && parsed.input.is_some() {
+        return Err(UsageError::MissingValue("<other>"));
+    };
+
+    if parsed.command == Command::Import && parsed.importer.is_none() {
+        return Err(UsageError::MissingValue("<format>"));
+    };
+
+    let needs_input = !matches!(parsed.command, Command::Build | Command::Repl | Command::Lsp | Command::Help | Command::Version) && !(parsed.command == Command::PlayMidi && parsed.list_ports);
+    if needs_input && parsed.input.is_none() {
+        return Err(UsageError::MissingInput);
+    };
+
+    Ok(parsed)
+}
+
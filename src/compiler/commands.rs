@@ -0,0 +1,293 @@
+//! Commands are compiled by the handler registered for their name in a [`Compiler`], which is how
+//! `rest`, `goto` and the other commands scripts start with are compiled too. Embedders can add their
+//! own or replace those, wrapping ones that play notes in [`NoteCommand`] so they're sung to and take
+//! overrides the way notes do:
+//!
+//! ```
+//! use roorle::compiler::{CompilingError, Compiler, Instruction};
+//! use roorle::compiler::commands::{CommandContext, NoteCommand};
+//! use roorle::syntax::parser::{Script, Value};
+//!
+//! // `twice C 1/8` plays the note two times
+//! let twice = |context: &mut CommandContext, arguments: &[Value]| -> Result<Vec<Instruction>, CompilingError> {
+//!     let Some((Value::String(note), arguments)) = arguments.split_first() else {
+//!         return Err(CompilingError::WrongAmountArguments { pos: context.pos(), expected: 2, got: arguments.len() });
+//!     };
+//!
+//!     let mut instructions = context.note(note, arguments)?;
+//!     instructions.extend(context.note(note, &[])?);
+//!
+//!     Ok(instructions)
+//! };
+//!
+//! let script = Script::try_from("bpm: 120\n@main\ntwice C 1/8 volume=1/2\nE 1/4")?;
+//! let program = Compiler::new().command("twice", NoteCommand(twice)).compile(&script)?;
+//! # Ok::<(), roorle::Error>(())
+//! ```
+
+use super::{
+    compile_goto, compile_note, compile_rest, helper, note_duration, parse_duration, parse_frequency, Compiler, CompilingError,
+    Instruction, InstructionData, LabelCache, Parameter, Scope, MAX_DEPTH, MAX_INSTRUCTIONS,
+};
+use crate::syntax::lexer::Span;
+use crate::syntax::parser::{Token, Value};
+
+
+/// Compiles a command into the instructions it plays
+pub trait CommandHandler {
+    fn compile(&self, context: &mut CommandContext<'_>, arguments: &[Value]) -> Result<Vec<Instruction>, CompilingError>;
+
+    /// Whether the command plays notes the way one written on its own does, taking the next syllable of
+    /// its label's `lyrics:` and overrides. Overrides on other commands are an error
+    fn plays_notes(&self) -> bool {
+        false
+    }
+}
+
+
+impl<F> CommandHandler for F
+where
+    F: Fn(&mut CommandContext<'_>, &[Value]) -> Result<Vec<Instruction>, CompilingError>,
+{
+    fn compile(&self, context: &mut CommandContext<'_>, arguments: &[Value]) -> Result<Vec<Instruction>, CompilingError> {
+        self(context, arguments)
+    }
+}
+
+
+/// A handler playing notes, see [`CommandHandler::plays_notes`]
+pub struct NoteCommand<H>(pub H);
+
+
+impl<H> CommandHandler for NoteCommand<H>
+where
+    H: CommandHandler,
+{
+    fn compile(&self, context: &mut CommandContext<'_>, arguments: &[Value]) -> Result<Vec<Instruction>, CompilingError> {
+        self.0.compile(context, arguments)
+    }
+
+    fn plays_notes(&self) -> bool {
+        true
+    }
+}
+
+
+/// Where a command is compiled: the label it's in, with the properties it sets for itself applied
+pub struct CommandContext<'c> {
+    pub(super) pos: usize,
+    pub(super) octave: u32,
+    pub(super) bpm: f64,
+    pub(super) instrument: usize,
+    pub(super) length: &'c mut Option<f64>,
+    /// How many instructions the label compiled to before the command
+    pub(super) compiled: usize,
+    /// Whether the rest of the label is left out, as it is after a `goto`
+    pub(super) exiting: bool,
+    pub(super) scope: &'c Scope,
+    pub(super) scopes: &'c [Scope],
+    pub(super) global_octave: u32,
+    pub(super) global_bpm: f64,
+    pub(super) tokens: &'c [Token],
+    pub(super) stack: &'c [&'c str],
+    pub(super) cache: &'c mut LabelCache,
+    pub(super) compiler: &'c Compiler,
+}
+
+
+impl CommandContext<'_> {
+    /// Index of the command's token
+    pub fn pos(&self) -> usize {
+        self.pos
+    }
+
+    pub fn octave(&self) -> u32 {
+        self.octave
+    }
+
+    pub fn bpm(&self) -> f64 {
+        self.bpm
+    }
+
+    /// The instrument notes of the command play on
+    pub fn instrument(&self) -> usize {
+        self.instrument
+    }
+
+    /// Fraction of a whole note the last note or rest lasted, which ones written without a duration reuse
+    pub fn length(&self) -> Option<f64> {
+        *self.length
+    }
+
+    pub fn set_length(&mut self, length: f64) {
+        *self.length = Some(length);
+    }
+
+    /// Seconds `fraction` of a whole note lasts at the command's tempo
+    pub fn duration(&self, fraction: f64) -> f64 {
+        note_duration(self.bpm, fraction)
+    }
+
+    /// Frequency of `note` in the command's octave, by the names the compiler knows
+    pub fn frequency(&self, note: &str) -> Result<f64, CompilingError> {
+        parse_frequency(note, self.octave, self.pos, &self.compiler.notes)
+    }
+
+    /// An instruction compiled from the command
+    pub fn instruction(&self, data: InstructionData) -> Instruction {
+        Instruction { pos: self.pos, span: Span::default(), data }
+    }
+
+    /// Compiles `note` with `arguments` the way it would be written on its own, other notes making a
+    /// chord with it and a duration at the end
+    pub fn note(&mut self, note: &str, arguments: &[Value]) -> Result<Vec<Instruction>, CompilingError> {
+        compile_note(note, self.octave, self.bpm, self.length, self.instrument, arguments, self.pos, &self.compiler.notes)
+    }
+
+    /// Compiles `label`, playing it from the command's one
+    fn label(&mut self, label: &str) -> Result<Vec<Instruction>, CompilingError> {
+        let mut stack = Vec::from(self.stack);
+        stack.push(self.scope.name.as_deref().unwrap());
+        if stack.len() > MAX_DEPTH {
+            return Err(CompilingError::ExpansionLimitExceeded { pos: self.pos, limit: MAX_DEPTH });
+        };
+
+        compile_goto(Some(label), Some(self.pos), self.scopes, self.global_octave, self.global_bpm, self.tokens, &stack, self.cache, self.compiler)
+    }
+
+    /// Whether the command's label is already being played, further up from it
+    fn is_recursive(&self) -> bool {
+        self.stack.contains(&self.scope.name.as_deref().unwrap())
+    }
+}
+
+
+/// The handlers of the commands every script can use
+pub(super) fn builtin() -> Vec<(&'static str, Box<dyn CommandHandler>)> {
+    vec![
+        ("rest", Box::new(rest)),
+        ("mark", Box::new(mark)),
+        ("ghost", Box::new(NoteCommand(ghost))),
+        ("goto", Box::new(goto)),
+        ("repeat", Box::new(repeat)),
+        ("automate", Box::new(automate)),
+    ]
+}
+
+
+fn rest(context: &mut CommandContext, arguments: &[Value]) -> Result<Vec<Instruction>, CompilingError> {
+    compile_rest(context.bpm, context.length, arguments, context.pos)
+}
+
+
+/// Named in `marks`, as the name can't be kept in the instruction
+fn mark(context: &mut CommandContext, arguments: &[Value]) -> Result<Vec<Instruction>, CompilingError> {
+    match arguments {
+        [] => Err(CompilingError::WrongAmountArguments { pos: context.pos, expected: 1, got: 0 }),
+        _ => Ok(vec![context.instruction(InstructionData::Mark)]),
+    }
+}
+
+
+/// The quieter instrument it plays on comes from `overridden`
+fn ghost(context: &mut CommandContext, arguments: &[Value]) -> Result<Vec<Instruction>, CompilingError> {
+    match arguments.split_first() {
+        Some((Value::String(note), arguments)) => context.note(note, arguments),
+        Some((v, _)) => Err(CompilingError::ValueTypeError { pos: Some(context.pos), expected: "string", got: helper::value_name(v) }),
+        None => Err(CompilingError::WrongAmountArguments { pos: context.pos, expected: 2, got: 0 }),
+    }
+}
+
+
+fn goto(context: &mut CommandContext, arguments: &[Value]) -> Result<Vec<Instruction>, CompilingError> {
+    let label = match arguments {
+        [Value::String(name)] => name.as_str(),
+        [v] => return Err(CompilingError::ValueTypeError { pos: Some(context.pos), expected: "string", got: helper::value_name(v) }),
+        _ => return Err(CompilingError::WrongAmountArguments { pos: context.pos, expected: 1, got: arguments.len() }),
+    };
+
+    if context.is_recursive() {
+        return Err(CompilingError::SelfRecursion { pos: context.pos });
+    };
+    context.exiting = true;
+
+    let body = context.label(label)?;
+    if context.compiled + body.len() > MAX_INSTRUCTIONS {
+        return Err(CompilingError::ExpansionLimitExceeded { pos: context.pos, limit: MAX_INSTRUCTIONS });
+    };
+
+    Ok(body)
+}
+
+
+fn repeat(context: &mut CommandContext, arguments: &[Value]) -> Result<Vec<Instruction>, CompilingError> {
+    let (label, count) = match arguments {
+        [Value::String(name), Value::Whole(count)] => (name.as_str(), *count as usize),
+        [Value::String(_), v] | [v, _] => return Err(CompilingError::ValueTypeError { pos: Some(context.pos), expected: "string", got: helper::value_name(v) }),
+        _ => return Err(CompilingError::WrongAmountArguments { pos: context.pos, expected: 2, got: arguments.len() }),
+    };
+
+    if context.is_recursive() {
+        context.exiting = true;
+
+        return Ok(Vec::new());
+    };
+
+    let body = context.label(label)?;
+
+    // checked before anything is allocated, as the count alone can make it enormous
+    let unrolled = body.len().checked_mul(count).and_then(|unrolled| unrolled.checked_add(context.compiled));
+    if unrolled.is_none_or(|unrolled| unrolled > MAX_INSTRUCTIONS) {
+        return Err(CompilingError::ExpansionLimitExceeded { pos: context.pos, limit: MAX_INSTRUCTIONS });
+    };
+
+    let mut instructions = Vec::with_capacity(body.len() * count);
+    for _ in 0..count {
+        instructions.extend_from_slice(&body);
+    };
+
+    Ok(instructions)
+}
+
+
+/// `automate cutoff 200 1 4000 1/2 800` jumps the cutoff to 200 Hz, ramps it to 4000 Hz over a whole note
+/// and then back down to 800 Hz over a half, while the label plays on. Notes with overrides play on
+/// instruments of their own, which it leaves alone
+fn automate(context: &mut CommandContext, arguments: &[Value]) -> Result<Vec<Instruction>, CompilingError> {
+    let (parameter, points) = match arguments {
+        [Value::String(name), points @ ..] if !points.is_empty() => match Parameter::from_name(name) {
+            Some(parameter) => (parameter, points),
+            None => return Err(CompilingError::UnknownOption { property: "automate", got: name.clone() }),
+        },
+        [Value::String(_)] | [] => return Err(CompilingError::WrongAmountArguments { pos: context.pos, expected: 2, got: arguments.len() }),
+        [v, ..] => return Err(CompilingError::ValueTypeError { pos: Some(context.pos), expected: "string", got: helper::value_name(v) }),
+    };
+    // a value, then a duration and a value for every point after it
+    if points.len() % 2 == 0 {
+        return Err(CompilingError::WrongAmountArguments { pos: context.pos, expected: arguments.len() + 1, got: arguments.len() });
+    };
+
+    let (first, rest) = points.split_first().unwrap();
+    let mut instructions = vec![context.instruction(InstructionData::Automate {
+        instrument: context.instrument,
+        parameter,
+        value: parameter.parse(first)?,
+        delay: 0.0,
+        ramp: false,
+    })];
+
+    let mut delay = 0.0;
+    for point in rest.chunks(2) {
+        delay += context.duration(parse_duration(&point[0])?);
+
+        instructions.push(context.instruction(InstructionData::Automate {
+            instrument: context.instrument,
+            parameter,
+            value: parameter.parse(&point[1])?,
+            delay,
+            ramp: true,
+        }));
+    };
+
+    Ok(instructions)
+}
@@ -0,0 +1,271 @@
+use std::collections::HashSet;
+use std::ops::Range;
+use crate::Error;
+use crate::syntax::lexer::{SourceMap, Span, Token as LToken, TokenStream};
+use crate::syntax::parser::{Script, Token, Value};
+use crate::syntax::visit::{self, Visitor};
+use super::{compile_tokens, Compiler, LabelCache, Program};
+
+
+/// Char offsets of the labels' `@` in `source`, `offset` added to each.
+///
+/// Follows the parser: a statement starts after a sentence end or right after a label's name, and a
+/// label is an `@` that starts one
+fn label_starts(source: &str, offset: usize) -> Vec<usize> {
+    let mut starts = Vec::new();
+    let mut statement_start = true;
+    let mut label_name = false;
+
+    for token in TokenStream::from(source.chars()) {
+        match token {
+            LToken::Word { value, start, .. } => {
+                if statement_start && value == "@" {
+                    starts.push(start + offset);
+                    statement_start = false;
+                    label_name = true;
+                } else {
+                    statement_start = label_name;
+                    label_name = false;
+                };
+            },
+            LToken::SentenceEnd { .. } => {
+                statement_start = true;
+                label_name = false;
+            },
+        };
+    };
+
+    starts
+}
+
+
+fn label_names(tokens: &[Token]) -> Vec<&str> {
+    tokens.iter()
+        .filter_map(|token| match token {
+            Token::Label { name } => Some(name.as_str()),
+            _ => None,
+        })
+        .collect()
+}
+
+
+/// Overrides of the commands that have any and of ghost notes, which decide how the instruments after
+/// the labels' are numbered
+fn overrides(tokens: &[Token]) -> Vec<(bool, &[(String, Value)])> {
+    tokens.iter()
+        .filter_map(|token| match token {
+            Token::Command { name, overrides, .. } if !overrides.is_empty() || name == "ghost" => Some((name == "ghost", overrides.as_slice())),
+            _ => None,
+        })
+        .collect()
+}
+
+
+/// Which label plays which through `repeat` or `goto`
+#[derive(Default)]
+struct Calls<'s> {
+    current: Option<&'s str>,
+    calls: Vec<(&'s str, &'s str)>,
+}
+
+
+impl<'s> Visitor<'s> for Calls<'s> {
+    fn visit_label(&mut self, _pos: usize, name: &'s str) {
+        self.current = Some(name);
+    }
+
+    fn visit_command(&mut self, _pos: usize, name: &'s str, arguments: &'s [Value]) {
+        if (name == "repeat" || name == "goto") && let (Some(caller), Some(Value::String(callee))) = (self.current, arguments.first()) {
+            self.calls.push((caller, callee.as_str()));
+        };
+    }
+}
+
+
+/// `names` and every label that reaches one of them through `repeat` or `goto`
+fn with_callers(tokens: &[Token], names: &[&str]) -> HashSet<String> {
+    let mut calls = Calls::default();
+    visit::walk_tokens(&mut calls, tokens);
+    let calls = calls.calls;
+
+    let mut affected = names.iter().map(|name| String::from(*name)).collect::<HashSet<_>>();
+    loop {
+        let before = affected.len();
+        for (caller, callee) in calls.iter() {
+            if affected.contains(*callee) {
+                affected.insert(String::from(*caller));
+            };
+        };
+
+        if affected.len() == before {
+            return affected;
+        };
+    };
+}
+
+
+/// A script kept compiled while it's being edited.
+///
+/// An edit only re-parses the labels it touches and only recompiles those and the labels that play them,
+/// everything else comes from the instructions compiled before. Edits to the global properties, to
+/// multiline comments or that don't parse fall back to compiling the whole script again
+pub struct Incremental {
+    source: String,
+    label_starts: Vec<usize>,
+    tokens: Vec<Token>,
+    spans: Vec<Span>,
+    cache: LabelCache,
+    compiler: Compiler,
+    result: Result<Program, Error>,
+    stale: bool,
+}
+
+
+impl Incremental {
+    pub fn new(source: &str) -> Self {
+        Self::with_compiler(source, Compiler::default())
+    }
+
+    /// Keeps `source` compiled by `compiler` rather than the default one
+    pub fn with_compiler(source: &str, compiler: Compiler) -> Self {
+        let mut cache = LabelCache::new();
+
+        let (tokens, spans, result, stale) = match Script::try_from(source) {
+            Ok(script) => {
+                let (tokens, spans) = script.into_parts();
+                let result = compile_tokens(&tokens, &spans, &mut cache, &compiler).map_err(Error::from);
+
+                (tokens, spans, result, false)
+            },
+            Err(err) => (Vec::new(), Vec::new(), Err(Error::from(err)), true),
+        };
+
+        Self { source: String::from(source), label_starts: label_starts(source, 0), tokens, spans, cache, compiler, result, stale }
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    pub fn tokens(&self) -> &[Token] {
+        &self.tokens
+    }
+
+    pub fn spans(&self) -> &[Span] {
+        &self.spans
+    }
+
+    pub fn program(&self) -> Result<&Program, &Error> {
+        self.result.as_ref()
+    }
+
+    fn rebuild(&mut self) {
+        let source = std::mem::take(&mut self.source);
+        *self = Self::with_compiler(&source, std::mem::take(&mut self.compiler));
+    }
+
+    /// Replaces the chars in `range` with `text` and recompiles what that changed
+    pub fn edit(&mut self, range: Range<usize>, text: &str) -> Result<&Program, &Error> {
+        let old_length = self.source.chars().count();
+        let byte = |source: &str, offset: usize| source.char_indices().nth(offset).map(|(i, _)| i).unwrap_or(source.len());
+
+        let removed = self.source[byte(&self.source, range.start)..byte(&self.source, range.end)].to_string();
+        self.source.replace_range(byte(&self.source, range.start)..byte(&self.source, range.end), text);
+        let delta = text.chars().count() as isize - (range.end - range.start) as isize;
+
+        // a label reaches up to the next one's `@`, edits right at the border touch both
+        let first = self.label_starts.iter().rposition(|start| *start < range.start);
+        let last = self.label_starts.iter().rposition(|start| *start <= range.end);
+
+        let is_comment = |c: char| c == LToken::MULTILINE_COMMENT_START || c == LToken::MULTILINE_COMMENT_END;
+        let (Some(first), Some(last)) = (first, last) else {
+            self.rebuild();
+            return self.program();
+        };
+        if self.stale || removed.chars().any(is_comment) {
+            self.rebuild();
+            return self.program();
+        };
+
+        let region_start = self.label_starts[first];
+        let old_region_end = self.label_starts.get(last + 1).copied().unwrap_or(old_length);
+        let region_end = (old_region_end as isize + delta) as usize;
+        let region = self.source[byte(&self.source, region_start)..byte(&self.source, region_end)].to_string();
+
+        let Ok(script) = Script::try_from(region.as_str()) else {
+            self.rebuild();
+            return self.program();
+        };
+        let (new_tokens, new_spans) = script.into_parts();
+
+        // the label after the region only stays one if the region still ends where a statement can start
+        let new_starts = label_starts(&region, region_start);
+        let next_stays = region_end == self.source.chars().count()
+            || label_starts(&format!("{region}@"), region_start).last() == Some(&region_end);
+
+        if region.chars().any(is_comment) || !matches!(new_tokens.first(), Some(Token::Label { .. }))
+            || !next_stays || new_starts.len() != label_names(&new_tokens).len() {
+            self.rebuild();
+            return self.program();
+        };
+
+        let label_tokens = self.tokens.iter().enumerate()
+            .filter(|(_, token)| matches!(token, Token::Label { .. }))
+            .map(|(i, _)| i)
+            .collect::<Vec<_>>();
+        let old_range = label_tokens[first]..label_tokens.get(last + 1).copied().unwrap_or(self.tokens.len());
+        let token_delta = new_tokens.len() as isize - old_range.len() as isize;
+
+        let old_names = label_names(&self.tokens[old_range.clone()]).into_iter().map(String::from).collect::<Vec<_>>();
+        let new_names = label_names(&new_tokens).into_iter().map(String::from).collect::<Vec<_>>();
+        let same_overrides = overrides(&self.tokens[old_range.clone()]) == overrides(&new_tokens);
+
+        self.tokens.splice(old_range.clone(), new_tokens);
+
+        let shift = |span: &Span, by: isize| Span::new((span.start as isize + by) as usize, (span.end as isize + by) as usize);
+        let spans_after = self.spans[old_range.end..].iter().map(|span| shift(span, delta)).collect::<Vec<_>>();
+        self.spans.truncate(old_range.start);
+        self.spans.extend(new_spans.iter().map(|span| shift(span, region_start as isize)));
+        self.spans.extend(spans_after);
+        // lines and bytes before the region may have changed along with it
+        let map = SourceMap::new(&self.source);
+        self.spans.iter_mut().for_each(|span| *span = map.span(span.start, span.end));
+
+        let after = self.label_starts[last + 1..].iter().map(|start| (*start as isize + delta) as usize).collect::<Vec<_>>();
+        self.label_starts.truncate(first);
+        self.label_starts.extend(new_starts);
+        self.label_starts.extend(after);
+
+        // instruments are numbered by label and then by override, so a different set of either
+        // invalidates everything
+        if old_names != new_names || !same_overrides {
+            self.cache.clear();
+        } else {
+            let names = new_names.iter().map(String::as_str).collect::<Vec<_>>();
+            let affected = with_callers(&self.tokens, &names);
+
+            self.cache.retain(|(name, _), _| !affected.contains(name));
+            for instructions in self.cache.values_mut() {
+                for instruction in instructions.iter_mut().filter(|instruction| instruction.pos >= old_range.end) {
+                    instruction.pos = (instruction.pos as isize + token_delta) as usize;
+                };
+            };
+        };
+
+        self.result = compile_tokens(&self.tokens, &self.spans, &mut self.cache, &self.compiler).map_err(Error::from);
+        self.program()
+    }
+
+    /// Replaces the whole source, recompiling only the part between what stayed the same at its start
+    /// and end, the way a file re-read from disk after an edit would change
+    pub fn set_source(&mut self, source: &str) -> Result<&Program, &Error> {
+        let old = self.source.chars().collect::<Vec<_>>();
+        let new = source.chars().collect::<Vec<_>>();
+
+        let prefix = old.iter().zip(new.iter()).take_while(|(a, b)| a == b).count();
+        let suffix = old[prefix..].iter().rev().zip(new[prefix..].iter().rev()).take_while(|(a, b)| a == b).count();
+
+        let text = new[prefix..new.len() - suffix].iter().collect::<String>();
+        self.edit(prefix..old.len() - suffix, &text)
+    }
+}
@@ -0,0 +1,187 @@
+use std::{fmt, collections::HashMap};
+use crate::json;
+use crate::syntax::lexer::{SourceMap, Span};
+use crate::syntax::parser::{ParsingError, Script, Token, Value};
+use super::{note_frequency, parse_duration, parse_octave};
+
+
+const SHORTEST_DURATION: f64 = 1.0 / 64.0;
+const AUDIBLE_RANGE: (f64, f64) = (20.0, 20_000.0);
+
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+
+#[derive(Clone, Debug)]
+pub struct Lint {
+    pub line: usize,
+    pub column: usize,
+    pub span: Span,
+    pub severity: Severity,
+    pub rule: &'static str,
+    pub message: String,
+}
+
+
+impl Lint {
+    pub fn to_json(&self, file: &str) -> String {
+        format!(
+            "{{\"file\":{},\"line\":{},\"column\":{},\"severity\":\"{}\",\"rule\":\"{}\",\"message\":{}}}",
+            json::string(file), self.line, self.column, self.severity, self.rule, json::string(&self.message),
+        )
+    }
+}
+
+
+impl fmt::Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}[{}]: {}", self.line, self.column, self.severity, self.rule, self.message)
+    }
+}
+
+
+pub fn line_and_column(source: &str, offset: usize) -> (usize, usize) {
+    SourceMap::new(source).line_and_column(offset)
+}
+
+
+fn referenced_label(token: &Token) -> Option<&str> {
+    match token {
+        Token::Command { name, arguments, .. } if name == "goto" || name == "repeat" => match arguments.first() {
+            Some(Value::String(label)) => Some(label.as_str()),
+            _ => None,
+        },
+        Token::Property { name, value: Value::String(label) } if name == "loop" => Some(label.as_str()),
+        _ => None,
+    }
+}
+
+
+fn lint_note(note: &str, arguments: &[Value], octave: u32, pos: usize, found: &mut Vec<(usize, Severity, &'static str, String)>) {
+    let notes = std::iter::once(note).chain(arguments.iter().filter_map(|argument| match argument {
+        Value::String(note) => Some(note.as_str()),
+        _ => None,
+    }));
+
+    for note in notes {
+        if let Some(frequency) = note_frequency(note, octave) && !(AUDIBLE_RANGE.0..=AUDIBLE_RANGE.1).contains(&frequency) {
+            found.push((pos, Severity::Warning, "inaudible-note", format!("`{note}` in octave {octave} is {frequency:.1} Hz, outside of the audible range")));
+        };
+    };
+
+    if let Some(duration) = arguments.last() && let Ok(length) = parse_duration(duration) && length < SHORTEST_DURATION {
+        found.push((pos, Severity::Warning, "short-duration", format!("note lasts {duration}, shorter than 1/64")));
+    };
+}
+
+
+/// Looks for things that compile but likely aren't what was meant, and for the few mistakes
+/// the compiler would stop at, so an editor can show them all at once
+pub fn lint(source: &str) -> Result<Vec<Lint>, ParsingError> {
+    let script = Script::try_from(source)?;
+    let tokens = script.get_tokens();
+
+    let mut labels: HashMap<&str, usize> = HashMap::new();
+    let mut references: HashMap<&str, Vec<usize>> = HashMap::new();
+    let mut octaves = vec![None];
+    let mut found = Vec::new();
+
+    for (pos, token) in tokens.iter().enumerate() {
+        match token {
+            Token::Label { name } => {
+                octaves.push(None);
+
+                if labels.contains_key(name.as_str()) {
+                    found.push((pos, Severity::Warning, "duplicate-label", format!("label `{name}` is already defined, this one is never used")));
+                } else {
+                    labels.insert(name, pos);
+                };
+            },
+            Token::Property { name, value } if name == "octave" => *octaves.last_mut().unwrap() = Some(value),
+            _ => { },
+        };
+
+        if let Some(label) = referenced_label(token) {
+            references.entry(label).or_default().push(pos);
+        };
+    };
+
+    let global_octave = parse_octave(octaves[0]).unwrap_or(4);
+
+    let mut scope = 0;
+    let mut exited = false;
+    for (pos, token) in tokens.iter().enumerate() {
+        match token {
+            Token::Label { .. } => {
+                scope += 1;
+                exited = false;
+            },
+            Token::Command { name, arguments, overrides } if scope > 0 => {
+                if exited {
+                    found.push((pos, Severity::Warning, "unreachable", String::from("commands after `goto` are never played")));
+                    exited = false;
+                };
+
+                // a ghost note is linted like the note after `ghost`
+                let (name, arguments) = match (name.as_str(), arguments.split_first()) {
+                    ("ghost", Some((Value::String(note), arguments))) => (note, arguments),
+                    _ => (name, arguments.as_slice()),
+                };
+
+                if name == "goto" {
+                    exited = true;
+                } else if note_frequency(name, 4).is_some() {
+                    let octave = overrides.iter().find(|(name, _)| name == "octave").map(|(_, octave)| octave).or(octaves[scope])
+                        .map(|octave| parse_octave(Some(octave)).unwrap_or(global_octave))
+                        .unwrap_or(global_octave);
+
+                    lint_note(name, arguments, octave, pos, &mut found);
+                };
+            },
+            _ => { },
+        };
+    };
+
+    if !labels.contains_key("main") {
+        found.push((0, Severity::Error, "missing-main", String::from("there is no `main` label to start playing from")));
+    };
+
+    for (label, positions) in references.iter() {
+        if !labels.contains_key(label) {
+            for pos in positions {
+                found.push((*pos, Severity::Error, "unknown-label", format!("label `{label}` isn't defined")));
+            };
+        };
+    };
+
+    for (label, pos) in labels.iter() {
+        if *label != "main" && !references.contains_key(label) {
+            found.push((*pos, Severity::Warning, "unused-label", format!("label `{label}` is never used")));
+        };
+    };
+
+    found.sort_by_key(|(pos, ..)| *pos);
+
+    Ok(found.into_iter()
+        .map(|(pos, severity, rule, message)| {
+            let span = script.get_span(pos).unwrap_or_default();
+            let (line, column) = line_and_column(source, span.start);
+
+            Lint { line, column, span, severity, rule, message }
+        })
+        .collect())
+}
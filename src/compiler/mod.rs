@@ -1,82 +1,1217 @@
-use std::{fmt, collections::HashMap};
+use std::{fmt, collections::{HashMap, VecDeque}};
+use crate::syntax::lexer::Span;
+use crate::interpreter::{effects, voice};
 use crate::syntax::parser::{Script, Token, Value};
+use commands::{CommandContext, CommandHandler};
+use notes::NoteTable;
+
+pub mod lint;
+pub mod notes;
+pub mod commands;
+pub mod properties;
+pub mod transpose;
+pub mod incremental;
 
 mod helper {
     use crate::syntax::parser::Value;
 
-    pub fn value_name(v: &Value) -> &'static str {
-        match v {
-            Value::Fraction { .. } => "fraction",
-            Value::String(..) => "string",
-            Value::Whole(..) => "whole",
-        }
-    }
+    pub fn value_name(v: &Value) -> &'static str {
+        match v {
+            Value::Fraction { .. } => "fraction",
+            Value::String(..) => "string",
+            Value::Whole(..) => "whole",
+            Value::List(..) => "list",
+        }
+    }
+}
+
+const A_4_FREQUENCY: f64 = 440.0;
+const A_4_ABSOLUTE_NOTE: i8 = 57;
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Program {
+    instructions: Vec<Instruction>,
+    instruments: Vec<Instrument>,
+    buses: Vec<Bus>,
+    bpm: f64,
+    tempos: Vec<f64>,
+    loop_label: Option<String>,
+    metadata: Metadata,
+    /// Syllables sung to the notes, by the token of the note
+    #[cfg_attr(feature = "serde", serde(default))]
+    lyrics: HashMap<usize, String>,
+    /// Names of the `mark` commands, by their token
+    #[cfg_attr(feature = "serde", serde(default))]
+    marks: HashMap<usize, String>,
+}
+
+
+impl Program {
+    pub fn get_instructions(&self) -> &[Instruction] {
+        &self.instructions
+    }
+
+    pub fn get_instruments(&self) -> &[Instrument] {
+        &self.instruments
+    }
+
+    pub fn get_buses(&self) -> &[Bus] {
+        &self.buses
+    }
+
+    pub fn get_bus_index(&self, name: &str) -> Option<usize> {
+        self.buses.iter().position(|bus| bus.name == name)
+    }
+
+    /// Tempo of every label, in the same order as the instruments
+    pub fn get_tempos(&self) -> &[f64] {
+        &self.tempos
+    }
+
+    pub fn get_metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
+    /// The name of the `mark` written at token `pos`
+    pub fn get_mark(&self, pos: usize) -> Option<&str> {
+        self.marks.get(&pos).map(String::as_str)
+    }
+
+    /// Every mark with the time it's reached at, in the order they're reached. A mark in a repeated
+    /// label is reached every time it's played
+    pub fn get_chapters(&self) -> Vec<Chapter> {
+        let mut chapters = Vec::new();
+
+        let mut time = 0.0;
+        for instruction in self.instructions.iter() {
+            match instruction.data {
+                InstructionData::Advance { duration } => time += duration,
+                InstructionData::Mark => chapters.push(Chapter {
+                    time,
+                    name: self.get_mark(instruction.pos).map(String::from).unwrap_or_default(),
+                }),
+                InstructionData::Play { .. }
+                | InstructionData::EnterLabel { .. }
+                | InstructionData::ExitLabel { .. }
+                | InstructionData::Tempo { .. }
+                | InstructionData::Meter { .. }
+                | InstructionData::Automate { .. } => { },
+            };
+        };
+
+        chapters
+    }
+
+    /// The syllable sung to the note written at token `pos`, every time it's played. `_` holds the
+    /// syllable before over the note
+    pub fn get_lyric(&self, pos: usize) -> Option<&str> {
+        self.lyrics.get(&pos).map(String::as_str)
+    }
+
+    /// Quarter notes a minute the script starts at
+    pub fn get_bpm(&self) -> f64 {
+        self.bpm
+    }
+
+    pub fn get_bar_duration(&self) -> f64 {
+        note_duration(self.bpm, 1.0)
+    }
+
+    pub fn get_duration(&self) -> f64 {
+        self.instructions.iter()
+            .map(|instruction| match instruction.data {
+                InstructionData::Advance { duration } => duration,
+                _ => 0.0,
+            })
+            .sum()
+    }
+
+    pub fn get_note_events(&self) -> Vec<NoteEvent> {
+        let mut events = Vec::new();
+
+        let mut time = 0.0;
+        for instruction in self.instructions.iter() {
+            match instruction.data {
+                InstructionData::Play { frequency, duration, instrument } => events.push(NoteEvent {
+                    pos: instruction.pos,
+                    span: instruction.span,
+                    start: time,
+                    duration,
+                    frequency,
+                    instrument,
+                }),
+                InstructionData::Advance { duration } => time += duration,
+                InstructionData::EnterLabel { .. }
+                | InstructionData::ExitLabel { .. }
+                | InstructionData::Mark
+                | InstructionData::Tempo { .. }
+                | InstructionData::Meter { .. }
+                | InstructionData::Automate { .. } => { },
+            };
+        };
+
+        events
+    }
+
+    pub fn get_label_regions(&self) -> Vec<LabelRegion> {
+        let mut regions = Vec::new();
+
+        let mut open = Vec::new();
+        for (i, instruction) in self.instructions.iter().enumerate() {
+            match instruction.data {
+                InstructionData::EnterLabel { instrument } => {
+                    open.push(regions.len());
+                    regions.push(LabelRegion { instrument, start_instruction: i, end_instruction: i });
+                },
+                InstructionData::ExitLabel { .. } => {
+                    let region: &mut LabelRegion = &mut regions[open.pop().unwrap()];
+
+                    region.end_instruction = i;
+                },
+                InstructionData::Advance { .. }
+                | InstructionData::Play { .. }
+                | InstructionData::Mark
+                | InstructionData::Tempo { .. }
+                | InstructionData::Meter { .. }
+                | InstructionData::Automate { .. } => { },
+            };
+        };
+
+        regions
+    }
+
+    /// Tempo at the start of the piece and at every point a label with a different one takes over
+    pub fn get_tempo_map(&self) -> Vec<TempoChange> {
+        let mut changes: Vec<TempoChange> = Vec::new();
+
+        let mut time = 0.0;
+        for instruction in self.instructions.iter() {
+            let bpm = match instruction.data {
+                InstructionData::Tempo { bpm } => bpm,
+                InstructionData::Advance { duration } => {
+                    time += duration;
+                    continue;
+                },
+                _ => continue,
+            };
+
+            if changes.last().is_some_and(|last| last.time == time) {
+                changes.pop();
+            };
+            if changes.last().is_none_or(|last| last.bpm != bpm) {
+                changes.push(TempoChange { time, bpm });
+            };
+        };
+
+        changes
+    }
+
+    /// Time every bar starts at, a bar lasting as long as the meter it's played in at its tempo
+    pub fn get_bars(&self) -> Vec<f64> {
+        let duration = self.get_duration();
+
+        // when bars start lasting a different number of seconds, and how many
+        let mut lengths: Vec<(f64, f64)> = Vec::new();
+        let (mut time, mut bpm, mut meter) = (0.0, self.bpm, 1.0);
+        for instruction in self.instructions.iter() {
+            match instruction.data {
+                InstructionData::Tempo { bpm: tempo } => bpm = tempo,
+                InstructionData::Meter { numerator, denominator } => meter = numerator as f64 / denominator as f64,
+                InstructionData::Advance { duration } => {
+                    time += duration;
+                    continue;
+                },
+                _ => continue,
+            };
+
+            let length = note_duration(bpm, meter);
+            if lengths.last().is_some_and(|last| last.0 == time) {
+                lengths.pop();
+            };
+            if lengths.last().is_none_or(|last| last.1 != length) {
+                lengths.push((time, length));
+            };
+        };
+
+        let mut bars = vec![0.0];
+        // how many bars into the piece the current length starts
+        let mut position = 0.0;
+        for (i, (start, length)) in lengths.iter().enumerate() {
+            let end = lengths.get(i + 1).map_or(duration, |next| next.0);
+
+            let mut bar = (position + 1e-9f64).ceil();
+            let mut time = start + (bar - position) * length;
+            while time < end - 1e-9 {
+                if time > 1e-9 {
+                    bars.push(time);
+                };
+
+                bar += 1.0;
+                time = start + (bar - position) * length;
+            };
+
+            position += (end - start) / length;
+        };
+
+        bars
+    }
+
+    pub fn get_loop_region(&self) -> Option<LabelRegion> {
+        let name = self.loop_label.as_ref()?;
+
+        self.get_label_regions().into_iter().find(|region| self.instruments[region.instrument].name.as_ref() == Some(name))
+    }
+}
+
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Chapter {
+    pub time: f64,
+    pub name: String,
+}
+
+
+#[derive(Copy, Clone, Debug)]
+pub struct LabelRegion {
+    pub instrument: usize,
+    pub start_instruction: usize,
+    pub end_instruction: usize,
+}
+
+
+#[derive(Copy, Clone, Debug)]
+pub struct TempoChange {
+    pub time: f64,
+    pub bpm: f64,
+}
+
+
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Metadata {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub composer: Option<String>,
+    pub comment: Option<String>,
+}
+
+
+impl Metadata {
+    pub fn is_empty(&self) -> bool {
+        self.title.is_none() && self.artist.is_none() && self.composer.is_none() && self.comment.is_none()
+    }
+}
+
+
+#[derive(Copy, Clone, Debug)]
+pub struct NoteEvent {
+    pub pos: usize,
+    /// Where the note was written, if the program was compiled from source
+    pub span: Span,
+    pub start: f64,
+    pub duration: f64,
+    pub frequency: f64,
+    pub instrument: usize,
+}
+
+
+impl fmt::Display for Program {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for instr in self.instructions[..self.instructions.len() - 1].iter() {
+            writeln!(f, "{instr}")?;
+        };
+
+        write!(f, "{}", self.instructions.last().unwrap())
+    }
+}
+
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Instrument {
+    pub name: Option<String>,
+    pub volume: f64,
+    pub filter: Option<Filter>,
+    pub distortion: Option<Distortion>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub ringmod: Option<RingModulator>,
+    pub bitcrusher: Option<Bitcrusher>,
+    pub lfos: Vec<Lfo>,
+    pub voice: Option<String>,
+    pub effects: Option<Vec<String>>,
+    pub bus: Option<String>,
+    /// Seconds a note takes to glide from the pitch of the one before it, 0 for none
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub portamento: f64,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub unison: Option<Unison>,
+    /// Color of the noise played with `voice: noise`
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub noise: NoiseColor,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub autopan: Option<AutoPan>,
+    /// From -1 for the left to 1 for the right, heard on instruments going straight to master
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub pan: f64,
+    /// How hard its notes are played, from 1 to 127 like MIDI's
+    #[cfg_attr(feature = "serde", serde(default = "default_velocity"))]
+    pub velocity: u32,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub response: VelocityResponse,
+}
+
+
+impl Default for Instrument {
+    fn default() -> Self {
+        Self {
+            name: None,
+            volume: 1.0,
+            filter: None,
+            distortion: None,
+            ringmod: None,
+            bitcrusher: None,
+            lfos: Vec::new(),
+            voice: None,
+            effects: None,
+            bus: None,
+            portamento: 0.0,
+            unison: None,
+            noise: NoiseColor::White,
+            pan: 0.0,
+            autopan: None,
+            velocity: Instrument::DEFAULT_VELOCITY,
+            response: VelocityResponse::default(),
+        }
+    }
+}
+
+
+impl Instrument {
+    pub const DEFAULT_VELOCITY: u32 = 100;
+    const MAX_VELOCITY: u32 = 127;
+
+    /// How much of its volume its notes are played at for their velocity
+    pub fn velocity_gain(&self) -> f64 {
+        1.0 - self.response.volume * (1.0 - self.velocity as f64 / Self::MAX_VELOCITY as f64)
+    }
+
+    /// Hertz its filter is opened by for its notes' velocity
+    pub fn velocity_brightness(&self) -> f64 {
+        self.response.cutoff * self.velocity as f64 / Self::MAX_VELOCITY as f64
+    }
+}
+
+
+#[cfg(feature = "serde")]
+fn default_velocity() -> u32 {
+    Instrument::DEFAULT_VELOCITY
+}
+
+
+/// How an instrument's notes change with their velocity: `volume` is how much of their volume soft notes
+/// lose, all of it at the softest, and `cutoff` the hertz the filter opens by at the hardest, so loud notes
+/// sound brighter rather than just louder
+#[derive(Copy, Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct VelocityResponse {
+    pub volume: f64,
+    pub cutoff: f64,
+}
+
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Bus {
+    pub name: String,
+    pub gain: f64,
+    pub pan: f64,
+    pub settings: Instrument,
+    pub sends: Vec<BusSend>,
+}
+
+
+impl Bus {
+    pub const MASTER: &'static str = "master";
+}
+
+
+#[derive(Copy, Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BusSend {
+    pub bus: usize,
+    pub amount: f64,
+}
+
+
+#[derive(Copy, Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Filter {
+    pub kind: FilterKind,
+    pub cutoff: f64,
+    pub resonance: f64,
+}
+
+
+impl Filter {
+    const DEFAULT_CUTOFF: f64 = 1000.0;
+    const DEFAULT_RESONANCE: f64 = std::f64::consts::FRAC_1_SQRT_2;
+}
+
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum FilterKind {
+    LowPass,
+    HighPass,
+}
+
+
+#[derive(Copy, Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Distortion {
+    pub kind: DistortionKind,
+    pub drive: f64,
+    pub tone: Option<f64>,
+}
+
+
+impl Distortion {
+    const DEFAULT_DRIVE: f64 = 4.0;
+}
+
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DistortionKind {
+    Overdrive,
+    Hard,
+}
+
+
+/// Multiplying by a sine of `carrier` hertz, blended with the dry signal by `mix`
+#[derive(Copy, Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RingModulator {
+    pub carrier: f64,
+    pub mix: f64,
+}
+
+
+#[derive(Copy, Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Bitcrusher {
+    pub bits: u32,
+    pub rate: Option<f64>,
+}
+
+
+impl Bitcrusher {
+    const MAX_BITS: u32 = 16;
+}
+
+
+/// Panning swaying from side to side, `depth` being how far it goes with 1 reaching either side. Only
+/// stereo buses can be panned, so it's heard on instruments going straight to master
+#[derive(Copy, Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AutoPan {
+    pub rate: f64,
+    pub depth: f64,
+}
+
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum NoiseColor {
+    #[default]
+    White,
+    /// Falling by 3 dB an octave, even across octaves like a snare or the wind
+    Pink,
+    /// Falling by 6 dB an octave, a rumble
+    Brown,
+}
+
+
+/// Copies of the oscillator played together, spread evenly over `detune` cents
+#[derive(Copy, Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Unison {
+    pub voices: u32,
+    pub detune: f64,
+}
+
+
+impl Unison {
+    const MAX_VOICES: u32 = 16;
+    const DEFAULT_DETUNE: f64 = 20.0;
+}
+
+
+#[derive(Copy, Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Lfo {
+    pub target: LfoTarget,
+    pub rate: f64,
+    pub depth: f64,
+}
+
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum LfoTarget {
+    Pitch,
+    Amplitude,
+    Cutoff,
+    /// Swayed by the mixer over the whole track, like an auto-pan, rather than by every note
+    Pan,
+}
+
+
+/// What `automate` can move over time
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Parameter {
+    Volume,
+    Pan,
+    /// Cutoff of the instrument's filter, doing nothing without one
+    Cutoff,
+}
+
+
+impl Parameter {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "volume" => Some(Self::Volume),
+            "pan" => Some(Self::Pan),
+            "cutoff" => Some(Self::Cutoff),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Volume => "volume",
+            Self::Pan => "pan",
+            Self::Cutoff => "cutoff",
+        }
+    }
+
+    /// The value written in a script for it, `pan` taking the same ones as the property
+    fn parse(&self, v: &Value) -> Result<f64, CompilingError> {
+        match self {
+            Self::Volume | Self::Cutoff => parse_number(v),
+            Self::Pan => parse_pan(Some(v)),
+        }
+    }
+}
+
+
+struct Scope {
+    pub name: Option<String>,
+    pub range: (usize, usize),
+    pub properties: HashMap<String, Value>,
+    /// Instruments of the commands with overrides, by the command's token
+    pub overridden: HashMap<usize, usize>,
+}
+
+
+/// The override giving a note its syllable, `lyric=Twin`
+const LYRIC: &str = "lyric";
+/// Most instructions a script can unroll into, far more than any piece needs but few enough to fit in memory
+const MAX_INSTRUCTIONS: usize = 2_000_000;
+/// Most `goto`s and `repeat`s that can be inside one another
+const MAX_DEPTH: usize = 64;
+/// Written between the beat and the tempo of a `bpm: 3/8 = 90`
+const BEAT_SEPARATOR: &str = "=";
+/// How loud `ghost` notes are next to the others without a `ghost:` property
+const GHOST_VOLUME: f64 = 0.25;
+
+
+fn parse_octave(v: Option<&Value>) -> Result<u32, CompilingError> {
+    match v {
+        None => Ok(4),
+        Some(Value::Whole(n)) => Ok(*n),
+        Some(v) => Err(CompilingError::ValueTypeError {
+            pos: None,
+            expected: "whole",
+            got: helper::value_name(v)
+        }),
+    }
+}
+
+/// The tempo in quarter notes a minute, either given as that or as a beat and how many of them are
+/// played a minute, like `3/8 = 90` for 90 dotted quarters a minute
+fn parse_bpm(v: Option<&Value>) -> Result<f64, CompilingError> {
+    match v {
+        None => Err(CompilingError::MissingGlobalProperty { missing: "bpm" }),
+        Some(Value::Whole(n)) => {
+            if *n < 1 {
+                Err(CompilingError::ValueOutOfRange { allowed: (Some(1), None), got: *n as f64, pos: None })
+            } else {
+                Ok(*n as f64)
+            }
+        },
+        Some(Value::Fraction { numerator, denominator }) => {
+            if *numerator == 0 {
+                Err(CompilingError::ValueOutOfRange { allowed: (Some(1), None), got: *numerator as f64, pos: None })
+            } else {
+                Ok(*numerator as f64 / *denominator as f64)
+            }
+        },
+        Some(Value::List(values)) => match values.as_slice() {
+            [beat, Value::String(separator), per_minute] if separator == BEAT_SEPARATOR => {
+                let beat = parse_bpm(Some(beat))?;
+                let per_minute = parse_bpm(Some(per_minute))?;
+
+                // as many quarter notes a minute as there are quarters in the beats
+                Ok(per_minute * beat * 4.0)
+            },
+            _ => Err(CompilingError::ValueTypeError { pos: None, expected: "number-like or beat", got: "list" }),
+        },
+        Some(v) => {
+            Err(CompilingError::ValueTypeError { pos: None, expected: "number-like", got: helper::value_name(v) })
+        }
+    }
+}
+
+/// Whether the script asks for `strict: on`, where anything the compiler would leave out is an error
+fn parse_strict(v: Option<&Value>) -> Result<bool, CompilingError> {
+    match v {
+        None => Ok(false),
+        Some(Value::String(s)) => match s.as_str() {
+            "on" => Ok(true),
+            "off" => Ok(false),
+            _ => Err(CompilingError::UnknownOption { property: "strict", got: s.clone() }),
+        },
+        Some(v) => Err(CompilingError::ValueTypeError { pos: None, expected: "string", got: helper::value_name(v) }),
+    }
+}
+
+/// A time signature as its numerator and denominator, 4/4 when there's none
+fn parse_meter(v: Option<&Value>) -> Result<(u32, u32), CompilingError> {
+    match v {
+        None => Ok((4, 4)),
+        Some(Value::Fraction { numerator, denominator }) => {
+            if *numerator == 0 {
+                Err(CompilingError::ValueOutOfRange { allowed: (Some(1), None), got: *numerator as f64, pos: None })
+            } else if !denominator.is_power_of_two() {
+                // which MIDI couldn't write down
+                Err(CompilingError::UnknownOption { property: "meter", got: format!("{numerator}/{denominator}") })
+            } else {
+                Ok((*numerator, *denominator))
+            }
+        },
+        Some(v) => Err(CompilingError::ValueTypeError { pos: None, expected: "fraction", got: helper::value_name(v) }),
+    }
+}
+
+fn parse_number(v: &Value) -> Result<f64, CompilingError> {
+    match v {
+        Value::Whole(n) => Ok(*n as f64),
+        Value::Fraction { numerator, denominator } => Ok(*numerator as f64 / *denominator as f64),
+        v => Err(CompilingError::ValueTypeError { pos: None, expected: "number-like", got: helper::value_name(v) }),
+    }
+}
+
+fn parse_with_unit(v: &Value, unit: &str) -> Result<f64, CompilingError> {
+    match v {
+        Value::String(s) => s.strip_suffix(unit)
+            .and_then(|n| n.parse::<f64>().ok())
+            .ok_or(CompilingError::ValueTypeError { pos: None, expected: "number-like", got: "string" }),
+        v => parse_number(v),
+    }
+}
+
+fn parse_filter(properties: &HashMap<String, Value>, global: Option<Filter>) -> Result<Option<Filter>, CompilingError> {
+    let kind = match properties.get("filter") {
+        None => global.map(|f| f.kind),
+        Some(Value::String(kind)) => match kind.as_str() {
+            "none" => None,
+            "lowpass" => Some(FilterKind::LowPass),
+            "highpass" => Some(FilterKind::HighPass),
+            _ => return Err(CompilingError::UnknownOption { property: "filter", got: kind.clone() }),
+        },
+        Some(v) => return Err(CompilingError::ValueTypeError { pos: None, expected: "string", got: helper::value_name(v) }),
+    };
+
+    match kind {
+        None => Ok(None),
+        Some(kind) => {
+            let cutoff = match properties.get("cutoff") {
+                None => global.map(|f| f.cutoff).unwrap_or(Filter::DEFAULT_CUTOFF),
+                Some(v) => parse_number(v)?,
+            };
+
+            let resonance = match properties.get("resonance") {
+                None => global.map(|f| f.resonance).unwrap_or(Filter::DEFAULT_RESONANCE),
+                Some(v) => parse_number(v)?,
+            };
+
+            if cutoff <= 0.0 {
+                Err(CompilingError::ValueOutOfRange { allowed: (Some(1), None), got: cutoff, pos: None })
+            } else if resonance <= 0.0 {
+                Err(CompilingError::ValueOutOfRange { allowed: (Some(1), None), got: resonance, pos: None })
+            } else {
+                Ok(Some(Filter { kind, cutoff, resonance }))
+            }
+        },
+    }
+}
+
+fn parse_distortion(properties: &HashMap<String, Value>, global: Option<Distortion>) -> Result<Option<Distortion>, CompilingError> {
+    let kind = match properties.get("distortion") {
+        None => global.map(|d| d.kind),
+        Some(Value::String(kind)) => match kind.as_str() {
+            "none" => None,
+            "overdrive" => Some(DistortionKind::Overdrive),
+            "hard" => Some(DistortionKind::Hard),
+            _ => return Err(CompilingError::UnknownOption { property: "distortion", got: kind.clone() }),
+        },
+        Some(v) => return Err(CompilingError::ValueTypeError { pos: None, expected: "string", got: helper::value_name(v) }),
+    };
+
+    match kind {
+        None => Ok(None),
+        Some(kind) => {
+            let drive = match properties.get("drive") {
+                None => global.map(|d| d.drive).unwrap_or(Distortion::DEFAULT_DRIVE),
+                Some(v) => parse_number(v)?,
+            };
+
+            let tone = match properties.get("tone") {
+                None => global.and_then(|d| d.tone),
+                Some(v) => Some(parse_number(v)?),
+            };
+
+            if drive < 1.0 {
+                Err(CompilingError::ValueOutOfRange { allowed: (Some(1), None), got: drive, pos: None })
+            } else if let Some(tone) = tone && tone <= 0.0 {
+                Err(CompilingError::ValueOutOfRange { allowed: (Some(1), None), got: tone, pos: None })
+            } else {
+                Ok(Some(Distortion { kind, drive, tone }))
+            }
+        },
+    }
+}
+
+fn parse_ringmod(properties: &HashMap<String, Value>, global: Option<RingModulator>) -> Result<Option<RingModulator>, CompilingError> {
+    let carrier = match properties.get("ringmod") {
+        None => global.map(|r| r.carrier),
+        Some(Value::String(s)) if s == "none" => None,
+        Some(v) => Some(parse_with_unit(v, "hz")?),
+    };
+
+    match carrier {
+        None => Ok(None),
+        Some(carrier) => {
+            let mix = match properties.get("ringmod_mix") {
+                None => global.map(|r| r.mix).unwrap_or(1.0),
+                Some(mix @ Value::String(..)) => parse_with_unit(mix, "%")? / 100.0,
+                Some(mix) => parse_number(mix)?,
+            };
+
+            if carrier <= 0.0 {
+                Err(CompilingError::ValueOutOfRange { allowed: (Some(1), None), got: carrier, pos: None })
+            } else if !(0.0..=1.0).contains(&mix) {
+                Err(CompilingError::ValueOutOfRange { allowed: (Some(0), Some(1)), got: mix, pos: None })
+            } else {
+                Ok(Some(RingModulator { carrier, mix }))
+            }
+        },
+    }
+}
+
+fn parse_bitcrusher(properties: &HashMap<String, Value>, global: Option<Bitcrusher>) -> Result<Option<Bitcrusher>, CompilingError> {
+    let bits = match properties.get("bitcrusher") {
+        None => global.map(|b| b.bits),
+        Some(Value::String(s)) if s == "none" => None,
+        Some(Value::String(s)) => return Err(CompilingError::UnknownOption { property: "bitcrusher", got: s.clone() }),
+        Some(Value::Whole(bits)) => {
+            if *bits < 1 || *bits > Bitcrusher::MAX_BITS {
+                return Err(CompilingError::ValueOutOfRange { allowed: (Some(1), Some(Bitcrusher::MAX_BITS)), got: *bits as f64, pos: None });
+            };
+
+            Some(*bits)
+        },
+        Some(v) => return Err(CompilingError::ValueTypeError { pos: None, expected: "whole", got: helper::value_name(v) }),
+    };
+
+    match bits {
+        None => Ok(None),
+        Some(bits) => {
+            let rate = match properties.get("crush_rate") {
+                None => global.and_then(|b| b.rate),
+                Some(v) => Some(parse_number(v)?),
+            };
+
+            if let Some(rate) = rate && rate < 1.0 {
+                Err(CompilingError::ValueOutOfRange { allowed: (Some(1), None), got: rate, pos: None })
+            } else {
+                Ok(Some(Bitcrusher { bits, rate }))
+            }
+        },
+    }
+}
+
+fn parse_unison(properties: &HashMap<String, Value>, global: Option<Unison>) -> Result<Option<Unison>, CompilingError> {
+    let voices = match properties.get("unison") {
+        None => global.map(|u| u.voices),
+        Some(Value::String(s)) if s == "none" => None,
+        Some(Value::String(s)) => return Err(CompilingError::UnknownOption { property: "unison", got: s.clone() }),
+        Some(Value::Whole(voices)) => {
+            if *voices < 1 || *voices > Unison::MAX_VOICES {
+                return Err(CompilingError::ValueOutOfRange { allowed: (Some(1), Some(Unison::MAX_VOICES)), got: *voices as f64, pos: None });
+            };
+
+            Some(*voices)
+        },
+        Some(v) => return Err(CompilingError::ValueTypeError { pos: None, expected: "whole", got: helper::value_name(v) }),
+    };
+
+    match voices {
+        None => Ok(None),
+        Some(voices) => {
+            let detune = match properties.get("detune") {
+                None => global.map(|u| u.detune).unwrap_or(Unison::DEFAULT_DETUNE),
+                Some(v) => parse_with_unit(v, "c")?,
+            };
+
+            if detune < 0.0 {
+                Err(CompilingError::ValueOutOfRange { allowed: (Some(0), None), got: detune, pos: None })
+            } else {
+                Ok(Some(Unison { voices, detune }))
+            }
+        },
+    }
+}
+
+fn parse_lfos(v: Option<&Value>, global: &[Lfo]) -> Result<Vec<Lfo>, CompilingError> {
+    let values = match v {
+        None => return Ok(Vec::from(global)),
+        Some(Value::String(s)) if s == "none" => return Ok(Vec::new()),
+        Some(Value::List(values)) if values.len() % 3 == 0 => values,
+        Some(v) => return Err(CompilingError::ValueTypeError { pos: None, expected: "target-rate-depth triples", got: helper::value_name(v) }),
+    };
+
+    let mut lfos = Vec::new();
+    for lfo in values.chunks(3) {
+        let (target, depth_unit) = match &lfo[0] {
+            Value::String(target) => match target.as_str() {
+                "pitch" => (LfoTarget::Pitch, "c"),
+                "amplitude" => (LfoTarget::Amplitude, "%"),
+                "cutoff" => (LfoTarget::Cutoff, "hz"),
+                "pan" => (LfoTarget::Pan, "%"),
+                _ => return Err(CompilingError::UnknownOption { property: "lfo", got: target.clone() }),
+            },
+            v => return Err(CompilingError::ValueTypeError { pos: None, expected: "string", got: helper::value_name(v) }),
+        };
+
+        let rate = parse_with_unit(&lfo[1], "hz")?;
+        let depth = match (&lfo[2], target) {
+            (Value::String(..), LfoTarget::Amplitude | LfoTarget::Pan) => parse_with_unit(&lfo[2], depth_unit)? / 100.0,
+            (depth, _) => parse_with_unit(depth, depth_unit)?,
+        };
+
+        if rate <= 0.0 {
+            return Err(CompilingError::ValueOutOfRange { allowed: (Some(1), None), got: rate, pos: None });
+        } else if matches!(target, LfoTarget::Amplitude | LfoTarget::Pan) && depth > 1.0 {
+            return Err(CompilingError::ValueOutOfRange { allowed: (Some(0), Some(1)), got: depth, pos: None });
+        };
+
+        lfos.push(Lfo { target, rate, depth });
+    };
+
+    Ok(lfos)
+}
+
+fn parse_autopan(v: Option<&Value>, global: Option<AutoPan>) -> Result<Option<AutoPan>, CompilingError> {
+    let (rate, depth) = match v {
+        None => return Ok(global),
+        Some(Value::String(s)) if s == "none" => return Ok(None),
+        Some(Value::List(values)) if values.len() == 2 => (parse_with_unit(&values[0], "hz")?, match &values[1] {
+            depth @ Value::String(..) => parse_with_unit(depth, "%")? / 100.0,
+            depth => parse_number(depth)?,
+        }),
+        Some(v) => return Err(CompilingError::ValueTypeError { pos: None, expected: "rate-depth pair", got: helper::value_name(v) }),
+    };
+
+    if rate <= 0.0 {
+        Err(CompilingError::ValueOutOfRange { allowed: (Some(1), None), got: rate, pos: None })
+    } else if !(0.0..=1.0).contains(&depth) {
+        Err(CompilingError::ValueOutOfRange { allowed: (Some(0), Some(1)), got: depth, pos: None })
+    } else {
+        Ok(Some(AutoPan { rate, depth }))
+    }
+}
+
+fn parse_portamento(v: Option<&Value>, global: f64) -> Result<f64, CompilingError> {
+    let milliseconds = match v {
+        None => return Ok(global),
+        Some(Value::String(s)) if s == "none" => return Ok(0.0),
+        Some(v) => parse_with_unit(v, "ms")?,
+    };
+
+    if milliseconds < 0.0 {
+        return Err(CompilingError::ValueOutOfRange { allowed: (Some(0), None), got: milliseconds, pos: None });
+    };
+
+    Ok(milliseconds / 1000.0)
+}
+
+fn parse_effects(v: Option<&Value>, global: &Option<Vec<String>>) -> Result<Option<Vec<String>>, CompilingError> {
+    let values = match v {
+        None => return Ok(global.clone()),
+        Some(Value::List(values)) => values.as_slice(),
+        Some(v) => std::slice::from_ref(v),
+    };
+
+    let mut effects = Vec::new();
+    for value in values {
+        match value {
+            Value::String(name) if name == "none" => { },
+            Value::String(name) => effects.push(name.clone()),
+            v => return Err(CompilingError::ValueTypeError { pos: None, expected: "string", got: helper::value_name(v) }),
+        };
+    };
+
+    Ok(Some(effects))
+}
+
+fn parse_instrument(properties: &HashMap<String, Value>, global: &Instrument) -> Result<Instrument, CompilingError> {
+    Ok(Instrument {
+        name: None,
+        volume: properties.get("volume").map(parse_number).unwrap_or(Ok(global.volume))?,
+        filter: parse_filter(properties, global.filter)?,
+        distortion: parse_distortion(properties, global.distortion)?,
+        ringmod: parse_ringmod(properties, global.ringmod)?,
+        bitcrusher: parse_bitcrusher(properties, global.bitcrusher)?,
+        lfos: parse_lfos(properties.get("lfo"), &global.lfos)?,
+        autopan: parse_autopan(properties.get("autopan"), global.autopan)?,
+        pan: properties.get("pan").map(|pan| parse_pan(Some(pan))).unwrap_or(Ok(global.pan))?,
+        velocity: match properties.get("velocity") {
+            None => global.velocity,
+            Some(Value::Whole(velocity)) => *velocity,
+            Some(v) => return Err(CompilingError::ValueTypeError { pos: None, expected: "whole", got: helper::value_name(v) }),
+        },
+        response: VelocityResponse {
+            volume: properties.get("velocity_volume").map(parse_number).unwrap_or(Ok(global.response.volume))?,
+            cutoff: properties.get("velocity_cutoff").map(parse_number).unwrap_or(Ok(global.response.cutoff))?,
+        },
+        voice: match properties.get("voice") {
+            None => global.voice.clone(),
+            Some(Value::String(name)) => Some(name.clone()),
+            Some(v) => return Err(CompilingError::ValueTypeError { pos: None, expected: "string", got: helper::value_name(v) }),
+        },
+        effects: parse_effects(properties.get("effects"), &global.effects)?,
+        bus: match properties.get("bus") {
+            None => global.bus.clone(),
+            Some(Value::String(name)) => Some(name.clone()),
+            Some(v) => return Err(CompilingError::ValueTypeError { pos: None, expected: "string", got: helper::value_name(v) }),
+        },
+        portamento: parse_portamento(properties.get("portamento"), global.portamento)?,
+        unison: parse_unison(properties, global.unison)?,
+        noise: match properties.get("noise") {
+            None => global.noise,
+            Some(Value::String(color)) => match color.as_str() {
+                "white" => NoiseColor::White,
+                "pink" => NoiseColor::Pink,
+                "brown" => NoiseColor::Brown,
+                _ => return Err(CompilingError::UnknownOption { property: "noise", got: color.clone() }),
+            },
+            Some(v) => return Err(CompilingError::ValueTypeError { pos: None, expected: "string", got: helper::value_name(v) }),
+        },
+    })
+}
+
+fn parse_sends(v: Option<&Value>) -> Result<Vec<(String, f64)>, CompilingError> {
+    let values = match v {
+        None => return Ok(Vec::new()),
+        Some(Value::String(s)) if s == "none" => return Ok(Vec::new()),
+        Some(Value::List(values)) if values.len() % 2 == 0 => values,
+        Some(v) => return Err(CompilingError::ValueTypeError { pos: None, expected: "bus-amount pairs", got: helper::value_name(v) }),
+    };
+
+    let mut sends = Vec::new();
+    for send in values.chunks(2) {
+        let bus = match &send[0] {
+            Value::String(bus) => bus.clone(),
+            v => return Err(CompilingError::ValueTypeError { pos: None, expected: "string", got: helper::value_name(v) }),
+        };
+
+        sends.push((bus, parse_with_unit(&send[1], "")?));
+    };
+
+    Ok(sends)
 }
 
-const A_4_FREQUENCY: f64 = 440.0;
-const A_4_ABSOLUTE_NOTE: i8 = 57;
+/// `L30`, `R30` or `C`, or a number from -1 (left) to 1 (right)
+fn parse_pan(v: Option<&Value>) -> Result<f64, CompilingError> {
+    let signed = match v {
+        Some(v @ (Value::Whole(_) | Value::Fraction { .. })) => Some(parse_number(v)?),
+        Some(Value::String(s)) if s.starts_with(['-', '+']) => s.parse::<f64>().ok().filter(|pan| pan.is_finite()),
+        _ => None,
+    };
+    if let Some(pan) = signed {
+        return if pan.abs() > 1.0 {
+            Err(CompilingError::ValueOutOfRange { allowed: (None, Some(1)), got: pan, pos: None })
+        } else {
+            Ok(pan)
+        };
+    };
 
-pub struct Program(Vec<Instruction>);
+    let (side, amount) = match v {
+        None => return Ok(0.0),
+        Some(Value::String(s)) if s.eq_ignore_ascii_case("c") || s == "center" => return Ok(0.0),
+        Some(Value::String(s)) if s.len() > 1 && s.is_char_boundary(1) => s.split_at(1),
+        Some(v) => return Err(CompilingError::ValueTypeError { pos: None, expected: "L, R or C with a percentage, or a number from -1 to 1", got: helper::value_name(v) }),
+    };
 
+    let amount = amount.parse::<u32>().map_err(|_| CompilingError::ValueTypeError { pos: None, expected: "percentage", got: "string" })?;
+    if amount > 100 {
+        return Err(CompilingError::ValueOutOfRange { allowed: (Some(0), Some(100)), got: amount as f64, pos: None });
+    };
 
-impl Program {
-    pub fn get_instructions(&self) -> &[Instruction] {
-        &self.0
+    match side {
+        "L" | "l" => Ok(-(amount as f64) / 100.0),
+        "R" | "r" => Ok(amount as f64 / 100.0),
+        _ => Err(CompilingError::UnknownOption { property: "pan", got: String::from(side) }),
     }
 }
 
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum VisitState {
+    Unvisited,
+    Visiting,
+    Visited,
+}
 
-impl fmt::Display for Program {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        for instr in self.0[..self.0.len() - 1].iter() {
-            writeln!(f, "{instr}")?;
+
+fn order_buses(bus: usize, names: &[String], sends: &[Vec<(String, f64)>], state: &mut [VisitState], order: &mut Vec<usize>) -> Result<(), CompilingError> {
+    match state[bus] {
+        VisitState::Visiting => return Err(CompilingError::CyclicSend { bus: names[bus].clone() }),
+        VisitState::Visited => return Ok(()),
+        VisitState::Unvisited => { },
+    };
+
+    state[bus] = VisitState::Visiting;
+    for (target, _) in sends[bus].iter() {
+        let target = names.iter().position(|name| name == target).unwrap();
+
+        if target != 0 {
+            order_buses(target, names, sends, state, order)?;
         };
+    };
+    state[bus] = VisitState::Visited;
 
-        write!(f, "{}", self.0.last().unwrap())
-    }
+    order.push(bus);
+
+    Ok(())
 }
 
+fn parse_buses(global_properties: &HashMap<String, Value>, instruments: &[Instrument]) -> Result<Vec<Bus>, CompilingError> {
+    let properties_of = |name: &str| {
+        let prefix = format!("{name}.");
 
-struct Scope {
-    pub name: Option<String>,
-    pub range: (usize, usize),
-    pub properties: HashMap<String, Value>,
+        global_properties.iter()
+            .filter_map(|(property, value)| Some((String::from(property.strip_prefix(&prefix)?), value.clone())))
+            .collect::<HashMap<_, _>>()
+    };
+
+    let mut names = vec![String::from(Bus::MASTER)];
+    let mut sends = vec![Vec::new()];
+    let mut queue = instruments.iter().filter_map(|instrument| instrument.bus.clone()).collect::<VecDeque<_>>();
+    while let Some(name) = queue.pop_front() {
+        if names.contains(&name) {
+            continue;
+        };
+
+        let bus_sends = parse_sends(properties_of(&name).get("sends"))?;
+        queue.extend(bus_sends.iter().map(|(target, _)| target.clone()));
+
+        names.push(name);
+        sends.push(bus_sends);
+    };
+
+    // every bus has to be processed before the buses it sends into, with master being the very last
+    let mut order = Vec::new();
+    let mut state = vec![VisitState::Unvisited; names.len()];
+    for bus in 1..names.len() {
+        order_buses(bus, &names, &sends, &mut state, &mut order)?;
+    };
+    order.reverse();
+    order.push(0);
+
+    let mut buses = Vec::new();
+    for bus in order.iter().copied() {
+        let properties = properties_of(&names[bus]);
+
+        buses.push(Bus {
+            name: names[bus].clone(),
+            gain: properties.get("gain").map(parse_number).unwrap_or(Ok(1.0))?,
+            pan: parse_pan(properties.get("pan"))?,
+            settings: parse_instrument(&properties, &Instrument::default())?,
+            sends: sends[bus].iter()
+                .map(|(target, amount)| BusSend {
+                    bus: order.iter().position(|bus| names[*bus] == *target).unwrap(),
+                    amount: *amount,
+                })
+                .collect(),
+        });
+    };
+
+    Ok(buses)
 }
 
+fn parse_metadata(properties: &HashMap<String, Value>) -> Metadata {
+    let text = |property| properties.get(property).map(Value::to_text);
 
-fn parse_octave(v: Option<&Value>) -> Result<u32, CompilingError> {
-    match v {
-        None => Ok(4),
-        Some(Value::Whole(n)) => Ok(*n),
-        Some(v) => Err(CompilingError::ValueTypeError {
-            pos: None,
-            expected: "whole",
-            got: helper::value_name(v)
-        }),
+    Metadata {
+        title: text("title"),
+        artist: text("artist"),
+        composer: text("composer"),
+        comment: text("comment"),
     }
 }
 
-fn parse_bpm(v: Option<&Value>) -> Result<f64, CompilingError> {
-    match v {
-        None => Err(CompilingError::MissingGlobalProperty { missing: "bpm" }),
-        Some(Value::Whole(n)) => {
-            if *n < 1 {
-                Err(CompilingError::ValueOutOfRange { allowed: (Some(1), None), got: *n, pos: None })
-            } else {
-                Ok(*n as f64)
-            }
-        },
-        Some(Value::Fraction { numerator, denominator }) => {
-            if *numerator == 0 {
-                Err(CompilingError::ValueOutOfRange { allowed: (Some(1), None), got: *numerator, pos: None })
-            } else {
-                Ok(*numerator as f64 / *denominator as f64)
-            }
-        },
-        Some(Value::String(..)) => {
-            Err(CompilingError::ValueTypeError { pos: None, expected: "number-like", got: "string" })
-        }
-    }
+/// The name of every `mark`, its arguments read as one text so it needn't be quoted
+fn parse_marks(tokens: &[Token]) -> HashMap<usize, String> {
+    tokens.iter().enumerate()
+        .filter_map(|(pos, token)| match token {
+            Token::Command { name, arguments, .. } if name == "mark" => {
+                Some((pos, arguments.iter().map(Value::to_text).collect::<Vec<_>>().join(" ")))
+            },
+            _ => None,
+        })
+        .collect()
+}
+
+/// The syllables of every note, from its `lyric=` or else the next one of its label's `lyrics:`
+fn parse_lyrics(tokens: &[Token], scopes: &[Scope], compiler: &Compiler) -> HashMap<usize, String> {
+    let mut lyrics = HashMap::new();
+
+    for scope in scopes.iter().skip(1) {
+        let text = scope.properties.get("lyrics").map(Value::to_text).unwrap_or_default();
+        let mut syllables = text.split_whitespace();
+
+        for (pos, token) in tokens.iter().enumerate().take(scope.range.1).skip(scope.range.0) {
+            let Token::Command { name, overrides, .. } = token else {
+                continue;
+            };
+            if !compiler.plays_notes(name) {
+                continue;
+            };
+
+            let lyric = match overrides.iter().find(|(property, _)| property == LYRIC) {
+                Some((_, value)) => Some(value.to_text()),
+                None => syllables.next().map(String::from),
+            };
+            if let Some(lyric) = lyric {
+                lyrics.insert(pos, lyric);
+            };
+        };
+    };
+
+    lyrics
 }
 
 fn parse_duration(v: &Value) -> Result<f64, CompilingError> {
@@ -87,8 +1222,8 @@ fn parse_duration(v: &Value) -> Result<f64, CompilingError> {
         Value::Fraction { numerator, denominator } => {
             Ok(*numerator as f64 / *denominator as f64)
         },
-        Value::String(..) => {
-            Err(CompilingError::ValueTypeError { pos: None, expected: "number-like", got: "string" })
+        v => {
+            Err(CompilingError::ValueTypeError { pos: None, expected: "number-like", got: helper::value_name(v) })
         }
     }
 }
@@ -108,42 +1243,60 @@ fn calculate_frequency(note: i8, octave: u32) -> f64 {
 }
 
 
-fn parse_frequency(note: &str, octave: u32, pos: usize) -> Result<f64, CompilingError> {
-    match note {
-        "Ces"         => Ok(calculate_frequency(-1, octave)),
-        "C"           => Ok(calculate_frequency(0, octave)),
-        "Cas" | "Des" => Ok(calculate_frequency(1, octave)),
-        "D"           => Ok(calculate_frequency(2, octave)),
-        "Das" | "Ees" => Ok(calculate_frequency(3, octave)),
-        "E"   | "Fes" => Ok(calculate_frequency(4, octave)),
-        "F"   | "Eas" => Ok(calculate_frequency(5, octave)),
-        "Fas" | "Ges" => Ok(calculate_frequency(6, octave)),
-        "G"           => Ok(calculate_frequency(7, octave)),
-        "Gas" | "Aes" => Ok(calculate_frequency(8, octave)),
-        "A"           => Ok(calculate_frequency(9, octave)),
-        "As"  | "Bes" => Ok(calculate_frequency(10, octave)),
-        "B"           => Ok(calculate_frequency(11, octave)),
-        "Bas"         => Ok(calculate_frequency(12, octave)),
+/// Semitones from the C of the octave by the default spellings, `Ces` and `Bas` reach just outside of it
+pub fn note_key(note: &str) -> Option<i8> {
+    notes::DEFAULT_NOTES.iter().find(|(name, _)| *name == note).map(|(_, key)| *key)
+}
+
 
-        unknown_note => Err(CompilingError::UnknownNote { pos, got: unknown_note.into() }),
+fn parse_frequency(note: &str, octave: u32, pos: usize, notes: &NoteTable) -> Result<f64, CompilingError> {
+    match notes.get(note) {
+        Some(key) => Ok(calculate_frequency(key, octave)),
+        None => Err(CompilingError::UnknownNote { pos, got: note.into() }),
     }
 }
 
 
-fn compile_note(note: &str, octave: u32, bpm: f64, arguments: &[Value], pos: usize) -> Result<Vec<Instruction>, CompilingError> {
+pub fn note_frequency(note: &str, octave: u32) -> Option<f64> {
+    note_key(note).map(|key| calculate_frequency(key, octave))
+}
+
+
+/// Seconds a `fraction` of a whole note lasts at `bpm` quarter notes a minute
+pub(crate) fn note_duration(bpm: f64, fraction: f64) -> f64 {
+    240.0 / bpm * fraction
+}
+
+
+/// The fraction of a whole note lasting `duration` seconds at `bpm`, what [`note_duration`] takes
+pub fn note_fraction(bpm: f64, duration: f64) -> f64 {
+    duration * bpm / 240.0
+}
+
+
+/// Plays the note and the ones in `arguments` before the duration. Without one it lasts `length`, the
+/// duration of the note or rest before it in the label (or the label's `length:` for the first), which
+/// a duration replaces
+#[allow(clippy::too_many_arguments)]
+fn compile_note(note: &str, octave: u32, bpm: f64, length: &mut Option<f64>, instrument: usize, arguments: &[Value], pos: usize, notes: &NoteTable) -> Result<Vec<Instruction>, CompilingError> {
+    let additional_notes = match (arguments.split_last(), *length) {
+        (None, Some(_)) | (Some((Value::String(_), _)), Some(_)) => arguments,
+        (Some((duration, additional_notes)), _) => {
+            *length = Some(parse_duration(duration)?);
+
+            additional_notes
+        },
+        (None, None) => return Err(CompilingError::WrongAmountArguments { pos, expected: 1, got: 0 }),
+    };
+
     let frequencies = {
         let mut frequencies = Vec::new();
 
-        frequencies.push(parse_frequency(note, octave, pos)?);
-
-        let got_arguments = arguments.len();
-        if got_arguments < 1 {
-            return Err(CompilingError::WrongAmountArguments { pos, expected: 1, got: got_arguments })
-        }
+        frequencies.push(parse_frequency(note, octave, pos, notes)?);
 
-        for arg in arguments[..arguments.len() - 1].iter() {
+        for arg in additional_notes.iter() {
             match arg {
-                Value::String(additional_note) => frequencies.push(parse_frequency(additional_note, octave, pos)?),
+                Value::String(additional_note) => frequencies.push(parse_frequency(additional_note, octave, pos, notes)?),
                 v => return Err(CompilingError::ValueTypeError { pos: Some(pos), got: helper::value_name(v), expected: "string" })
             };
         };
@@ -151,200 +1304,448 @@ fn compile_note(note: &str, octave: u32, bpm: f64, arguments: &[Value], pos: usi
         frequencies
     };
 
+    let duration = note_duration(bpm, length.unwrap());
 
-    let expected_arguments_count = frequencies.len();
-    let arguments_count = arguments.len();
-    if arguments_count != expected_arguments_count {
-        Err(CompilingError::WrongAmountArguments { pos, expected: expected_arguments_count, got: arguments_count })
-    } else {
-        let duration = bpm / 60.0 * parse_duration(arguments.last().unwrap())?;
+    let mut instructions = Vec::new();
 
-        Ok({
-            let mut instructions = Vec::new();
+    for frequency in frequencies.iter().cloned() {
+        instructions.push(Instruction { pos, span: Span::default(), data: InstructionData::Play { frequency, duration, instrument } })
+    };
 
-            for frequency in frequencies.iter().cloned() {
-                instructions.push(Instruction { pos, data: InstructionData::Play { frequency, duration } })
-            };
+    instructions.push(Instruction { pos, span: Span::default(), data: InstructionData::Advance { duration } });
 
-            instructions.push(Instruction { pos, data: InstructionData::Advance { duration } });
+    Ok(instructions)
+}
 
-            instructions
-        })
-    }
+
+/// Waits for the duration without playing anything, `length` being used and replaced like a note's
+fn compile_rest(bpm: f64, length: &mut Option<f64>, arguments: &[Value], pos: usize) -> Result<Vec<Instruction>, CompilingError> {
+    let length = match (arguments, *length) {
+        ([duration], _) => *length.insert(parse_duration(duration)?),
+        ([], Some(length)) => length,
+        _ => return Err(CompilingError::WrongAmountArguments { pos, expected: 1, got: arguments.len() }),
+    };
+
+    let duration = note_duration(bpm, length);
+
+    Ok(vec![Instruction { pos, span: Span::default(), data: InstructionData::Advance { duration } }])
 }
 
 
-fn compile_goto(name: Option<&str>, pos: Option<usize>, scopes: &[Scope], global_octave: u32, global_bpm: f64, tokens: &[Token], stack: &[&str]) -> Result<Vec<Instruction>, CompilingError> {
+type LabelCache = HashMap<(String, Vec<String>), Vec<Instruction>>;
+
+#[allow(clippy::too_many_arguments)]
+fn compile_goto(name: Option<&str>, pos: Option<usize>, scopes: &[Scope], global_octave: u32, global_bpm: f64, tokens: &[Token], stack: &[&str], cache: &mut LabelCache, compiler: &Compiler) -> Result<Vec<Instruction>, CompilingError> {
+    let key = {
+        let mut stack = stack.iter().map(|name| String::from(*name)).collect::<Vec<_>>();
+        stack.sort();
+
+        (String::from(name.unwrap_or("main")), stack)
+    };
+
+    if let Some(instructions) = cache.get(&key) {
+        return Ok(instructions.clone());
+    };
+
+    let instructions = compile_label(name, pos, scopes, global_octave, global_bpm, tokens, stack, cache, compiler)?;
+    cache.insert(key, instructions.clone());
+
+    Ok(instructions)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn compile_label(name: Option<&str>, pos: Option<usize>, scopes: &[Scope], global_octave: u32, global_bpm: f64, tokens: &[Token], stack: &[&str], cache: &mut LabelCache, compiler: &Compiler) -> Result<Vec<Instruction>, CompilingError> {
     macro_rules! get_from_scope {
         ($scope:ident, $name:literal, $parser:ident, $global:ident) => { $scope.properties.get($name).map(|local| $parser(Some(local))).unwrap_or(Ok($global))? };
     }
 
-    match scopes.iter().find(|s| s.name.as_ref().is_some_and(|s| s == name.unwrap_or("main"))) {
+    match scopes.iter().position(|s| s.name.as_ref().is_some_and(|s| s == name.unwrap_or("main"))) {
         None => Err(if let Some(name) = name { CompilingError::LabelNotFound { pos: pos.unwrap(), name: String::from(name) } } else { CompilingError::NoMain }),
-        Some(scope) => {
+        Some(instrument) => {
+            let scope = &scopes[instrument];
+
             let bpm = get_from_scope!(scope, "bpm", parse_bpm, global_bpm);
             let octave = get_from_scope!(scope, "octave", parse_octave, global_octave);
+            // the duration of the last note or rest, which the next ones without one reuse
+            let mut length = scope.properties.get("length").or_else(|| scopes[0].properties.get("length")).map(parse_duration).transpose()?;
 
-            let mut instructions = Vec::new();
+            let mut instructions = vec![Instruction { pos: scope.range.0, span: Span::default(), data: InstructionData::EnterLabel { instrument } }];
             for (pos, token) in tokens[scope.range.0..scope.range.1].iter().enumerate() {
                 let adapted_pos = pos + scope.range.0;
 
-                if let Token::Command { name, arguments } = token {
+                if let Token::Command { name, arguments, overrides } = token {
                     let name = name.as_str();
 
-                    let mut exiting = false;
-                    instructions.append(&mut match name {
-                        note @
-                        ("Ces" | "C" | "Cas" |
-                        "Des" | "D" | "Das" |
-                        "Es" | "E" | "Eas" |
-                        "Fes" | "F" | "Fas" |
-                        "Ges" | "G" | "Gas" |
-                        "Aes" | "A" | "As" |
-                        "Bes" | "B" | "Bas") => compile_note(note, octave, bpm, arguments, adapted_pos)?,
-
-                        "goto" => {
-                            let arguments_len = arguments.len();
-                            if arguments_len != 1 {
-                                return Err(CompilingError::WrongAmountArguments { pos: adapted_pos, expected: 1, got: arguments_len });
-                            };
-
-                            let label = match arguments.get(0).unwrap() {
-                                Value::String(name) => name.as_str(),
-                                v => return Err(CompilingError::ValueTypeError { pos: Some(adapted_pos), expected: "string", got: helper::value_name(v) }),
-                            };
-
-                            let scope_name = scope.name.as_ref().unwrap().as_str();
-                            if stack.contains(&scope_name) {
-                                return Err(CompilingError::SelfRecursion { pos: adapted_pos })
-                            } else {
-                                exiting = true;
-
-                                let extended_stack = {
-                                    let mut new_stack = Vec::from(stack);
-                                    new_stack.push(scope_name);
-                                    new_stack
-                                };
-
-                                compile_goto(Some(label), Some(adapted_pos), scopes, global_octave, global_bpm, tokens, &extended_stack)?
-                            }
-                        },
-
-                        "repeat" => {
-                            let arguments_len = arguments.len();
-                            if arguments_len != 2 {
-                                return Err(CompilingError::WrongAmountArguments { pos: adapted_pos, expected: 2, got: arguments_len });
-                            };
-
-                            let label = match arguments.get(0).unwrap() {
-                                Value::String(name) => name.as_str(),
-                                v => return Err(CompilingError::ValueTypeError { pos: Some(adapted_pos), expected: "string", got: helper::value_name(v) }),
-                            };
-
-                            let count = match arguments.get(1).unwrap() {
-                                Value::Whole(n) => n,
-                                v => return Err(CompilingError::ValueTypeError { pos: Some(adapted_pos), expected: "string", got: helper::value_name(v) }),
-                            };
-
-                            let mut accum_instructions = Vec::new();
-                            let scope_name = scope.name.as_ref().unwrap().as_str();
-                            if stack.contains(&scope_name) {
-                                exiting = true;
-                            } else {
-                                let extended_stack = {
-                                    let mut new_stack = Vec::from(stack);
-                                    new_stack.push(scope_name);
-                                    new_stack
-                                };
-
-                                for _ in 0..*count {
-                                    accum_instructions.append(&mut compile_goto(Some(label), Some(adapted_pos), scopes, global_octave, global_bpm, tokens, &extended_stack)?);
-                                }
-                            }
-                            accum_instructions
-                        },
-
-                        _ => return Err(CompilingError::UnknownCommand { pos: adapted_pos, name: String::from(name) }),
+                    let overridden = |property: &str| overrides.iter().find(|(name, _)| name == property).map(|(_, value)| value);
+                    let octave = overridden("octave").map(|octave| parse_octave(Some(octave))).unwrap_or(Ok(octave))?;
+                    let bpm = overridden("bpm").map(|bpm| parse_bpm(Some(bpm))).unwrap_or(Ok(bpm))?;
+                    let instrument = scope.overridden.get(&adapted_pos).copied().unwrap_or(instrument);
+
+                    let mut context = CommandContext {
+                        pos: adapted_pos,
+                        octave,
+                        bpm,
+                        instrument,
+                        length: &mut length,
+                        compiled: instructions.len(),
+                        exiting: false,
+                        scope,
+                        scopes,
+                        global_octave,
+                        global_bpm,
+                        tokens,
+                        stack,
+                        cache,
+                        compiler,
+                    };
+
+                    instructions.append(&mut match compiler.commands.get(name) {
+                        Some(handler) => handler.compile(&mut context, arguments)?,
+                        None if compiler.notes.contains(name) => context.note(name, arguments)?,
+                        None => return Err(CompilingError::UnknownCommand { pos: adapted_pos, name: String::from(name) }),
                     });
 
-                    if exiting {
+                    if context.exiting {
                         break;
                     };
                 };
             };
 
+            instructions.push(Instruction { pos: scope.range.0, span: Span::default(), data: InstructionData::ExitLabel { instrument } });
+
             Ok(instructions)
         }
     }
 }
 
 
-impl TryFrom<&Script> for Program {
-    type Error = CompilingError;
+/// Compiles `tokens`, reusing and filling `cache` with the instructions of the labels it compiles
+fn compile_tokens(tokens: &[Token], spans: &[Span], cache: &mut LabelCache, compiler: &Compiler) -> Result<Program, CompilingError> {
+    let mut scopes = {
+        let mut scopes = Vec::new();
 
-    fn try_from(script: &Script) -> Result<Self, Self::Error> {
-        let scopes = {
-            let mut scopes = Vec::new();
-
-            let mut scope_name = None;
-            let mut scope_properties = HashMap::new();
-            let mut last_ends = 0;
-
-            for (pos, token) in script.get_tokens().iter().enumerate() {
-                match token {
-                    Token::Label { name } => {
-                        scopes.push(Scope {
-                            range: (last_ends, pos),
-                            name: scope_name,
-                            properties: scope_properties,
-                        });
-
-                        last_ends = pos;
-
-                        scope_name = Some(name.clone());
-                        scope_properties = HashMap::new();
-                    },
-                    Token::Property { name, value } => {
-                        scope_properties.insert(name.clone(), value.clone());
-                    },
-                    Token::Command { name, .. } => {
-                        if scope_name.is_none() {
-                            return Err(CompilingError::CommandCalledInGlobal { pos, name: name.clone() });
-                        };
-                    },
+        let mut scope_name = None;
+        let mut scope_properties = HashMap::new();
+        let mut last_ends = 0;
+
+        for (pos, token) in tokens.iter().enumerate() {
+            match token {
+                Token::Label { name } => {
+                    scopes.push(Scope {
+                        range: (last_ends, pos),
+                        name: scope_name,
+                        properties: scope_properties,
+                        overridden: HashMap::new(),
+                    });
+
+                    last_ends = pos;
+
+                    scope_name = Some(name.clone());
+                    scope_properties = HashMap::new();
+                },
+                Token::Property { name, value } => {
+                    scope_properties.insert(name.clone(), value.clone());
+                },
+                Token::Command { name, .. } => {
+                    if scope_name.is_none() {
+                        return Err(CompilingError::CommandCalledInGlobal { pos, name: name.clone() });
+                    };
+                },
+            };
+        };
+
+        scopes.push(Scope {
+            range: (last_ends, tokens.len()),
+            name: scope_name,
+            properties: scope_properties,
+            overridden: HashMap::new(),
+        });
+
+        scopes
+    };
+
+    let strict = compiler.strict || parse_strict(scopes[0].properties.get("strict"))?;
+    for (label, scope) in scopes.iter().enumerate() {
+        for (pos, token) in tokens.iter().enumerate().take(scope.range.1).skip(scope.range.0) {
+            if let Token::Property { name, value } = token {
+                properties::validate(name, value, pos, label == 0, strict)?;
+            };
+        };
+    };
+
+    // ghost notes and commands with overrides play on a copy of their label's instrument with those set,
+    // numbered after the labels' and shared by the commands of a label setting the same ones
+    let mut overridden = Vec::new();
+    let labels = scopes.len();
+    for (label, scope) in scopes.iter_mut().enumerate().skip(1) {
+        for (pos, token) in tokens.iter().enumerate().take(scope.range.1).skip(scope.range.0) {
+            let Token::Command { name, overrides, .. } = token else {
+                continue;
+            };
+            if !compiler.plays_notes(name) && let Some((property, _)) = overrides.first() {
+                return Err(CompilingError::InvalidOverride { pos, property: property.clone() });
+            };
+
+            // a lyric is sung along with the note rather than changing how it sounds
+            let overrides = overrides.iter()
+                .filter(|(property, _)| property != LYRIC)
+                .map(|(property, value)| (String::from(properties::resolve(property)), value))
+                .collect::<Vec<_>>();
+            let ghost = name == "ghost";
+            if overrides.is_empty() && !ghost {
+                continue;
+            };
+
+            for (property, value) in overrides.iter() {
+                match properties::get(property) {
+                    Some(schema) if schema.overridable => schema.validate(value, pos)?,
+                    _ => return Err(CompilingError::InvalidOverride { pos, property: property.clone() }),
                 };
             };
 
-            scopes.push(Scope {
-                range: (last_ends, script.get_tokens().len()),
-                name: scope_name,
-                properties: scope_properties,
+            let found = overridden.iter().position(|other| *other == (label, overrides.clone(), ghost));
+            let instrument = labels + found.unwrap_or_else(|| {
+                overridden.push((label, overrides, ghost));
+                overridden.len() - 1
+            });
+
+            scope.overridden.insert(pos, instrument);
+        };
+    };
+
+    let global_properties = &scopes.get(0).unwrap().properties;
+
+    let mut instruments = {
+        let global_instrument = parse_instrument(global_properties, &Instrument::default())?;
+
+        let mut instruments = Vec::new();
+        for scope in scopes.iter() {
+            instruments.push(Instrument {
+                name: scope.name.clone(),
+                ..parse_instrument(&scope.properties, &global_instrument)?
             });
+        };
+
+        instruments
+    };
+
+    let bpm = parse_bpm(global_properties.get("bpm"))?;
+    let mut tempos = scopes.iter()
+        .map(|scope| scope.properties.get("bpm").map(|local| parse_bpm(Some(local))).unwrap_or(Ok(bpm)))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    for (label, overrides, ghost) in overridden {
+        let properties = overrides.into_iter().map(|(property, value)| (property, value.clone())).collect::<HashMap<_, _>>();
+
+        let mut instrument = Instrument {
+            name: scopes[label].name.clone(),
+            ..parse_instrument(&properties, &instruments[label])?
+        };
+        if ghost {
+            instrument.volume *= properties.get("ghost")
+                .or_else(|| scopes[label].properties.get("ghost"))
+                .or_else(|| global_properties.get("ghost"))
+                .map(parse_number)
+                .unwrap_or(Ok(GHOST_VOLUME))?;
+        };
+
+        instruments.push(instrument);
+        tempos.push(properties.get("bpm").map(|local| parse_bpm(Some(local))).unwrap_or(Ok(tempos[label]))?);
+    };
+
+    let meter = parse_meter(global_properties.get("meter"))?;
+    let meters = scopes.iter()
+        .map(|scope| scope.properties.get("meter").map(|local| parse_meter(Some(local))).unwrap_or(Ok(meter)))
+        .collect::<Result<Vec<_>, _>>()?;
 
-            scopes
+    let instructions = {
+        let global_octave = parse_octave(global_properties.get("octave"))?;
+
+        let instructions = compile_goto(None, None, &scopes, global_octave, bpm, tokens, &[], cache, compiler)?;
+        let mut instructions = with_tempo_changes(instructions, &tempos, &meters);
+
+        // cached instructions can outlive the spans they were compiled with, so they're only filled in here
+        for instruction in instructions.iter_mut() {
+            instruction.span = spans.get(instruction.pos).copied().unwrap_or_default();
+        };
+
+        instructions
+    };
+
+    let loop_label = match global_properties.get("loop") {
+        None => None,
+        Some(Value::String(name)) => {
+            if !scopes.iter().any(|scope| scope.name.as_ref() == Some(name)) {
+                return Err(CompilingError::UnknownOption { property: "loop", got: name.clone() });
+            };
+
+            Some(name.clone())
+        },
+        Some(v) => return Err(CompilingError::ValueTypeError { pos: None, expected: "string", got: helper::value_name(v) }),
+    };
+
+    let buses = parse_buses(global_properties, &instruments)?;
+
+    // voices and effects that don't exist are otherwise left to fall back to a sine or out of the chain
+    if strict {
+        for instrument in instruments.iter().chain(buses.iter().map(|bus| &bus.settings)) {
+            if let Some(name) = instrument.voice.as_ref().filter(|name| !voice::exists(name)) {
+                return Err(CompilingError::UnknownOption { property: "voice", got: name.clone() });
+            };
+            if let Some(name) = instrument.effects.iter().flatten().find(|name| !effects::exists(name)) {
+                return Err(CompilingError::UnknownOption { property: "effects", got: name.clone() });
+            };
+        };
+    };
+
+    let metadata = parse_metadata(global_properties);
+    let lyrics = parse_lyrics(tokens, &scopes, compiler);
+    let marks = parse_marks(tokens);
+
+    Ok(Program { instructions, instruments, buses, bpm, tempos, loop_label, metadata, lyrics, marks })
+}
+
+
+/// Puts a `Tempo` and a `Meter` after every label entered or left where the one that goes on playing
+/// has a different tempo or time signature than what was playing before
+fn with_tempo_changes(instructions: Vec<Instruction>, tempos: &[f64], meters: &[(u32, u32)]) -> Vec<Instruction> {
+    let mut changed = Vec::with_capacity(instructions.len());
+
+    let mut open = Vec::new();
+    let (mut bpm, mut meter) = (None, None);
+    for instruction in instructions {
+        match instruction.data {
+            InstructionData::EnterLabel { instrument } => open.push(instrument),
+            InstructionData::ExitLabel { .. } => { open.pop(); },
+            _ => {
+                changed.push(instruction);
+                continue;
+            },
         };
 
-        let instructions = {
-            let global_properties = &scopes.get(0).unwrap().properties;
+        let pos = instruction.pos;
+        changed.push(instruction);
 
-            let global_octave = parse_octave(global_properties.get("octave"))?;
-            let global_bpm = parse_bpm(global_properties.get("bpm"))?;
+        let Some(label) = open.last().copied() else {
+            continue;
+        };
+        if bpm != Some(tempos[label]) {
+            bpm = Some(tempos[label]);
+            changed.push(Instruction { pos, span: Span::default(), data: InstructionData::Tempo { bpm: tempos[label] } });
+        };
+        if meter != Some(meters[label]) {
+            meter = Some(meters[label]);
 
-            compile_goto(None, None, &scopes, global_octave, global_bpm, script.get_tokens(), &[])?
+            let (numerator, denominator) = meters[label];
+            changed.push(Instruction { pos, span: Span::default(), data: InstructionData::Meter { numerator, denominator } });
         };
+    };
 
-        Ok(Self(instructions))
+    changed
+}
+
+
+/// Compiles scripts the way [`Program::try_from`] does, with what it can tell apart set differently
+pub struct Compiler {
+    notes: NoteTable,
+    commands: HashMap<String, Box<dyn CommandHandler>>,
+    strict: bool,
+}
+
+
+impl Default for Compiler {
+    fn default() -> Self {
+        Self {
+            notes: NoteTable::default(),
+            commands: commands::builtin().into_iter().map(|(name, handler)| (String::from(name), handler)).collect(),
+            strict: false,
+        }
     }
 }
 
 
-#[derive(Debug)]
+impl fmt::Debug for Compiler {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Compiler")
+            .field("notes", &self.notes)
+            .field("commands", &self.commands.keys().collect::<Vec<_>>())
+            .field("strict", &self.strict)
+            .finish()
+    }
+}
+
+
+impl Compiler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Compiles the commands called `name` with `handler`, instead of the one they had if there was one.
+    /// Commands come before notes, so one can take a note's name, see [`commands`]
+    pub fn command(mut self, name: impl Into<String>, handler: impl CommandHandler + 'static) -> Self {
+        self.commands.insert(name.into(), Box::new(handler));
+        self
+    }
+
+    /// Whether the command `name` plays notes, as notes and commands that aren't registered do
+    fn plays_notes(&self, name: &str) -> bool {
+        self.commands.get(name).is_none_or(|handler| handler.plays_notes())
+    }
+
+    /// Names of the commands scripts can use, in no particular order
+    pub fn get_commands(&self) -> impl Iterator<Item = &str> {
+        self.commands.keys().map(String::as_str)
+    }
+
+    /// Names notes are played by, see [`notes`]
+    pub fn notes(mut self, notes: NoteTable) -> Self {
+        self.notes = notes;
+        self
+    }
+
+    pub fn get_notes(&self) -> &NoteTable {
+        &self.notes
+    }
+
+    /// Makes unknown properties, voices and effects errors, as every script setting `strict: on` does,
+    /// rather than leaving them out
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    pub fn is_strict(&self) -> bool {
+        self.strict
+    }
+
+    pub fn compile(&self, script: &Script) -> Result<Program, CompilingError> {
+        compile_tokens(script.get_tokens(), script.get_spans(), &mut LabelCache::new(), self)
+    }
+}
+
+
+impl TryFrom<&Script> for Program {
+    type Error = CompilingError;
+
+    fn try_from(script: &Script) -> Result<Self, Self::Error> {
+        Compiler::default().compile(script)
+    }
+}
+
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Instruction {
     pub pos: usize,
+    /// Where the token the instruction was compiled from was written
+    pub span: Span,
+    #[cfg_attr(feature = "serde", serde(flatten))]
     pub data: InstructionData,
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(tag = "kind", rename_all = "snake_case"))]
 pub enum InstructionData {
     Advance {
         duration: f64,
@@ -352,7 +1753,60 @@ pub enum InstructionData {
     Play {
         frequency: f64,
         duration: f64,
+        instrument: usize,
+    },
+    EnterLabel {
+        instrument: usize,
+    },
+    ExitLabel {
+        instrument: usize,
     },
+    /// Where a `mark` was written, named by [`Program::get_mark`]
+    Mark,
+    /// The tempo from here on, until the next one
+    Tempo {
+        bpm: f64,
+    },
+    /// The time signature from here on, until the next one
+    Meter {
+        numerator: u32,
+        denominator: u32,
+    },
+    /// A point of the curve `automate` moves a parameter of the instrument along, reached `delay`
+    /// seconds after the instruction. Ramped to from the point before it, or jumped to when not `ramp`
+    Automate {
+        instrument: usize,
+        parameter: Parameter,
+        value: f64,
+        delay: f64,
+        ramp: bool,
+    },
+}
+
+
+impl Instruction {
+    pub fn to_json(&self) -> String {
+        let data = match self.data {
+            InstructionData::Play { frequency, duration, instrument } => format!("\"kind\":\"play\",\"frequency\":{frequency},\"duration\":{duration},\"instrument\":{instrument}"),
+            InstructionData::Advance { duration } => format!("\"kind\":\"advance\",\"duration\":{duration}"),
+            InstructionData::EnterLabel { instrument } => format!("\"kind\":\"enter_label\",\"instrument\":{instrument}"),
+            InstructionData::ExitLabel { instrument } => format!("\"kind\":\"exit_label\",\"instrument\":{instrument}"),
+            InstructionData::Mark => String::from("\"kind\":\"mark\""),
+            InstructionData::Tempo { bpm } => format!("\"kind\":\"tempo\",\"bpm\":{bpm}"),
+            InstructionData::Meter { numerator, denominator } => format!("\"kind\":\"meter\",\"numerator\":{numerator},\"denominator\":{denominator}"),
+            InstructionData::Automate { instrument, parameter, value, delay, ramp } => format!(
+                "\"kind\":\"automate\",\"instrument\":{instrument},\"parameter\":\"{}\",\"value\":{value},\"delay\":{delay},\"ramp\":{ramp}",
+                parameter.name(),
+            ),
+        };
+
+        let span = &self.span;
+
+        format!(
+            "{{\"pos\":{},\"start\":{},\"end\":{},\"byte_start\":{},\"byte_end\":{},\"line\":{},\"column\":{},{data}}}",
+            self.pos, span.start, span.end, span.byte_start, span.byte_end, span.line, span.column,
+        )
+    }
 }
 
 
@@ -361,8 +1815,16 @@ impl fmt::Display for Instruction {
         write!(f, "{}: ", self.pos + 1)?;
 
         match self.data {
-            InstructionData::Play { frequency, duration } => write!(f, "play {frequency:.2}Hz {duration:.5}s"),
+            InstructionData::Play { frequency, duration, instrument } => write!(f, "play {frequency:.2}Hz {duration:.5}s (instrument {instrument})"),
             InstructionData::Advance { duration } => write!(f, "advance {duration:.5}s"),
+            InstructionData::EnterLabel { instrument } => write!(f, "enter label (instrument {instrument})"),
+            InstructionData::ExitLabel { instrument } => write!(f, "exit label (instrument {instrument})"),
+            InstructionData::Mark => write!(f, "mark"),
+            InstructionData::Tempo { bpm } => write!(f, "tempo {bpm}"),
+            InstructionData::Meter { numerator, denominator } => write!(f, "meter {numerator}/{denominator}"),
+            InstructionData::Automate { instrument, parameter, value, delay, ramp } => {
+                write!(f, "{} {} to {value} after {delay:.5}s (instrument {instrument})", if ramp { "ramp" } else { "set" }, parameter.name())
+            },
         }
     }
 }
@@ -380,7 +1842,7 @@ pub enum CompilingError {
     },
     ValueOutOfRange {
         allowed: (Option<u32>, Option<u32>),
-        got: u32,
+        got: f64,
         pos: Option<usize>,
     },
     UnknownCommand {
@@ -408,4 +1870,53 @@ pub enum CompilingError {
         pos: usize,
         got: String,
     },
+    UnknownOption {
+        property: &'static str,
+        got: String,
+    },
+    /// A `property=value` on a command that can't be set per command, or on one that can't have any
+    InvalidOverride {
+        pos: usize,
+        property: String,
+    },
+    CyclicSend {
+        bus: String,
+    },
+    /// A property nothing reads, close enough to one that's read to be a misspelling of it
+    UnknownProperty {
+        pos: usize,
+        name: String,
+        suggestion: Option<String>,
+    },
+    /// A property written where it's not read, like `loop:` in a label or `gain:` outside of a bus
+    MisplacedProperty {
+        pos: usize,
+        name: String,
+    },
+    /// A `goto` or `repeat` nesting deeper than `limit` labels, or unrolling the script past `limit` instructions
+    ExpansionLimitExceeded {
+        pos: usize,
+        limit: usize,
+    },
+}
+
+
+impl CompilingError {
+    /// Index of the script token the error happened at, if it's known
+    pub fn pos(&self) -> Option<usize> {
+        match self {
+            Self::ValueTypeError { pos, .. } | Self::ValueOutOfRange { pos, .. } => *pos,
+            Self::UnknownCommand { pos, .. }
+            | Self::WrongAmountArguments { pos, .. }
+            | Self::CommandCalledInGlobal { pos, .. }
+            | Self::LabelNotFound { pos, .. }
+            | Self::SelfRecursion { pos }
+            | Self::UnknownNote { pos, .. }
+            | Self::InvalidOverride { pos, .. }
+            | Self::ExpansionLimitExceeded { pos, .. }
+            | Self::UnknownProperty { pos, .. }
+            | Self::MisplacedProperty { pos, .. } => Some(*pos),
+            Self::MissingGlobalProperty { .. } | Self::NoMain | Self::UnknownOption { .. } | Self::CyclicSend { .. } => None,
+        }
+    }
 }
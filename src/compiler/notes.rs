@@ -0,0 +1,106 @@
+//! Which names scripts can play notes by, and how many semitones above the `C` of the octave each is.
+//!
+//! The default table spells sharps with `as` and flats with `es`, the way the notes are named in German,
+//! but names can be added, taken out or replaced altogether, for English spellings or for the syllables
+//! of solfège:
+//!
+//! ```
+//! use roorle::compiler::Compiler;
+//! use roorle::compiler::notes::NoteTable;
+//! use roorle::syntax::parser::Script;
+//!
+//! let solfege = NoteTable::empty()
+//!     .with("Do", 0).with("Re", 2).with("Mi", 4).with("Fa", 5).with("Sol", 7).with("La", 9).with("Si", 11);
+//!
+//! let script = Script::try_from("bpm: 120\n@main\nDo 1/4\nMi 1/4\nSol 1/2")?;
+//! let program = Compiler::new().notes(solfege).compile(&script)?;
+//! # Ok::<(), roorle::Error>(())
+//! ```
+
+use std::collections::HashMap;
+
+
+/// The names scripts play notes by unless told otherwise, `Ces` and `Bas` reaching just outside of the
+/// octave
+pub const DEFAULT_NOTES: &[(&str, i8)] = &[
+    ("Ces", -1),
+    ("C", 0),
+    ("Cas", 1), ("Des", 1),
+    ("D", 2),
+    ("Das", 3), ("Ees", 3),
+    ("E", 4), ("Fes", 4),
+    ("F", 5), ("Eas", 5),
+    ("Fas", 6), ("Ges", 6),
+    ("G", 7),
+    ("Gas", 8), ("Aes", 8),
+    ("A", 9),
+    ("As", 10), ("Bes", 10),
+    ("B", 11),
+    ("Bas", 12),
+];
+
+
+/// Names of notes and their semitones above the `C` of the octave they're played in
+#[derive(Clone, Debug, PartialEq)]
+pub struct NoteTable {
+    keys: HashMap<String, i8>,
+}
+
+
+impl Default for NoteTable {
+    fn default() -> Self {
+        Self {
+            keys: DEFAULT_NOTES.iter().map(|(name, key)| (String::from(*name), *key)).collect(),
+        }
+    }
+}
+
+
+impl NoteTable {
+    /// The default spellings
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A table without any names, for naming systems that replace the default one
+    pub fn empty() -> Self {
+        Self { keys: HashMap::new() }
+    }
+
+    /// Lets `name` be played `key` semitones above the octave's `C`, returning what it was before if it
+    /// was already a note
+    pub fn insert(&mut self, name: impl Into<String>, key: i8) -> Option<i8> {
+        self.keys.insert(name.into(), key)
+    }
+
+    pub fn with(mut self, name: impl Into<String>, key: i8) -> Self {
+        self.insert(name, key);
+        self
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<i8> {
+        self.keys.remove(name)
+    }
+
+    pub fn get(&self, name: &str) -> Option<i8> {
+        self.keys.get(name).copied()
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.keys.contains_key(name)
+    }
+
+    /// Every name and its semitones, in no particular order
+    pub fn iter(&self) -> impl Iterator<Item = (&str, i8)> {
+        self.keys.iter().map(|(name, key)| (name.as_str(), *key))
+    }
+}
+
+
+impl<S: Into<String>> Extend<(S, i8)> for NoteTable {
+    fn extend<I: IntoIterator<Item = (S, i8)>>(&mut self, names: I) {
+        for (name, key) in names {
+            self.insert(name, key);
+        };
+    }
+}
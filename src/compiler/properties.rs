@@ -0,0 +1,242 @@
+//! Every property scripts can set, the values it takes and where it can be written. The compiler checks
+//! properties against it before reading them, so a `bmp: 120` is caught as a misspelling of `bpm` rather
+//! than leaving the piece at a tempo nobody asked for
+
+use crate::syntax::parser::Value;
+use super::{helper, Bitcrusher, CompilingError, Instrument, Unison};
+
+
+/// The kinds of [`Value`] there are
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ValueKind {
+    Whole,
+    Fraction,
+    String,
+    List,
+}
+
+
+impl ValueKind {
+    pub fn of(value: &Value) -> Self {
+        match value {
+            Value::Whole(..) => Self::Whole,
+            Value::Fraction { .. } => Self::Fraction,
+            Value::String(..) => Self::String,
+            Value::List(..) => Self::List,
+        }
+    }
+}
+
+
+/// Where a property can be written
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum PropertyScope {
+    /// Before the first label, for the whole piece
+    Global,
+    /// In a label, or before the first one for every label that doesn't set it
+    Label,
+    /// Before the first label as `<bus>.<property>`, for a bus
+    Bus,
+}
+
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct PropertySchema {
+    pub name: &'static str,
+    pub kinds: &'static [ValueKind],
+    /// Smallest and largest whole and fraction values it takes, where it has either
+    pub range: (Option<u32>, Option<u32>),
+    pub scopes: &'static [PropertyScope],
+    /// Whether commands can set it for themselves with `name=value`
+    pub overridable: bool,
+}
+
+
+impl PropertySchema {
+    const fn new(name: &'static str, kinds: &'static [ValueKind], scopes: &'static [PropertyScope]) -> Self {
+        Self { name, kinds, range: (None, None), scopes, overridable: false }
+    }
+
+    const fn range(self, min: Option<u32>, max: Option<u32>) -> Self {
+        Self { range: (min, max), ..self }
+    }
+
+    const fn overridable(self) -> Self {
+        Self { overridable: true, ..self }
+    }
+
+    /// What its values are called in errors
+    pub fn expected(&self) -> &'static str {
+        match self.kinds {
+            [ValueKind::Whole] => "whole",
+            [ValueKind::Fraction] => "fraction",
+            [ValueKind::String] => "string",
+            [ValueKind::Whole, ValueKind::Fraction] => "number-like",
+            [ValueKind::String, ValueKind::List] => "string or list",
+            [ValueKind::Whole, ValueKind::Fraction, ValueKind::List] => "number-like or beat",
+            _ => "number-like or string",
+        }
+    }
+
+    /// Checks `value` is of a kind the property takes and within its range, `pos` being where it was written
+    pub fn validate(&self, value: &Value, pos: usize) -> Result<(), CompilingError> {
+        if !self.kinds.contains(&ValueKind::of(value)) {
+            return Err(CompilingError::ValueTypeError { pos: Some(pos), expected: self.expected(), got: helper::value_name(value) });
+        };
+
+        let number = match value {
+            Value::Whole(n) => *n as f64,
+            Value::Fraction { numerator, denominator } => *numerator as f64 / *denominator as f64,
+            _ => return Ok(()),
+        };
+
+        let (min, max) = self.range;
+        if min.is_some_and(|min| number < min as f64) || max.is_some_and(|max| number > max as f64) {
+            return Err(CompilingError::ValueOutOfRange { allowed: self.range, got: number, pos: Some(pos) });
+        };
+
+        Ok(())
+    }
+}
+
+
+const NUMBER: &[ValueKind] = &[ValueKind::Whole, ValueKind::Fraction];
+/// Numbers, and lists for a beat and its tempo
+const TEMPO: &[ValueKind] = &[ValueKind::Whole, ValueKind::Fraction, ValueKind::List];
+const WHOLE: &[ValueKind] = &[ValueKind::Whole];
+const FRACTION: &[ValueKind] = &[ValueKind::Fraction];
+const STRING: &[ValueKind] = &[ValueKind::String];
+/// Numbers, and strings for `none` or for numbers with their unit
+const NUMBER_OR_STRING: &[ValueKind] = &[ValueKind::Whole, ValueKind::Fraction, ValueKind::String];
+const WHOLE_OR_STRING: &[ValueKind] = &[ValueKind::Whole, ValueKind::String];
+const LIST: &[ValueKind] = &[ValueKind::String, ValueKind::List];
+const TEXT: &[ValueKind] = &[ValueKind::Whole, ValueKind::Fraction, ValueKind::String, ValueKind::List];
+
+const GLOBAL: &[PropertyScope] = &[PropertyScope::Global];
+const LABEL: &[PropertyScope] = &[PropertyScope::Label];
+const TIMING: &[PropertyScope] = &[PropertyScope::Global, PropertyScope::Label];
+const INSTRUMENT: &[PropertyScope] = &[PropertyScope::Global, PropertyScope::Label, PropertyScope::Bus];
+const BUS: &[PropertyScope] = &[PropertyScope::Bus];
+
+
+pub const PROPERTIES: &[PropertySchema] = &[
+    PropertySchema::new("bpm", TEMPO, TIMING).overridable(),
+    PropertySchema::new("octave", WHOLE, TIMING).overridable(),
+    PropertySchema::new("length", NUMBER, TIMING),
+    PropertySchema::new("meter", FRACTION, TIMING),
+    PropertySchema::new("ghost", NUMBER, TIMING).overridable(),
+    PropertySchema::new("lyrics", TEXT, LABEL),
+    PropertySchema::new("loop", STRING, GLOBAL),
+    PropertySchema::new("title", TEXT, GLOBAL),
+    PropertySchema::new("artist", TEXT, GLOBAL),
+    PropertySchema::new("composer", TEXT, GLOBAL),
+    PropertySchema::new("comment", TEXT, GLOBAL),
+    PropertySchema::new("strict", STRING, GLOBAL),
+    PropertySchema::new("volume", NUMBER, INSTRUMENT).overridable(),
+    PropertySchema::new("voice", STRING, INSTRUMENT).overridable(),
+    PropertySchema::new("filter", STRING, INSTRUMENT).overridable(),
+    PropertySchema::new("cutoff", NUMBER, INSTRUMENT).overridable(),
+    PropertySchema::new("resonance", NUMBER, INSTRUMENT).overridable(),
+    PropertySchema::new("distortion", STRING, INSTRUMENT).overridable(),
+    PropertySchema::new("drive", NUMBER, INSTRUMENT).range(Some(1), None).overridable(),
+    PropertySchema::new("tone", NUMBER, INSTRUMENT).overridable(),
+    PropertySchema::new("ringmod", NUMBER_OR_STRING, INSTRUMENT).overridable(),
+    PropertySchema::new("ringmod_mix", NUMBER_OR_STRING, INSTRUMENT).range(Some(0), Some(1)).overridable(),
+    PropertySchema::new("bitcrusher", WHOLE_OR_STRING, INSTRUMENT).range(Some(1), Some(Bitcrusher::MAX_BITS)).overridable(),
+    PropertySchema::new("crush_rate", NUMBER, INSTRUMENT).range(Some(1), None).overridable(),
+    PropertySchema::new("lfo", LIST, INSTRUMENT).overridable(),
+    PropertySchema::new("autopan", LIST, INSTRUMENT).overridable(),
+    PropertySchema::new("effects", LIST, INSTRUMENT).overridable(),
+    PropertySchema::new("bus", STRING, INSTRUMENT).overridable(),
+    PropertySchema::new("portamento", NUMBER_OR_STRING, INSTRUMENT).overridable(),
+    PropertySchema::new("unison", WHOLE_OR_STRING, INSTRUMENT).range(Some(1), Some(Unison::MAX_VOICES)).overridable(),
+    PropertySchema::new("detune", NUMBER_OR_STRING, INSTRUMENT).overridable(),
+    PropertySchema::new("noise", STRING, INSTRUMENT).overridable(),
+    PropertySchema::new("pan", NUMBER_OR_STRING, INSTRUMENT).overridable(),
+    PropertySchema::new("velocity", WHOLE, INSTRUMENT).range(Some(1), Some(Instrument::MAX_VELOCITY)).overridable(),
+    PropertySchema::new("velocity_volume", NUMBER, INSTRUMENT).range(Some(0), Some(1)).overridable(),
+    PropertySchema::new("velocity_cutoff", NUMBER, INSTRUMENT).overridable(),
+    PropertySchema::new("gain", NUMBER, BUS),
+    PropertySchema::new("sends", LIST, BUS),
+];
+
+
+pub fn get(name: &str) -> Option<&'static PropertySchema> {
+    PROPERTIES.iter().find(|property| property.name == name)
+}
+
+
+/// Shorter names overrides can be written with, like `vol=0.6`
+const ALIASES: &[(&str, &str)] = &[("vol", "volume")];
+
+
+/// The property an override written as `name` sets
+pub fn resolve(name: &str) -> &str {
+    ALIASES.iter().find(|(alias, _)| *alias == name).map_or(name, |(_, property)| property)
+}
+
+
+/// Edits turning `a` into `b`, swapping two neighbouring letters counting as one
+fn distance(a: &str, b: &str) -> usize {
+    let a = a.chars().collect::<Vec<_>>();
+    let b = b.chars().collect::<Vec<_>>();
+
+    let mut rows = vec![(0..=b.len()).collect::<Vec<_>>()];
+    for i in 1..=a.len() {
+        let mut row = vec![i; b.len() + 1];
+        for j in 1..=b.len() {
+            let cost = (a[i - 1] != b[j - 1]) as usize;
+            row[j] = (rows[i - 1][j] + 1).min(row[j - 1] + 1).min(rows[i - 1][j - 1] + cost);
+
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                row[j] = row[j].min(rows[i - 2][j - 2] + 1);
+            };
+        };
+        rows.push(row);
+    };
+
+    rows[a.len()][b.len()]
+}
+
+
+/// The property `name` is most likely a misspelling of, if it's close enough to one to be
+pub fn suggest(name: &str) -> Option<&'static str> {
+    let allowed = (name.chars().count() / 3).max(1);
+
+    PROPERTIES.iter()
+        .map(|property| (distance(name, property.name), property.name))
+        .filter(|(distance, _)| *distance <= allowed)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, name)| name)
+}
+
+
+/// Checks the property `name` written at `pos`, before the first label if `global`. Unless `strict`, names
+/// too far from every known property to be a misspelling of one are let through
+pub fn validate(name: &str, value: &Value, pos: usize, global: bool, strict: bool) -> Result<(), CompilingError> {
+    let (bus, property) = match name.split_once('.') {
+        Some((bus, property)) => (Some(bus), property),
+        None => (None, name),
+    };
+    let scope = match (bus, global) {
+        (Some(_), _) => PropertyScope::Bus,
+        (None, true) => PropertyScope::Global,
+        (None, false) => PropertyScope::Label,
+    };
+
+    let Some(schema) = get(property) else {
+        let suggestion = suggest(property).map(|suggestion| bus.map_or_else(|| String::from(suggestion), |bus| format!("{bus}.{suggestion}")));
+
+        return match suggestion {
+            None if !strict => Ok(()),
+            suggestion => Err(CompilingError::UnknownProperty { pos, name: String::from(name), suggestion }),
+        };
+    };
+
+    // buses are only set up before the first label
+    if !schema.scopes.contains(&scope) || (scope == PropertyScope::Bus && !global) {
+        return Err(CompilingError::MisplacedProperty { pos, name: String::from(name) });
+    };
+
+    schema.validate(value, pos)
+}
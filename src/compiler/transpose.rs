@@ -0,0 +1,156 @@
+use std::fmt;
+use crate::syntax::formatter;
+use crate::syntax::lexer::{Token as LToken, TokenStream};
+use crate::syntax::parser::{ParsingError, Script};
+use super::note_key;
+
+
+const DEFAULT_OCTAVE: i32 = 4;
+
+// indexed by key + 1, E flat is spelled `Das` as `Es` is only understood as a command and `Ees` only as an argument
+const SHARPS: [&str; 14] = ["Ces", "C", "Cas", "D", "Das", "E", "F", "Fas", "G", "Gas", "A", "As", "B", "Bas"];
+const FLATS: [&str; 14] = ["Ces", "C", "Des", "D", "Das", "E", "F", "Ges", "G", "Aes", "A", "Bes", "B", "Bas"];
+
+
+#[derive(Debug)]
+pub enum TransposeError {
+    ParsingError(ParsingError),
+    OutOfOctave {
+        label: String,
+    },
+}
+
+
+impl fmt::Display for TransposeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ParsingError(err) => write!(f, "{err:?}"),
+            Self::OutOfOctave { label } => write!(f, "the notes of `@{label}` no longer fit in a single octave"),
+        }
+    }
+}
+
+
+impl From<ParsingError> for TransposeError {
+    fn from(err: ParsingError) -> Self {
+        Self::ParsingError(err)
+    }
+}
+
+
+struct Word {
+    value: String,
+    start: usize,
+}
+
+
+fn sentences(source: &str) -> Vec<Vec<Word>> {
+    let mut sentences = vec![Vec::new()];
+
+    for token in TokenStream::from(source.chars()) {
+        match token {
+            LToken::Word { value, start, .. } => sentences.last_mut().unwrap().push(Word { value, start }),
+            LToken::SentenceEnd { .. } => if !sentences.last().unwrap().is_empty() {
+                sentences.push(Vec::new());
+            },
+        };
+    };
+
+    sentences.retain(|sentence| !sentence.is_empty());
+    sentences
+}
+
+
+fn octave_of(sentence: &[Word]) -> Option<(i32, &Word)> {
+    match sentence {
+        [name, separator, value] if name.value == "octave" && separator.value == ":" => Some((value.value.parse().ok()?, value)),
+        _ => None,
+    }
+}
+
+
+fn notes_of(sentence: &[Word]) -> Vec<(i8, &Word)> {
+    match sentence {
+        [command, ..] if command.value == "goto" || command.value == "repeat" => Vec::new(),
+        [_, separator, ..] if separator.value == ":" => Vec::new(),
+        [command, notes @ ..] if command.value == "ghost" => notes.iter().filter_map(|word| Some((note_key(&word.value)?, word))).collect(),
+        [first, ..] if note_key(&first.value).is_none() => Vec::new(),
+        words => words.iter().filter_map(|word| Some((note_key(&word.value)?, word))).collect(),
+    }
+}
+
+
+/// Octave a label has to move to for all of its notes to still be spellable, the closest one using the
+/// fewest `Ces` and `Bas`
+fn fitting_octave(absolute: &[i32], preferred: i32) -> Option<i32> {
+    let lowest = *absolute.iter().min()?;
+    let highest = *absolute.iter().max()?;
+
+    ((lowest - 12).div_euclid(12)..=(highest + 1).div_euclid(12) + 1)
+        .filter(|octave| *octave >= 0 && absolute.iter().all(|key| (-1..=12).contains(&(key - octave * 12))))
+        .min_by_key(|octave| {
+            let edges = absolute.iter().filter(|key| !(0..=11).contains(&(*key - octave * 12))).count();
+
+            (edges, (octave - preferred).abs())
+        })
+}
+
+
+/// Moves every note in `source` by `semitones`, respelling them with sharps going up and flats going down.
+///
+/// Notes don't carry octaves of their own, so labels whose notes would leave their octave get a new
+/// `octave` property instead. Labels whose transposed notes don't fit between one octave's `Ces` and `Bas`
+/// can't be written down and fail with [`TransposeError::OutOfOctave`]
+pub fn transpose(source: &str, semitones: i32) -> Result<String, TransposeError> {
+    Script::try_from(source)?;
+
+    let sentences = sentences(source);
+    let spelling = if semitones < 0 { &FLATS } else { &SHARPS };
+
+    let global_octave = sentences.iter()
+        .take_while(|sentence| sentence[0].value != "@")
+        .find_map(|sentence| octave_of(sentence).map(|(octave, _)| octave))
+        .unwrap_or(DEFAULT_OCTAVE);
+
+    let mut edits: Vec<(usize, usize, String)> = Vec::new();
+
+    let labels = sentences.iter().enumerate().filter(|(_, sentence)| sentence[0].value == "@").map(|(i, _)| i).collect::<Vec<_>>();
+    for (n, label) in labels.iter().enumerate() {
+        let scope = &sentences[*label + 1..labels.get(n + 1).copied().unwrap_or(sentences.len())];
+        let name = &sentences[*label][1];
+
+        let local_octave = scope.iter().find_map(|sentence| octave_of(sentence));
+        let octave = local_octave.map(|(octave, _)| octave).unwrap_or(global_octave);
+
+        let notes = scope.iter().flat_map(|sentence| notes_of(sentence)).collect::<Vec<_>>();
+        let absolute = notes.iter().map(|(key, _)| octave * 12 + *key as i32 + semitones).collect::<Vec<_>>();
+
+        let Some(new_octave) = fitting_octave(&absolute, octave + semitones.div_euclid(12)) else {
+            if notes.is_empty() {
+                continue;
+            };
+
+            return Err(TransposeError::OutOfOctave { label: name.value.clone() });
+        };
+
+        for ((_, word), key) in notes.iter().zip(absolute.iter()) {
+            edits.push((word.start, word.value.chars().count(), String::from(spelling[(key - new_octave * 12 + 1) as usize])));
+        };
+
+        if new_octave != octave {
+            match local_octave {
+                Some((_, word)) => edits.push((word.start, word.value.chars().count(), new_octave.to_string())),
+                None => edits.push((name.start + name.value.chars().count(), 0, format!("\noctave: {new_octave}"))),
+            };
+        };
+    };
+
+    edits.sort_by_key(|(start, ..)| *start);
+
+    let mut chars = source.chars().collect::<Vec<_>>();
+    for (start, length, replacement) in edits.into_iter().rev() {
+        chars.splice(start..start + length, replacement.chars());
+    };
+
+    Ok(formatter::format(&chars.into_iter().collect::<String>())?)
+}
@@ -0,0 +1,142 @@
+use std::fmt;
+use roorle::compiler::{self, NoteEvent, Program};
+use roorle::json;
+use crate::info::{self, note_name};
+
+
+/// Notes closer together than this are played at the same time
+const TOLERANCE: f64 = 1e-6;
+
+
+/// A note as it sounds, independent of how the script was written
+#[derive(Clone, Debug)]
+pub struct Note {
+    pub name: String,
+    pub label: String,
+    pub start: f64,
+    pub duration: f64,
+    /// Length in bars the way a script would write it
+    pub length: String,
+}
+
+
+#[derive(Debug)]
+pub enum Change {
+    Added(Note),
+    Removed(Note),
+    Retimed {
+        from: Note,
+        to: Note,
+    },
+}
+
+
+impl Change {
+    fn time(&self) -> f64 {
+        match self {
+            Self::Added(note) | Self::Removed(note) | Self::Retimed { to: note, .. } => note.start,
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        let note = |note: &Note| format!(
+            "\"note\":{},\"label\":{},\"start\":{},\"duration\":{},\"length\":{}",
+            json::string(&note.name), json::string(&note.label), note.start, note.duration, json::string(&note.length),
+        );
+
+        match self {
+            Self::Added(added) => format!("{{\"change\":\"added\",{}}}", note(added)),
+            Self::Removed(removed) => format!("{{\"change\":\"removed\",{}}}", note(removed)),
+            Self::Retimed { from, to } => format!(
+                "{{\"change\":\"retimed\",{},\"from_start\":{},\"from_duration\":{},\"from_length\":{}}}",
+                note(to), from.start, from.duration, json::string(&from.length),
+            ),
+        }
+    }
+}
+
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Added(note) => write!(f, "+ {}  {} {}  @{}", info::seconds(note.start), note.name, note.length, note.label),
+            Self::Removed(note) => write!(f, "- {}  {} {}  @{}", info::seconds(note.start), note.name, note.length, note.label),
+            Self::Retimed { from, to } => write!(
+                f, "~ {} -> {}  {} {} -> {}  @{}",
+                info::seconds(from.start), info::seconds(to.start), to.name, from.length, to.length, to.label,
+            ),
+        }
+    }
+}
+
+
+fn notes(program: &Program) -> Vec<Note> {
+    let instruments = program.get_instruments();
+    let tempos = program.get_tempos();
+
+    program.get_note_events().iter()
+        .map(|event: &NoteEvent| Note {
+            name: note_name(event.frequency),
+            label: instruments[event.instrument].name.clone().unwrap_or_default(),
+            start: event.start,
+            duration: event.duration,
+            length: info::fraction(compiler::note_fraction(tempos[event.instrument], event.duration)),
+        })
+        .collect()
+}
+
+
+/// Takes the note of `candidates` that `accepts`, the one closest in time to `note` if there are several
+fn take_closest(candidates: &mut Vec<Note>, note: &Note, accepts: impl Fn(&Note) -> bool) -> Option<Note> {
+    let (i, _) = candidates.iter().enumerate()
+        .filter(|(_, candidate)| accepts(candidate))
+        .min_by(|(_, a), (_, b)| (a.start - note.start).abs().total_cmp(&(b.start - note.start).abs()))?;
+
+    Some(candidates.remove(i))
+}
+
+
+/// What changed between the notes `old` and `new` play, in the order they're heard.
+///
+/// Labels, formatting and the way notes are spelled don't matter, only the pitch, start and length do.
+/// A pitch that's still played but at another time or for another length is retimed rather than
+/// removed and added again
+pub fn changes(old: &Program, new: &Program) -> Vec<Change> {
+    let mut removed = Vec::new();
+    let mut added = notes(new);
+
+    for note in notes(old) {
+        let same = |other: &Note| other.name == note.name
+            && (other.start - note.start).abs() < TOLERANCE
+            && (other.duration - note.duration).abs() < TOLERANCE;
+
+        if take_closest(&mut added, &note, same).is_none() {
+            removed.push(note);
+        };
+    };
+
+    let mut changes = Vec::new();
+    for note in removed {
+        match take_closest(&mut added, &note, |other| other.name == note.name) {
+            Some(to) => changes.push(Change::Retimed { from: note, to }),
+            None => changes.push(Change::Removed(note)),
+        };
+    };
+    changes.extend(added.into_iter().map(Change::Added));
+
+    changes.sort_by(|a, b| a.time().total_cmp(&b.time()));
+    changes
+}
+
+
+/// One line per kind of change, e.g. `2 added, 1 removed, 0 retimed`
+pub fn summary(changes: &[Change]) -> String {
+    let count = |kind: fn(&Change) -> bool| changes.iter().filter(|change| kind(change)).count();
+
+    format!(
+        "{} added, {} removed, {} retimed",
+        count(|change| matches!(change, Change::Added(_))),
+        count(|change| matches!(change, Change::Removed(_))),
+        count(|change| matches!(change, Change::Retimed { .. })),
+    )
+}
@@ -0,0 +1,205 @@
+//! C interface for embedding roorle, built with the `ffi` feature. `include/roorle.h` declares it.
+//!
+//! Every function returning a `c_int` returns [`ROORLE_OK`] or one of the other `ROORLE_*` codes, and
+//! leaves a description of what went wrong for [`roorle_last_error`]. Panics are caught and reported as
+//! [`ROORLE_PANIC`] instead of unwinding into the caller
+
+use std::cell::RefCell;
+use std::ffi::{c_char, c_int, CStr, CString};
+use std::panic::{self, AssertUnwindSafe};
+use crate::compiler::Program;
+use crate::interpreter::{self, RenderOptions, TooLong, DEFAULT_MAX_RENDER_SECONDS};
+use crate::interpreter::render::SampleIterator;
+use crate::Error;
+
+
+pub const ROORLE_OK: c_int = 0;
+pub const ROORLE_INVALID_ARGUMENT: c_int = 1;
+pub const ROORLE_PARSING_ERROR: c_int = 2;
+pub const ROORLE_COMPILING_ERROR: c_int = 3;
+pub const ROORLE_BUFFER_TOO_SMALL: c_int = 4;
+pub const ROORLE_PANIC: c_int = 5;
+/// The render would be longer than [`DEFAULT_MAX_RENDER_SECONDS`]
+pub const ROORLE_TOO_LONG: c_int = 6;
+
+
+thread_local! {
+    static LAST_ERROR: RefCell<CString> = RefCell::new(CString::default());
+}
+
+
+/// Opaque handle to a compiled script
+pub struct RoorleProgram(Program);
+
+
+fn set_last_error(message: String) {
+    let message = CString::new(message.replace('\0', " ")).unwrap_or_default();
+
+    LAST_ERROR.with(|last| *last.borrow_mut() = message);
+}
+
+
+/// Runs `f`, turning its error or panic into a code and remembering the message
+fn guard(f: impl FnOnce() -> Result<(), (c_int, String)>) -> c_int {
+    let result = panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|payload| {
+        let message = payload.downcast_ref::<&str>().map(|s| String::from(*s))
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| String::from("unknown panic"));
+
+        Err((ROORLE_PANIC, message))
+    });
+
+    match result {
+        Ok(()) => {
+            set_last_error(String::new());
+
+            ROORLE_OK
+        },
+        Err((code, message)) => {
+            set_last_error(message);
+
+            code
+        },
+    }
+}
+
+
+fn invalid(message: &str) -> (c_int, String) {
+    (ROORLE_INVALID_ARGUMENT, String::from(message))
+}
+
+
+fn too_long(err: TooLong) -> (c_int, String) {
+    (ROORLE_TOO_LONG, err.to_string())
+}
+
+
+fn options(sample_rate: u32, channels: u16) -> Result<RenderOptions, (c_int, String)> {
+    if sample_rate == 0 || !(1..=2).contains(&channels) {
+        return Err(invalid("the sample rate has to be positive and there can only be 1 or 2 channels"));
+    };
+
+    Ok(RenderOptions { sample_rate, channels, max_render_seconds: Some(DEFAULT_MAX_RENDER_SECONDS), ..RenderOptions::default() })
+}
+
+
+/// Reports through `written` that a render needs `needed` elements, failing if `buffer` can't hold them
+///
+/// # Safety
+/// `written` has to be null or valid for one write
+unsafe fn reserve<T>(needed: usize, buffer: *mut T, length: usize, written: *mut usize) -> Result<(), (c_int, String)> {
+    if written.is_null() || (buffer.is_null() && length > 0) {
+        return Err(invalid("`buffer` and `written` can't be null"));
+    };
+
+    unsafe { *written = needed };
+
+    if needed > length {
+        return Err((ROORLE_BUFFER_TOO_SMALL, format!("the render needs a buffer of {needed} elements")));
+    };
+
+    Ok(())
+}
+
+
+/// Compiles the NUL-terminated UTF-8 `source` into `*program`, which has to be freed with [`roorle_free`]
+///
+/// # Safety
+/// `source` has to be a valid C string and `program` valid for one write
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn roorle_compile(source: *const c_char, program: *mut *mut RoorleProgram) -> c_int {
+    guard(|| {
+        if source.is_null() || program.is_null() {
+            return Err(invalid("`source` and `program` can't be null"));
+        };
+
+        let source = unsafe { CStr::from_ptr(source) }.to_str().map_err(|_| invalid("`source` isn't valid UTF-8"))?;
+
+        let compiled = crate::compile(source).map_err(|err| match err {
+            Error::ParsingError(_) => (ROORLE_PARSING_ERROR, err.to_string()),
+            Error::CompilingError(_) => (ROORLE_COMPILING_ERROR, err.to_string()),
+        })?;
+
+        unsafe { *program = Box::into_raw(Box::new(RoorleProgram(compiled))) };
+
+        Ok(())
+    })
+}
+
+
+/// Frees a program made by [`roorle_compile`], null is ignored
+///
+/// # Safety
+/// `program` has to come from [`roorle_compile`] and not have been freed already
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn roorle_free(program: *mut RoorleProgram) {
+    if !program.is_null() {
+        drop(unsafe { Box::from_raw(program) });
+    };
+}
+
+
+/// Length of the piece in seconds, 0 for a null program
+///
+/// # Safety
+/// `program` has to be null or a live program from [`roorle_compile`]
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn roorle_duration(program: *const RoorleProgram) -> f64 {
+    unsafe { program.as_ref() }.map(|program| program.0.get_duration()).unwrap_or(0.0)
+}
+
+
+/// Renders interleaved samples between -1 and 1 into `buffer`, which holds `length` of them.
+///
+/// `*written` is set to the amount of samples the render has, so calling this with a `length` of 0
+/// finds out how big the buffer has to be
+///
+/// # Safety
+/// `program` has to be a live program from [`roorle_compile`], `buffer` valid for `length` writes and
+/// `written` valid for one
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn roorle_render(program: *const RoorleProgram, sample_rate: u32, channels: u16, buffer: *mut f32, length: usize, written: *mut usize) -> c_int {
+    guard(|| {
+        let program = unsafe { program.as_ref() }.ok_or_else(|| invalid("`program` can't be null"))?;
+        let options = options(sample_rate, channels)?;
+
+        let mut samples = SampleIterator::new(&program.0, &options).map_err(too_long)?;
+        let needed = samples.len();
+        unsafe { reserve(needed, buffer, length, written) }?;
+
+        if needed > 0 {
+            samples.fill(unsafe { std::slice::from_raw_parts_mut(buffer, needed) });
+        };
+
+        Ok(())
+    })
+}
+
+
+/// Renders the bytes of a 16-bit WAV file into `buffer`, sized like for [`roorle_render`]
+///
+/// # Safety
+/// Same as [`roorle_render`]
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn roorle_render_wav(program: *const RoorleProgram, sample_rate: u32, channels: u16, buffer: *mut u8, length: usize, written: *mut usize) -> c_int {
+    guard(|| {
+        let program = unsafe { program.as_ref() }.ok_or_else(|| invalid("`program` can't be null"))?;
+        let options = options(sample_rate, channels)?;
+
+        let wav = interpreter::wav::interpret(&program.0, &options).map_err(too_long)?;
+        unsafe { reserve(wav.len(), buffer, length, written) }?;
+
+        unsafe { std::ptr::copy_nonoverlapping(wav.as_ptr(), buffer, wav.len()) };
+
+        Ok(())
+    })
+}
+
+
+/// What the last failing call on this thread went wrong with, empty after a successful one.
+///
+/// The string stays valid until the next call into roorle on the same thread
+#[unsafe(no_mangle)]
+pub extern "C" fn roorle_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| last.borrow().as_ptr())
+}
@@ -0,0 +1,517 @@
+//! ABC notation import.
+//!
+//! Only the first tune of a file and the first voice of a tune are read. The key signature and
+//! accidentals are applied to the notes, `|: :|` repeats become repeated sections and first and second
+//! endings are written out in the order they're played. Decorations, chord symbols, grace notes and
+//! lyrics are skipped
+
+use std::collections::HashMap;
+use std::fmt;
+use super::{Length, Sections, Tune};
+
+
+/// Tempo when a tune has no `Q:` field, in quarter notes per minute
+const DEFAULT_TEMPO: f64 = 120.0;
+/// Natural notes in the order key signatures sharpen them, flats going the other way
+const SHARPS: [char; 7] = ['F', 'C', 'G', 'D', 'A', 'E', 'B'];
+const LETTERS: &str = "CDEFGAB";
+
+
+#[derive(Debug)]
+pub enum AbcError {
+    /// There's no `K:` field, which starts the music of a tune
+    NoTune,
+    NoNotes,
+    InvalidField {
+        line: usize,
+        field: String,
+    },
+}
+
+
+impl fmt::Display for AbcError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoTune => write!(f, "no tune found, the `K:` field is missing"),
+            Self::NoNotes => write!(f, "the tune has no notes"),
+            Self::InvalidField { line, field } => write!(f, "line {line}: can't read `{field}`"),
+        }
+    }
+}
+
+
+impl std::error::Error for AbcError { }
+
+
+/// Reads `3`, `3/2`, `/2`, `/` and `//`, the multipliers ABC puts after notes
+fn parse_multiplier(chars: &[char], i: &mut usize) -> Length {
+    let number = |i: &mut usize| {
+        let start = *i;
+        while chars.get(*i).is_some_and(char::is_ascii_digit) {
+            *i += 1;
+        };
+
+        chars[start..*i].iter().collect::<String>().parse::<u32>().ok()
+    };
+
+    let numerator = number(i).unwrap_or(1);
+    let mut denominator = 1;
+    while chars.get(*i) == Some(&'/') {
+        *i += 1;
+        denominator *= number(i).filter(|denominator| *denominator > 0).unwrap_or(2);
+    };
+
+    Length::new(numerator, denominator)
+}
+
+
+fn parse_fraction(text: &str) -> Option<Length> {
+    match text.trim().split_once('/') {
+        Some((numerator, denominator)) => {
+            let denominator = denominator.trim().parse().ok().filter(|denominator| *denominator > 0)?;
+
+            Some(Length::new(numerator.trim().parse().ok()?, denominator))
+        },
+        None => Some(Length::new(text.trim().parse().ok()?, 1)),
+    }
+}
+
+
+/// Alterations the key makes to `C D E F G A B`, in semitones
+fn parse_key(text: &str) -> Option<[i32; 7]> {
+    let mut chars = text.trim().chars().peekable();
+
+    // `K:none`, highland pipes and keys giving only a clef leave the notes as they're written
+    let Some(tonic) = chars.next_if(|tonic| ('A'..='G').contains(tonic)) else {
+        return Some([0; 7]);
+    };
+    let mut fifths = SHARPS.iter().position(|letter| *letter == tonic).unwrap() as i32 - 1;
+    match chars.peek() {
+        Some('#') => fifths += 7,
+        Some('b') => fifths -= 7,
+        _ => { },
+    };
+    chars.next_if(|sign| matches!(sign, '#' | 'b'));
+
+    let mode = chars.collect::<String>();
+    let mode = mode.split_whitespace().next().unwrap_or("").to_ascii_lowercase();
+    fifths += match mode.get(..3).unwrap_or(&mode) {
+        "" | "maj" | "ion" => 0,
+        "m" | "min" | "aeo" => -3,
+        "mix" => -1,
+        "dor" => -2,
+        "phr" => -4,
+        "lyd" => 1,
+        "loc" => -5,
+        // the next word is a clef or another key setting rather than a mode
+        _ if mode.contains('=') => 0,
+        _ => return None,
+    };
+    let fifths = fifths.clamp(-7, 7);
+
+    let mut alterations = [0; 7];
+    for (n, letter) in SHARPS.iter().enumerate() {
+        let n = n as i32;
+        let alteration = if n < fifths { 1 } else if n >= 7 + fifths { -1 } else { 0 };
+
+        alterations[LETTERS.find(*letter).unwrap()] = alteration;
+    };
+
+    Some(alterations)
+}
+
+
+/// Length of a bar, with `C` and `C|` standing for 4/4 and 2/2
+fn parse_meter(text: &str) -> Option<Length> {
+    match text.trim() {
+        "C" => Some(Length::new(4, 4)),
+        "C|" => Some(Length::new(2, 2)),
+        "" | "none" => Some(Length::new(1, 1)),
+        // additive meters like `2+3/8` too
+        text => {
+            let (beats, unit) = text.split_once('/')?;
+            let beats = beats.trim_matches(['(', ')', ' ']).split('+').map(|beats| beats.trim().parse::<u32>().ok()).sum::<Option<u32>>()?;
+
+            Some(Length::new(beats, unit.trim().parse().ok().filter(|unit| *unit > 0)?))
+        },
+    }
+}
+
+
+/// Quarter notes per minute, given the unit note length a bare `Q:120` counts
+fn parse_tempo(text: &str, unit: Length) -> Option<f64> {
+    // descriptions like `"Allegro"` are only for people
+    let text = text.split('"').step_by(2).collect::<String>();
+
+    let (beat, count) = match text.split_once('=') {
+        Some((beats, count)) => {
+            let beat = beats.split_whitespace()
+                .map(|beat| if beat.starts_with('C') { Some(unit) } else { parse_fraction(beat) })
+                .try_fold(Length::new(0, 1), |sum, beat| Some(sum + beat?))?;
+
+            (beat, count)
+        },
+        None => (unit, text.as_str()),
+    };
+    let count = count.trim().parse::<f64>().ok()?;
+
+    Some(count * 4.0 * beat.numerator as f64 / beat.denominator as f64).filter(|tempo| *tempo > 0.0)
+}
+
+
+struct Reader {
+    unit: Option<Length>,
+    meter: Length,
+    key: [i32; 7],
+    /// Accidentals written earlier in the bar, by letter and octave
+    accidentals: HashMap<(char, i32), i32>,
+    /// Line and text of the `Q:` field giving the tune's tempo
+    tempo: Option<(usize, String)>,
+    /// Voice being read, every other one is skipped
+    voice: Option<String>,
+    skipping: bool,
+
+    sections: Sections,
+    /// Last note, chord or rest, kept until it's clear whether it's tied or broken
+    pending: Option<(Vec<u8>, Length)>,
+    tied: bool,
+    /// What the next note is multiplied by after a `>` or `<`
+    broken: Option<Length>,
+    /// Multiplier of the notes in a tuplet and how many of them are left
+    tuplet: Option<(Length, u32)>,
+}
+
+
+impl Reader {
+    fn unit(&self) -> Length {
+        // tunes in meters shorter than 3/4 count in 16th notes
+        self.unit.unwrap_or(if self.meter.numerator * 4 < self.meter.denominator * 3 { Length::new(1, 16) } else { Length::new(1, 8) })
+    }
+
+    fn has_notes(&self) -> bool {
+        !self.sections.is_empty() || self.pending.is_some()
+    }
+
+    fn field(&mut self, name: char, value: &str, line: usize) -> Result<(), AbcError> {
+        let invalid = || AbcError::InvalidField { line, field: format!("{name}:{value}") };
+
+        match name {
+            'V' => {
+                let voice = value.split_whitespace().next().unwrap_or("");
+
+                match self.voice.as_deref() {
+                    Some(first) => self.skipping = first != voice,
+                    None => self.voice = Some(String::from(voice)),
+                };
+            },
+            _ if self.skipping => { },
+            'K' => self.key = parse_key(value).ok_or_else(invalid)?,
+            'L' => self.unit = Some(parse_fraction(value).filter(|unit| !unit.is_zero()).ok_or_else(invalid)?),
+            'M' => self.meter = parse_meter(value).ok_or_else(invalid)?,
+            'Q' if !self.has_notes() => self.tempo = Some((line, String::from(value))),
+            'Q' => {
+                let tempo = parse_tempo(value, self.unit()).ok_or_else(invalid)?;
+
+                self.flush();
+                self.sections.change_bpm(Tune::bpm_of(tempo));
+            },
+            _ => { },
+        };
+
+        Ok(())
+    }
+
+    fn flush(&mut self) {
+        if let Some((keys, length)) = self.pending.take() {
+            self.sections.current.push(keys, length);
+        };
+    }
+
+    fn emit(&mut self, keys: Vec<u8>, length: Length) {
+        let mut length = length;
+        if let Some(broken) = self.broken.take() {
+            length = length * broken;
+        };
+        if let Some((multiplier, left)) = self.tuplet.take() {
+            length = length * multiplier;
+
+            if left > 1 {
+                self.tuplet = Some((multiplier, left - 1));
+            };
+        };
+
+        match self.pending.as_mut() {
+            Some((pending, pending_length)) if self.tied && *pending == keys => *pending_length = *pending_length + length,
+            _ => {
+                self.flush();
+                self.pending = Some((keys, length));
+            },
+        };
+        self.tied = false;
+    }
+
+    fn note(&mut self, chars: &[char], i: &mut usize) -> Option<(u8, Length)> {
+        let mut accidental = None;
+        loop {
+            match chars.get(*i) {
+                Some('^') => accidental = Some(accidental.unwrap_or(0) + 1),
+                Some('_') => accidental = Some(accidental.unwrap_or(0) - 1),
+                Some('=') => accidental = Some(0),
+                _ => break,
+            };
+            *i += 1;
+        };
+
+        let letter = chars.get(*i).copied().filter(|letter| LETTERS.contains(letter.to_ascii_uppercase()))?;
+        *i += 1;
+
+        let mut octave = if letter.is_ascii_lowercase() { 1 } else { 0 };
+        loop {
+            match chars.get(*i) {
+                Some('\'') => octave += 1,
+                Some(',') => octave -= 1,
+                _ => break,
+            };
+            *i += 1;
+        };
+        let multiplier = parse_multiplier(chars, i);
+
+        let letter = letter.to_ascii_uppercase();
+        let index = LETTERS.find(letter).unwrap();
+        let alteration = match accidental {
+            Some(accidental) => {
+                self.accidentals.insert((letter, octave), accidental);
+                accidental
+            },
+            None => self.accidentals.get(&(letter, octave)).copied().unwrap_or(self.key[index]),
+        };
+
+        // `C` is middle C, MIDI key 60
+        let semitone = [0, 2, 4, 5, 7, 9, 11][index];
+        let key = (60 + 12 * octave + semitone + alteration).clamp(0, 127) as u8;
+
+        Some((key, self.unit() * multiplier))
+    }
+
+    fn music(&mut self, line: &str, number: usize) -> Result<(), AbcError> {
+        let chars = line.chars().collect::<Vec<_>>();
+        let skip_to = |i: usize, end: char| chars[i + 1..].iter().position(|c| *c == end).map_or(chars.len(), |at| i + at + 2);
+
+        let mut i = 0;
+        while i < chars.len() {
+            match chars[i] {
+                '[' if chars.get(i + 1).is_some_and(char::is_ascii_alphabetic) && chars.get(i + 2) == Some(&':') => {
+                    let end = skip_to(i, ']');
+                    let value = chars[i + 3..end.saturating_sub(1).max(i + 3)].iter().collect::<String>();
+
+                    self.field(chars[i + 1], &value, number)?;
+                    i = end;
+                },
+                _ if self.skipping => i += 1,
+                quote @ ('"' | '!' | '+') => i = skip_to(i, quote),
+                '{' => i = skip_to(i, '}'),
+                // another voice sharing the bar
+                '&' => i = chars[i..].iter().position(|c| *c == '|').map_or(chars.len(), |at| i + at),
+                '(' if chars.get(i + 1).is_some_and(char::is_ascii_digit) => {
+                    i += 1;
+                    let mut numbers = [None; 3];
+                    for (n, number) in numbers.iter_mut().enumerate() {
+                        if n > 0 && chars.get(i) != Some(&':') {
+                            break;
+                        };
+                        if n > 0 {
+                            i += 1;
+                        };
+
+                        let start = i;
+                        while chars.get(i).is_some_and(char::is_ascii_digit) {
+                            i += 1;
+                        };
+                        *number = chars[start..i].iter().collect::<String>().parse::<u32>().ok().filter(|number| *number > 0);
+                    };
+
+                    let notes = numbers[0].unwrap_or(3);
+                    let compound = self.meter.numerator.is_multiple_of(3) && self.meter.numerator > 3;
+                    let time = numbers[1].unwrap_or(match notes {
+                        2 | 4 | 8 => 3,
+                        3 | 6 => 2,
+                        _ if compound => 3,
+                        _ => 2,
+                    });
+
+                    self.tuplet = Some((Length::new(time, notes), numbers[2].unwrap_or(notes)));
+                },
+                '-' => {
+                    self.tied = true;
+                    i += 1;
+                },
+                direction @ ('>' | '<') => {
+                    let mut count = 0;
+                    while chars.get(i) == Some(&direction) {
+                        count += 1;
+                        i += 1;
+                    };
+
+                    let short = Length::new(1, 1 << count.min(4));
+                    let long = Length::new((2 << count.min(4)) - 1, 1 << count.min(4));
+                    let (first, second) = if direction == '>' { (long, short) } else { (short, long) };
+
+                    if let Some((_, length)) = self.pending.as_mut() {
+                        *length = *length * first;
+                    };
+                    self.broken = Some(second);
+                },
+                '|' | ':' => {
+                    let start = i;
+                    while chars.get(i).is_some_and(|c| matches!(c, '|' | ':' | ']')) {
+                        i += 1;
+                    };
+                    let bar = chars[start..i].iter().collect::<String>();
+                    self.accidentals.clear();
+
+                    self.flush();
+                    if bar.starts_with(':') && bar.len() > 1 {
+                        self.sections.end_repeat(2);
+                    } else if bar.contains("||") || bar.contains("|]") {
+                        self.sections.close(1);
+                    };
+                    if bar.ends_with(':') && bar.len() > 1 {
+                        self.sections.start_repeat();
+                    };
+                },
+                '[' if chars.get(i + 1) == Some(&'|') => {
+                    i += 2;
+                    self.accidentals.clear();
+                    self.flush();
+                    self.sections.close(1);
+                },
+                '[' if chars.get(i + 1).is_some_and(char::is_ascii_digit) => i += 1,
+                // the number of an ending
+                digit if digit.is_ascii_digit() => {
+                    if digit == '1' && !chars.get(i + 1).is_some_and(char::is_ascii_digit) {
+                        self.flush();
+                        self.sections.start_first_ending();
+                    };
+
+                    while chars.get(i).is_some_and(|c| c.is_ascii_digit() || matches!(c, ',' | '-')) {
+                        i += 1;
+                    };
+                },
+                '[' => {
+                    i += 1;
+
+                    let mut keys = Vec::new();
+                    let mut length = None;
+                    while i < chars.len() && chars[i] != ']' {
+                        match self.note(&chars, &mut i) {
+                            Some((key, note_length)) => {
+                                keys.push(key);
+                                length.get_or_insert(note_length);
+                            },
+                            None => i += 1,
+                        };
+                    };
+                    i += 1;
+                    let multiplier = parse_multiplier(&chars, &mut i);
+
+                    if let Some(length) = length {
+                        self.emit(keys, length * multiplier);
+                    };
+                },
+                'z' | 'x' => {
+                    i += 1;
+                    let length = self.unit() * parse_multiplier(&chars, &mut i);
+
+                    self.emit(Vec::new(), length);
+                },
+                // rests lasting whole bars
+                'Z' | 'X' => {
+                    i += 1;
+                    let length = self.meter * parse_multiplier(&chars, &mut i);
+
+                    self.emit(Vec::new(), length);
+                },
+                '^' | '_' | '=' | 'A'..='G' | 'a'..='g' => match self.note(&chars, &mut i) {
+                    Some((key, length)) => self.emit(vec![key], length),
+                    None => i += 1,
+                },
+                _ => i += 1,
+            };
+        };
+
+        Ok(())
+    }
+}
+
+
+/// Reads the first tune of an ABC file
+pub fn import(source: &str) -> Result<Tune, AbcError> {
+    let mut reader = Reader {
+        unit: None,
+        meter: Length::new(4, 4),
+        key: [0; 7],
+        accidentals: HashMap::new(),
+        tempo: None,
+        voice: None,
+        skipping: false,
+        sections: Sections::new(),
+        pending: None,
+        tied: false,
+        broken: None,
+        tuplet: None,
+    };
+    let mut title = None;
+    let mut header = true;
+    let mut found = false;
+
+    for (number, line) in source.lines().enumerate() {
+        let number = number + 1;
+        let line = line.split('%').next().unwrap_or("").trim_end();
+
+        let field = match line.as_bytes() {
+            [name, b':', ..] if name.is_ascii_alphabetic() => Some((*name as char, line[2..].trim())),
+            _ => None,
+        };
+
+        match field {
+            // the next tune
+            Some(('X', _)) if found => break,
+            Some(('T', value)) if header => {
+                title.get_or_insert_with(|| String::from(value));
+            },
+            Some(('K', value)) if header => {
+                reader.field('K', value, number)?;
+                header = false;
+                found = true;
+            },
+            Some(('V', value)) if header => {
+                reader.voice.get_or_insert_with(|| String::from(value.split_whitespace().next().unwrap_or("")));
+            },
+            Some((name, value)) => reader.field(name, value, number)?,
+            // tunes are separated by empty lines
+            None if !header && line.trim().is_empty() && reader.has_notes() => break,
+            None if !header => reader.music(line, number)?,
+            None => { },
+        };
+    };
+
+    if !found {
+        return Err(AbcError::NoTune);
+    };
+
+    reader.flush();
+    if reader.sections.is_empty() {
+        return Err(AbcError::NoNotes);
+    };
+
+    let tempo = match reader.tempo.as_ref() {
+        Some((line, tempo)) => parse_tempo(tempo, reader.unit()).ok_or_else(|| AbcError::InvalidField { line: *line, field: format!("Q:{tempo}") })?,
+        None => DEFAULT_TEMPO,
+    };
+
+    Ok(Tune {
+        title: title.filter(|title| !title.is_empty()),
+        bpm: Tune::bpm_of(tempo),
+        sections: reader.sections.finish(),
+    })
+}
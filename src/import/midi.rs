@@ -0,0 +1,240 @@
+//! Standard MIDI File import.
+//!
+//! Notes of every track and channel except the drum channel are merged onto one timeline, quantized to
+//! 1/192 of a whole note (enough for 64th notes and 32nd triplets) and cut into chords wherever a note
+//! starts or stops. Tempo changes start a new section with its own `bpm`
+
+use std::collections::HashMap;
+use std::fmt;
+use super::{Sections, Tune};
+
+
+/// Positions are rounded to this fraction of a whole note
+const GRID: u32 = 192;
+const DEFAULT_TEMPO: u32 = 500_000;
+/// Channel 10, which General MIDI plays drums on instead of pitches
+const DRUM_CHANNEL: u8 = 9;
+
+
+#[derive(Debug)]
+pub enum MidiError {
+    NotMidi,
+    Truncated,
+    /// Files timed in SMPTE frames instead of beats
+    SmpteTiming,
+    NoNotes,
+}
+
+
+impl fmt::Display for MidiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotMidi => write!(f, "not a standard MIDI file"),
+            Self::Truncated => write!(f, "the MIDI file ends in the middle of a chunk"),
+            Self::SmpteTiming => write!(f, "MIDI files timed in SMPTE frames aren't supported"),
+            Self::NoNotes => write!(f, "the MIDI file has no notes"),
+        }
+    }
+}
+
+
+impl std::error::Error for MidiError { }
+
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, length: usize) -> Result<&'a [u8], MidiError> {
+        let taken = self.bytes.get(self.pos..self.pos + length).ok_or(MidiError::Truncated)?;
+        self.pos += length;
+
+        Ok(taken)
+    }
+
+    fn byte(&mut self) -> Result<u8, MidiError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn peek(&self) -> Result<u8, MidiError> {
+        self.bytes.get(self.pos).copied().ok_or(MidiError::Truncated)
+    }
+
+    fn u32(&mut self) -> Result<u32, MidiError> {
+        Ok(u32::from_be_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn variable_length(&mut self) -> Result<u32, MidiError> {
+        let mut value = 0;
+        for _ in 0..4 {
+            let byte = self.byte()?;
+            value = (value << 7) | (byte & 0x7F) as u32;
+
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            };
+        };
+
+        Err(MidiError::NotMidi)
+    }
+
+    fn is_done(&self) -> bool {
+        self.pos >= self.bytes.len()
+    }
+}
+
+
+struct Note {
+    start: u64,
+    end: u64,
+    key: u8,
+}
+
+
+#[derive(Default)]
+struct Track {
+    notes: Vec<Note>,
+    /// Ticks and microseconds per quarter note
+    tempos: Vec<(u64, u32)>,
+    name: Option<String>,
+}
+
+
+fn read_track(bytes: &[u8]) -> Result<Track, MidiError> {
+    let mut reader = Reader { bytes, pos: 0 };
+    let mut track = Track::default();
+    let mut playing: HashMap<(u8, u8), Vec<u64>> = HashMap::new();
+    let mut running = None;
+    let mut tick = 0;
+
+    while !reader.is_done() {
+        tick += reader.variable_length()? as u64;
+
+        let status = if reader.peek()? & 0x80 != 0 { reader.byte()? } else { running.ok_or(MidiError::NotMidi)? };
+
+        match status {
+            0xFF => {
+                running = None;
+
+                let kind = reader.byte()?;
+                let length = reader.variable_length()? as usize;
+                let data = reader.take(length)?;
+
+                match kind {
+                    0x03 if track.name.is_none() => track.name = Some(String::from_utf8_lossy(data).trim().to_string()),
+                    0x51 if length == 3 => track.tempos.push((tick, u32::from_be_bytes([0, data[0], data[1], data[2]]))),
+                    0x2F => break,
+                    _ => { },
+                };
+            },
+            0xF0 | 0xF7 => {
+                running = None;
+
+                let length = reader.variable_length()? as usize;
+                reader.take(length)?;
+            },
+            0x80..=0xEF => {
+                running = Some(status);
+
+                let channel = status & 0x0F;
+                let data = reader.take(if matches!(status >> 4, 0xC | 0xD) { 1 } else { 2 })?;
+
+                match (status >> 4, data) {
+                    _ if channel == DRUM_CHANNEL => { },
+                    (0x9, [key, velocity]) if *velocity > 0 => playing.entry((channel, *key)).or_default().push(tick),
+                    (0x8 | 0x9, [key, _]) => if let Some(starts) = playing.get_mut(&(channel, *key)) && !starts.is_empty() {
+                        track.notes.push(Note { start: starts.remove(0), end: tick, key: *key });
+                    },
+                    _ => { },
+                };
+            },
+            _ => return Err(MidiError::NotMidi),
+        };
+    };
+
+    // notes nobody let go of stop with the track
+    for ((_, key), starts) in playing {
+        track.notes.extend(starts.into_iter().map(|start| Note { start, end: tick, key }));
+    };
+
+    Ok(track)
+}
+
+
+/// Reads a Standard MIDI File into a tune, titled after its first track's name
+pub fn import(bytes: &[u8]) -> Result<Tune, MidiError> {
+    let mut reader = Reader { bytes, pos: 0 };
+
+    if reader.take(4).map_err(|_| MidiError::NotMidi)? != b"MThd" {
+        return Err(MidiError::NotMidi);
+    };
+    let header_length = reader.u32()? as usize;
+    if header_length < 6 {
+        return Err(MidiError::NotMidi);
+    };
+    let header = reader.take(header_length)?;
+    let division = u16::from_be_bytes([header[4], header[5]]);
+    if division & 0x8000 != 0 {
+        return Err(MidiError::SmpteTiming);
+    };
+    if division == 0 {
+        return Err(MidiError::NotMidi);
+    };
+
+    let mut tracks = Vec::new();
+    while !reader.is_done() {
+        let kind = reader.take(4)?;
+        let length = reader.u32()? as usize;
+        let chunk = reader.take(length)?;
+
+        if kind == b"MTrk" {
+            tracks.push(read_track(chunk)?);
+        };
+    };
+
+    // ticks are counted in quarter notes, positions in whole ones
+    let position = |tick: u64| (tick as f64 * GRID as f64 / (4.0 * division as f64)).round() as u32;
+
+    let mut notes = tracks.iter()
+        .flat_map(|track| track.notes.iter())
+        .map(|note| (position(note.start), position(note.end), note.key))
+        .filter(|(start, end, _)| end > start)
+        .collect::<Vec<_>>();
+    notes.sort_by_key(|(start, _, key)| (*start, *key));
+
+    let end = notes.iter().map(|(_, end, _)| *end).max().ok_or(MidiError::NoNotes)?;
+
+    let mut tempos = tracks.iter()
+        .flat_map(|track| track.tempos.iter())
+        .map(|(tick, tempo)| (position(*tick), *tempo))
+        .filter(|(at, _)| *at < end)
+        .collect::<Vec<_>>();
+    tempos.sort_by_key(|(at, _)| *at);
+    if tempos.first().is_none_or(|(at, _)| *at > 0) {
+        tempos.insert(0, (0, DEFAULT_TEMPO));
+    };
+    // of several changes at the same time only the last one counts, and changes to the same tempo don't
+    tempos.dedup_by(|later, earlier| if later.0 == earlier.0 { *earlier = *later; true } else { false });
+    tempos.dedup_by_key(|(_, tempo)| *tempo);
+
+    let bpm = |tempo: u32| Tune::bpm_of(60_000_000.0 / tempo as f64);
+
+    let mut sections = Sections::new();
+    for (i, (from, tempo)) in tempos.iter().enumerate() {
+        let to = tempos.get(i + 1).map(|(at, _)| *at).unwrap_or(end);
+
+        if i > 0 {
+            sections.change_bpm(bpm(*tempo));
+        };
+        sections.current.push_notes(&notes, *from, to, GRID);
+    };
+
+    Ok(Tune {
+        title: tracks.first().and_then(|track| track.name.clone()).filter(|name| !name.is_empty()),
+        bpm: bpm(tempos[0].1),
+        sections: sections.finish(),
+    })
+}
@@ -0,0 +1,230 @@
+//! Music Macro Language import.
+//!
+//! Reads the classic commands: notes `a`-`g` with `+`, `#` and `-`, rests `r` and `p`, note numbers
+//! `n`, octaves `o`, `>` and `<`, default lengths `l`, tempos `t`, ties `&` and `^` and loops `[...]n`
+//! with `|` leaving the rest of the loop on its last time. Octaves are numbered like roorle's and
+//! commands for volume, gate time and instruments are skipped. Only the first channel is read, channels
+//! being separated by `;`
+
+use std::fmt;
+use super::{Length, Sections, Tune};
+
+
+const DEFAULT_TEMPO: u32 = 120;
+const DEFAULT_OCTAVE: i32 = 4;
+
+
+#[derive(Debug)]
+pub enum MmlError {
+    /// A command at the given character needs a number it doesn't have or it can't use
+    InvalidNumber(usize),
+    UnmatchedLoop(usize),
+    NoNotes,
+}
+
+
+impl fmt::Display for MmlError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidNumber(pos) => write!(f, "character {}: invalid number", pos + 1),
+            Self::UnmatchedLoop(pos) => write!(f, "character {}: loop isn't closed or opened", pos + 1),
+            Self::NoNotes => write!(f, "the MML has no notes"),
+        }
+    }
+}
+
+
+impl std::error::Error for MmlError { }
+
+
+fn number(chars: &[char], i: &mut usize) -> Option<u32> {
+    let start = *i;
+    while chars.get(*i).is_some_and(char::is_ascii_digit) {
+        *i += 1;
+    };
+
+    chars[start..*i].iter().collect::<String>().parse().ok()
+}
+
+
+/// Reads a length like `8` or `4..`, `default` if there's no number
+fn length(chars: &[char], i: &mut usize, default: Length) -> Result<Length, MmlError> {
+    let start = *i;
+    let mut length = match number(chars, i) {
+        Some(0) => return Err(MmlError::InvalidNumber(start)),
+        Some(denominator) => Length::new(1, denominator),
+        None => default,
+    };
+
+    let mut dot = length;
+    while chars.get(*i) == Some(&'.') {
+        dot = dot * Length::new(1, 2);
+        length = length + dot;
+        *i += 1;
+    };
+
+    Ok(length)
+}
+
+
+/// Where the `]` closing the loop opened at `start` is, skipping the loops inside it
+fn loop_end(chars: &[char], start: usize) -> Option<usize> {
+    let mut depth = 0;
+    for (i, c) in chars.iter().enumerate().skip(start) {
+        match c {
+            '[' => depth += 1,
+            ']' if depth == 1 => return Some(i),
+            ']' => depth -= 1,
+            _ => { },
+        };
+    };
+
+    None
+}
+
+
+/// Reads MML into a tune, every `t` after the first note starting a new section
+pub fn import(source: &str) -> Result<Tune, MmlError> {
+    let channel = source.split(';').next().unwrap_or("");
+    let chars = channel.to_ascii_lowercase().chars().collect::<Vec<_>>();
+
+    let mut sections = Sections::new();
+    let mut tempo = None;
+    let mut octave = DEFAULT_OCTAVE;
+    let mut default_length = Length::new(1, 4);
+    let mut pending: Option<(Vec<u8>, Length)> = None;
+    let mut tied = false;
+    // open loops by where they start and how many more times they're played, the outermost one written
+    // as a repeated section when it can be
+    let mut loops: Vec<(usize, u32)> = Vec::new();
+    let mut sectioned = false;
+
+    let flush = |sections: &mut Sections, pending: &mut Option<(Vec<u8>, Length)>| {
+        if let Some((keys, length)) = pending.take() {
+            sections.current.push(keys, length);
+        };
+    };
+
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        i += 1;
+
+        match c {
+            'a'..='g' | 'n' | 'r' | 'p' => {
+                let key = match c {
+                    'r' | 'p' => None,
+                    'n' => match number(&chars, &mut i) {
+                        Some(0) => None,
+                        // `n1` is the lowest `c`, an octave below roorle's octave 0
+                        Some(note) => Some((note as i32 + 11).clamp(0, 127)),
+                        None => return Err(MmlError::InvalidNumber(i)),
+                    },
+                    letter => {
+                        let mut key = 12 * (octave + 1) + [9, 11, 0, 2, 4, 5, 7][(letter as u8 - b'a') as usize];
+                        while let Some(accidental) = chars.get(i).filter(|c| matches!(c, '+' | '#' | '-')) {
+                            key += if *accidental == '-' { -1 } else { 1 };
+                            i += 1;
+                        };
+
+                        Some(key.clamp(0, 127))
+                    },
+                };
+                let length = if c == 'n' { default_length } else { length(&chars, &mut i, default_length)? };
+                let keys = key.map(|key| vec![key as u8]).unwrap_or_default();
+
+                match pending.as_mut() {
+                    Some((pending_keys, pending_length)) if tied && *pending_keys == keys => *pending_length = *pending_length + length,
+                    _ => {
+                        flush(&mut sections, &mut pending);
+                        pending = Some((keys, length));
+                    },
+                };
+                tied = false;
+            },
+            '&' => tied = true,
+            // lengthens the last note
+            '^' => {
+                let extra = length(&chars, &mut i, default_length)?;
+
+                if let Some((_, length)) = pending.as_mut() {
+                    *length = *length + extra;
+                };
+            },
+            'o' => octave = number(&chars, &mut i).ok_or(MmlError::InvalidNumber(i))? as i32,
+            '>' => octave += 1,
+            '<' => octave -= 1,
+            'l' => default_length = length(&chars, &mut i, default_length)?,
+            't' => {
+                let start = i;
+                let bpm = number(&chars, &mut i).filter(|bpm| *bpm > 0).ok_or(MmlError::InvalidNumber(start))?;
+
+                if pending.is_none() && sections.is_empty() {
+                    tempo = Some(bpm);
+                } else {
+                    flush(&mut sections, &mut pending);
+                    sections.change_bpm(Tune::bpm_of(bpm as f64));
+                };
+            },
+            '[' => {
+                let end = loop_end(&chars, i - 1).ok_or(MmlError::UnmatchedLoop(i - 1))?;
+                let mut after = end + 1;
+                let times = number(&chars, &mut after).unwrap_or(2);
+
+                // a loop is a repeated section unless it's inside another, breaks out with `|` or could
+                // sound different the next time around
+                let body = &chars[i..end];
+                let count = |c| body.iter().filter(|other| **other == c).count();
+                let same_every_time = !body.iter().any(|c| matches!(c, '|' | 't' | 'o' | 'l')) && count('>') == count('<');
+
+                if loops.is_empty() && same_every_time {
+                    flush(&mut sections, &mut pending);
+                    sections.start_repeat();
+                    sectioned = true;
+                };
+                loops.push((i, times.saturating_sub(1)));
+            },
+            ']' => {
+                let (start, left) = loops.pop().ok_or(MmlError::UnmatchedLoop(i - 1))?;
+
+                if loops.is_empty() && sectioned {
+                    flush(&mut sections, &mut pending);
+                    sections.end_repeat(left + 1);
+                    sectioned = false;
+                    number(&chars, &mut i);
+                } else if left > 0 {
+                    loops.push((start, left - 1));
+                    i = start;
+                } else {
+                    number(&chars, &mut i);
+                };
+            },
+            '|' => if let Some((start, 0)) = loops.last().copied() {
+                loops.pop();
+                i = loop_end(&chars, start - 1).ok_or(MmlError::UnmatchedLoop(start - 1))? + 1;
+                number(&chars, &mut i);
+            },
+            '/' if chars.get(i) == Some(&'*') => {
+                i = chars[i..].windows(2).position(|pair| pair == ['*', '/']).map_or(chars.len(), |at| i + at + 2);
+            },
+            // volume, gate time, instrument and the like take a number that's skipped along with them
+            _ => { },
+        };
+    };
+
+    if !loops.is_empty() {
+        return Err(MmlError::UnmatchedLoop(loops.last().unwrap().0 - 1));
+    };
+    flush(&mut sections, &mut pending);
+
+    let sections = sections.finish();
+    if sections.iter().all(|section| section.events.iter().all(|event| event.keys.is_empty())) {
+        return Err(MmlError::NoNotes);
+    };
+
+    Ok(Tune {
+        title: None,
+        bpm: Tune::bpm_of(tempo.unwrap_or(DEFAULT_TEMPO) as f64),
+        sections,
+    })
+}
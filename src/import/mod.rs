@@ -0,0 +1,311 @@
+//! Converting music written in other formats into roorle scripts.
+//!
+//! Every importer reads its format into a [`Tune`], which [`Tune::script`] writes the way a person
+//! would: lengths as fractions of a whole note, one label per section and notes spelled by name. Labels
+//! in roorle are played one after another, so parts meant to be heard together are merged into chords
+
+use crate::score::{LabelBuilder, ScoreBuilder};
+use crate::pitch::NOTE_NAMES;
+use crate::syntax::parser::{Script, Value};
+
+pub mod midi;
+pub mod abc;
+pub mod musicxml;
+pub mod rtttl;
+pub mod mml;
+pub mod wav;
+
+
+/// `numerator / denominator` of a whole note, always kept reduced
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Length {
+    pub numerator: u32,
+    pub denominator: u32,
+}
+
+
+impl Length {
+    pub fn new(numerator: u32, denominator: u32) -> Self {
+        let divisor = gcd(numerator, denominator).max(1);
+
+        Self { numerator: numerator / divisor, denominator: denominator / divisor }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.numerator == 0
+    }
+}
+
+
+impl std::ops::Add for Length {
+    type Output = Self;
+
+    fn add(self, other: Length) -> Self {
+        let denominator = self.denominator / gcd(self.denominator, other.denominator) * other.denominator;
+
+        Self::new(
+            self.numerator * (denominator / self.denominator) + other.numerator * (denominator / other.denominator),
+            denominator,
+        )
+    }
+}
+
+
+impl std::ops::Mul for Length {
+    type Output = Self;
+
+    fn mul(self, other: Length) -> Self {
+        Self::new(self.numerator * other.numerator, self.denominator * other.denominator)
+    }
+}
+
+
+impl From<Length> for Value {
+    fn from(length: Length) -> Self {
+        match length.denominator {
+            1 => Value::Whole(length.numerator),
+            denominator => Value::Fraction { numerator: length.numerator, denominator },
+        }
+    }
+}
+
+
+fn gcd(a: u32, b: u32) -> u32 {
+    if b == 0 { a } else { gcd(b, a % b) }
+}
+
+
+/// Name and octave of a MIDI key, 60 being `C` in octave 4. The lowest octave, below roorle's octave 0,
+/// is played an octave higher
+pub fn key_name(key: u8) -> (&'static str, u32) {
+    (NOTE_NAMES[key as usize % 12], (key as u32 / 12).saturating_sub(1))
+}
+
+
+/// Notes played together, or a rest if there are no keys
+#[derive(Clone, Debug)]
+pub struct Event {
+    /// MIDI keys, 60 being middle C
+    pub keys: Vec<u8>,
+    pub length: Length,
+}
+
+
+#[derive(Clone, Debug)]
+pub struct Section {
+    /// Label the section is written into, which can't be `main`
+    pub name: String,
+    /// Tempo of the section if it differs from the tune's
+    pub bpm: Option<Value>,
+    pub repeat: u32,
+    pub events: Vec<Event>,
+}
+
+
+impl Section {
+    pub fn new(name: &str) -> Self {
+        Self { name: String::from(name), bpm: None, repeat: 1, events: Vec::new() }
+    }
+
+    /// Adds an event, joining rests that follow each other into one
+    pub fn push(&mut self, keys: Vec<u8>, length: Length) {
+        if length.is_zero() {
+            return;
+        };
+
+        match self.events.last_mut() {
+            Some(last) if keys.is_empty() && last.keys.is_empty() => last.length = last.length + length,
+            _ => self.events.push(Event { keys, length }),
+        };
+    }
+
+    /// Adds the notes sounding between `from` and `to`, given as their start, end and key in `grid`ths of
+    /// a whole note, cut into chords wherever one of them starts or stops
+    pub fn push_notes(&mut self, notes: &[(u32, u32, u8)], from: u32, to: u32, grid: u32) {
+        let notes = notes.iter()
+            .filter(|(start, end, _)| *start < to && *end > from)
+            .collect::<Vec<_>>();
+
+        let mut cuts = notes.iter()
+            .flat_map(|(start, end, _)| [*start, *end])
+            .filter(|at| (from..to).contains(at))
+            .chain([from, to])
+            .collect::<Vec<_>>();
+        cuts.sort_unstable();
+        cuts.dedup();
+
+        for cut in cuts.windows(2) {
+            let keys = notes.iter()
+                .filter(|(start, end, _)| *start <= cut[0] && *end > cut[0])
+                .map(|(_, _, key)| *key)
+                .collect();
+
+            self.push(keys, Length::new(cut[1] - cut[0], grid));
+        };
+    }
+
+    fn label(&self, label: LabelBuilder) -> LabelBuilder {
+        let label = match self.bpm.clone() {
+            Some(bpm) => label.property("bpm", bpm),
+            None => label,
+        };
+
+        self.events.iter().fold(label, |label, event| {
+            let Some(highest) = event.keys.iter().max() else {
+                return label.rest(event.length);
+            };
+
+            // a command only has one octave, so the other keys of a chord are moved into the highest one's
+            let (_, octave) = key_name(*highest);
+            let mut keys = event.keys.clone();
+            keys.sort_unstable_by(|a, b| b.cmp(a));
+
+            let mut names = Vec::new();
+            for key in keys.iter() {
+                let (name, _) = key_name(*key);
+
+                if !names.contains(&name) {
+                    names.push(name);
+                };
+            };
+
+            label.chord(names[0], &names[1..], octave, event.length)
+        })
+    }
+}
+
+
+/// Cuts a piece into sections as its repeats, endings and tempo changes go by
+#[derive(Debug)]
+pub struct Sections {
+    sections: Vec<Section>,
+    /// Section notes are being added to
+    pub current: Section,
+    named: u32,
+    /// Tempo of the sections after the last change in the middle of the piece
+    bpm: Option<Value>,
+    /// Whether a first ending is being read
+    ending: bool,
+    /// Music before the first ending, played again before the second one
+    body: Option<Section>,
+}
+
+
+impl Default for Sections {
+    fn default() -> Self {
+        Self { sections: Vec::new(), current: Section::new(""), named: 0, bpm: None, ending: false, body: None }
+    }
+}
+
+
+impl Sections {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sections.is_empty() && self.current.events.is_empty()
+    }
+
+    /// Ends the current section, to be played `repeat` times, and returns it unless it was empty
+    pub fn close(&mut self, repeat: u32) -> Option<Section> {
+        let mut next = Section::new("");
+        next.bpm = self.bpm.clone();
+        let mut section = std::mem::replace(&mut self.current, next);
+        if section.events.is_empty() {
+            return None;
+        };
+
+        self.named += 1;
+        section.name = format!("part{}", self.named);
+        section.repeat = repeat;
+        self.sections.push(section.clone());
+
+        Some(section)
+    }
+
+    pub fn start_repeat(&mut self) {
+        self.close(1);
+        self.body = None;
+        self.ending = false;
+    }
+
+    pub fn start_first_ending(&mut self) {
+        self.body = self.close(1);
+        self.ending = true;
+    }
+
+    /// Goes back to the start of the repeat, or to the part before the first ending to then play the
+    /// second one
+    pub fn end_repeat(&mut self, times: u32) {
+        if self.ending {
+            self.close(1);
+            if let Some(body) = self.body.take() {
+                self.sections.push(body);
+            };
+            self.ending = false;
+        } else {
+            self.close(times);
+        };
+    }
+
+    /// Plays the rest of the piece at `bpm`
+    pub fn change_bpm(&mut self, bpm: Value) {
+        self.bpm = Some(bpm);
+        self.close(1);
+        self.current.bpm = self.bpm.clone();
+    }
+
+    pub fn finish(mut self) -> Vec<Section> {
+        self.close(1);
+
+        self.sections
+    }
+}
+
+
+/// A piece read from another format
+#[derive(Clone, Debug)]
+pub struct Tune {
+    pub title: Option<String>,
+    pub bpm: Value,
+    /// Played in order, each as many times as it repeats. Sections sharing a name are the same music
+    /// played again, and only the first of them is written out
+    pub sections: Vec<Section>,
+}
+
+
+impl Tune {
+    /// The `bpm` for `quarters_per_minute`, as a fraction when it isn't whole
+    pub fn bpm_of(quarters_per_minute: f64) -> Value {
+        let bpm = quarters_per_minute;
+
+        if bpm.fract().abs() < 1e-9 {
+            Value::Whole(bpm as u32)
+        } else {
+            Value::from(Length::new((bpm * 1000.0).round() as u32, 1000))
+        }
+    }
+
+    pub fn script(&self) -> Script {
+        let mut score = ScoreBuilder::new();
+        if let Some(title) = self.title.as_deref() {
+            score = score.property("title", title);
+        };
+        score = score.property("bpm", self.bpm.clone());
+
+        match self.sections.as_slice() {
+            [section] if section.repeat == 1 => score.label("main", |label| section.label(label)).script(),
+            sections => {
+                let score = score.label("main", |label| sections.iter()
+                    .fold(label, |label, section| label.repeat(&section.name, section.repeat)));
+
+                sections.iter()
+                    .enumerate()
+                    .filter(|(i, section)| sections[..*i].iter().all(|earlier| earlier.name != section.name))
+                    .fold(score, |score, (_, section)| score.label(&section.name, |label| section.label(label)))
+                    .script()
+            },
+        }
+    }
+}
@@ -0,0 +1,437 @@
+//! MusicXML import.
+//!
+//! Reads uncompressed `score-partwise` files, the kind MuseScore, Finale and Sibelius export as
+//! `.musicxml`. Roorle plays one label at a time, so the parts and voices are merged into chords measure
+//! by measure. Repeats, first and second endings and tempo marks are kept, ties join their notes and
+//! transposing instruments are written at the pitch they sound. Grace notes, cue notes and unpitched
+//! percussion are skipped
+
+use std::collections::HashMap;
+use std::fmt;
+use super::{Sections, Tune};
+
+
+/// Tempo when a score has no tempo marks, in quarter notes per minute
+const DEFAULT_TEMPO: f64 = 120.0;
+
+
+#[derive(Debug)]
+pub enum MusicXmlError {
+    InvalidXml {
+        line: usize,
+    },
+    NotMusicXml,
+    /// `.mxl` files, which are zipped
+    Compressed,
+    /// Files written measure by measure instead of part by part
+    Timewise,
+    NoNotes,
+}
+
+
+impl fmt::Display for MusicXmlError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidXml { line } => write!(f, "line {line}: invalid XML"),
+            Self::NotMusicXml => write!(f, "not a MusicXML score"),
+            Self::Compressed => write!(f, "compressed .mxl scores aren't supported, export an uncompressed .musicxml one"),
+            Self::Timewise => write!(f, "only part by part (score-partwise) MusicXML is supported"),
+            Self::NoNotes => write!(f, "the score has no notes"),
+        }
+    }
+}
+
+
+impl std::error::Error for MusicXmlError { }
+
+
+#[derive(Debug, Default)]
+struct Element {
+    name: String,
+    attributes: Vec<(String, String)>,
+    children: Vec<Element>,
+    text: String,
+}
+
+
+impl Element {
+    fn child(&self, name: &str) -> Option<&Element> {
+        self.children.iter().find(|child| child.name == name)
+    }
+
+    fn children<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Element> {
+        self.children.iter().filter(move |child| child.name == name)
+    }
+
+    fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes.iter().find(|(attribute, _)| attribute == name).map(|(_, value)| value.as_str())
+    }
+
+    /// Trimmed text of the child called `name`
+    fn text_of(&self, name: &str) -> Option<&str> {
+        self.child(name).map(|child| child.text.trim())
+    }
+
+    fn number_of<N: std::str::FromStr>(&self, name: &str) -> Option<N> {
+        self.text_of(name)?.parse().ok()
+    }
+}
+
+
+/// Replaces `&amp;` and the other references with the characters they stand for
+fn unescape(text: &str) -> String {
+    let mut unescaped = String::new();
+    let mut rest = text;
+
+    while let Some(at) = rest.find('&') {
+        unescaped.push_str(&rest[..at]);
+        rest = &rest[at..];
+
+        let Some(end) = rest.find(';') else { break };
+        let character = match &rest[1..end] {
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "amp" => Some('&'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            reference => reference.strip_prefix("#x").map(|hex| u32::from_str_radix(hex, 16).ok())
+                .unwrap_or_else(|| reference.strip_prefix('#').and_then(|decimal| decimal.parse().ok()))
+                .and_then(char::from_u32),
+        };
+
+        match character {
+            Some(character) => {
+                unescaped.push(character);
+                rest = &rest[end + 1..];
+            },
+            None => {
+                unescaped.push('&');
+                rest = &rest[1..];
+            },
+        };
+    };
+    unescaped.push_str(rest);
+
+    unescaped
+}
+
+
+/// Reads the elements of an XML document, leaving out comments, declarations and processing instructions
+fn parse_xml(source: &str) -> Result<Element, MusicXmlError> {
+    let invalid = |at: usize| MusicXmlError::InvalidXml { line: source[..at].matches('\n').count() + 1 };
+    let find = |from: usize, pattern: &str| source[from..].find(pattern).map(|at| from + at).ok_or_else(|| invalid(from));
+
+    let mut stack = vec![Element::default()];
+    let mut i = 0;
+    while i < source.len() {
+        let rest = &source[i..];
+
+        if rest.starts_with("<!--") {
+            i = find(i, "-->")? + 3;
+        } else if rest.starts_with("<![CDATA[") {
+            let end = find(i, "]]>")?;
+            stack.last_mut().unwrap().text.push_str(&source[i + 9..end]);
+            i = end + 3;
+        } else if rest.starts_with("<?") {
+            i = find(i, "?>")? + 2;
+        } else if rest.starts_with("<!") {
+            // a doctype can hold declarations with their own `>` between brackets
+            let mut depth = 0;
+            let end = rest.char_indices().find(|(_, c)| {
+                match c {
+                    '[' => depth += 1,
+                    ']' => depth -= 1,
+                    _ => { },
+                };
+
+                *c == '>' && depth == 0
+            }).ok_or_else(|| invalid(i))?.0;
+            i += end + 1;
+        } else if let Some(rest) = rest.strip_prefix("</") {
+            let end = find(i, ">")?;
+            let element = stack.pop().filter(|element| element.name == rest[..end - i - 2].trim()).ok_or_else(|| invalid(i))?;
+            stack.last_mut().ok_or_else(|| invalid(i))?.children.push(element);
+            i = end + 1;
+        } else if rest.starts_with('<') {
+            let mut quote = None;
+            let end = i + rest.char_indices().find(|(_, c)| {
+                match (quote, *c) {
+                    (None, '"' | '\'') => quote = Some(*c),
+                    (Some(open), c) if open == c => quote = None,
+                    _ => { },
+                };
+
+                *c == '>' && quote.is_none()
+            }).ok_or_else(|| invalid(i))?.0;
+
+            let tag = &source[i + 1..end];
+            let (tag, empty) = match tag.strip_suffix('/') {
+                Some(tag) => (tag, true),
+                None => (tag, false),
+            };
+            let name_end = tag.find(|c: char| c.is_whitespace()).unwrap_or(tag.len());
+            let mut element = Element { name: String::from(&tag[..name_end]), ..Element::default() };
+
+            let mut attributes = tag[name_end..].trim_start();
+            while !attributes.is_empty() {
+                let (name, value) = attributes.split_once('=').ok_or_else(|| invalid(i))?;
+                let value = value.trim_start();
+                let quote = value.chars().next().filter(|c| matches!(c, '"' | '\'')).ok_or_else(|| invalid(i))?;
+                let value_end = value[1..].find(quote).ok_or_else(|| invalid(i))? + 1;
+
+                element.attributes.push((String::from(name.trim()), unescape(&value[1..value_end])));
+                attributes = value[value_end + 1..].trim_start();
+            };
+
+            if empty {
+                stack.last_mut().unwrap().children.push(element);
+            } else {
+                stack.push(element);
+            };
+            i = end + 1;
+        } else {
+            let end = source[i..].find('<').map_or(source.len(), |at| i + at);
+            stack.last_mut().unwrap().text.push_str(&unescape(&source[i..end]));
+            i = end;
+        };
+    };
+
+    match (stack.pop(), stack.is_empty()) {
+        (Some(document), true) => document.children.into_iter().next().ok_or(MusicXmlError::NotMusicXml),
+        _ => Err(invalid(source.len())),
+    }
+}
+
+
+/// What happens at a measure besides its notes
+#[derive(Default)]
+struct Measure {
+    length: u64,
+    /// Quarter notes per minute from here on
+    tempo: Option<f64>,
+    starts_repeat: bool,
+    /// How many times the music before is played, if it ends a repeat
+    ends_repeat: Option<u32>,
+    starts_first_ending: bool,
+}
+
+
+/// A note in ticks from the start of its measure
+struct Note {
+    start: u64,
+    end: u64,
+    key: u8,
+    tied_to_previous: bool,
+}
+
+
+fn gcd(a: u64, b: u64) -> u64 {
+    if b == 0 { a } else { gcd(b, a % b) }
+}
+
+
+/// Key a `<pitch>` sounds at, moved by the `<transpose>` of the part
+fn key(pitch: &Element, transpose: i32) -> Option<u8> {
+    let semitone = match pitch.text_of("step")? {
+        "C" => 0,
+        "D" => 2,
+        "E" => 4,
+        "F" => 5,
+        "G" => 7,
+        "A" => 9,
+        "B" => 11,
+        _ => return None,
+    };
+    // quarter tones are rounded to the nearest key
+    let alter = pitch.number_of::<f64>("alter").unwrap_or(0.0).round() as i32;
+    let octave = pitch.number_of::<i32>("octave")?;
+
+    Some((12 * (octave + 1) + semitone + alter + transpose).clamp(0, 127) as u8)
+}
+
+
+/// Reads a MusicXML score, titled after its work or movement title
+pub fn import(source: &str) -> Result<Tune, MusicXmlError> {
+    if source.starts_with("PK") {
+        return Err(MusicXmlError::Compressed);
+    };
+
+    let score = parse_xml(source)?;
+    match score.name.as_str() {
+        "score-partwise" => { },
+        "score-timewise" => return Err(MusicXmlError::Timewise),
+        _ => return Err(MusicXmlError::NotMusicXml),
+    };
+
+    let parts = score.children("part").collect::<Vec<_>>();
+
+    // every part counts ticks in its own divisions of a quarter note, which can change, so they're all
+    // brought to the smallest one they share
+    let quarter = parts.iter()
+        .flat_map(|part| part.children("measure"))
+        .flat_map(|measure| measure.children("attributes"))
+        .filter_map(|attributes| attributes.number_of::<u64>("divisions"))
+        .filter(|divisions| *divisions > 0)
+        .fold(1, |quarter, divisions| quarter / gcd(quarter, divisions) * divisions);
+
+    let mut measures: Vec<Measure> = Vec::new();
+    let mut part_notes: Vec<Vec<Vec<Note>>> = Vec::new();
+
+    for part in parts.iter() {
+        let mut divisions = 1;
+        let mut transpose = 0;
+        let mut notes = Vec::new();
+
+        for (index, measure) in part.children("measure").enumerate() {
+            if measures.len() <= index {
+                measures.push(Measure::default());
+            };
+            let info = &mut measures[index];
+
+            let mut measure_notes = Vec::new();
+            let mut cursor = 0u64;
+            let mut last_start = 0;
+            let mut length = 0;
+
+            for element in measure.children.iter() {
+                let duration = || element.number_of::<u64>("duration").unwrap_or(0) * (quarter / divisions);
+
+                match element.name.as_str() {
+                    "attributes" => {
+                        divisions = element.number_of("divisions").filter(|divisions| *divisions > 0).unwrap_or(divisions);
+
+                        if let Some(transposition) = element.child("transpose") {
+                            transpose = transposition.number_of::<i32>("chromatic").unwrap_or(0) + 12 * transposition.number_of::<i32>("octave-change").unwrap_or(0);
+                        };
+                    },
+                    "note" if element.child("grace").is_some() || element.child("cue").is_some() => { },
+                    "note" => {
+                        let start = if element.child("chord").is_some() { last_start } else { cursor };
+                        let end = start + duration();
+                        last_start = start;
+                        cursor = cursor.max(end);
+
+                        if let Some(key) = element.child("pitch").and_then(|pitch| key(pitch, transpose)) {
+                            let tied_to_previous = element.children("tie").any(|tie| tie.attribute("type") == Some("stop"));
+
+                            measure_notes.push(Note { start, end, key, tied_to_previous });
+                        };
+                    },
+                    "backup" => cursor = cursor.saturating_sub(duration()),
+                    "forward" => cursor += duration(),
+                    "direction" | "sound" => {
+                        let tempo = element.child("sound").unwrap_or(element).attribute("tempo").and_then(|tempo| tempo.parse::<f64>().ok());
+
+                        if let Some(tempo) = tempo.filter(|tempo| *tempo > 0.0) {
+                            info.tempo.get_or_insert(tempo);
+                        };
+                    },
+                    "barline" => {
+                        if let Some(repeat) = element.child("repeat") {
+                            match repeat.attribute("direction") {
+                                Some("forward") => info.starts_repeat = true,
+                                Some("backward") => info.ends_repeat = Some(repeat.attribute("times").and_then(|times| times.parse().ok()).unwrap_or(2)),
+                                _ => { },
+                            };
+                        };
+
+                        if let Some(ending) = element.child("ending") && ending.attribute("type") == Some("start") {
+                            info.starts_first_ending |= ending.attribute("number").is_some_and(|number| number.split([',', ' ']).next() == Some("1"));
+                        };
+                    },
+                    _ => { },
+                };
+
+                length = length.max(cursor);
+            };
+
+            info.length = info.length.max(length);
+            notes.push(measure_notes);
+        };
+
+        part_notes.push(notes);
+    };
+
+    // notes of every part in ticks from the start of the score
+    let starts = measures.iter()
+        .scan(0, |start, measure| {
+            let measure_start = *start;
+            *start += measure.length;
+
+            Some(measure_start)
+        })
+        .collect::<Vec<_>>();
+    let tick = |at: u64| u32::try_from(at).unwrap_or(u32::MAX);
+    let grid = tick(quarter * 4);
+
+    let mut notes: Vec<(u32, u32, u8)> = Vec::new();
+    for part in part_notes.iter() {
+        // the part's notes still waiting for the ones tied to them, by key
+        let mut tied: HashMap<u8, usize> = HashMap::new();
+
+        for (measure, measure_notes) in part.iter().enumerate() {
+            for note in measure_notes.iter() {
+                let (start, end) = (starts[measure] + note.start, starts[measure] + note.end);
+
+                let previous = tied.get(&note.key).copied()
+                    .filter(|previous: &usize| note.tied_to_previous && notes[*previous].1 == tick(start));
+                match previous {
+                    Some(previous) => notes[previous].1 = tick(end),
+                    None => {
+                        tied.insert(note.key, notes.len());
+                        notes.push((tick(start), tick(end), note.key));
+                    },
+                };
+            };
+        };
+    };
+
+    if notes.is_empty() {
+        return Err(MusicXmlError::NoNotes);
+    };
+
+    let tempo = measures.first().and_then(|measure| measure.tempo).unwrap_or(DEFAULT_TEMPO);
+    let mut current_tempo = tempo;
+    let mut sections = Sections::new();
+    let mut from = 0;
+
+    for (measure, info) in measures.iter().enumerate() {
+        let start = tick(starts[measure]);
+        let end = tick(starts[measure] + info.length);
+        let changes_tempo = info.tempo.is_some_and(|tempo| (tempo - current_tempo).abs() > 1e-9);
+
+        if info.starts_repeat || info.starts_first_ending || changes_tempo {
+            sections.current.push_notes(&notes, from, start, grid);
+            from = start;
+        };
+        if info.starts_repeat {
+            sections.start_repeat();
+        };
+        if info.starts_first_ending {
+            sections.start_first_ending();
+        };
+        if let Some(tempo) = info.tempo.filter(|_| changes_tempo) {
+            current_tempo = tempo;
+            sections.change_bpm(Tune::bpm_of(tempo));
+        };
+
+        if let Some(times) = info.ends_repeat {
+            sections.current.push_notes(&notes, from, end, grid);
+            from = end;
+            sections.end_repeat(times);
+        };
+    };
+    let end = tick(starts.last().copied().unwrap_or(0) + measures.last().map_or(0, |measure| measure.length));
+    sections.current.push_notes(&notes, from, end, grid);
+
+    let title = score.child("work").and_then(|work| work.text_of("work-title"))
+        .or_else(|| score.text_of("movement-title"))
+        .filter(|title| !title.is_empty())
+        .map(String::from);
+
+    Ok(Tune {
+        title,
+        bpm: Tune::bpm_of(tempo),
+        sections: sections.finish(),
+    })
+}
@@ -0,0 +1,114 @@
+//! RTTTL ringtone import.
+//!
+//! A ringtone is a name, its defaults and its notes, like `tune:d=4,o=5,b=120:8c,d6,p,2e.`. Octaves are
+//! numbered like roorle's, `a4` being 440 Hz, and the beats are quarter notes
+
+use std::fmt;
+use super::{Length, Section, Tune};
+
+
+#[derive(Debug)]
+pub enum RtttlError {
+    /// Missing the `:` between the name, the defaults and the notes
+    NotRtttl,
+    InvalidSetting(String),
+    InvalidNote(String),
+    NoNotes,
+}
+
+
+impl fmt::Display for RtttlError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotRtttl => write!(f, "not an RTTTL ringtone, expected `name:defaults:notes`"),
+            Self::InvalidSetting(setting) => write!(f, "can't read the default `{setting}`"),
+            Self::InvalidNote(note) => write!(f, "can't read the note `{note}`"),
+            Self::NoNotes => write!(f, "the ringtone has no notes"),
+        }
+    }
+}
+
+
+impl std::error::Error for RtttlError { }
+
+
+/// Reads a ringtone, titled after its name
+pub fn import(source: &str) -> Result<Tune, RtttlError> {
+    let mut parts = source.trim().splitn(3, ':');
+    let (Some(name), Some(defaults), Some(notes)) = (parts.next(), parts.next(), parts.next()) else {
+        return Err(RtttlError::NotRtttl);
+    };
+
+    // the defaults the format itself gives
+    let (mut duration, mut octave, mut beats) = (4, 6, 63);
+    for setting in defaults.split(',').map(str::trim).filter(|setting| !setting.is_empty()) {
+        let invalid = || RtttlError::InvalidSetting(String::from(setting));
+        let (key, value) = setting.split_once('=').ok_or_else(invalid)?;
+        let value = value.trim().parse::<u32>().ok().filter(|value| *value > 0).ok_or_else(invalid)?;
+
+        match key.trim().to_ascii_lowercase().as_str() {
+            "d" => duration = value,
+            "o" => octave = value,
+            "b" => beats = value,
+            // settings like the loop count don't change the notes
+            _ => { },
+        };
+    };
+
+    let mut section = Section::new("part1");
+    for note in notes.split(',').map(str::trim).filter(|note| !note.is_empty()) {
+        let invalid = || RtttlError::InvalidNote(String::from(note));
+        let lowercase = note.to_ascii_lowercase();
+        let mut rest = lowercase.as_str();
+
+        let digits = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+        let note_duration = match &rest[..digits] {
+            "" => duration,
+            digits => digits.parse().ok().filter(|duration| *duration > 0).ok_or_else(invalid)?,
+        };
+        rest = &rest[digits..];
+
+        let mut chars = rest.chars();
+        let semitone = match chars.next() {
+            Some('p') => None,
+            Some('c') => Some(0),
+            Some('d') => Some(2),
+            Some('e') => Some(4),
+            Some('f') => Some(5),
+            Some('g') => Some(7),
+            Some('a') => Some(9),
+            Some('b' | 'h') => Some(11),
+            _ => return Err(invalid()),
+        };
+        rest = chars.as_str();
+
+        let sharp = rest.starts_with('#');
+        rest = rest.trim_start_matches('#');
+
+        // the dot is written before the octave or after it
+        let dotted = rest.contains('.');
+        let rest = rest.replace('.', "");
+        let note_octave = match rest.as_str() {
+            "" => octave,
+            digits => digits.parse().map_err(|_| invalid())?,
+        };
+
+        let length = Length::new(1, note_duration) * if dotted { Length::new(3, 2) } else { Length::new(1, 1) };
+        let keys = match semitone {
+            Some(semitone) => vec![(12 * (note_octave + 1) + semitone + sharp as u32).min(127) as u8],
+            None => Vec::new(),
+        };
+
+        section.push(keys, length);
+    };
+
+    if section.events.iter().all(|event| event.keys.is_empty()) {
+        return Err(RtttlError::NoNotes);
+    };
+
+    Ok(Tune {
+        title: Some(name.trim()).filter(|name| !name.is_empty()).map(String::from),
+        bpm: Tune::bpm_of(beats as f64),
+        sections: vec![section],
+    })
+}
@@ -0,0 +1,279 @@
+//! Melody detection in WAV recordings, experimental.
+//!
+//! The recording is mixed to mono, brought down to about 11 kHz and cut into frames, each of which gets
+//! a pitch from the YIN algorithm or none if it's quiet or noisy. Runs of frames on the same key become
+//! notes and the tempo is whichever one puts their starts closest to a grid of 16th notes, so a hummed or
+//! whistled melody comes back as a script. Only one note is heard at a time
+
+use std::fmt;
+use crate::syntax::parser::Value;
+use super::{Section, Tune};
+
+
+/// Rate the recording is brought down to before looking for pitches, plenty for voices and whistles
+const ANALYSIS_RATE: u32 = 11_025;
+/// Seconds between the starts of frames
+const HOP: f64 = 0.01;
+/// Seconds of every frame compared with itself, which is also the longest period found
+const WINDOW: f64 = 0.04;
+const LOWEST_FREQUENCY: f64 = 60.0;
+const HIGHEST_FREQUENCY: f64 = 1500.0;
+/// How far below the loudest frame one can be, in parts of its RMS, before it's taken for silence
+const SILENCE: f32 = 0.1;
+/// How periodic a frame has to be to have a pitch, lower being stricter
+const YIN_THRESHOLD: f64 = 0.15;
+/// Notes shorter than this many seconds are taken for glitches and join the one before
+const SHORTEST_NOTE: f64 = 0.06;
+/// 16th notes, the grid notes are moved to
+const GRID: u32 = 16;
+
+
+#[derive(Debug)]
+pub enum WavError {
+    NotWav,
+    Truncated,
+    /// Encodings other than integer and float PCM, by their format tag
+    UnsupportedFormat(u16),
+    NoNotes,
+}
+
+
+impl fmt::Display for WavError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotWav => write!(f, "not a WAV file"),
+            Self::Truncated => write!(f, "the WAV file ends in the middle of a chunk"),
+            Self::UnsupportedFormat(format) => write!(f, "WAV files encoded with format {format} aren't supported, only PCM and float"),
+            Self::NoNotes => write!(f, "no melody was heard in the recording"),
+        }
+    }
+}
+
+
+impl std::error::Error for WavError { }
+
+
+/// Samples of a WAV file mixed down to one channel, and their rate
+pub fn read(bytes: &[u8]) -> Result<(Vec<f32>, u32), WavError> {
+    if bytes.len() < 12 || &bytes[..4] != b"RIFF" || &bytes[8..12] != b"WAVE" {
+        return Err(WavError::NotWav);
+    };
+
+    let u16_at = |at: usize| u16::from_le_bytes([bytes[at], bytes[at + 1]]);
+    let u32_at = |at: usize| u32::from_le_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]]);
+
+    let mut format = None;
+    let mut pos = 12;
+    while pos + 8 <= bytes.len() {
+        let id = &bytes[pos..pos + 4];
+        let size = u32_at(pos + 4) as usize;
+        let body = pos + 8;
+
+        match id {
+            b"fmt " if size >= 16 && body + size <= bytes.len() => {
+                let mut tag = u16_at(body);
+                // the extensible format keeps the real one in its sub-format
+                if tag == 0xFFFE && size >= 26 {
+                    tag = u16_at(body + 24);
+                };
+
+                format = Some((tag, u16_at(body + 2).max(1) as usize, u32_at(body + 4), u16_at(body + 14)));
+            },
+            b"data" => {
+                let (tag, channels, rate, bits) = format.ok_or(WavError::NotWav)?;
+                // some writers leave the size of streamed data at its maximum
+                let data = &bytes[body..(body + size).min(bytes.len())];
+
+                let sample = |frame: &[u8]| -> Option<f32> {
+                    Some(match (tag, bits) {
+                        (1, 8) => (frame[0] as f32 - 128.0) / 128.0,
+                        (1, 16) => i16::from_le_bytes([frame[0], frame[1]]) as f32 / 32_768.0,
+                        (1, 24) => i32::from_le_bytes([0, frame[0], frame[1], frame[2]]) as f32 / 2_147_483_648.0,
+                        (1, 32) => i32::from_le_bytes([frame[0], frame[1], frame[2], frame[3]]) as f32 / 2_147_483_648.0,
+                        (3, 32) => f32::from_le_bytes([frame[0], frame[1], frame[2], frame[3]]),
+                        (3, 64) => f64::from_le_bytes(frame[..8].try_into().unwrap()) as f32,
+                        _ => return None,
+                    })
+                };
+
+                let width = (bits as usize).div_ceil(8);
+                if sample(&[0; 8][..width.max(1)]).is_none() || rate == 0 {
+                    return Err(WavError::UnsupportedFormat(tag));
+                };
+
+                let samples = data.chunks_exact(width * channels)
+                    .map(|frame| frame.chunks_exact(width).filter_map(sample).sum::<f32>() / channels as f32)
+                    .collect();
+
+                return Ok((samples, rate));
+            },
+            _ => { },
+        };
+
+        // chunks are padded to an even size
+        pos = body + size + size % 2;
+    };
+
+    Err(WavError::Truncated)
+}
+
+
+/// Averages every `factor` samples, which keeps what's below the new rate well enough for finding pitches
+fn downsample(samples: &[f32], factor: usize) -> Vec<f32> {
+    samples.chunks(factor)
+        .map(|chunk| chunk.iter().sum::<f32>() / chunk.len() as f32)
+        .collect()
+}
+
+
+/// Frequency of a frame by the YIN algorithm, if it has one
+fn yin(frame: &[f32], window: usize, shortest: usize, longest: usize) -> Option<f64> {
+    let difference = (0..=longest)
+        .map(|lag| (0..window).map(|i| (frame[i] - frame[i + lag]) as f64).map(|delta| delta * delta).sum::<f64>())
+        .collect::<Vec<_>>();
+
+    // the difference divided by its mean up to the lag, so short lags aren't favoured
+    let mut total = 0.0;
+    let normalized = difference.iter().enumerate()
+        .map(|(lag, difference)| {
+            total += difference;
+
+            if lag == 0 || total == 0.0 { 1.0 } else { difference * lag as f64 / total }
+        })
+        .collect::<Vec<_>>();
+
+    let mut lag = (shortest..longest).find(|lag| normalized[*lag] < YIN_THRESHOLD)?;
+    while lag + 1 < longest && normalized[lag + 1] < normalized[lag] {
+        lag += 1;
+    };
+
+    // a parabola through the minimum and its neighbours finds where between samples it really is
+    let (before, at, after) = (normalized[lag - 1], normalized[lag], normalized[lag + 1]);
+    let curve = before + after - 2.0 * at;
+    let shift = if curve.abs() > f64::EPSILON { (before - after) / (2.0 * curve) } else { 0.0 };
+
+    Some(1.0 / (lag as f64 + shift.clamp(-1.0, 1.0)))
+}
+
+
+/// The key heard in every frame, `HOP` seconds apart
+fn detect_keys(samples: &[f32], rate: u32) -> Vec<Option<u8>> {
+    let factor = (rate / ANALYSIS_RATE).max(1) as usize;
+    let samples = downsample(samples, factor);
+    let rate = rate as f64 / factor as f64;
+
+    let hop = (HOP * rate).round().max(1.0) as usize;
+    let window = (WINDOW * rate).round() as usize;
+    let shortest = (rate / HIGHEST_FREQUENCY).floor().max(2.0) as usize;
+    let longest = (rate / LOWEST_FREQUENCY).ceil().min(window as f64) as usize;
+
+    let frames = (0..samples.len().saturating_sub(window + longest + 1)).step_by(hop)
+        .map(|start| &samples[start..start + window + longest + 1])
+        .collect::<Vec<_>>();
+
+    let rms = |frame: &[f32]| (frame[..window].iter().map(|sample| sample * sample).sum::<f32>() / window as f32).sqrt();
+    let loudest = frames.iter().map(|frame| rms(frame)).fold(0.0, f32::max);
+
+    frames.iter()
+        .map(|frame| {
+            if rms(frame) < loudest * SILENCE {
+                return None;
+            };
+
+            let frequency = yin(frame, window, shortest, longest)? * rate;
+
+            Some((69.0 + 12.0 * (frequency / 440.0).log2()).round().clamp(0.0, 127.0) as u8)
+        })
+        .collect()
+}
+
+
+/// The whole `bpm` that puts the most note starts on 16th notes, preferring ones nearer to 110 since half
+/// and double the tempo fit about as well
+fn estimate_bpm(starts: &[f64]) -> u32 {
+    let error = |bpm: u32| {
+        let sixteenth = 15.0 / bpm as f64;
+
+        starts.iter()
+            .map(|start| (start - starts[0]) / sixteenth)
+            .map(|at| (at - at.round()).abs())
+            .sum::<f64>() / starts.len().max(1) as f64
+    };
+
+    let bpms = (50..=200).map(|bpm| (bpm, error(bpm))).collect::<Vec<_>>();
+    let best = bpms.iter().map(|(_, error)| *error).fold(f64::INFINITY, f64::min);
+
+    bpms.iter()
+        .filter(|(_, error)| *error <= best + 0.02)
+        .min_by(|(a, _), (b, _)| (*a as f64 - 110.0).abs().total_cmp(&(*b as f64 - 110.0).abs()))
+        .map_or(120, |(bpm, _)| *bpm)
+}
+
+
+/// Listens for a melody in a WAV file
+pub fn import(bytes: &[u8]) -> Result<Tune, WavError> {
+    let (samples, rate) = read(bytes)?;
+    let mut keys = detect_keys(&samples, rate);
+
+    // a median of five frames smooths over the odd octave jump
+    keys = (0..keys.len())
+        .map(|i| {
+            let mut around = keys[i.saturating_sub(2)..(i + 3).min(keys.len())].to_vec();
+            around.sort_unstable();
+
+            around[around.len() / 2]
+        })
+        .collect();
+
+    // runs of frames with the same key, as the frame they start at and their key
+    let mut runs: Vec<(usize, usize, Option<u8>)> = Vec::new();
+    for (frame, key) in keys.iter().enumerate() {
+        match runs.last_mut() {
+            Some((_, end, last)) if last == key => *end = frame + 1,
+            _ => runs.push((frame, frame + 1, *key)),
+        };
+    };
+
+    let shortest = (SHORTEST_NOTE / HOP).round() as usize;
+    let mut notes: Vec<(usize, usize, Option<u8>)> = Vec::new();
+    for run in runs {
+        match notes.last_mut() {
+            Some(last) if run.1 - run.0 < shortest || last.2 == run.2 => last.1 = run.1,
+            _ => notes.push(run),
+        };
+    };
+    let notes = notes.into_iter()
+        .filter_map(|(start, end, key)| Some((start as f64 * HOP, end as f64 * HOP, key?)))
+        .collect::<Vec<_>>();
+
+    if notes.is_empty() {
+        return Err(WavError::NoNotes);
+    };
+
+    let bpm = estimate_bpm(&notes.iter().map(|(start, _, _)| *start).collect::<Vec<_>>());
+    let sixteenth = 15.0 / bpm as f64;
+    let origin = notes[0].0;
+    let position = |seconds: f64| ((seconds - origin) / sixteenth).round().max(0.0) as u32;
+
+    let mut grid_notes = notes.iter()
+        .map(|(start, end, key)| (position(*start), position(*end), *key))
+        .collect::<Vec<_>>();
+    // a note lasts until the next one starts at the latest, and at least a 16th
+    for i in 0..grid_notes.len() {
+        let next = grid_notes.get(i + 1).map_or(u32::MAX, |next| next.0);
+        let (start, end, _) = &mut grid_notes[i];
+
+        *end = (*end).max(*start + 1).min(next);
+    };
+    grid_notes.retain(|(start, end, _)| end > start);
+
+    let end = grid_notes.iter().map(|(_, end, _)| *end).max().unwrap_or(0);
+    let mut section = Section::new("part1");
+    section.push_notes(&grid_notes, 0, end, GRID);
+
+    Ok(Tune {
+        title: None,
+        bpm: Value::Whole(bpm),
+        sections: vec![section],
+    })
+}
@@ -0,0 +1,245 @@
+use std::fmt::Write;
+use roorle::compiler::{self, InstructionData, Program};
+use roorle::json;
+use roorle::pitch::Pitch;
+
+
+const HISTOGRAM_WIDTH: usize = 40;
+const LARGEST_DENOMINATOR: u32 = 128;
+
+
+/// Counts by name, in the order they should be shown
+type Histogram = Vec<(String, usize)>;
+
+
+/// Name of the closest note, the way a script would write it, with the octave appended
+pub fn note_name(frequency: f64) -> String {
+    Pitch::from_frequency(frequency).to_string()
+}
+
+
+pub fn seconds(time: f64) -> String {
+    format!("{}:{:05.2}", (time / 60.0).floor(), time % 60.0)
+}
+
+
+struct Region {
+    instrument: usize,
+    start: f64,
+    end: f64,
+    children: Vec<Region>,
+}
+
+
+impl Region {
+    fn same_shape(&self, other: &Region) -> bool {
+        self.instrument == other.instrument
+            && (self.end - self.start - (other.end - other.start)).abs() < 1e-9
+            && self.children.len() == other.children.len()
+            && self.children.iter().zip(other.children.iter()).all(|(a, b)| a.same_shape(b))
+    }
+}
+
+
+fn label_tree(program: &Program) -> Vec<Region> {
+    let mut roots = Vec::new();
+    let mut open: Vec<Region> = Vec::new();
+
+    let mut time = 0.0;
+    for instruction in program.get_instructions() {
+        match instruction.data {
+            InstructionData::EnterLabel { instrument } => open.push(Region { instrument, start: time, end: time, children: Vec::new() }),
+            InstructionData::ExitLabel { .. } => if let Some(mut region) = open.pop() {
+                region.end = time;
+
+                match open.last_mut() {
+                    Some(parent) => parent.children.push(region),
+                    None => roots.push(region),
+                };
+            },
+            InstructionData::Advance { duration } => time += duration,
+            InstructionData::Play { .. } | InstructionData::Mark | InstructionData::Tempo { .. } | InstructionData::Meter { .. }
+            | InstructionData::Automate { .. } => { },
+        };
+    };
+
+    roots
+}
+
+
+fn write_regions(report: &mut String, program: &Program, regions: &[Region], depth: usize) {
+    let mut i = 0;
+    while i < regions.len() {
+        let region = &regions[i];
+        let repeats = regions[i..].iter().take_while(|other| other.same_shape(region)).count();
+
+        let name = program.get_instruments()[region.instrument].name.as_deref().unwrap_or("?");
+        let times = if repeats > 1 { format!(" x{repeats}") } else { String::new() };
+        let _ = writeln!(report, "  {}@{name}{times}  {} - {}", "  ".repeat(depth), seconds(region.start), seconds(regions[i + repeats - 1].end));
+
+        write_regions(report, program, &region.children, depth + 1);
+
+        i += repeats;
+    };
+}
+
+
+/// What a compiled script amounts to, without rendering it
+pub fn report(program: &Program) -> String {
+    let mut report = String::new();
+
+    let duration = program.get_duration();
+    let notes = program.get_note_events();
+
+    let metadata = program.get_metadata();
+    if let Some(title) = metadata.title.as_ref() {
+        let _ = writeln!(report, "title:    {title}");
+    };
+
+    let _ = writeln!(report, "duration: {} ({duration:.3}s)", seconds(duration));
+    let _ = writeln!(report, "bars:     {:.2}", duration / program.get_bar_duration());
+    let _ = writeln!(report, "notes:    {}", notes.len());
+
+    let lowest = notes.iter().map(|note| note.frequency).min_by(f64::total_cmp);
+    let highest = notes.iter().map(|note| note.frequency).max_by(f64::total_cmp);
+    if let (Some(lowest), Some(highest)) = (lowest, highest) {
+        let _ = writeln!(report, "range:    {} ({lowest:.2} Hz) - {} ({highest:.2} Hz)", note_name(lowest), note_name(highest));
+    };
+
+    let mut used = notes.iter().map(|note| note.frequency).collect::<Vec<_>>();
+    used.sort_by(f64::total_cmp);
+    used.dedup_by(|a, b| note_name(*a) == note_name(*b));
+    let _ = writeln!(report, "used:     {}", used.iter().map(|frequency| note_name(*frequency)).collect::<Vec<_>>().join(" "));
+
+    let _ = writeln!(report, "\ntempo:");
+    for change in program.get_tempo_map() {
+        let _ = writeln!(report, "  {}  {} bpm", seconds(change.time), change.bpm);
+    };
+
+    let _ = writeln!(report, "\nlabels:");
+    write_regions(&mut report, program, &label_tree(program), 0);
+
+    report
+}
+
+
+/// Writes a length in bars the way a script would, e.g. `3/8`
+pub fn fraction(bars: f64) -> String {
+    let denominator = (1..=LARGEST_DENOMINATOR)
+        .find(|denominator| (bars * *denominator as f64 - (bars * *denominator as f64).round()).abs() < 1e-6)
+        .unwrap_or(LARGEST_DENOMINATOR);
+    let numerator = (bars * denominator as f64).round() as u32;
+
+    if denominator == 1 { format!("{numerator}") } else { format!("{numerator}/{denominator}") }
+}
+
+
+/// How many times every pitch and every note length is played, lowest and shortest first
+pub fn histograms(program: &Program) -> (Histogram, Histogram) {
+    let notes = program.get_note_events();
+
+    let mut pitches: Vec<(i32, String, usize)> = Vec::new();
+    let mut durations: Vec<(f64, String, usize)> = Vec::new();
+    for note in notes.iter() {
+        let key = Pitch::from_frequency(note.frequency).key;
+        match pitches.iter_mut().find(|(other, ..)| *other == key) {
+            Some((_, _, count)) => *count += 1,
+            None => pitches.push((key, note_name(note.frequency), 1)),
+        };
+
+        let bars = compiler::note_fraction(program.get_tempos()[note.instrument], note.duration);
+        let name = fraction(bars);
+        match durations.iter_mut().find(|(_, other, _)| *other == name) {
+            Some((_, _, count)) => *count += 1,
+            None => durations.push((bars, name, 1)),
+        };
+    };
+
+    pitches.sort_by_key(|(key, ..)| *key);
+    durations.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    (
+        pitches.into_iter().map(|(_, name, count)| (name, count)).collect(),
+        durations.into_iter().map(|(_, name, count)| (name, count)).collect(),
+    )
+}
+
+
+fn write_histogram(report: &mut String, title: &str, rows: &[(String, usize)]) {
+    let _ = writeln!(report, "{title}:");
+
+    let most = rows.iter().map(|(_, count)| *count).max().unwrap_or(1);
+    let width = rows.iter().map(|(name, _)| name.len()).max().unwrap_or(0);
+    for (name, count) in rows {
+        let bar = "#".repeat((count * HISTOGRAM_WIDTH).div_ceil(most));
+        let _ = writeln!(report, "  {name:<width$}  {bar} {count}");
+    };
+}
+
+
+pub fn statistics(program: &Program) -> String {
+    let (pitches, durations) = histograms(program);
+
+    let mut report = String::new();
+    write_histogram(&mut report, "pitches", &pitches);
+    let _ = writeln!(report);
+    write_histogram(&mut report, "durations", &durations);
+
+    report
+}
+
+
+pub fn statistics_json(program: &Program) -> String {
+    let (pitches, durations) = histograms(program);
+
+    let rows = |rows: Histogram, key: &str| json::array(rows.into_iter()
+        .map(|(name, count)| format!("{{{}:{},\"count\":{count}}}", json::string(key), json::string(&name))));
+
+    format!("{{\"pitches\":{},\"durations\":{}}}", rows(pitches, "note"), rows(durations, "duration"))
+}
+
+
+/// Tempo changes and bar starts by time, each with the bar it's in counting from 1
+fn tempo_events(program: &Program) -> Vec<(f64, &'static str, usize, f64)> {
+    let bars = program.get_bars();
+    let changes = program.get_tempo_map();
+    let bar = |time: f64| bars.iter().filter(|start| **start <= time + 1e-9).count();
+    let bpm = |time: f64| changes.iter().rev().find(|change| change.time <= time + 1e-9).map_or(0.0, |change| change.bpm);
+
+    let mut events = bars.iter()
+        .map(|start| (*start, "bar", bar(*start), bpm(*start)))
+        .chain(changes.iter().map(|change| (change.time, "tempo", bar(change.time), change.bpm)))
+        .collect::<Vec<_>>();
+    events.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    events
+}
+
+
+/// The tempo map as CSV, a row for every tempo change and every bar
+pub fn tempo_map(program: &Program) -> String {
+    let mut csv = String::from("time,event,bar,bpm\n");
+
+    for (time, event, bar, bpm) in tempo_events(program) {
+        let _ = writeln!(csv, "{time:.6},{event},{bar},{bpm}");
+    };
+
+    csv
+}
+
+
+/// Every mark as a chapter, for players that can jump between them
+pub fn chapters_json(program: &Program) -> String {
+    let chapters = program.get_chapters().into_iter()
+        .map(|chapter| format!("{{\"time\":{},\"name\":{}}}", chapter.time, json::string(&chapter.name)));
+
+    format!("{{\"chapters\":{}}}", json::array(chapters))
+}
+
+
+pub fn tempo_map_json(program: &Program) -> String {
+    let changes = program.get_tempo_map().into_iter()
+        .map(|change| format!("{{\"time\":{},\"bpm\":{}}}", change.time, change.bpm));
+
+    format!("{{\"tempo\":{},\"bars\":{}}}", json::array(changes), json::array(program.get_bars().into_iter().map(|time| time.to_string())))
+}
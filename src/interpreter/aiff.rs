@@ -0,0 +1,154 @@
+use std::io::{self, Seek, SeekFrom, Write};
+use crate::compiler::{Metadata, Program};
+use crate::interpreter::{Backend, RenderOptions, SampleSize, TooLong};
+use crate::interpreter::render::{self, SampleIterator};
+
+
+const CHUNK_SAMPLES: usize = 4096;
+const HEADER_SIZE: u64 = 54;
+
+
+fn extended_float(value: u32) -> [u8; 10] {
+    let mut bytes = [0; 10];
+
+    if value == 0 {
+        return bytes;
+    };
+
+    let exponent = 31 - value.leading_zeros();
+    let mantissa = (value as u64) << (63 - exponent);
+
+    bytes[..2].copy_from_slice(&(16383 + exponent as u16).to_be_bytes());
+    bytes[2..].copy_from_slice(&mantissa.to_be_bytes());
+
+    bytes
+}
+
+
+fn write_header<W: Write>(writer: &mut W, options: &RenderOptions, frames: u32, trailing_size: u32) -> io::Result<()> {
+    let data_size = frames * options.sample_size as u32 / 8 * options.channels as u32;
+
+    let mut buffer = Vec::new();
+
+    buffer.append(&mut b"FORM".to_vec());
+    buffer.append(&mut (4 + 26 + 16 + data_size + data_size % 2 + trailing_size).to_be_bytes().to_vec());
+    buffer.append(&mut b"AIFF".to_vec());
+    buffer.append(&mut b"COMM".to_vec());
+    buffer.append(&mut 18_u32.to_be_bytes().to_vec());
+    buffer.append(&mut options.channels.to_be_bytes().to_vec());
+    buffer.append(&mut frames.to_be_bytes().to_vec());
+    buffer.append(&mut (options.sample_size as u16).to_be_bytes().to_vec());
+    buffer.append(&mut extended_float(options.sample_rate).to_vec());
+    buffer.append(&mut b"SSND".to_vec());
+    buffer.append(&mut (8 + data_size).to_be_bytes().to_vec());
+    buffer.append(&mut 0_u32.to_be_bytes().to_vec());
+    buffer.append(&mut 0_u32.to_be_bytes().to_vec());
+
+    writer.write_all(&buffer)
+}
+
+
+fn text_chunks(metadata: &Metadata) -> Vec<u8> {
+    // there's only the one author chunk, a composer besides the artist is noted in an annotation
+    let composer = metadata.composer.as_ref().filter(|_| metadata.artist.is_some()).map(|composer| format!("composer: {composer}"));
+    let fields = [
+        (b"NAME", metadata.title.as_deref()),
+        (b"AUTH", metadata.artist.as_deref().or(metadata.composer.as_deref())),
+        (b"ANNO", composer.as_deref()),
+        (b"ANNO", metadata.comment.as_deref()),
+    ];
+
+    let mut buffer = Vec::new();
+    for (id, text) in fields {
+        let Some(text) = text else {
+            continue;
+        };
+
+        buffer.append(&mut id.to_vec());
+        buffer.append(&mut (text.len() as u32).to_be_bytes().to_vec());
+        buffer.append(&mut text.as_bytes().to_vec());
+        if text.len() % 2 == 1 {
+            buffer.push(0);
+        };
+    };
+
+    buffer
+}
+
+
+fn quantize(value: f64, sample_size: SampleSize) -> Vec<u8> {
+    let value = value.clamp(-1.0, 1.0);
+
+    match sample_size {
+        SampleSize::Small => ((i8::MAX as f64 * value).round() as i8).to_be_bytes().to_vec(),
+        SampleSize::Large => ((i16::MAX as f64 * value).round() as i16).to_be_bytes().to_vec(),
+    }
+}
+
+
+pub fn interpret_to_writer<W: Write + Seek>(program: &Program, options: &RenderOptions, writer: &mut W) -> io::Result<()> {
+    let start = writer.stream_position()?;
+
+    let (window_start, window_end) = render::sample_window(program, options);
+    let expected_size = (window_end - window_start) as u64 * (options.sample_size as u64 / 8) * options.channels as u64;
+    if HEADER_SIZE - 8 + expected_size + 1 > u32::MAX as u64 {
+        return Err(io::Error::new(io::ErrorKind::FileTooLarge, format!("render needs {expected_size} bytes of audio, which doesn't fit into an AIFF file (at most 4 GiB)")));
+    };
+
+    let values = SampleIterator::new(program, options)?;
+
+    write_header(writer, options, 0, 0)?;
+
+    let mut samples = 0_u32;
+    let mut chunk = Vec::with_capacity(CHUNK_SAMPLES * options.sample_size as usize / 8);
+    for value in values {
+        chunk.append(&mut quantize(value as f64, options.sample_size));
+        samples += 1;
+
+        if chunk.len() >= chunk.capacity() {
+            writer.write_all(&chunk)?;
+            chunk.clear();
+        };
+    };
+
+    writer.write_all(&chunk)?;
+
+    if samples * options.sample_size as u32 / 8 % 2 == 1 {
+        writer.write_all(&[0])?;
+    };
+
+    let trailing_chunks = text_chunks(program.get_metadata());
+    writer.write_all(&trailing_chunks)?;
+
+    let end = writer.stream_position()?;
+
+    writer.seek(SeekFrom::Start(start))?;
+    write_header(writer, options, samples / options.channels as u32, trailing_chunks.len() as u32)?;
+    writer.seek(SeekFrom::Start(end))?;
+
+    Ok(())
+}
+
+
+pub struct Aiff<W>(pub W);
+
+
+impl<W: Write + Seek> Backend for Aiff<W> {
+    type Output = ();
+    type Error = io::Error;
+
+    fn render(&mut self, program: &Program, options: &RenderOptions) -> io::Result<()> {
+        interpret_to_writer(program, options, &mut self.0)
+    }
+}
+
+
+pub fn interpret(program: &Program, options: &RenderOptions) -> Result<Vec<u8>, TooLong> {
+    options.check_length(program)?;
+
+    let mut buffer = io::Cursor::new(Vec::new());
+
+    interpret_to_writer(program, options, &mut buffer).expect("writing into memory doesn't fail");
+
+    Ok(buffer.into_inner())
+}
@@ -0,0 +1,94 @@
+//! Parameters moved over time by `automate`, as curves through the points the program sets them to.
+//! A parameter follows its curve from its first point on, and stays at the last one after it
+
+use std::collections::HashMap;
+use crate::compiler::{InstructionData, Parameter, Program};
+
+
+/// Samples between the times voices are given the automated parameters, short enough for ramps not
+/// to be heard moving in steps
+pub const CONTROL_INTERVAL: usize = 64;
+
+
+#[derive(Copy, Clone, Debug)]
+struct Point {
+    sample: usize,
+    value: f64,
+    /// Whether it's ramped to from the point before, rather than jumped to
+    ramp: bool,
+}
+
+
+/// The curve of one parameter of one instrument
+#[derive(Clone, Debug, Default)]
+pub struct Lane {
+    points: Vec<Point>,
+}
+
+
+impl Lane {
+    /// Where the curve is at `sample`, if it has started by then
+    pub fn value(&self, sample: usize) -> Option<f64> {
+        let next = self.points.partition_point(|point| point.sample <= sample);
+        let last = self.points[..next].last()?;
+
+        match self.points.get(next) {
+            Some(point) if point.ramp => {
+                let progress = (sample - last.sample) as f64 / (point.sample - last.sample) as f64;
+
+                Some(last.value + (point.value - last.value) * progress)
+            },
+            _ => Some(last.value),
+        }
+    }
+}
+
+
+/// Every curve of a program, by instrument and parameter
+#[derive(Clone, Debug, Default)]
+pub struct Automation {
+    lanes: HashMap<(usize, Parameter), Lane>,
+}
+
+
+impl Automation {
+    pub fn new(program: &Program, sample_rate: u32) -> Self {
+        let mut lanes: HashMap<_, Lane> = HashMap::new();
+
+        let mut time = 0.0;
+        for instruction in program.get_instructions() {
+            match instruction.data {
+                InstructionData::Automate { instrument, parameter, value, delay, ramp } => {
+                    let sample = ((time + delay) * sample_rate as f64).round() as usize;
+                    let lane = lanes.entry((instrument, parameter)).or_default();
+
+                    // a point written later replaces the ones at or after it, the curve going as far as
+                    // it got before it
+                    let kept = lane.points.partition_point(|point| point.sample < sample);
+                    if kept < lane.points.len() && let Some(reached) = lane.value(sample) {
+                        lane.points.truncate(kept);
+                        lane.points.push(Point { sample, value: reached, ramp: true });
+                    };
+                    lane.points.push(Point { sample, value, ramp });
+                },
+                InstructionData::Advance { duration } => time += duration,
+                _ => { },
+            };
+        };
+
+        Self { lanes }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lanes.is_empty()
+    }
+
+    pub fn lane(&self, instrument: usize, parameter: Parameter) -> Option<&Lane> {
+        self.lanes.get(&(instrument, parameter))
+    }
+
+    /// Where `parameter` of `instrument` is at `sample`, if it's automated by then
+    pub fn value(&self, instrument: usize, parameter: Parameter, sample: usize) -> Option<f64> {
+        self.lane(instrument, parameter)?.value(sample)
+    }
+}
@@ -0,0 +1,38 @@
+//! Notes as CSV, a row for every note with when it's played, its pitch and where it was written, for
+//! looking at a piece in a spreadsheet or with pandas
+
+use std::fmt::Write;
+use crate::compiler::Program;
+use crate::compiler::lint::line_and_column;
+use crate::import::key_name;
+use crate::interpreter::midi::frequency_to_note;
+
+
+/// Quotes `field` if it has anything that would break the row
+fn field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        String::from(field)
+    }
+}
+
+
+/// The notes of `program` in the order they start. The line of every note comes from `source`, the
+/// script the program was compiled from, and is left empty without it
+pub fn interpret(program: &Program, source: Option<&str>) -> String {
+    let mut csv = String::from("start,duration,frequency,note,line,label\n");
+
+    let mut events = program.get_note_events();
+    events.sort_by(|a, b| a.start.total_cmp(&b.start));
+
+    for event in events {
+        let (name, octave) = key_name(frequency_to_note(event.frequency));
+        let line = source.map(|source| line_and_column(source, event.span.start).0.to_string()).unwrap_or_default();
+        let label = program.get_instruments()[event.instrument].name.as_deref().unwrap_or("");
+
+        let _ = writeln!(csv, "{:.6},{:.6},{:.3},{name}{octave},{line},{}", event.start, event.duration, event.frequency, field(label));
+    };
+
+    csv
+}
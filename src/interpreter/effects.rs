@@ -0,0 +1,283 @@
+use std::collections::HashMap;
+use std::sync::{LazyLock, RwLock};
+use crate::compiler::{Bitcrusher, Distortion, DistortionKind, Filter, FilterKind, Instrument, RingModulator};
+use crate::interpreter::{math, voice, RenderOptions};
+
+
+const DEFAULT_CHAIN: [&str; 3] = ["distortion", "ringmod", "bitcrusher"];
+
+static REGISTRY: LazyLock<RwLock<HashMap<String, Box<EffectFactory>>>> = LazyLock::new(|| RwLock::new(HashMap::new()));
+
+
+pub type EffectFactory = dyn Fn(&Instrument, &RenderOptions) -> Box<dyn Effect> + Send + Sync;
+
+
+pub trait Effect {
+    fn process(&mut self, value: f64) -> f64;
+
+    fn process_block(&mut self, block: &mut [f64]) {
+        block.iter_mut().for_each(|value| *value = self.process(*value));
+    }
+}
+
+
+pub fn register<F>(name: &str, factory: F)
+    where F: Fn(&Instrument, &RenderOptions) -> Box<dyn Effect> + Send + Sync + 'static
+{
+    REGISTRY.write().expect("effect registry isn't poisoned").insert(String::from(name), Box::new(factory));
+}
+
+
+/// Whether `name` in `effects:` is an effect, rather than a name left out of the chain
+pub fn exists(name: &str) -> bool {
+    DEFAULT_CHAIN.contains(&name) || REGISTRY.read().expect("effect registry isn't poisoned").contains_key(name)
+}
+
+
+pub fn chain(instrument: &Instrument, options: &RenderOptions) -> Vec<Box<dyn Effect>> {
+    let names = match instrument.effects.as_ref() {
+        None => DEFAULT_CHAIN.iter().map(|name| String::from(*name)).collect(),
+        Some(names) => names.clone(),
+    };
+
+    let mut effects: Vec<Box<dyn Effect>> = Vec::new();
+    for name in names {
+        match name.as_str() {
+            "distortion" => if let Some(distortion) = instrument.distortion.as_ref() {
+                effects.push(Box::new(Waveshaper::new(distortion, options)));
+            },
+            "ringmod" => if let Some(ringmod) = instrument.ringmod.as_ref() {
+                effects.push(Box::new(RingMod::new(ringmod, options)));
+            },
+            "bitcrusher" => if let Some(bitcrusher) = instrument.bitcrusher.as_ref() {
+                effects.push(Box::new(Crusher::new(bitcrusher, options.sample_rate)));
+            },
+            name => if let Some(factory) = REGISTRY.read().expect("effect registry isn't poisoned").get(name) {
+                effects.push(factory(instrument, options));
+            },
+        };
+    };
+
+    effects
+}
+
+
+#[derive(Clone, Debug)]
+pub struct Biquad {
+    b0: f64,
+    b1: f64,
+    b2: f64,
+    a1: f64,
+    a2: f64,
+    x1: f64,
+    x2: f64,
+    y1: f64,
+    y2: f64,
+    deterministic: bool,
+}
+
+
+impl Biquad {
+    pub fn new(filter: &Filter, options: &RenderOptions) -> Self {
+        let mut biquad = Self {
+            b0: 0.0,
+            b1: 0.0,
+            b2: 0.0,
+            a1: 0.0,
+            a2: 0.0,
+            x1: 0.0,
+            x2: 0.0,
+            y1: 0.0,
+            y2: 0.0,
+            deterministic: options.deterministic,
+        };
+
+        biquad.retune(filter, options.sample_rate);
+
+        biquad
+    }
+
+    pub fn from_coefficients(b: [f64; 3], a: [f64; 2]) -> Self {
+        Self {
+            b0: b[0],
+            b1: b[1],
+            b2: b[2],
+            a1: a[0],
+            a2: a[1],
+            x1: 0.0,
+            x2: 0.0,
+            y1: 0.0,
+            y2: 0.0,
+            deterministic: false,
+        }
+    }
+
+    pub fn retune(&mut self, filter: &Filter, sample_rate: u32) {
+        let nyquist = sample_rate as f64 / 2.0;
+        let cutoff = filter.cutoff.min(nyquist * 0.99);
+
+        let omega = 2.0 * std::f64::consts::PI * cutoff / sample_rate as f64;
+        let (sin, cos) = if self.deterministic {
+            let turns = cutoff / sample_rate as f64;
+
+            (math::sin(turns), math::sin(turns + 0.25))
+        } else {
+            omega.sin_cos()
+        };
+        let alpha = sin / (2.0 * filter.resonance);
+
+        let (b0, b1, b2) = match filter.kind {
+            FilterKind::LowPass => ((1.0 - cos) / 2.0, 1.0 - cos, (1.0 - cos) / 2.0),
+            FilterKind::HighPass => ((1.0 + cos) / 2.0, -(1.0 + cos), (1.0 + cos) / 2.0),
+        };
+
+        let a0 = 1.0 + alpha;
+
+        self.b0 = b0 / a0;
+        self.b1 = b1 / a0;
+        self.b2 = b2 / a0;
+        self.a1 = -2.0 * cos / a0;
+        self.a2 = (1.0 - alpha) / a0;
+    }
+
+    pub fn process(&mut self, x: f64) -> f64 {
+        let y = self.b0 * x + self.b1 * self.x1 + self.b2 * self.x2 - self.a1 * self.y1 - self.a2 * self.y2;
+
+        self.x2 = self.x1;
+        self.x1 = x;
+        self.y2 = self.y1;
+        self.y1 = y;
+
+        y
+    }
+}
+
+
+impl Effect for Biquad {
+    fn process(&mut self, value: f64) -> f64 {
+        Biquad::process(self, value)
+    }
+}
+
+
+#[derive(Clone, Debug)]
+pub struct Waveshaper {
+    kind: DistortionKind,
+    drive: f64,
+    tone: Option<f64>,
+    last: f64,
+    deterministic: bool,
+}
+
+
+impl Waveshaper {
+    pub fn new(distortion: &Distortion, options: &RenderOptions) -> Self {
+        let exponent = |tone: f64| -2.0 * std::f64::consts::PI * tone / options.sample_rate as f64;
+
+        Self {
+            kind: distortion.kind,
+            drive: distortion.drive,
+            tone: distortion.tone.map(|tone| if options.deterministic { math::exp(exponent(tone)) } else { exponent(tone).exp() }),
+            last: 0.0,
+            deterministic: options.deterministic,
+        }
+    }
+
+    pub fn process(&mut self, x: f64) -> f64 {
+        let shaped = match self.kind {
+            DistortionKind::Overdrive if self.deterministic => math::tanh(x * self.drive) / math::tanh(self.drive),
+            DistortionKind::Overdrive => (x * self.drive).tanh() / self.drive.tanh(),
+            DistortionKind::Hard => (x * self.drive).clamp(-1.0, 1.0),
+        };
+
+        match self.tone {
+            None => shaped,
+            Some(pole) => {
+                self.last = (1.0 - pole) * shaped + pole * self.last;
+
+                self.last
+            },
+        }
+    }
+}
+
+
+impl Effect for Waveshaper {
+    fn process(&mut self, value: f64) -> f64 {
+        Waveshaper::process(self, value)
+    }
+}
+
+
+#[derive(Clone, Debug)]
+pub struct RingMod {
+    step: f64,
+    mix: f64,
+    phase: f64,
+    sine_table: bool,
+}
+
+
+impl RingMod {
+    pub fn new(ringmod: &RingModulator, options: &RenderOptions) -> Self {
+        Self {
+            step: ringmod.carrier / options.sample_rate as f64,
+            mix: ringmod.mix,
+            phase: 0.0,
+            sine_table: options.uses_sine_table(),
+        }
+    }
+
+    pub fn process(&mut self, x: f64) -> f64 {
+        let modulated = x * voice::sine(self.phase, self.sine_table);
+        self.phase = (self.phase + self.step).fract();
+
+        x + (modulated - x) * self.mix
+    }
+}
+
+
+impl Effect for RingMod {
+    fn process(&mut self, value: f64) -> f64 {
+        RingMod::process(self, value)
+    }
+}
+
+
+#[derive(Clone, Debug)]
+pub struct Crusher {
+    levels: f64,
+    step: f64,
+    phase: f64,
+    held: f64,
+}
+
+
+impl Crusher {
+    pub fn new(bitcrusher: &Bitcrusher, sample_rate: u32) -> Self {
+        Self {
+            levels: 2.0_f64.powi(bitcrusher.bits as i32 - 1),
+            step: bitcrusher.rate.map(|rate| (rate / sample_rate as f64).min(1.0)).unwrap_or(1.0),
+            phase: 1.0,
+            held: 0.0,
+        }
+    }
+
+    pub fn process(&mut self, x: f64) -> f64 {
+        if self.phase >= 1.0 {
+            self.phase -= 1.0;
+            self.held = (x * self.levels).round() / self.levels;
+        };
+
+        self.phase += self.step;
+
+        self.held
+    }
+}
+
+
+impl Effect for Crusher {
+    fn process(&mut self, value: f64) -> f64 {
+        Crusher::process(self, value)
+    }
+}
@@ -0,0 +1,333 @@
+use std::io::{self, Seek, SeekFrom, Write};
+use crate::compiler::{Metadata, Program};
+use crate::interpreter::{Backend, RenderOptions, TooLong};
+use crate::interpreter::render::SampleIterator;
+
+
+const BLOCK_SIZE: usize = 4096;
+const MAX_FIXED_ORDER: usize = 4;
+const MAX_RICE_PARAMETER: u32 = 14;
+const VENDOR: &str = "roorle";
+
+
+struct BitWriter {
+    bytes: Vec<u8>,
+    current: u64,
+    bits: u32,
+}
+
+
+impl BitWriter {
+    pub fn new() -> Self {
+        Self { bytes: Vec::new(), current: 0, bits: 0 }
+    }
+
+    pub fn write(&mut self, value: u64, bits: u32) {
+        for i in (0..bits).rev() {
+            self.current = (self.current << 1) | ((value >> i) & 1);
+            self.bits += 1;
+
+            if self.bits == 8 {
+                self.bytes.push(self.current as u8);
+                self.current = 0;
+                self.bits = 0;
+            };
+        };
+    }
+
+    pub fn write_signed(&mut self, value: i64, bits: u32) {
+        self.write(value as u64 & ((1 << bits) - 1), bits);
+    }
+
+    pub fn write_unary(&mut self, zeros: u64) {
+        for _ in 0..zeros {
+            self.write(0, 1);
+        };
+
+        self.write(1, 1);
+    }
+
+    pub fn align(&mut self) {
+        if self.bits > 0 {
+            self.write(0, 8 - self.bits);
+        };
+    }
+
+    pub fn into_bytes(mut self) -> Vec<u8> {
+        self.align();
+
+        self.bytes
+    }
+}
+
+
+fn crc8(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(0_u8, |mut crc, byte| {
+        crc ^= byte;
+
+        for _ in 0..8 {
+            crc = if crc & 0x80 != 0 { (crc << 1) ^ 0x07 } else { crc << 1 };
+        };
+
+        crc
+    })
+}
+
+
+fn crc16(bytes: &[u8]) -> u16 {
+    bytes.iter().fold(0_u16, |mut crc, byte| {
+        crc ^= (*byte as u16) << 8;
+
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 { (crc << 1) ^ 0x8005 } else { crc << 1 };
+        };
+
+        crc
+    })
+}
+
+
+fn write_utf8_number(writer: &mut BitWriter, n: u64) {
+    if n < 0x80 {
+        writer.write(n, 8);
+    } else {
+        let mut continuation = Vec::new();
+        let mut rest = n;
+        while rest >= (0x40 >> continuation.len()) {
+            continuation.push(0x80 | (rest & 0x3F));
+            rest >>= 6;
+        };
+
+        let extra = continuation.len() as u32;
+        let leading = (0xFF_u64 << (7 - extra)) & 0xFF;
+
+        writer.write(leading | rest, 8);
+        for byte in continuation.iter().rev() {
+            writer.write(*byte, 8);
+        };
+    };
+}
+
+
+fn fixed_residuals(samples: &[i64], order: usize) -> Vec<i64> {
+    (order..samples.len())
+        .map(|i| match order {
+            0 => samples[i],
+            1 => samples[i] - samples[i - 1],
+            2 => samples[i] - 2 * samples[i - 1] + samples[i - 2],
+            3 => samples[i] - 3 * samples[i - 1] + 3 * samples[i - 2] - samples[i - 3],
+            _ => samples[i] - 4 * samples[i - 1] + 6 * samples[i - 2] - 4 * samples[i - 3] + samples[i - 4],
+        })
+        .collect()
+}
+
+
+fn rice_cost(folded: &[u64], parameter: u32) -> u64 {
+    folded.iter().map(|n| (n >> parameter) + 1 + parameter as u64).sum()
+}
+
+
+fn write_subframe(writer: &mut BitWriter, samples: &[i64], bits_per_sample: u32) {
+    let order = (0..=MAX_FIXED_ORDER.min(samples.len().saturating_sub(1)))
+        .min_by_key(|order| fixed_residuals(samples, *order).iter().map(|r| r.unsigned_abs()).sum::<u64>())
+        .unwrap();
+
+    let folded = fixed_residuals(samples, order).iter()
+        .map(|r| if *r >= 0 { (*r as u64) << 1 } else { ((-*r as u64) << 1) - 1 })
+        .collect::<Vec<_>>();
+
+    let parameter = (0..=MAX_RICE_PARAMETER).min_by_key(|parameter| rice_cost(&folded, *parameter)).unwrap();
+
+    writer.write(0, 1);
+    writer.write(0b001000 | order as u64, 6);
+    writer.write(0, 1);
+
+    for sample in samples[..order].iter() {
+        writer.write_signed(*sample, bits_per_sample);
+    };
+
+    // rice coding with 4-bit parameters and a single partition
+    writer.write(0b00, 2);
+    writer.write(0, 4);
+    writer.write(parameter as u64, 4);
+
+    for n in folded {
+        writer.write_unary(n >> parameter);
+        writer.write(n & ((1 << parameter) - 1), parameter);
+    };
+}
+
+
+fn encode_frame(channels: &[Vec<i64>], frame_number: u64, bits_per_sample: u32) -> Vec<u8> {
+    let header = {
+        let mut writer = BitWriter::new();
+
+        writer.write(0b11111111111110, 14);
+        writer.write(0, 1);
+        writer.write(0, 1);
+        writer.write(0b0111, 4);
+        writer.write(0b0000, 4);
+        writer.write(channels.len() as u64 - 1, 4);
+        writer.write(if bits_per_sample == 8 { 0b001 } else { 0b100 }, 3);
+        writer.write(0, 1);
+        write_utf8_number(&mut writer, frame_number);
+        writer.write(channels[0].len() as u64 - 1, 16);
+
+        let mut header = writer.into_bytes();
+        header.push(crc8(&header));
+        header
+    };
+
+    let mut frame = {
+        let mut writer = BitWriter::new();
+
+        for samples in channels {
+            write_subframe(&mut writer, samples, bits_per_sample);
+        };
+
+        let mut frame = header;
+        frame.append(&mut writer.into_bytes());
+        frame
+    };
+
+    let crc = crc16(&frame);
+    frame.extend_from_slice(&crc.to_be_bytes());
+
+    frame
+}
+
+
+fn write_stream_info<W: Write>(writer: &mut W, options: &RenderOptions, total_samples: u64, frame_sizes: (u32, u32), last: bool) -> io::Result<()> {
+    let bits_per_sample = options.sample_size as u64;
+
+    let mut info = BitWriter::new();
+
+    info.write(last as u64, 1);
+    info.write(0, 7);
+    info.write(34, 24);
+
+    info.write(BLOCK_SIZE as u64, 16);
+    info.write(BLOCK_SIZE as u64, 16);
+    info.write(frame_sizes.0 as u64, 24);
+    info.write(frame_sizes.1 as u64, 24);
+    info.write(options.sample_rate as u64, 20);
+    info.write(options.channels as u64 - 1, 3);
+    info.write(bits_per_sample - 1, 5);
+    info.write(total_samples, 36);
+    info.write(0, 64);
+    info.write(0, 64);
+
+    writer.write_all(b"fLaC")?;
+    writer.write_all(&info.into_bytes())
+}
+
+
+fn vorbis_comment(metadata: &Metadata) -> Vec<u8> {
+    let fields = [
+        ("TITLE", &metadata.title),
+        ("ARTIST", &metadata.artist),
+        ("COMPOSER", &metadata.composer),
+        ("COMMENT", &metadata.comment),
+    ];
+
+    let comments = fields.into_iter()
+        .filter_map(|(name, text)| Some(format!("{name}={}", text.as_ref()?)))
+        .collect::<Vec<_>>();
+
+    let mut body = Vec::new();
+
+    body.append(&mut (VENDOR.len() as u32).to_le_bytes().to_vec());
+    body.append(&mut VENDOR.as_bytes().to_vec());
+    body.append(&mut (comments.len() as u32).to_le_bytes().to_vec());
+    for comment in comments {
+        body.append(&mut (comment.len() as u32).to_le_bytes().to_vec());
+        body.append(&mut comment.into_bytes());
+    };
+
+    let mut buffer = Vec::new();
+
+    buffer.push(0x80 | 4);
+    buffer.extend_from_slice(&(body.len() as u32).to_be_bytes()[1..]);
+    buffer.append(&mut body);
+
+    buffer
+}
+
+
+pub fn interpret_to_writer<W: Write + Seek>(program: &Program, options: &RenderOptions, writer: &mut W) -> io::Result<()> {
+    let bits_per_sample = options.sample_size as u32;
+    let scale = ((1_i64 << (bits_per_sample - 1)) - 1) as f64;
+
+    let samples = SampleIterator::new(program, options)?;
+
+    let start = writer.stream_position()?;
+
+    let metadata = program.get_metadata();
+
+    write_stream_info(writer, options, 0, (0, 0), metadata.is_empty())?;
+
+    if !metadata.is_empty() {
+        writer.write_all(&vorbis_comment(metadata))?;
+    };
+
+    let mut samples = samples.map(|value| (value.clamp(-1.0, 1.0) as f64 * scale).round() as i64);
+
+    let channels = options.channels as usize;
+
+    let mut total_samples = 0;
+    let mut frame_sizes = (u32::MAX, 0);
+    let mut frame_number = 0;
+    loop {
+        let block = samples.by_ref().take(BLOCK_SIZE * channels).collect::<Vec<_>>();
+        if block.is_empty() {
+            break;
+        };
+
+        let block = (0..channels)
+            .map(|channel| block.iter().skip(channel).step_by(channels).copied().collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+
+        let frame = encode_frame(&block, frame_number, bits_per_sample);
+        writer.write_all(&frame)?;
+
+        total_samples += block[0].len() as u64;
+        frame_sizes = (frame_sizes.0.min(frame.len() as u32), frame_sizes.1.max(frame.len() as u32));
+        frame_number += 1;
+    };
+
+    if total_samples == 0 {
+        frame_sizes = (0, 0);
+    };
+
+    let end = writer.stream_position()?;
+
+    writer.seek(SeekFrom::Start(start))?;
+    write_stream_info(writer, options, total_samples, frame_sizes, metadata.is_empty())?;
+    writer.seek(SeekFrom::Start(end))?;
+
+    Ok(())
+}
+
+
+pub struct Flac<W>(pub W);
+
+
+impl<W: Write + Seek> Backend for Flac<W> {
+    type Output = ();
+    type Error = io::Error;
+
+    fn render(&mut self, program: &Program, options: &RenderOptions) -> io::Result<()> {
+        interpret_to_writer(program, options, &mut self.0)
+    }
+}
+
+
+pub fn interpret(program: &Program, options: &RenderOptions) -> Result<Vec<u8>, TooLong> {
+    options.check_length(program)?;
+
+    let mut buffer = io::Cursor::new(Vec::new());
+
+    interpret_to_writer(program, options, &mut buffer).expect("writing into memory doesn't fail");
+
+    Ok(buffer.into_inner())
+}
@@ -0,0 +1,87 @@
+use crate::interpreter::effects::Biquad;
+
+
+const BLOCK_SECONDS: f64 = 0.4;
+const BLOCK_OVERLAP: f64 = 0.75;
+const ABSOLUTE_GATE: f64 = -70.0;
+const RELATIVE_GATE: f64 = -10.0;
+
+
+fn k_weighting(sample_rate: u32) -> (Biquad, Biquad) {
+    let shelf = {
+        let k = (std::f64::consts::PI * 1681.974450955533 / sample_rate as f64).tan();
+        let q = 0.7071752369554196;
+        let vh = 10.0_f64.powf(3.999843853973347 / 20.0);
+        let vb = vh.powf(0.4996667741545416);
+
+        let a0 = 1.0 + k / q + k * k;
+
+        Biquad::from_coefficients(
+            [(vh + vb * k / q + k * k) / a0, 2.0 * (k * k - vh) / a0, (vh - vb * k / q + k * k) / a0],
+            [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+        )
+    };
+
+    let high_pass = {
+        let k = (std::f64::consts::PI * 38.13547087602444 / sample_rate as f64).tan();
+        let q = 0.5003270373238773;
+
+        let a0 = 1.0 + k / q + k * k;
+
+        Biquad::from_coefficients([1.0, -2.0, 1.0], [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0])
+    };
+
+    (shelf, high_pass)
+}
+
+
+fn block_loudness(mean_square: f64) -> f64 {
+    -0.691 + 10.0 * mean_square.log10()
+}
+
+
+pub fn integrated_loudness(samples: impl IntoIterator<Item = f64>, sample_rate: u32, channels: usize) -> Option<f64> {
+    let segment_size = (BLOCK_SECONDS * (1.0 - BLOCK_OVERLAP) * sample_rate as f64).round() as usize;
+    let segments_per_block = (1.0 / (1.0 - BLOCK_OVERLAP)).round() as usize;
+
+    let segments = {
+        let mut filters = (0..channels).map(|_| k_weighting(sample_rate)).collect::<Vec<_>>();
+
+        let mut segments = Vec::new();
+        let mut energy = 0.0;
+        let mut count = 0;
+        for (i, sample) in samples.into_iter().enumerate() {
+            let (shelf, high_pass) = &mut filters[i % channels];
+            let weighted = high_pass.process(shelf.process(sample));
+
+            energy += weighted * weighted;
+            if i % channels < channels - 1 {
+                continue;
+            };
+            count += 1;
+
+            if count == segment_size {
+                segments.push(energy);
+                energy = 0.0;
+                count = 0;
+            };
+        };
+
+        segments
+    };
+
+    let blocks = segments.windows(segments_per_block)
+        .map(|block| block.iter().sum::<f64>() / (segment_size * segments_per_block) as f64)
+        .filter(|mean_square| block_loudness(*mean_square) > ABSOLUTE_GATE)
+        .collect::<Vec<_>>();
+
+    if blocks.is_empty() {
+        return None;
+    };
+
+    let relative_gate = block_loudness(blocks.iter().sum::<f64>() / blocks.len() as f64) + RELATIVE_GATE;
+
+    let gated = blocks.iter().filter(|mean_square| block_loudness(**mean_square) > relative_gate).collect::<Vec<_>>();
+
+    Some(block_loudness(gated.iter().copied().sum::<f64>() / gated.len() as f64))
+}
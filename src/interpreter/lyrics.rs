@@ -0,0 +1,146 @@
+//! Lyrics as LRC or SRT subtitles, for karaoke videos and players that show lyrics along with the music.
+//!
+//! Every time a label is entered or left a new line starts, so lines follow how the script is split
+//! into labels. A syllable ending with `-` is joined to the next one without a space, the `-` left out,
+//! and a `_` holds the syllable before it over its note
+
+use std::fmt::Write as _;
+use std::io::{self, Write};
+use crate::compiler::{InstructionData, Program};
+use crate::interpreter::{Backend, RenderOptions};
+
+
+/// A syllable holding the one before over its note
+const MELISMA: &str = "_";
+
+
+pub struct Lrc<W>(pub W);
+
+
+impl<W: Write> Backend for Lrc<W> {
+    type Output = ();
+    type Error = io::Error;
+
+    fn render(&mut self, program: &Program, options: &RenderOptions) -> io::Result<()> {
+        options.check_length(program)?;
+
+        write!(self.0, "{}", lrc(program))
+    }
+}
+
+
+pub struct Srt<W>(pub W);
+
+
+impl<W: Write> Backend for Srt<W> {
+    type Output = ();
+    type Error = io::Error;
+
+    fn render(&mut self, program: &Program, options: &RenderOptions) -> io::Result<()> {
+        options.check_length(program)?;
+
+        write!(self.0, "{}", srt(program))
+    }
+}
+
+
+/// A line of lyrics, from when its first syllable is sung to when its last one ends
+#[derive(Clone, Debug, PartialEq)]
+pub struct Line {
+    pub start: f64,
+    pub end: f64,
+    pub text: String,
+}
+
+
+/// The lines of lyrics of `program` in the order they're sung
+pub fn lines(program: &Program) -> Vec<Line> {
+    let mut lines = Vec::new();
+    let mut line: Option<Line> = None;
+    // the notes of a chord share their syllable
+    let mut last_sung = None;
+
+    let mut time = 0.0;
+    for instruction in program.get_instructions() {
+        match instruction.data {
+            InstructionData::Play { duration, .. } => {
+                let Some(lyric) = program.get_lyric(instruction.pos) else {
+                    continue;
+                };
+                if last_sung == Some((instruction.pos, time)) {
+                    continue;
+                };
+                last_sung = Some((instruction.pos, time));
+
+                if lyric == MELISMA {
+                    if let Some(line) = line.as_mut() {
+                        line.end = line.end.max(time + duration);
+                    };
+                    continue;
+                };
+
+                let line = line.get_or_insert(Line { start: time, end: time, text: String::new() });
+                line.end = line.end.max(time + duration);
+                match line.text.strip_suffix('-') {
+                    Some(joined) => line.text.truncate(joined.len()),
+                    None if !line.text.is_empty() => line.text.push(' '),
+                    None => { },
+                };
+                line.text.push_str(lyric);
+            },
+            InstructionData::Advance { duration } => time += duration,
+            InstructionData::EnterLabel { .. } | InstructionData::ExitLabel { .. } => lines.extend(line.take()),
+            InstructionData::Mark | InstructionData::Tempo { .. } | InstructionData::Meter { .. } | InstructionData::Automate { .. } => { },
+        };
+    };
+    lines.extend(line);
+
+    lines
+}
+
+
+/// `[mm:ss.xx]` of LRC
+fn lrc_time(seconds: f64) -> String {
+    let hundredths = (seconds * 100.0).round() as u64;
+
+    format!("[{:02}:{:02}.{:02}]", hundredths / 6000, hundredths / 100 % 60, hundredths % 100)
+}
+
+
+/// `hh:mm:ss,mmm` of SRT
+fn srt_time(seconds: f64) -> String {
+    let milliseconds = (seconds * 1000.0).round() as u64;
+
+    format!("{:02}:{:02}:{:02},{:03}", milliseconds / 3_600_000, milliseconds / 60_000 % 60, milliseconds / 1000 % 60, milliseconds % 1000)
+}
+
+
+/// A line of LRC per line of lyrics, timed to when it starts, after the title and artist if there are any
+pub fn lrc(program: &Program) -> String {
+    let mut lrc = String::new();
+
+    let metadata = program.get_metadata();
+    for (tag, value) in [("ti", &metadata.title), ("ar", &metadata.artist), ("au", &metadata.composer)] {
+        if let Some(value) = value {
+            let _ = writeln!(lrc, "[{tag}:{value}]");
+        };
+    };
+
+    for line in lines(program) {
+        let _ = writeln!(lrc, "{}{}", lrc_time(line.start), line.text);
+    };
+
+    lrc
+}
+
+
+/// A subtitle per line of lyrics, shown while it's sung
+pub fn srt(program: &Program) -> String {
+    let mut srt = String::new();
+
+    for (i, line) in lines(program).iter().enumerate() {
+        let _ = writeln!(srt, "{}\n{} --> {}\n{}\n", i + 1, srt_time(line.start), srt_time(line.end), line.text);
+    };
+
+    srt
+}
@@ -0,0 +1,120 @@
+//! Math done with nothing but additions, multiplications and divisions, which IEEE 754 rounds the same
+//! everywhere. The system's `sin`, `exp` and the like can differ in their last bits from one platform
+//! to another, so deterministic renders go through these instead
+
+use std::f64::consts::{LN_2, SQRT_2, TAU};
+
+
+/// `LN_2` split in two, the first part having few enough bits that multiplying it by a whole number is exact
+const LN_2_HIGH: f64 = 0.693_147_180_369_123_8;
+const LN_2_LOW: f64 = 1.908_214_929_270_587_7e-10;
+
+
+/// Sine of `turns` whole turns, so `0.25` is a quarter of one
+pub fn sin(turns: f64) -> f64 {
+    let turns = turns - turns.floor();
+
+    // the second half of a turn mirrors the first below zero, and each quarter of it mirrors the other
+    let (turns, sign) = if turns >= 0.5 { (turns - 0.5, -1.0) } else { (turns, 1.0) };
+    let turns = if turns > 0.25 { 0.5 - turns } else { turns };
+
+    let x = TAU * turns;
+    let x2 = x * x;
+
+    // Taylor series, which is well past the precision of a double by its 21st power up to a quarter turn
+    let mut term = x;
+    let mut sum = x;
+    for n in (2..=20).step_by(2) {
+        term *= -x2 / (n * (n + 1)) as f64;
+        sum += term;
+    };
+
+    sign * sum
+}
+
+
+pub fn exp(x: f64) -> f64 {
+    if x.is_nan() {
+        return x;
+    } else if x > 709.8 {
+        return f64::INFINITY;
+    } else if x < -745.2 {
+        return 0.0;
+    };
+
+    // e^x = 2^k * e^r, with r no further than half of ln 2 from zero
+    let k = (x / LN_2).round();
+    let r = x - k * LN_2_HIGH - k * LN_2_LOW;
+
+    let mut term = 1.0;
+    let mut sum = 1.0;
+    for n in 1..=20 {
+        term *= r / n as f64;
+        sum += term;
+    };
+
+    // in two steps, as 2^k alone can fall out of the doubles both ways when the result doesn't
+    let k = k as i32;
+    sum * power_of_two(k / 2) * power_of_two(k - k / 2)
+}
+
+
+/// Natural logarithm
+pub fn ln(x: f64) -> f64 {
+    if x.is_nan() || x < 0.0 {
+        return f64::NAN;
+    } else if x == 0.0 {
+        return f64::NEG_INFINITY;
+    } else if x.is_infinite() {
+        return x;
+    };
+
+    // subnormals are brought up to where their exponent is in the bits
+    let (x, shift) = if x < f64::MIN_POSITIVE { (x * power_of_two(54), -54) } else { (x, 0) };
+
+    let bits = x.to_bits();
+    let mut exponent = ((bits >> 52) & 0x7FF) as i32 - 1023 + shift;
+    let mut mantissa = f64::from_bits((bits & 0x000F_FFFF_FFFF_FFFF) | 0x3FF0_0000_0000_0000);
+    if mantissa > SQRT_2 {
+        mantissa /= 2.0;
+        exponent += 1;
+    };
+
+    // ln m = 2 atanh s, s being within 0.18 of zero
+    let s = (mantissa - 1.0) / (mantissa + 1.0);
+    let s2 = s * s;
+    let mut power = s;
+    let mut sum = s;
+    for n in (3..=29).step_by(2) {
+        power *= s2;
+        sum += power / n as f64;
+    };
+
+    exponent as f64 * LN_2_HIGH + (exponent as f64 * LN_2_LOW + 2.0 * sum)
+}
+
+
+pub fn powf(base: f64, exponent: f64) -> f64 {
+    exp(exponent * ln(base))
+}
+
+
+pub fn tanh(x: f64) -> f64 {
+    if x.abs() > 22.0 {
+        return x.signum();
+    } else if x.abs() < 0.02 {
+        // near zero the series keeps the precision the quotient below loses
+        let x2 = x * x;
+
+        return x * (1.0 - x2 / 3.0 + 2.0 * x2 * x2 / 15.0 - 17.0 * x2 * x2 * x2 / 315.0 + 62.0 * x2 * x2 * x2 * x2 / 2835.0);
+    };
+
+    let e = exp(2.0 * x);
+
+    (e - 1.0) / (e + 1.0)
+}
+
+
+fn power_of_two(k: i32) -> f64 {
+    f64::from_bits(((k + 1023).clamp(0, 2046) as u64) << 52)
+}
@@ -1,6 +1,185 @@
-use crate::compiler::Program;
+use std::io::{self, Write};
+use crate::compiler::{InstructionData, Program, TempoChange};
+use crate::interpreter::{Backend, RenderOptions};
+use crate::pitch::Pitch;
+
+
+const TICKS_PER_QUARTER: u16 = 480;
+/// MIDI clocks between metronome clicks, one every quarter note
+const CLOCKS_PER_CLICK: u8 = 24;
+/// 32nd notes in a quarter note, as MIDI counts them
+const THIRTY_SECONDS_PER_QUARTER: u8 = 8;
+
+
+fn write_variable_length(buffer: &mut Vec<u8>, mut value: u32) {
+    let mut bytes = vec![(value & 0x7F) as u8];
+    value >>= 7;
+
+    while value > 0 {
+        bytes.push((value & 0x7F) as u8 | 0x80);
+        value >>= 7;
+    };
+
+    bytes.reverse();
+    buffer.append(&mut bytes);
+}
+
+
+fn microseconds_per_quarter(bpm: f64) -> u32 {
+    ((60_000_000.0 / bpm).round() as u32).clamp(1, 0xFF_FFFF)
+}
+
+
+/// Ticks `seconds` into the piece, counted at the tempo of every part of it
+fn seconds_to_ticks(changes: &[TempoChange], seconds: f64) -> u32 {
+    let mut ticks = 0.0;
+    for (i, change) in changes.iter().enumerate() {
+        if change.time >= seconds && i > 0 {
+            break;
+        };
+
+        let end = changes.get(i + 1).map_or(seconds, |next| next.time.min(seconds));
+        ticks += (end - change.time) * 1_000_000.0 / microseconds_per_quarter(change.bpm) as f64 * TICKS_PER_QUARTER as f64;
+    };
+
+    ticks.round() as u32
+}
+
+
+/// Tempos and time signatures with the time they take over at, as meta events
+fn meta_events(program: &Program, changes: &[TempoChange]) -> Vec<(f64, Vec<u8>)> {
+    let mut events = Vec::new();
+
+    for change in changes {
+        let mut event = vec![0xFF, 0x51, 0x03];
+        event.extend_from_slice(&microseconds_per_quarter(change.bpm).to_be_bytes()[1..]);
+
+        events.push((change.time, event));
+    };
+
+    let mut time = 0.0;
+    let mut meters: Vec<(f64, [u8; 7])> = Vec::new();
+    for instruction in program.get_instructions() {
+        match instruction.data {
+            InstructionData::Advance { duration } => time += duration,
+            InstructionData::Meter { numerator, denominator } => {
+                // only the last of the ones at the same time is played in
+                if meters.last().is_some_and(|last| last.0 == time) {
+                    meters.pop();
+                };
+
+                let event = [0xFF, 0x58, 0x04, numerator.min(255) as u8, denominator.trailing_zeros() as u8, CLOCKS_PER_CLICK, THIRTY_SECONDS_PER_QUARTER];
+                if meters.last().is_none_or(|last| last.1 != event) {
+                    meters.push((time, event));
+                };
+            },
+            _ => { },
+        };
+    };
+    events.extend(meters.into_iter().map(|(time, event)| (time, event.to_vec())));
+
+    events
+}
+
+
+pub fn frequency_to_note(frequency: f64) -> u8 {
+    Pitch::from_frequency(frequency).midi_key()
+}
+
+
+pub fn note_messages(program: &Program) -> Vec<(f64, [u8; 3])> {
+    let mut messages = Vec::new();
+
+    for note in program.get_note_events() {
+        let key = frequency_to_note(note.frequency);
+        let instrument = &program.get_instruments()[note.instrument];
+        let velocity = (instrument.volume * instrument.velocity as f64).round().clamp(1.0, 127.0) as u8;
+
+        messages.push((note.start, [0x90, key, velocity]));
+        messages.push((note.start + note.duration, [0x80, key, 0]));
+    };
+
+    // note-offs go first so repeated notes at the same time don't cut each other off
+    messages.sort_by(|(a_time, a_message), (b_time, b_message)| a_time.total_cmp(b_time).then((a_message[0] == 0x90).cmp(&(b_message[0] == 0x90))));
+
+    messages
+}
+
+
+pub struct Midi<W>(pub W);
+
+
+impl<W: Write> Backend for Midi<W> {
+    type Output = ();
+    type Error = io::Error;
+
+    fn render(&mut self, program: &Program, options: &RenderOptions) -> io::Result<()> {
+        options.check_length(program)?;
+
+        self.0.write_all(&interpret(program))
+    }
+}
 
 
 pub fn interpret(program: &Program) -> Vec<u8> {
-    todo!("midi interpreter")
+    let mut changes = program.get_tempo_map();
+    if changes.is_empty() {
+        changes.push(TempoChange { time: 0.0, bpm: program.get_bpm() });
+    };
+
+    // meta events go before the notes at the same time, being what they're played in
+    let mut events = meta_events(program, &changes);
+    events.extend(note_messages(program).into_iter().map(|(seconds, message)| (seconds, message.to_vec())));
+    events.sort_by(|(a_time, a_event), (b_time, b_event)| a_time.total_cmp(b_time).then((a_event[0] != 0xFF).cmp(&(b_event[0] != 0xFF))));
+
+    let events = events.into_iter()
+        .map(|(seconds, event)| (seconds_to_ticks(&changes, seconds), event))
+        .collect::<Vec<_>>();
+
+    let mut track = {
+        let mut track = Vec::new();
+
+        let metadata = program.get_metadata();
+        let texts = [
+            (0x03, metadata.title.clone()),
+            (0x01, metadata.artist.as_ref().map(|artist| format!("Artist: {artist}"))),
+            (0x01, metadata.composer.as_ref().map(|composer| format!("Composer: {composer}"))),
+            (0x01, metadata.comment.clone()),
+        ];
+
+        for (kind, text) in texts {
+            let Some(text) = text else {
+                continue;
+            };
+
+            track.append(&mut vec![0x00, 0xFF, kind]);
+            write_variable_length(&mut track, text.len() as u32);
+            track.append(&mut text.into_bytes());
+        };
+
+        let mut last_tick = 0;
+        for (tick, event) in events {
+            write_variable_length(&mut track, tick - last_tick);
+            track.extend_from_slice(&event);
+
+            last_tick = tick;
+        };
+
+        track.append(&mut vec![0x00, 0xFF, 0x2F, 0x00]);
+
+        track
+    };
+
+    let mut buffer = Vec::new();
+
+    buffer.append(&mut b"MThd".to_vec());
+    buffer.append(&mut 6_u32.to_be_bytes().to_vec());
+    buffer.append(&mut 0_u16.to_be_bytes().to_vec());
+    buffer.append(&mut 1_u16.to_be_bytes().to_vec());
+    buffer.append(&mut TICKS_PER_QUARTER.to_be_bytes().to_vec());
+    buffer.append(&mut b"MTrk".to_vec());
+    buffer.append(&mut (track.len() as u32).to_be_bytes().to_vec());
+    buffer.append(&mut track);
+
+    buffer
 }
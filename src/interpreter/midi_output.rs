@@ -0,0 +1,95 @@
+use std::thread;
+use std::time::{Duration, Instant};
+use midir::{MidiOutput, MidiOutputConnection};
+use crate::compiler::Program;
+use crate::interpreter::{midi, Backend, RenderOptions, TooLong};
+
+
+const CLIENT_NAME: &str = "roorle";
+const ALL_NOTES_OFF: [u8; 3] = [0xB0, 123, 0];
+
+
+pub fn list_ports() -> Result<Vec<String>, MidiOutputError> {
+    let output = MidiOutput::new(CLIENT_NAME).map_err(|_| MidiOutputError::Init)?;
+
+    output.ports().iter()
+        .map(|port| output.port_name(port).map_err(|_| MidiOutputError::PortInfo))
+        .collect()
+}
+
+
+fn connect(port_name: Option<&str>) -> Result<MidiOutputConnection, MidiOutputError> {
+    let output = MidiOutput::new(CLIENT_NAME).map_err(|_| MidiOutputError::Init)?;
+
+    let port = {
+        let mut found = None;
+
+        for port in output.ports() {
+            let name = output.port_name(&port).map_err(|_| MidiOutputError::PortInfo)?;
+
+            if port_name.is_none_or(|wanted| name.contains(wanted)) {
+                found = Some(port);
+                break;
+            };
+        };
+
+        found.ok_or_else(|| MidiOutputError::PortNotFound { name: port_name.map(String::from) })?
+    };
+
+    output.connect(&port, CLIENT_NAME).map_err(|err| MidiOutputError::Connect { reason: err.to_string() })
+}
+
+
+pub struct LiveMidi {
+    pub port: Option<String>,
+}
+
+
+impl Backend for LiveMidi {
+    type Output = ();
+    type Error = MidiOutputError;
+
+    fn render(&mut self, program: &Program, options: &RenderOptions) -> Result<(), MidiOutputError> {
+        options.check_length(program).map_err(MidiOutputError::TooLong)?;
+
+        play(program, self.port.as_deref())
+    }
+}
+
+
+pub fn play(program: &Program, port_name: Option<&str>) -> Result<(), MidiOutputError> {
+    let mut connection = connect(port_name)?;
+
+    let started_at = Instant::now();
+    for (seconds, message) in midi::note_messages(program) {
+        let send_at = started_at + Duration::from_secs_f64(seconds);
+
+        if let Some(wait) = send_at.checked_duration_since(Instant::now()) {
+            thread::sleep(wait);
+        };
+
+        connection.send(&message).map_err(|err| MidiOutputError::Send { reason: err.to_string() })?;
+    };
+
+    connection.send(&ALL_NOTES_OFF).map_err(|err| MidiOutputError::Send { reason: err.to_string() })?;
+    connection.close();
+
+    Ok(())
+}
+
+
+#[derive(Debug)]
+pub enum MidiOutputError {
+    Init,
+    PortInfo,
+    PortNotFound {
+        name: Option<String>,
+    },
+    Connect {
+        reason: String,
+    },
+    Send {
+        reason: String,
+    },
+    TooLong(TooLong),
+}
@@ -0,0 +1,194 @@
+use crate::compiler::{AutoPan, Bus, BusSend, Instrument, Lfo, LfoTarget, Parameter, Program};
+use crate::interpreter::{mixing, voice, RenderOptions};
+use crate::interpreter::automation::Automation;
+use crate::interpreter::effects::{self, Effect};
+
+
+struct Track {
+    pub effects: Vec<Box<dyn Effect>>,
+    pub bus: usize,
+    pub autopan: Option<AutoPan>,
+    /// The instrument's LFOs swaying the pan, the others are played by the voices
+    pub lfos: Vec<Lfo>,
+    pub pan: f64,
+    /// Samples mixed so far, which is where the auto-pan and the LFOs are in their sway
+    pub samples: usize,
+}
+
+
+impl Track {
+    pub fn new(program: &Program, instrument: &Instrument, options: &RenderOptions) -> Self {
+        let bus = instrument.bus.as_deref().unwrap_or(Bus::MASTER);
+
+        Self {
+            effects: effects::chain(instrument, options),
+            bus: program.get_bus_index(bus).expect("buses of every instrument are collected by the compiler"),
+            autopan: instrument.autopan,
+            lfos: instrument.lfos.iter().filter(|lfo| lfo.target == LfoTarget::Pan).copied().collect(),
+            pan: instrument.pan,
+            samples: 0,
+        }
+    }
+
+    pub fn process_block(&mut self, block: &mut [f64]) {
+        for effect in self.effects.iter_mut() {
+            effect.process_block(block);
+        };
+    }
+}
+
+
+struct MixBus {
+    pub gain: f64,
+    pub pan: f64,
+    pub sends: Vec<BusSend>,
+    pub chains: Vec<Vec<Box<dyn Effect>>>,
+    pub blocks: Vec<Vec<f64>>,
+}
+
+
+impl MixBus {
+    pub fn new(bus: &Bus, channels: usize, block_size: usize, options: &RenderOptions) -> Self {
+        Self {
+            gain: bus.gain,
+            pan: bus.pan,
+            sends: bus.sends.clone(),
+            chains: (0..channels).map(|_| effects::chain(&bus.settings, options)).collect(),
+            blocks: vec![vec![0.0; block_size]; channels],
+        }
+    }
+
+    pub fn receive(&mut self, source: &[f64], gain: f64, pan: f64) {
+        let channels = self.blocks.len();
+
+        for (channel, block) in self.blocks.iter_mut().enumerate() {
+            let gain = gain * balance(pan, channel, channels);
+
+            if gain == 1.0 {
+                mixing::mix(&mut block[..source.len()], source);
+            } else {
+                mixing::mix_scaled(&mut block[..source.len()], source, gain);
+            };
+        };
+    }
+
+    /// Like [`receive`](Self::receive), but the pan of every sample is given by `pan`
+    pub fn receive_panned(&mut self, source: &[f64], pan: impl Fn(usize) -> f64) {
+        let channels = self.blocks.len();
+
+        for (channel, block) in self.blocks.iter_mut().enumerate() {
+            for (i, (value, source)) in block.iter_mut().zip(source).enumerate() {
+                *value += source * balance(pan(i), channel, channels);
+            };
+        };
+    }
+
+    pub fn process(&mut self, length: usize) {
+        let channels = self.blocks.len();
+
+        for (channel, (block, chain)) in self.blocks.iter_mut().zip(self.chains.iter_mut()).enumerate() {
+            let block = &mut block[..length];
+
+            for effect in chain.iter_mut() {
+                effect.process_block(block);
+            };
+
+            let gain = self.gain * balance(self.pan, channel, channels);
+            if gain != 1.0 {
+                block.iter_mut().for_each(|value| *value *= gain);
+            };
+        };
+    }
+}
+
+
+fn balance(pan: f64, channel: usize, channels: usize) -> f64 {
+    match (channels, channel) {
+        (2, 0) => (1.0 - pan).min(1.0),
+        (2, 1) => (1.0 + pan).min(1.0),
+        _ => 1.0,
+    }
+}
+
+
+pub struct Mixer {
+    tracks: Vec<Track>,
+    buses: Vec<MixBus>,
+    sample_rate: u32,
+    sine_table: bool,
+}
+
+
+impl Mixer {
+    pub fn new(program: &Program, options: &RenderOptions, block_size: usize) -> Self {
+        let buses = program.get_buses();
+
+        Self {
+            tracks: program.get_instruments().iter()
+                .map(|instrument| Track::new(program, instrument, options))
+                .collect(),
+            buses: buses.iter().enumerate()
+                .map(|(i, bus)| {
+                    let channels = if i == buses.len() - 1 { options.channels as usize } else { 1 };
+
+                    MixBus::new(bus, channels, block_size, options)
+                })
+                .collect(),
+            sample_rate: options.sample_rate,
+            sine_table: options.uses_sine_table(),
+        }
+    }
+
+    /// Mixes `length` samples of every instrument's track, the first of them being the `start`th sample
+    /// of the piece
+    pub fn process(&mut self, tracks: &mut [Vec<f64>], length: usize, start: usize, automation: &Automation) {
+        for bus in self.buses.iter_mut() {
+            bus.blocks.iter_mut().for_each(|block| block[..length].fill(0.0));
+        };
+
+        for (instrument, (block, track)) in tracks.iter_mut().zip(self.tracks.iter_mut()).enumerate() {
+            let block = &mut block[..length];
+
+            track.process_block(block);
+
+            let lane = automation.lane(instrument, Parameter::Pan);
+            if self.buses[track.bus].blocks.len() == 2 && (track.autopan.is_some() || !track.lfos.is_empty() || lane.is_some()) {
+                let (mixed, dt) = (track.samples, 1.0 / self.sample_rate as f64);
+                let (pan, autopan, lfos, sine_table) = (track.pan, track.autopan, &track.lfos, self.sine_table);
+
+                self.buses[track.bus].receive_panned(block, |i| {
+                    let elapsed = (mixed + i) as f64 * dt;
+                    let pan = lane.and_then(|lane| lane.value(start + i)).unwrap_or(pan)
+                        + lfos.iter().map(|lfo| lfo.depth * voice::sine((elapsed * lfo.rate).fract(), sine_table)).sum::<f64>();
+
+                    match autopan {
+                        Some(AutoPan { rate, depth }) => pan + depth * voice::sine((elapsed * rate).fract(), sine_table),
+                        None => pan,
+                    }
+                });
+            } else {
+                self.buses[track.bus].receive(block, 1.0, track.pan);
+            };
+            track.samples += length;
+        };
+
+        for i in 0..self.buses.len() {
+            let (bus, later) = self.buses[i..].split_first_mut().unwrap();
+
+            bus.process(length);
+
+            let Some(master) = later.last_mut() else {
+                break;
+            };
+            master.receive(&bus.blocks[0][..length], 1.0, bus.pan);
+
+            for send in bus.sends.iter() {
+                later[send.bus - i - 1].receive(&bus.blocks[0][..length], send.amount, 0.0);
+            };
+        };
+    }
+
+    pub fn output(&self) -> &[Vec<f64>] {
+        &self.buses.last().expect("master bus is always present").blocks
+    }
+}
@@ -0,0 +1,97 @@
+use crate::interpreter::SampleSize;
+
+
+#[cfg(feature = "simd")]
+const LANES: usize = 8;
+
+
+#[cfg(feature = "simd")]
+pub fn mix(destination: &mut [f64], source: &[f64]) {
+    use std::simd::f64x8;
+
+    let mut destination_chunks = destination.chunks_exact_mut(LANES);
+    let mut source_chunks = source.chunks_exact(LANES);
+    for (destination, source) in destination_chunks.by_ref().zip(source_chunks.by_ref()) {
+        (f64x8::from_slice(destination) + f64x8::from_slice(source)).copy_to_slice(destination);
+    };
+
+    mix_scalar(destination_chunks.into_remainder(), source_chunks.remainder());
+}
+
+
+#[cfg(not(feature = "simd"))]
+pub fn mix(destination: &mut [f64], source: &[f64]) {
+    mix_scalar(destination, source);
+}
+
+
+fn mix_scalar(destination: &mut [f64], source: &[f64]) {
+    for (destination, source) in destination.iter_mut().zip(source.iter()) {
+        *destination += source;
+    };
+}
+
+
+#[cfg(feature = "simd")]
+pub fn mix_scaled(destination: &mut [f64], source: &[f64], gain: f64) {
+    use std::simd::f64x8;
+
+    let mut destination_chunks = destination.chunks_exact_mut(LANES);
+    let mut source_chunks = source.chunks_exact(LANES);
+    for (destination, source) in destination_chunks.by_ref().zip(source_chunks.by_ref()) {
+        (f64x8::from_slice(destination) + f64x8::from_slice(source) * f64x8::splat(gain)).copy_to_slice(destination);
+    };
+
+    mix_scaled_scalar(destination_chunks.into_remainder(), source_chunks.remainder(), gain);
+}
+
+
+#[cfg(not(feature = "simd"))]
+pub fn mix_scaled(destination: &mut [f64], source: &[f64], gain: f64) {
+    mix_scaled_scalar(destination, source, gain);
+}
+
+
+fn mix_scaled_scalar(destination: &mut [f64], source: &[f64], gain: f64) {
+    for (destination, source) in destination.iter_mut().zip(source.iter()) {
+        *destination += source * gain;
+    };
+}
+
+
+#[cfg(feature = "simd")]
+pub fn quantize_block(samples: &[f32], sample_size: SampleSize, buffer: &mut Vec<u8>) {
+    use std::simd::{f32x8, f64x8, i16x8, StdFloat};
+    use std::simd::num::{SimdFloat, SimdInt};
+
+    let scale = f64x8::splat(match sample_size {
+        SampleSize::Small => i8::MAX as f64,
+        SampleSize::Large => i16::MAX as f64,
+    });
+
+    let mut chunks = samples.chunks_exact(LANES);
+    for chunk in chunks.by_ref() {
+        let values = f32x8::from_slice(chunk).cast::<f64>().simd_clamp(f64x8::splat(-1.0), f64x8::splat(1.0));
+        let quantized = (values * scale).round().cast::<i16>();
+
+        match sample_size {
+            SampleSize::Small => buffer.extend_from_slice((quantized + i16x8::splat(128)).cast::<u8>().as_array()),
+            SampleSize::Large => quantized.to_array().iter().for_each(|value| buffer.extend_from_slice(&value.to_le_bytes())),
+        };
+    };
+
+    quantize_scalar(chunks.remainder(), sample_size, buffer);
+}
+
+
+#[cfg(not(feature = "simd"))]
+pub fn quantize_block(samples: &[f32], sample_size: SampleSize, buffer: &mut Vec<u8>) {
+    quantize_scalar(samples, sample_size, buffer);
+}
+
+
+fn quantize_scalar(samples: &[f32], sample_size: SampleSize, buffer: &mut Vec<u8>) {
+    for value in samples {
+        buffer.append(&mut sample_size.quantize(*value as f64));
+    };
+}
@@ -1,2 +1,238 @@
+use std::{fmt, io};
+use std::path::Path;
+use crate::compiler::Program;
+
 pub mod wav;
 pub mod midi;
+pub mod rtttl;
+pub mod csv;
+pub mod lyrics;
+pub mod raw;
+pub mod flac;
+pub mod aiff;
+pub mod effects;
+pub mod render;
+pub mod scheduler;
+pub mod automation;
+pub mod mixing;
+pub mod math;
+pub mod mixer;
+pub mod voice;
+pub mod loudness;
+pub mod visual;
+pub mod terminal;
+#[cfg(feature = "playback")]
+pub mod playback;
+#[cfg(feature = "midi-output")]
+pub mod midi_output;
+
+
+/// Longest render the command line and the bindings allow unless told otherwise, an hour
+pub const DEFAULT_MAX_RENDER_SECONDS: f64 = 3600.0;
+
+
+pub trait Backend {
+    type Output;
+    type Error;
+
+    fn render(&mut self, program: &Program, options: &RenderOptions) -> Result<Self::Output, Self::Error>;
+}
+
+
+/// What a program can be rendered into
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Format {
+    Wav,
+    Flac,
+    Aiff,
+    Raw,
+    Midi,
+    Rtttl,
+    Svg,
+    Png,
+    Spectrogram,
+    Lrc,
+    Srt,
+}
+
+
+impl Format {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "wav" | "wave" => Some(Self::Wav),
+            "flac" => Some(Self::Flac),
+            "aiff" | "aif" => Some(Self::Aiff),
+            "raw" | "pcm" => Some(Self::Raw),
+            "midi" | "mid" => Some(Self::Midi),
+            "rtttl" | "rtx" => Some(Self::Rtttl),
+            "svg" => Some(Self::Svg),
+            "png" => Some(Self::Png),
+            "spectrogram" => Some(Self::Spectrogram),
+            "lrc" => Some(Self::Lrc),
+            "srt" => Some(Self::Srt),
+            _ => None,
+        }
+    }
+
+    /// The format of a file named with its [`extension`](Self::extension), so spectrograms are told
+    /// apart from PNGs by the `.spectrogram` before their `.png`
+    pub fn from_path(path: &str) -> Option<Self> {
+        let path = Path::new(path);
+        let format = Self::from_name(path.extension()?.to_str()?)?;
+
+        let inner = path.file_stem().map(Path::new).and_then(Path::extension);
+        if format == Self::Png && inner.is_some_and(|inner| inner.eq_ignore_ascii_case("spectrogram")) {
+            return Some(Self::Spectrogram);
+        };
+
+        Some(format)
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Wav => "wav",
+            Self::Flac => "flac",
+            Self::Aiff => "aiff",
+            Self::Raw => "raw",
+            Self::Midi => "mid",
+            Self::Rtttl => "rtttl",
+            Self::Svg => "svg",
+            Self::Png => "png",
+            Self::Spectrogram => "spectrogram.png",
+            Self::Lrc => "lrc",
+            Self::Srt => "srt",
+        }
+    }
+}
+
+
+#[derive(Copy, Clone, Debug)]
+pub enum SampleSize {
+    Small = 8,
+    Large = 16,
+}
+
+
+impl SampleSize {
+    pub fn quantize(self, value: f64) -> Vec<u8> {
+        let value = value.clamp(-1.0, 1.0);
+
+        match self {
+            SampleSize::Small => (((i8::MAX as f64 * value).round() as i16 + 128) as u8).to_le_bytes().to_vec(),
+            SampleSize::Large => ((i16::MAX as f64 * value).round() as i16).to_le_bytes().to_vec(),
+        }
+    }
+}
+
+
+#[derive(Copy, Clone, Debug)]
+pub struct RenderOptions {
+    pub sample_rate: u32,
+    pub sample_size: SampleSize,
+    pub channels: u16,
+    pub master_gain: f64,
+    pub soft_clip: bool,
+    /// Peak in dBFS the render is scaled to, taking over from `loudness` when both are set
+    pub normalize: Option<f64>,
+    /// Integrated loudness in LUFS the render is scaled to, unless it's normalized
+    pub loudness: Option<f64>,
+    pub fade_in: f64,
+    pub fade_out: f64,
+    pub from: f64,
+    pub to: Option<f64>,
+    pub terminal_view: bool,
+    pub sine_table: bool,
+    /// The same samples on every platform, with sines from the table and no math from the system's libm.
+    /// Measuring loudness for `loudness` still uses it
+    pub deterministic: bool,
+    /// Mixed into the noise of every note, so renders can differ without the script changing
+    pub seed: u64,
+    /// Longest render allowed, so a slip like `repeat verse 100000` fails with [`TooLong`] before anything is
+    /// rendered. Every backend checks it
+    pub max_render_seconds: Option<f64>,
+}
+
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        Self {
+            sample_rate: 48000,
+            sample_size: SampleSize::Large,
+            channels: 1,
+            master_gain: 0.25,
+            soft_clip: true,
+            normalize: None,
+            loudness: None,
+            fade_in: 0.0,
+            fade_out: 0.0,
+            from: 0.0,
+            to: None,
+            terminal_view: false,
+            sine_table: false,
+            deterministic: false,
+            seed: 0,
+            max_render_seconds: None,
+        }
+    }
+}
+
+
+impl RenderOptions {
+    pub fn master(&self, value: f64) -> f64 {
+        let value = value * self.master_gain;
+
+        if self.soft_clip && self.deterministic {
+            math::tanh(value)
+        } else if self.soft_clip {
+            value.tanh()
+        } else {
+            value.clamp(-1.0, 1.0)
+        }
+    }
+
+    /// Fails if rendering `program` from `from` to `to` would take longer than `max_render_seconds`
+    pub fn check_length(&self, program: &Program) -> Result<(), TooLong> {
+        let Some(limit) = self.max_render_seconds else {
+            return Ok(());
+        };
+
+        let (start, end) = render::sample_window(program, self);
+        let seconds = (end - start) as f64 / self.sample_rate as f64;
+
+        if seconds > limit {
+            Err(TooLong { seconds, limit })
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Whether sines are looked up in a table, which deterministic renders always do
+    pub fn uses_sine_table(&self) -> bool {
+        self.sine_table || self.deterministic
+    }
+}
+
+
+/// A render that would be longer than [`RenderOptions::max_render_seconds`]
+#[derive(Copy, Clone, Debug)]
+pub struct TooLong {
+    pub seconds: f64,
+    pub limit: f64,
+}
+
+
+impl fmt::Display for TooLong {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "the render would last {:.1} seconds, longer than the {} allowed", self.seconds, self.limit)
+    }
+}
+
+
+impl std::error::Error for TooLong { }
+
+
+impl From<TooLong> for io::Error {
+    fn from(err: TooLong) -> Self {
+        io::Error::new(io::ErrorKind::InvalidInput, err)
+    }
+}
@@ -0,0 +1,145 @@
+use std::sync::mpsc;
+use cpal::{FromSample, SampleFormat, SizedSample};
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use crate::compiler::Program;
+use crate::interpreter::{Backend, RenderOptions, TooLong};
+use crate::interpreter::render::SampleIterator;
+
+
+const CHUNK_SAMPLES: usize = 4096;
+const BUFFERED_CHUNKS: usize = 8;
+
+
+struct ChunkReader {
+    receiver: mpsc::Receiver<Vec<f32>>,
+    finished: mpsc::Sender<()>,
+    chunk: Vec<f32>,
+    position: usize,
+    depleted: bool,
+}
+
+
+impl ChunkReader {
+    pub fn next_sample(&mut self) -> f32 {
+        if self.position >= self.chunk.len() {
+            match self.receiver.try_recv() {
+                Ok(chunk) => {
+                    self.chunk = chunk;
+                    self.position = 0;
+                },
+                Err(mpsc::TryRecvError::Empty) => return 0.0,
+                Err(mpsc::TryRecvError::Disconnected) => {
+                    if !self.depleted {
+                        self.depleted = true;
+                        let _ = self.finished.send(());
+                    };
+
+                    return 0.0;
+                },
+            };
+        };
+
+        self.position += 1;
+
+        self.chunk[self.position - 1]
+    }
+}
+
+
+fn build_stream<T>(device: &cpal::Device, config: &cpal::StreamConfig, source_channels: usize, mut reader: ChunkReader) -> Result<cpal::Stream, PlaybackError>
+    where T: SizedSample + FromSample<f32>
+{
+    let channels = config.channels as usize;
+    let mut values = vec![0.0; source_channels];
+
+    device.build_output_stream(
+        config,
+        move |data: &mut [T], _| {
+            for frame in data.chunks_mut(channels) {
+                values.iter_mut().for_each(|value| *value = reader.next_sample());
+
+                for (i, slot) in frame.iter_mut().enumerate() {
+                    *slot = T::from_sample(values[i % source_channels]);
+                };
+            };
+        },
+        |err| eprintln!("playback error: {err}"),
+        None,
+    ).map_err(PlaybackError::BuildStream)
+}
+
+
+pub struct Playback;
+
+
+impl Backend for Playback {
+    type Output = ();
+    type Error = PlaybackError;
+
+    fn render(&mut self, program: &Program, options: &RenderOptions) -> Result<(), PlaybackError> {
+        play(program, options)
+    }
+}
+
+
+pub fn play(program: &Program, options: &RenderOptions) -> Result<(), PlaybackError> {
+    let device = cpal::default_host().default_output_device().ok_or(PlaybackError::NoOutputDevice)?;
+    let supported_config = device.default_output_config().map_err(PlaybackError::DefaultConfig)?;
+
+    let options = RenderOptions { sample_rate: supported_config.sample_rate().0, ..*options };
+    let mut samples = SampleIterator::new(program, &options).map_err(PlaybackError::TooLong)?;
+
+    let (sender, receiver) = mpsc::sync_channel(BUFFERED_CHUNKS);
+    let (finished, finished_receiver) = mpsc::channel();
+
+    let reader = ChunkReader {
+        receiver,
+        finished,
+        chunk: Vec::new(),
+        position: 0,
+        depleted: false,
+    };
+
+    let config = supported_config.config();
+    let stream = match supported_config.sample_format() {
+        SampleFormat::F32 => build_stream::<f32>(&device, &config, options.channels as usize, reader)?,
+        SampleFormat::I16 => build_stream::<i16>(&device, &config, options.channels as usize, reader)?,
+        SampleFormat::U16 => build_stream::<u16>(&device, &config, options.channels as usize, reader)?,
+        SampleFormat::I32 => build_stream::<i32>(&device, &config, options.channels as usize, reader)?,
+        format => return Err(PlaybackError::UnsupportedSampleFormat(format)),
+    };
+
+    stream.play().map_err(PlaybackError::PlayStream)?;
+
+    loop {
+        let mut chunk = vec![0.0; CHUNK_SAMPLES];
+
+        let filled = samples.fill(&mut chunk);
+        if filled == 0 {
+            break;
+        };
+
+        chunk.truncate(filled);
+
+        if sender.send(chunk).is_err() {
+            break;
+        };
+    };
+
+    drop(sender);
+
+    let _ = finished_receiver.recv();
+
+    Ok(())
+}
+
+
+#[derive(Debug)]
+pub enum PlaybackError {
+    NoOutputDevice,
+    UnsupportedSampleFormat(SampleFormat),
+    DefaultConfig(cpal::DefaultStreamConfigError),
+    BuildStream(cpal::BuildStreamError),
+    PlayStream(cpal::PlayStreamError),
+    TooLong(TooLong),
+}
@@ -0,0 +1,66 @@
+use std::io::{self, Write};
+use crate::compiler::Program;
+use crate::interpreter::{mixing, Backend, RenderOptions, SampleSize, TooLong};
+use crate::interpreter::render::SampleIterator;
+
+
+const CHUNK_SAMPLES: usize = 4096;
+
+
+pub fn format_report(options: &RenderOptions) -> String {
+    let (encoding, ffmpeg, aplay) = match options.sample_size {
+        SampleSize::Small => ("unsigned 8-bit, offset binary (silence is 128)", "u8", "U8"),
+        SampleSize::Large => ("signed 16-bit little-endian", "s16le", "S16_LE"),
+    };
+
+    format!(
+        "{encoding}, {channels} channel{plural}, {rate} Hz\nffmpeg: -f {ffmpeg} -ar {rate} -ac {channels}\naplay: -f {aplay} -r {rate} -c {channels}",
+        rate = options.sample_rate,
+        channels = options.channels,
+        plural = if options.channels == 1 { "" } else { "s" },
+    )
+}
+
+
+pub fn interpret_to_writer<W: Write>(program: &Program, options: &RenderOptions, writer: &mut W) -> io::Result<()> {
+    let mut samples = SampleIterator::new(program, options)?;
+
+    let mut block = vec![0.0; CHUNK_SAMPLES];
+    let mut chunk = Vec::with_capacity(CHUNK_SAMPLES * options.sample_size as usize / 8);
+    loop {
+        let filled = samples.fill(&mut block);
+        if filled == 0 {
+            break;
+        };
+
+        mixing::quantize_block(&block[..filled], options.sample_size, &mut chunk);
+        writer.write_all(&chunk)?;
+        chunk.clear();
+    };
+
+    Ok(())
+}
+
+
+pub struct Raw<W>(pub W);
+
+
+impl<W: Write> Backend for Raw<W> {
+    type Output = ();
+    type Error = io::Error;
+
+    fn render(&mut self, program: &Program, options: &RenderOptions) -> io::Result<()> {
+        interpret_to_writer(program, options, &mut self.0)
+    }
+}
+
+
+pub fn interpret(program: &Program, options: &RenderOptions) -> Result<Vec<u8>, TooLong> {
+    options.check_length(program)?;
+
+    let mut buffer = Vec::new();
+
+    interpret_to_writer(program, options, &mut buffer).expect("writing into memory doesn't fail");
+
+    Ok(buffer)
+}
@@ -0,0 +1,442 @@
+use crate::compiler::{InstructionData, Parameter, Program};
+use crate::interpreter::{loudness, math, RenderOptions, TooLong};
+use crate::interpreter::automation::{self, Automation};
+use crate::interpreter::mixer::Mixer;
+use crate::interpreter::scheduler::{self, Event, EventKind};
+use crate::interpreter::terminal::TerminalView;
+use crate::interpreter::voice::{self, Voice, VoiceSettings};
+
+
+const PROGRESS_INTERVAL: usize = 4096;
+pub const BLOCK_SIZE: usize = 4096;
+struct Sound {
+    pub id: usize,
+    pub voice: Box<dyn Voice>,
+    pub started: usize,
+    pub instrument: usize,
+}
+
+
+pub struct Renderer<'p> {
+    program: &'p Program,
+    options: RenderOptions,
+    events: Vec<Event>,
+    next_event: usize,
+    samples_stepped: u32,
+    sounds: Vec<Sound>,
+    automation: Automation,
+    mixer: Mixer,
+    instrument_values: Vec<f64>,
+    instrument_blocks: Vec<Vec<f64>>,
+    block_filled: usize,
+    block_position: usize,
+    frame: usize,
+    channel: usize,
+    end: u32,
+    executed: usize,
+}
+
+
+impl<'p> Renderer<'p> {
+    pub fn new(program: &'p Program, options: &RenderOptions) -> Self {
+        let instruments = program.get_instruments().len();
+
+        let (start, end) = sample_window(program, options);
+
+        let mut renderer = Self {
+            program,
+            options: *options,
+            events: scheduler::schedule(program, options.sample_rate),
+            next_event: 0,
+            samples_stepped: 0,
+            sounds: Vec::new(),
+            automation: Automation::new(program, options.sample_rate),
+            mixer: Mixer::new(program, options, BLOCK_SIZE),
+            instrument_values: vec![0.0; instruments],
+            instrument_blocks: vec![vec![0.0; BLOCK_SIZE]; instruments],
+            block_filled: 0,
+            block_position: 0,
+            frame: 0,
+            channel: 0,
+            end: end as u32,
+            executed: 0,
+        };
+
+        renderer.seek(start as u32);
+
+        renderer
+    }
+}
+
+
+impl Renderer<'_> {
+    fn dispatch(&mut self, event: Event) {
+        self.executed = self.executed.max(event.instruction + 1);
+
+        match event.kind {
+            EventKind::NoteOn { id, frequency, duration, instrument, glide } => self.sounds.push(Sound {
+                id,
+                voice: voice::create(&VoiceSettings {
+                    frequency,
+                    duration,
+                    glide,
+                    instrument: &self.program.get_instruments()[instrument],
+                    options: &self.options,
+                }),
+                started: event.sample,
+                instrument,
+            }),
+            EventKind::NoteOff { id } => {
+                if let Some(sound) = self.sounds.iter_mut().find(|sound| sound.id == id) {
+                    sound.voice.note_off();
+                };
+            },
+        };
+    }
+
+    fn dispatch_until(&mut self, sample: u32) {
+        while let Some(event) = self.events.get(self.next_event).copied() && event.sample <= sample as usize {
+            self.dispatch(event);
+            self.next_event += 1;
+        };
+
+        self.sounds.retain(|sound| !sound.voice.is_finished());
+    }
+
+    fn seek(&mut self, target: u32) {
+        if target == 0 {
+            return;
+        };
+
+        self.dispatch_until(target - 1);
+        self.samples_stepped = target;
+
+        let dt = 1.0 / self.options.sample_rate as f64;
+        for sound in self.sounds.iter_mut() {
+            sound.voice.skip(dt, target as usize - sound.started);
+        };
+    }
+
+    pub fn position(&self) -> usize {
+        self.samples_stepped as usize - (self.block_filled - self.block_position)
+    }
+
+    pub fn current_instruction(&self) -> Option<usize> {
+        self.executed.checked_sub(1)
+    }
+
+    fn fill_block(&mut self) -> usize {
+        let dt = 1.0 / self.options.sample_rate as f64;
+
+        self.instrument_blocks.iter_mut().for_each(|block| block.fill(0.0));
+
+        let mut filled = 0;
+        while filled < BLOCK_SIZE && self.samples_stepped < self.end {
+            self.dispatch_until(self.samples_stepped);
+
+            let next_event = self.events.get(self.next_event).map_or(usize::MAX, |event| event.sample);
+            let mut length = (next_event - self.samples_stepped as usize).min(BLOCK_SIZE - filled).min((self.end - self.samples_stepped) as usize);
+
+            if !self.automation.is_empty() {
+                length = length.min(automation::CONTROL_INTERVAL);
+
+                for sound in self.sounds.iter_mut() {
+                    for parameter in [Parameter::Volume, Parameter::Cutoff] {
+                        if let Some(value) = self.automation.value(sound.instrument, parameter, self.samples_stepped as usize) {
+                            sound.voice.automate(parameter, value);
+                        };
+                    };
+                };
+            };
+
+            for sound in self.sounds.iter_mut() {
+                for value in self.instrument_blocks[sound.instrument][filled..filled + length].iter_mut() {
+                    if sound.voice.is_finished() {
+                        break;
+                    };
+
+                    *value += sound.voice.sample(dt);
+                };
+            };
+
+            self.samples_stepped += length as u32;
+            filled += length;
+        };
+
+        if filled > 0 {
+            let start = self.samples_stepped as usize - filled;
+            self.mixer.process(&mut self.instrument_blocks, filled, start, &self.automation);
+        };
+
+        filled
+    }
+
+    fn step(&mut self) -> Option<usize> {
+        if self.block_position == self.block_filled {
+            self.block_filled = self.fill_block();
+            self.block_position = 0;
+
+            if self.block_filled == 0 {
+                return None;
+            };
+        };
+
+        self.block_position += 1;
+
+        Some(self.block_position - 1)
+    }
+
+    pub fn next_stems(&mut self) -> Option<&[f64]> {
+        let i = self.step()?;
+
+        for (value, block) in self.instrument_values.iter_mut().zip(self.instrument_blocks.iter()) {
+            *value = block[i];
+        };
+
+        Some(&self.instrument_values)
+    }
+}
+
+
+impl Iterator for Renderer<'_> {
+    type Item = f64;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.channel == 0 {
+            self.frame = self.step()?;
+        };
+
+        let output = self.mixer.output();
+        let value = output[self.channel][self.frame];
+        self.channel = (self.channel + 1) % output.len();
+
+        Some(self.options.master(value))
+    }
+}
+
+
+pub fn total_samples(program: &Program, sample_rate: u32) -> usize {
+    sample_offset(program, program.get_instructions().len(), sample_rate)
+}
+
+
+pub fn sample_window(program: &Program, options: &RenderOptions) -> (usize, usize) {
+    let total = total_samples(program, options.sample_rate);
+
+    let start = ((options.from * options.sample_rate as f64).round() as usize).min(total);
+    let end = options.to.map(|to| (to * options.sample_rate as f64).round() as usize).unwrap_or(total).clamp(start, total);
+
+    (start, end)
+}
+
+
+pub fn sample_offset(program: &Program, instruction: usize, sample_rate: u32) -> usize {
+    let time = program.get_instructions()[..instruction].iter()
+        .fold(0.0, |time, instruction| match instruction.data {
+            InstructionData::Advance { duration } => time + duration,
+            _ => time,
+        });
+
+    (time * sample_rate as f64).round() as usize
+}
+
+
+#[derive(Copy, Clone, Debug)]
+pub struct Fades {
+    channels: usize,
+    start: usize,
+    end: usize,
+    fade_in: usize,
+    fade_out: usize,
+}
+
+
+/// Fades into the start and out of the end of the part of the piece that's rendered
+impl Fades {
+    pub fn new(program: &Program, options: &RenderOptions) -> Self {
+        let (start, end) = sample_window(program, options);
+
+        Self {
+            channels: options.channels as usize,
+            start,
+            end,
+            fade_in: ((options.fade_in * options.sample_rate as f64).round() as usize).min(end - start),
+            fade_out: ((options.fade_out * options.sample_rate as f64).round() as usize).min(end - start),
+        }
+    }
+
+    pub fn gain(&self, i: usize) -> f64 {
+        let mut gain = 1.0;
+
+        let into = i.saturating_sub(self.start);
+        if into < self.fade_in {
+            gain *= into as f64 / self.fade_in as f64;
+        };
+
+        if i >= self.end - self.fade_out {
+            gain *= (self.end - i - 1) as f64 / self.fade_out as f64;
+        };
+
+        gain
+    }
+
+    pub fn apply<'p>(self, renderer: Renderer<'p>) -> impl Iterator<Item = f64> + 'p {
+        let start = renderer.position();
+
+        renderer.enumerate().map(move |(i, sample)| sample * self.gain(start + i / self.channels))
+    }
+}
+
+
+#[derive(Copy, Clone, Debug)]
+pub struct Progress {
+    pub fraction: f64,
+    pub instruction: Option<usize>,
+    pub pos: Option<usize>,
+    /// The loudest sample rendered so far, so a render can be measured while it's written
+    pub peak: f32,
+}
+
+
+pub struct SampleIterator<'p> {
+    renderer: Renderer<'p>,
+    fades: Fades,
+    gain: f64,
+    start: usize,
+    position: usize,
+    channel: usize,
+    peak: f32,
+    view: Option<TerminalView>,
+    progress: Option<Box<dyn FnMut(Progress) + 'p>>,
+}
+
+
+impl<'p> SampleIterator<'p> {
+    /// Fails without rendering anything if the render would be longer than `max_render_seconds`
+    pub fn new(program: &'p Program, options: &RenderOptions) -> Result<Self, TooLong> {
+        options.check_length(program)?;
+
+        let fades = Fades::new(program, options);
+        let amplitude = |decibels: f64| {
+            if options.deterministic { math::powf(10.0, decibels / 20.0) } else { 10.0_f64.powf(decibels / 20.0) }
+        };
+
+        let gain = if let Some(peak_dbfs) = options.normalize {
+            let peak = fades.apply(Renderer::new(program, options)).fold(0.0_f64, |peak, sample| peak.max(sample.abs()));
+
+            if peak > 0.0 { amplitude(peak_dbfs) / peak } else { 1.0 }
+        } else if let Some(target_lufs) = options.loudness {
+            loudness::integrated_loudness(fades.apply(Renderer::new(program, options)), options.sample_rate, options.channels as usize)
+                .map(|loudness| amplitude(target_lufs - loudness))
+                .unwrap_or(1.0)
+        } else {
+            1.0
+        };
+
+        let renderer = Renderer::new(program, options);
+
+        Ok(Self {
+            start: renderer.position(),
+            position: renderer.position(),
+            channel: 0,
+            peak: 0.0,
+            renderer,
+            fades,
+            gain,
+            view: options.terminal_view.then(|| TerminalView::new(program, options)),
+            progress: None,
+        })
+    }
+
+    pub fn on_progress<F>(&mut self, callback: F)
+        where F: FnMut(Progress) + 'p
+    {
+        self.progress = Some(Box::new(callback));
+    }
+
+    pub fn progress(&self) -> Progress {
+        let total = self.fades.end - self.start;
+        let instruction = self.renderer.current_instruction();
+
+        Progress {
+            fraction: if total == 0 { 1.0 } else { (self.position - self.start) as f64 / total as f64 },
+            instruction,
+            pos: instruction.map(|instruction| self.renderer.program.get_instructions()[instruction].pos),
+            peak: self.peak,
+        }
+    }
+
+    fn report_progress(&mut self) {
+        let progress = self.progress();
+
+        if let Some(callback) = self.progress.as_mut() {
+            callback(progress);
+        };
+    }
+
+    pub fn fill(&mut self, buffer: &mut [f32]) -> usize {
+        let mut filled = 0;
+
+        for (slot, sample) in buffer.iter_mut().zip(self.by_ref()) {
+            *slot = sample;
+            filled += 1;
+        };
+
+        filled
+    }
+}
+
+
+impl Iterator for SampleIterator<'_> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let Some(sample) = self.renderer.next() else {
+            if let Some(mut view) = self.view.take() {
+                view.finish();
+            };
+
+            if self.progress.is_some() {
+                self.report_progress();
+                self.progress = None;
+            };
+
+            return None;
+        };
+        let sample = (sample * self.fades.gain(self.position) * self.gain) as f32;
+        self.peak = self.peak.max(sample.abs());
+
+        if let Some(view) = self.view.as_mut() {
+            view.update(self.position, sample);
+        };
+
+        self.channel = (self.channel + 1) % self.fades.channels;
+        if self.channel > 0 {
+            return Some(sample);
+        };
+
+        self.position += 1;
+
+        if self.progress.is_some() && (self.position - self.start).is_multiple_of(PROGRESS_INTERVAL) {
+            self.report_progress();
+        };
+
+        Some(sample)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.fades.end.saturating_sub(self.position)
+            .saturating_mul(self.fades.channels)
+            .saturating_sub(self.channel);
+
+        (remaining, Some(remaining))
+    }
+}
+
+
+impl ExactSizeIterator for SampleIterator<'_> { }
+
+
+pub fn render(program: &Program, options: &RenderOptions) -> Result<Vec<f64>, TooLong> {
+    Ok(SampleIterator::new(program, options)?.map(f64::from).collect())
+}
@@ -0,0 +1,134 @@
+//! RTTTL, the ringtone format of old Nokia phones.
+//!
+//! Ringtones play one note at a time, so of notes starting together only the highest is kept and a note
+//! stops when the next one starts. Lengths are rounded to 1/64 of a whole note, the shortest a dotted
+//! 32nd note can be split into, and a note too long for a dotted whole note is held by a pause after it
+
+use std::io::{self, Write};
+use crate::compiler::Program;
+use crate::interpreter::{Backend, RenderOptions};
+use crate::interpreter::midi::frequency_to_note;
+
+
+/// RTTTL names of the twelve keys of an octave
+const NOTE_NAMES: [&str; 12] = ["c", "c#", "d", "d#", "e", "f", "f#", "g", "g#", "a", "a#", "b"];
+/// Lengths in 64ths of a whole note that a note or pause can have, with their duration and whether
+/// they're dotted, longest first
+const LENGTHS: [(u64, u32, bool); 12] = [
+    (96, 1, true), (64, 1, false), (48, 2, true), (32, 2, false), (24, 4, true), (16, 4, false),
+    (12, 8, true), (8, 8, false), (6, 16, true), (4, 16, false), (3, 32, true), (2, 32, false),
+];
+
+
+pub struct Rtttl<W>(pub W);
+
+
+impl<W: Write> Backend for Rtttl<W> {
+    type Output = ();
+    type Error = io::Error;
+
+    fn render(&mut self, program: &Program, options: &RenderOptions) -> io::Result<()> {
+        options.check_length(program)?;
+
+        writeln!(self.0, "{}", interpret(program))
+    }
+}
+
+
+/// A note or, without a key, a pause
+struct Item {
+    key: Option<u8>,
+    duration: u32,
+    dotted: bool,
+}
+
+
+/// Splits `length` 64ths into the longest lengths RTTTL has, leaving out a 64th it can't write
+fn split(mut length: u64) -> Vec<(u32, bool)> {
+    let mut parts = Vec::new();
+
+    while let Some((value, duration, dotted)) = LENGTHS.iter().find(|(value, _, _)| *value <= length) {
+        parts.push((*duration, *dotted));
+        length -= value;
+    };
+
+    parts
+}
+
+
+pub fn interpret(program: &Program) -> String {
+    // a quarter note per beat, at the tempo of the whole script
+    let beats = program.get_bpm().round().max(1.0);
+    let sixty_fourths = |seconds: f64| (seconds / (240.0 / beats) * 64.0).round().max(0.0) as u64;
+
+    let mut events = program.get_note_events();
+    events.sort_by(|a, b| a.start.total_cmp(&b.start).then(b.frequency.total_cmp(&a.frequency)));
+    events.dedup_by_key(|event| sixty_fourths(event.start));
+
+    let mut items = Vec::new();
+    let mut time = 0;
+    for (i, event) in events.iter().enumerate() {
+        let start = sixty_fourths(event.start);
+        let end = sixty_fourths(event.start + event.duration)
+            .min(events.get(i + 1).map_or(u64::MAX, |next| sixty_fourths(next.start)));
+
+        items.extend(split(start.saturating_sub(time)).into_iter().map(|(duration, dotted)| Item { key: None, duration, dotted }));
+
+        let mut parts = split(end.saturating_sub(start)).into_iter();
+        if let Some((duration, dotted)) = parts.next() {
+            items.push(Item { key: Some(frequency_to_note(event.frequency)), duration, dotted });
+        };
+        items.extend(parts.map(|(duration, dotted)| Item { key: None, duration, dotted }));
+
+        time = time.max(end).max(start);
+    };
+
+    // the most common length and octave go in the defaults so the notes can leave them out
+    let most_common = |values: Vec<u32>, fallback| {
+        let mut counts = Vec::<(u32, usize)>::new();
+        for value in values {
+            match counts.iter_mut().find(|(counted, _)| *counted == value) {
+                Some((_, count)) => *count += 1,
+                None => counts.push((value, 1)),
+            };
+        };
+
+        counts.into_iter().max_by_key(|(value, count)| (*count, u32::MAX - value)).map_or(fallback, |(value, _)| value)
+    };
+    let duration = most_common(items.iter().map(|item| item.duration).collect(), 4);
+    let octave = most_common(items.iter().filter_map(|item| item.key).map(|key| (key as u32 / 12).saturating_sub(1)).collect(), 5);
+
+    let name = program.get_metadata().title.as_deref()
+        .map(|title| title.chars().filter(|c| !matches!(c, ':' | ',')).collect::<String>())
+        .filter(|title| !title.trim().is_empty())
+        .unwrap_or_else(|| String::from("roorle"));
+
+    let notes = items.iter()
+        .map(|item| {
+            let mut note = String::new();
+            if item.duration != duration {
+                note.push_str(&item.duration.to_string());
+            };
+
+            match item.key {
+                Some(key) => {
+                    note.push_str(NOTE_NAMES[key as usize % 12]);
+
+                    let key_octave = (key as u32 / 12).saturating_sub(1);
+                    if key_octave != octave {
+                        note.push_str(&key_octave.to_string());
+                    };
+                },
+                None => note.push('p'),
+            };
+
+            if item.dotted {
+                note.push('.');
+            };
+
+            note
+        })
+        .collect::<Vec<_>>();
+
+    format!("{}:d={duration},o={octave},b={beats}:{}", name.trim(), notes.join(","))
+}
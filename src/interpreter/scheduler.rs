@@ -0,0 +1,84 @@
+use std::collections::HashMap;
+use crate::compiler::{InstructionData, Program};
+
+
+#[derive(Copy, Clone, Debug)]
+pub struct Event {
+    pub sample: usize,
+    pub instruction: usize,
+    pub kind: EventKind,
+}
+
+
+#[derive(Copy, Clone, Debug)]
+pub enum EventKind {
+    NoteOn {
+        id: usize,
+        frequency: f64,
+        duration: f64,
+        instrument: usize,
+        /// Frequency the note glides from, for instruments with portamento
+        glide: Option<f64>,
+    },
+    NoteOff {
+        id: usize,
+    },
+}
+
+
+pub fn schedule(program: &Program, sample_rate: u32) -> Vec<Event> {
+    let mut events = Vec::new();
+
+    let mut time = 0.0;
+    let mut notes = 0;
+    // by label, when its last chord started, the chord before it and the notes of the last one so far. Copies
+    // of a label's instrument for commands with overrides share its name, so they glide along with it
+    let mut chords = HashMap::new();
+    for (i, instruction) in program.get_instructions().iter().enumerate() {
+        match instruction.data {
+            InstructionData::Play { frequency, duration, instrument } => {
+                let settings = &program.get_instruments()[instrument];
+                let glide = if settings.portamento > 0.0 {
+                    let (start, previous, current) = chords.entry(settings.name.as_deref()).or_insert((time, Vec::new(), Vec::new()));
+                    if time > *start {
+                        *start = time;
+                        *previous = std::mem::take(current);
+                    };
+
+                    // notes of a chord glide from the ones at the same place in the chord before, or its last
+                    let glide = previous.get(current.len()).or(previous.last()).copied();
+                    current.push(frequency);
+
+                    glide
+                } else {
+                    None
+                };
+
+                events.push(Event {
+                    sample: (time * sample_rate as f64).round() as usize,
+                    instruction: i,
+                    kind: EventKind::NoteOn { id: notes, frequency, duration, instrument, glide },
+                });
+                events.push(Event {
+                    sample: ((time + duration) * sample_rate as f64).round() as usize,
+                    instruction: i,
+                    kind: EventKind::NoteOff { id: notes },
+                });
+
+                notes += 1;
+            },
+            InstructionData::Advance { duration } => time += duration,
+            InstructionData::EnterLabel { .. }
+            | InstructionData::ExitLabel { .. }
+            | InstructionData::Mark
+            | InstructionData::Tempo { .. }
+            | InstructionData::Meter { .. }
+            | InstructionData::Automate { .. } => { },
+        };
+    };
+
+    // stable, so a note-on always precedes the note-off of the same note
+    events.sort_by_key(|event| event.sample);
+
+    events
+}
@@ -0,0 +1,113 @@
+use std::io::Write;
+use crate::compiler::{self, InstructionData, NoteEvent, Program};
+use crate::interpreter::RenderOptions;
+use crate::pitch::Pitch;
+
+
+const REFRESH_RATE: usize = 20;
+const METER_WIDTH: usize = 30;
+const METER_FLOOR: f64 = -60.0;
+
+
+pub struct TerminalView {
+    sample_rate: u32,
+    /// Time every bar starts at
+    bars: Vec<f64>,
+    /// Time the tempo or the meter changes at, with how long a beat lasts from then on and how many
+    /// there are in a bar
+    beats: Vec<(f64, f64, u32)>,
+    notes: Vec<NoteEvent>,
+    next_note: usize,
+    active: Vec<NoteEvent>,
+    interval: usize,
+    counter: usize,
+    peak: f32,
+}
+
+
+impl TerminalView {
+    pub fn new(program: &Program, options: &RenderOptions) -> Self {
+        let mut notes = program.get_note_events();
+        notes.sort_by(|a, b| a.start.total_cmp(&b.start));
+
+        let mut beats = Vec::new();
+        let (mut time, mut bpm, mut meter) = (0.0, None, None);
+        for instruction in program.get_instructions() {
+            match instruction.data {
+                InstructionData::Tempo { bpm: tempo } => bpm = Some(tempo),
+                InstructionData::Meter { numerator, denominator } => meter = Some((numerator, denominator)),
+                InstructionData::Advance { duration } => {
+                    time += duration;
+                    continue;
+                },
+                _ => continue,
+            };
+
+            if let (Some(bpm), Some((numerator, denominator))) = (bpm, meter) {
+                beats.push((time, compiler::note_duration(bpm, 1.0 / denominator as f64), numerator));
+            };
+        };
+
+        Self {
+            sample_rate: options.sample_rate,
+            bars: program.get_bars(),
+            beats,
+            notes,
+            next_note: 0,
+            active: Vec::new(),
+            interval: (options.sample_rate as usize * options.channels as usize / REFRESH_RATE).max(1),
+            counter: 0,
+            peak: 0.0,
+        }
+    }
+
+    pub fn update(&mut self, position: usize, sample: f32) {
+        self.peak = self.peak.max(sample.abs());
+        self.counter += 1;
+
+        if self.counter < self.interval {
+            return;
+        };
+
+        let seconds = position as f64 / self.sample_rate as f64;
+        self.draw(seconds);
+
+        self.counter = 0;
+        self.peak = 0.0;
+    }
+
+    pub fn finish(&mut self) {
+        eprintln!();
+    }
+
+    fn draw(&mut self, seconds: f64) {
+        while let Some(note) = self.notes.get(self.next_note) && note.start <= seconds {
+            self.active.push(*note);
+            self.next_note += 1;
+        };
+        self.active.retain(|note| note.start + note.duration > seconds);
+
+        let bar = self.bars.partition_point(|start| *start <= seconds + 1e-9).max(1);
+        let start = self.bars.get(bar - 1).copied().unwrap_or(0.0);
+        let beat = match self.beats.iter().rev().find(|(time, ..)| *time <= start + 1e-9) {
+            Some((_, length, beats)) if *length > 0.0 => (((seconds - start) / length) as u32 + 1).min(*beats),
+            _ => 1,
+        };
+
+        let notes = self.active.iter()
+            .map(|note| Pitch::from_frequency(note.frequency).to_string())
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        let decibels = 20.0 * (self.peak as f64).max(f64::MIN_POSITIVE).log10();
+        let filled = ((1.0 - decibels / METER_FLOOR).clamp(0.0, 1.0) * METER_WIDTH as f64).round() as usize;
+
+        eprint!(
+            "\r\x1b[2K{seconds:7.2}s  bar {bar:3} beat {beat}  [{}{}] {:6.1} dB  {notes}",
+            "#".repeat(filled),
+            " ".repeat(METER_WIDTH - filled),
+            decibels.max(METER_FLOOR),
+        );
+        let _ = std::io::stderr().flush();
+    }
+}
@@ -0,0 +1,371 @@
+use std::fmt::Write as _;
+use std::io::{self, Write};
+use crate::compiler::Program;
+use crate::interpreter::{render, Backend, RenderOptions, TooLong};
+use crate::interpreter::midi::frequency_to_note;
+use crate::pitch::Pitch;
+
+
+const PIXELS_PER_SECOND: f64 = 100.0;
+const ROW_HEIGHT: f64 = 8.0;
+const MARGIN: f64 = 32.0;
+const FFT_SIZE: usize = 2048;
+const SPECTROGRAM_FLOOR: f64 = -120.0;
+
+const BACKGROUND: [u8; 3] = [0xFF, 0xFF, 0xFF];
+const BLACK_KEY_ROW: [u8; 3] = [0xEE, 0xEE, 0xEE];
+const BAR_LINE: [u8; 3] = [0x99, 0x99, 0x99];
+const PALETTE: [[u8; 3]; 6] = [
+    [0x1F, 0x77, 0xB4],
+    [0xFF, 0x7F, 0x0E],
+    [0x2C, 0xA0, 0x2C],
+    [0xD6, 0x27, 0x28],
+    [0x94, 0x67, 0xBD],
+    [0x8C, 0x56, 0x4B],
+];
+
+
+#[derive(Copy, Clone, Debug)]
+struct Rect {
+    x: f64,
+    y: f64,
+    width: f64,
+    height: f64,
+    color: [u8; 3],
+}
+
+
+struct Scene {
+    width: f64,
+    height: f64,
+    rects: Vec<Rect>,
+    labels: Vec<(f64, String)>,
+}
+
+
+impl Scene {
+    pub fn piano_roll(program: &Program) -> Self {
+        let notes = program.get_note_events();
+
+        let (lowest, highest) = notes.iter()
+            .map(|note| frequency_to_note(note.frequency))
+            .fold((u8::MAX, u8::MIN), |(lowest, highest), note| (lowest.min(note), highest.max(note)));
+        let (lowest, highest) = if notes.is_empty() { (60, 72) } else { (lowest.saturating_sub(2), highest.saturating_add(2).min(127)) };
+
+        let width = MARGIN + program.get_duration() * PIXELS_PER_SECOND;
+        let height = (highest - lowest + 1) as f64 * ROW_HEIGHT;
+        let row_y = |note: u8| (highest - note) as f64 * ROW_HEIGHT;
+
+        let mut rects = Vec::new();
+        let mut labels = Vec::new();
+
+        for note in lowest..=highest {
+            let pitch = Pitch::from_key(note as i32);
+            if pitch.is_sharp() {
+                rects.push(Rect { x: 0.0, y: row_y(note), width, height: ROW_HEIGHT, color: BLACK_KEY_ROW });
+            };
+
+            if note % 12 == 0 {
+                labels.push((row_y(note) + ROW_HEIGHT, pitch.to_string()));
+            };
+        };
+
+        let bar_duration = program.get_bar_duration();
+        if bar_duration > 0.0 {
+            let mut bar = 0.0;
+            while bar <= program.get_duration() {
+                rects.push(Rect { x: MARGIN + bar * PIXELS_PER_SECOND, y: 0.0, width: 1.0, height, color: BAR_LINE });
+
+                bar += bar_duration;
+            };
+        };
+
+        for note in notes.iter() {
+            rects.push(Rect {
+                x: MARGIN + note.start * PIXELS_PER_SECOND,
+                y: row_y(frequency_to_note(note.frequency)) + 1.0,
+                width: (note.duration * PIXELS_PER_SECOND - 1.0).max(1.0),
+                height: ROW_HEIGHT - 2.0,
+                color: PALETTE[note.instrument % PALETTE.len()],
+            });
+        };
+
+        Self { width, height, rects, labels }
+    }
+
+    pub fn to_svg(&self) -> String {
+        let mut svg = String::new();
+
+        let _ = writeln!(svg, r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}">"#, self.width.ceil(), self.height.ceil());
+        let _ = writeln!(svg, r#"<rect width="100%" height="100%" fill="{}"/>"#, hex(BACKGROUND));
+
+        for rect in self.rects.iter() {
+            let _ = writeln!(svg, r#"<rect x="{:.1}" y="{:.1}" width="{:.1}" height="{:.1}" fill="{}"/>"#, rect.x, rect.y, rect.width, rect.height, hex(rect.color));
+        };
+
+        for (y, text) in self.labels.iter() {
+            let _ = writeln!(svg, r#"<text x="2" y="{:.1}" font-family="monospace" font-size="{}">{text}</text>"#, y - 1.0, ROW_HEIGHT);
+        };
+
+        svg.push_str("</svg>\n");
+
+        svg
+    }
+
+    pub fn to_image(&self) -> Image {
+        let mut image = Image::new(self.width.ceil() as usize, self.height.ceil() as usize, BACKGROUND);
+
+        for rect in self.rects.iter() {
+            image.fill_rect(rect);
+        };
+
+        image
+    }
+}
+
+
+fn fft(re: &mut [f64], im: &mut [f64]) {
+    let n = re.len();
+
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        };
+        j |= bit;
+
+        if i < j {
+            re.swap(i, j);
+            im.swap(i, j);
+        };
+    };
+
+    let mut length = 2;
+    while length <= n {
+        let angle = -2.0 * std::f64::consts::PI / length as f64;
+
+        for start in (0..n).step_by(length) {
+            for k in 0..length / 2 {
+                let (w_im, w_re) = (angle * k as f64).sin_cos();
+                let (a, b) = (start + k, start + k + length / 2);
+
+                let t_re = re[b] * w_re - im[b] * w_im;
+                let t_im = re[b] * w_im + im[b] * w_re;
+
+                re[b] = re[a] - t_re;
+                im[b] = im[a] - t_im;
+                re[a] += t_re;
+                im[a] += t_im;
+            };
+        };
+
+        length <<= 1;
+    };
+}
+
+
+fn heat(level: f64) -> [u8; 3] {
+    let level = level.clamp(0.0, 1.0);
+
+    let channel = |from: f64, to: f64| ((level - from) / (to - from)).clamp(0.0, 1.0) * 255.0;
+
+    [channel(0.25, 0.6) as u8, channel(0.6, 0.95) as u8, (channel(0.0, 0.3) - channel(0.45, 0.7)).max(channel(0.9, 1.0)) as u8]
+}
+
+
+fn hex(color: [u8; 3]) -> String {
+    format!("#{:02x}{:02x}{:02x}", color[0], color[1], color[2])
+}
+
+
+struct Image {
+    width: usize,
+    height: usize,
+    pixels: Vec<[u8; 3]>,
+}
+
+
+impl Image {
+    pub fn new(width: usize, height: usize, color: [u8; 3]) -> Self {
+        Self { width, height, pixels: vec![color; width * height] }
+    }
+
+    pub fn fill_rect(&mut self, rect: &Rect) {
+        let x_range = (rect.x.round().max(0.0) as usize).min(self.width)..((rect.x + rect.width).round().max(0.0) as usize).min(self.width);
+        let y_range = (rect.y.round().max(0.0) as usize).min(self.height)..((rect.y + rect.height).round().max(0.0) as usize).min(self.height);
+
+        for y in y_range {
+            self.pixels[y * self.width + x_range.start..y * self.width + x_range.end].fill(rect.color);
+        };
+    }
+
+    pub fn to_png(&self) -> Vec<u8> {
+        let mut raw = Vec::with_capacity((self.width * 3 + 1) * self.height);
+        for row in self.pixels.chunks(self.width.max(1)) {
+            raw.push(0);
+            row.iter().for_each(|pixel| raw.extend_from_slice(pixel));
+        };
+
+        let mut header = Vec::new();
+        header.append(&mut (self.width as u32).to_be_bytes().to_vec());
+        header.append(&mut (self.height as u32).to_be_bytes().to_vec());
+        header.append(&mut vec![8, 2, 0, 0, 0]);
+
+        let mut buffer = b"\x89PNG\r\n\x1a\n".to_vec();
+
+        png_chunk(&mut buffer, b"IHDR", &header);
+        png_chunk(&mut buffer, b"IDAT", &zlib_stored(&raw));
+        png_chunk(&mut buffer, b"IEND", &[]);
+
+        buffer
+    }
+}
+
+
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = u32::MAX;
+
+    for byte in bytes {
+        crc ^= *byte as u32;
+
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xEDB88320 } else { crc >> 1 };
+        };
+    };
+
+    !crc
+}
+
+
+fn adler32(bytes: &[u8]) -> u32 {
+    let (mut a, mut b) = (1_u32, 0_u32);
+
+    for byte in bytes {
+        a = (a + *byte as u32) % 65521;
+        b = (b + a) % 65521;
+    };
+
+    (b << 16) | a
+}
+
+
+fn zlib_stored(bytes: &[u8]) -> Vec<u8> {
+    let mut buffer = vec![0x78, 0x01];
+
+    let mut blocks = bytes.chunks(u16::MAX as usize).peekable();
+    if blocks.peek().is_none() {
+        buffer.append(&mut vec![1, 0, 0, 0xFF, 0xFF]);
+    };
+
+    while let Some(block) = blocks.next() {
+        buffer.push(blocks.peek().is_none() as u8);
+        buffer.append(&mut (block.len() as u16).to_le_bytes().to_vec());
+        buffer.append(&mut (!(block.len() as u16)).to_le_bytes().to_vec());
+        buffer.extend_from_slice(block);
+    };
+
+    buffer.append(&mut adler32(bytes).to_be_bytes().to_vec());
+
+    buffer
+}
+
+
+fn png_chunk(buffer: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    let mut chunk = kind.to_vec();
+    chunk.extend_from_slice(data);
+
+    buffer.append(&mut (data.len() as u32).to_be_bytes().to_vec());
+    buffer.append(&mut chunk.clone());
+    buffer.append(&mut crc32(&chunk).to_be_bytes().to_vec());
+}
+
+
+#[derive(Copy, Clone, Debug)]
+pub enum ImageFormat {
+    Svg,
+    Png,
+}
+
+
+pub struct PianoRoll<W> {
+    pub writer: W,
+    pub format: ImageFormat,
+}
+
+
+impl<W: Write> Backend for PianoRoll<W> {
+    type Output = ();
+    type Error = io::Error;
+
+    fn render(&mut self, program: &Program, options: &RenderOptions) -> io::Result<()> {
+        options.check_length(program)?;
+
+        match self.format {
+            ImageFormat::Svg => self.writer.write_all(piano_roll_svg(program).as_bytes()),
+            ImageFormat::Png => self.writer.write_all(&piano_roll_png(program)),
+        }
+    }
+}
+
+
+pub struct Spectrogram<W>(pub W);
+
+
+impl<W: Write> Backend for Spectrogram<W> {
+    type Output = ();
+    type Error = io::Error;
+
+    fn render(&mut self, program: &Program, options: &RenderOptions) -> io::Result<()> {
+        self.0.write_all(&spectrogram_png(program, options)?)
+    }
+}
+
+
+pub fn piano_roll_svg(program: &Program) -> String {
+    Scene::piano_roll(program).to_svg()
+}
+
+
+pub fn piano_roll_png(program: &Program) -> Vec<u8> {
+    Scene::piano_roll(program).to_image().to_png()
+}
+
+
+pub fn spectrogram_png(program: &Program, options: &RenderOptions) -> Result<Vec<u8>, TooLong> {
+    let samples = render::render(program, &RenderOptions { channels: 1, ..*options })?;
+
+    let hop = ((options.sample_rate as f64 / PIXELS_PER_SECOND).round() as usize).max(1);
+    let window = (0..FFT_SIZE)
+        .map(|i| 0.5 - 0.5 * (2.0 * std::f64::consts::PI * i as f64 / FFT_SIZE as f64).cos())
+        .collect::<Vec<_>>();
+    let scale = 2.0 / window.iter().sum::<f64>();
+
+    let columns = samples.len().div_ceil(hop).max(1);
+    let rows = FFT_SIZE / 2;
+
+    let mut image = Image::new(columns, rows, [0, 0, 0]);
+    for column in 0..columns {
+        let mut re = vec![0.0; FFT_SIZE];
+        let mut im = vec![0.0; FFT_SIZE];
+
+        let center = column * hop;
+        for (i, value) in re.iter_mut().enumerate() {
+            if let Some(sample) = (center + i).checked_sub(FFT_SIZE / 2).and_then(|at| samples.get(at)) {
+                *value = sample * window[i];
+            };
+        };
+
+        fft(&mut re, &mut im);
+
+        for bin in 0..rows {
+            let magnitude = (re[bin].powi(2) + im[bin].powi(2)).sqrt() * scale;
+            let decibels = 20.0 * magnitude.max(f64::MIN_POSITIVE).log10();
+
+            image.pixels[(rows - 1 - bin) * columns + column] = heat(1.0 - decibels / SPECTROGRAM_FLOOR);
+        };
+    };
+
+    Ok(image.to_png())
+}
@@ -0,0 +1,367 @@
+use std::collections::HashMap;
+use std::sync::{LazyLock, RwLock};
+use crate::compiler::{Filter, Instrument, Lfo, LfoTarget, NoiseColor, Parameter, Unison};
+use crate::interpreter::{math, RenderOptions};
+use crate::interpreter::effects::Biquad;
+
+
+const SINE_TABLE_SIZE: usize = 4096;
+
+static SINE_TABLE: LazyLock<Vec<f64>> = LazyLock::new(|| {
+    (0..=SINE_TABLE_SIZE)
+        .map(|i| math::sin(i as f64 / SINE_TABLE_SIZE as f64))
+        .collect()
+});
+
+static REGISTRY: LazyLock<RwLock<HashMap<String, Box<VoiceFactory>>>> = LazyLock::new(|| RwLock::new(HashMap::new()));
+
+
+pub type VoiceFactory = dyn Fn(&VoiceSettings) -> Box<dyn Voice> + Send + Sync;
+
+
+pub trait Voice {
+    fn sample(&mut self, dt: f64) -> f64;
+
+    fn note_off(&mut self);
+
+    fn is_finished(&self) -> bool;
+
+    fn skip(&mut self, dt: f64, samples: usize) {
+        for _ in 0..samples {
+            self.sample(dt);
+        };
+    }
+
+    /// Moves `parameter` to `value` as `automate` has it at the sample played next. Voices that don't
+    /// have the parameter leave it be
+    fn automate(&mut self, _parameter: Parameter, _value: f64) { }
+}
+
+
+pub struct VoiceSettings<'a> {
+    pub frequency: f64,
+    pub duration: f64,
+    /// Frequency to glide from over the instrument's portamento, if the note has one before it
+    pub glide: Option<f64>,
+    pub instrument: &'a Instrument,
+    pub options: &'a RenderOptions,
+}
+
+
+pub fn sine(phase: f64, table: bool) -> f64 {
+    if table {
+        let position = phase * SINE_TABLE_SIZE as f64;
+        let index = position as usize;
+        let fraction = position - index as f64;
+
+        SINE_TABLE[index] + (SINE_TABLE[index + 1] - SINE_TABLE[index]) * fraction
+    } else {
+        (2.0 * std::f64::consts::PI * phase).sin()
+    }
+}
+
+
+fn powf(base: f64, exponent: f64, deterministic: bool) -> f64 {
+    if deterministic { math::powf(base, exponent) } else { base.powf(exponent) }
+}
+
+
+pub fn register<F>(name: &str, factory: F)
+    where F: Fn(&VoiceSettings) -> Box<dyn Voice> + Send + Sync + 'static
+{
+    REGISTRY.write().expect("voice registry isn't poisoned").insert(String::from(name), Box::new(factory));
+}
+
+
+/// Whether `voice: name` plays a voice of its own rather than falling back to a sine
+pub fn exists(name: &str) -> bool {
+    name == SineVoice::NAME || name == NoiseVoice::NAME || REGISTRY.read().expect("voice registry isn't poisoned").contains_key(name)
+}
+
+
+pub fn create(settings: &VoiceSettings) -> Box<dyn Voice> {
+    if let Some(name) = settings.instrument.voice.as_ref()
+        && let Some(factory) = REGISTRY.read().expect("voice registry isn't poisoned").get(name)
+    {
+        return factory(settings);
+    };
+
+    match settings.instrument.voice.as_deref() {
+        Some(NoiseVoice::NAME) => Box::new(NoiseVoice::new(settings)),
+        _ => Box::new(SineVoice::new(settings)),
+    }
+}
+
+
+/// The instrument's filter, opened up for the velocity its notes are played at
+fn brightened(instrument: &Instrument) -> Option<Filter> {
+    instrument.filter.map(|filter| Filter { cutoff: filter.cutoff + instrument.velocity_brightness(), ..filter })
+}
+
+
+/// Moves the voice's filter to `cutoff` hertz, which its LFOs then move around
+fn retune(filter: Option<&mut Biquad>, settings: Option<&mut Filter>, cutoff: f64, sample_rate: u32) {
+    let cutoff = cutoff.max(1.0);
+
+    if let (Some(filter), Some(settings)) = (filter, settings) && settings.cutoff != cutoff {
+        settings.cutoff = cutoff;
+        filter.retune(settings, sample_rate);
+    };
+}
+
+
+/// `value` through the voice's filter, its cutoff moved by `cutoff_delta` hertz
+fn filter(filter: Option<&mut Biquad>, settings: Option<Filter>, cutoff_delta: f64, sample_rate: u32, value: f64) -> f64 {
+    match filter {
+        None => value,
+        Some(filter) => {
+            if cutoff_delta != 0.0 && let Some(settings) = settings {
+                filter.retune(&Filter { cutoff: (settings.cutoff + cutoff_delta).max(1.0), ..settings }, sample_rate);
+            };
+
+            filter.process(value)
+        },
+    }
+}
+
+
+#[derive(Clone, Debug)]
+pub struct SineVoice {
+    frequency: f64,
+    glide: Option<(f64, f64)>,
+    volume: f64,
+    /// How much of the instrument's volume and how many more hertz of its cutoff the note has for its velocity
+    velocity: (f64, f64),
+    lfos: Vec<Lfo>,
+    filter_settings: Option<Filter>,
+    filter: Option<Biquad>,
+    sample_rate: u32,
+    sine_table: bool,
+    deterministic: bool,
+    /// Frequency ratio and phase of every copy of the oscillator, just the one without unison
+    oscillators: Vec<(f64, f64)>,
+    samples: u64,
+    released: bool,
+}
+
+
+impl SineVoice {
+    /// Played for `voice: sine`, and for voices that aren't registered
+    pub const NAME: &'static str = "sine";
+
+    pub fn new(settings: &VoiceSettings) -> Self {
+        let sample_rate = settings.options.sample_rate;
+
+        Self {
+            frequency: settings.frequency,
+            glide: settings.glide
+                .filter(|_| settings.instrument.portamento > 0.0)
+                .map(|from| (from, settings.instrument.portamento)),
+            volume: settings.instrument.volume * settings.instrument.velocity_gain(),
+            velocity: (settings.instrument.velocity_gain(), settings.instrument.velocity_brightness()),
+            lfos: settings.instrument.lfos.clone(),
+            filter_settings: brightened(settings.instrument),
+            filter: brightened(settings.instrument).as_ref().map(|filter| Biquad::new(filter, settings.options)),
+            sample_rate,
+            sine_table: settings.options.uses_sine_table(),
+            deterministic: settings.options.deterministic,
+            oscillators: match settings.instrument.unison {
+                // starting together so the note is as loud as without unison, drifting apart as it plays
+                Some(Unison { voices, detune }) if voices > 1 => (0..voices)
+                    .map(|i| detune * (i as f64 / (voices - 1) as f64 - 0.5))
+                    .map(|cents| (powf(2.0, cents / 1200.0, settings.options.deterministic), 0.0))
+                    .collect(),
+                _ => vec![(1.0, 0.0)],
+            },
+            samples: 0,
+            released: false,
+        }
+    }
+}
+
+
+impl Voice for SineVoice {
+    fn sample(&mut self, dt: f64) -> f64 {
+        self.samples += 1;
+        let elapsed = self.samples as f64 * dt;
+
+        // the glide is even in pitch rather than in frequency, like a slide between keys
+        let mut frequency = match self.glide {
+            Some((from, time)) if elapsed < time => from * powf(self.frequency / from, elapsed / time, self.deterministic),
+            _ => self.frequency,
+        };
+        let mut amplitude = 1.0;
+        let mut cutoff_delta = 0.0;
+        for lfo in self.lfos.iter() {
+            let wave = sine((elapsed * lfo.rate).fract(), self.sine_table);
+
+            match lfo.target {
+                LfoTarget::Pitch => frequency *= powf(2.0, lfo.depth * wave / 1200.0, self.deterministic),
+                LfoTarget::Amplitude => amplitude *= 1.0 - lfo.depth * (1.0 + wave) / 2.0,
+                LfoTarget::Cutoff => cutoff_delta += lfo.depth * wave,
+                LfoTarget::Pan => { },
+            };
+        };
+
+        let mut value = 0.0;
+        for (ratio, phase) in self.oscillators.iter_mut() {
+            value += sine(*phase, self.sine_table);
+            *phase = (*phase + frequency * *ratio * dt).fract();
+        };
+        let value = value / self.oscillators.len() as f64 * self.volume * amplitude;
+
+        filter(self.filter.as_mut(), self.filter_settings, cutoff_delta, self.sample_rate, value)
+    }
+
+    fn note_off(&mut self) {
+        self.released = true;
+    }
+
+    fn is_finished(&self) -> bool {
+        self.released
+    }
+
+    fn skip(&mut self, dt: f64, mut samples: usize) {
+        if let Some((_, time)) = self.glide {
+            let gliding = ((time / dt).ceil() as usize).saturating_sub(self.samples as usize).min(samples);
+            for _ in 0..gliding {
+                self.sample(dt);
+            };
+            samples -= gliding;
+        };
+
+        self.samples += samples as u64;
+        for (ratio, phase) in self.oscillators.iter_mut() {
+            *phase = (*phase + self.frequency * *ratio * samples as f64 * dt).fract();
+        };
+    }
+
+    fn automate(&mut self, parameter: Parameter, value: f64) {
+        match parameter {
+            Parameter::Volume => self.volume = value * self.velocity.0,
+            Parameter::Cutoff => retune(self.filter.as_mut(), self.filter_settings.as_mut(), value + self.velocity.1, self.sample_rate),
+            // panned by the mixer once the voices of the instrument are mixed
+            Parameter::Pan => { },
+        };
+    }
+}
+
+
+/// Noise of the instrument's `noise` color, played with `voice: noise` whatever the note. Every note
+/// gets the same noise for the same pitch, duration and seed, so renders don't change from one run to another
+#[derive(Clone, Debug)]
+pub struct NoiseVoice {
+    color: NoiseColor,
+    volume: f64,
+    velocity: (f64, f64),
+    lfos: Vec<Lfo>,
+    filter_settings: Option<Filter>,
+    filter: Option<Biquad>,
+    sample_rate: u32,
+    sine_table: bool,
+    /// State of the xorshift generator behind the white noise
+    state: u64,
+    /// Poles of the filter turning white noise pink, or the running sum turning it brown
+    poles: [f64; 7],
+    samples: u64,
+    released: bool,
+}
+
+
+impl NoiseVoice {
+    pub const NAME: &'static str = "noise";
+
+    pub fn new(settings: &VoiceSettings) -> Self {
+        let sample_rate = settings.options.sample_rate;
+        let seed = settings.frequency.to_bits() ^ settings.duration.to_bits().rotate_left(32) ^ settings.options.seed ^ 0x9E37_79B9_7F4A_7C15;
+
+        Self {
+            color: settings.instrument.noise,
+            volume: settings.instrument.volume * settings.instrument.velocity_gain(),
+            velocity: (settings.instrument.velocity_gain(), settings.instrument.velocity_brightness()),
+            lfos: settings.instrument.lfos.clone(),
+            filter_settings: brightened(settings.instrument),
+            filter: brightened(settings.instrument).as_ref().map(|filter| Biquad::new(filter, settings.options)),
+            sample_rate,
+            sine_table: settings.options.uses_sine_table(),
+            // the generator is stuck at zero
+            state: seed.max(1),
+            poles: [0.0; 7],
+            samples: 0,
+            released: false,
+        }
+    }
+
+    /// White noise between -1 and 1
+    fn white(&mut self) -> f64 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+
+        (self.state >> 11) as f64 / (1_u64 << 53) as f64 * 2.0 - 1.0
+    }
+}
+
+
+impl Voice for NoiseVoice {
+    fn sample(&mut self, dt: f64) -> f64 {
+        self.samples += 1;
+        let elapsed = self.samples as f64 * dt;
+
+        let mut amplitude = 1.0;
+        let mut cutoff_delta = 0.0;
+        for lfo in self.lfos.iter() {
+            let wave = sine((elapsed * lfo.rate).fract(), self.sine_table);
+
+            match lfo.target {
+                LfoTarget::Pitch => { },
+                LfoTarget::Amplitude => amplitude *= 1.0 - lfo.depth * (1.0 + wave) / 2.0,
+                LfoTarget::Cutoff => cutoff_delta += lfo.depth * wave,
+                LfoTarget::Pan => { },
+            };
+        };
+
+        let white = self.white();
+        let noise = match self.color {
+            NoiseColor::White => white,
+            // Paul Kellet's filter, within a few tenths of a decibel of pink above 10 Hz
+            NoiseColor::Pink => {
+                let p = &mut self.poles;
+                p[0] = 0.99886 * p[0] + white * 0.0555179;
+                p[1] = 0.99332 * p[1] + white * 0.0750759;
+                p[2] = 0.96900 * p[2] + white * 0.1538520;
+                p[3] = 0.86650 * p[3] + white * 0.3104856;
+                p[4] = 0.55000 * p[4] + white * 0.5329522;
+                p[5] = -0.7616 * p[5] - white * 0.0168980;
+                let pink = p[0] + p[1] + p[2] + p[3] + p[4] + p[5] + p[6] + white * 0.5362;
+                p[6] = white * 0.115926;
+
+                pink * 0.11
+            },
+            // leaking a little so it doesn't wander off to one side
+            NoiseColor::Brown => {
+                self.poles[0] = (self.poles[0] + white * 0.02) / 1.02;
+
+                self.poles[0] * 3.5
+            },
+        };
+
+        filter(self.filter.as_mut(), self.filter_settings, cutoff_delta, self.sample_rate, noise * self.volume * amplitude)
+    }
+
+    fn note_off(&mut self) {
+        self.released = true;
+    }
+
+    fn is_finished(&self) -> bool {
+        self.released
+    }
+
+    fn automate(&mut self, parameter: Parameter, value: f64) {
+        match parameter {
+            Parameter::Volume => self.volume = value * self.velocity.0,
+            Parameter::Cutoff => retune(self.filter.as_mut(), self.filter_settings.as_mut(), value + self.velocity.1, self.sample_rate),
+            Parameter::Pan => { },
+        };
+    }
+}
@@ -1,95 +1,377 @@
-use std::collections::LinkedList;
-use crate::compiler::{Instruction, InstructionData, Program};
+use std::io::{self, Seek, SeekFrom, Write};
+use crate::compiler::{InstructionData, Metadata, Program};
+use crate::interpreter::{mixing, Backend, RenderOptions, TooLong};
+use crate::interpreter::render::{self, Fades, Progress, Renderer, SampleIterator};
 
 
-#[derive(Clone, Debug)]
-struct Sound {
-    pub frequency: f64,
-    pub started_at: f64,
-    pub ends_at: f64,
-    pub volume: f64,
+const CHUNK_SAMPLES: usize = 4096;
+const HEADER_SIZE: u64 = 44;
+const RF64_HEADER_SIZE: u64 = HEADER_SIZE + 36;
+
+
+fn write_header<W: Write>(writer: &mut W, options: &RenderOptions, data_size: u64, trailing_size: u64, rf64: bool) -> io::Result<()> {
+    let RenderOptions { sample_rate, sample_size, channels, .. } = *options;
+
+    let padded_size = data_size + data_size % 2 + trailing_size;
+
+    let mut buffer = Vec::new();
+
+    if rf64 {
+        buffer.append(&mut b"RF64".to_vec());
+        buffer.append(&mut u32::MAX.to_le_bytes().to_vec());
+        buffer.append(&mut b"WAVE".to_vec());
+        buffer.append(&mut b"ds64".to_vec());
+        buffer.append(&mut 28_u32.to_le_bytes().to_vec());
+        buffer.append(&mut (RF64_HEADER_SIZE - 8 + padded_size).to_le_bytes().to_vec());
+        buffer.append(&mut data_size.to_le_bytes().to_vec());
+        buffer.append(&mut (data_size / (sample_size as u64 / 8 * channels as u64)).to_le_bytes().to_vec());
+        buffer.append(&mut 0_u32.to_le_bytes().to_vec());
+    } else {
+        buffer.append(&mut b"RIFF".to_vec());
+        buffer.append(&mut ((HEADER_SIZE - 8 + padded_size) as u32).to_le_bytes().to_vec());
+        buffer.append(&mut b"WAVE".to_vec());
+    };
+
+    buffer.append(&mut b"fmt\x20".to_vec());
+    buffer.append(&mut 16_u32.to_le_bytes().to_vec());
+    buffer.append(&mut 1_u16.to_le_bytes().to_vec());
+    buffer.append(&mut channels.to_le_bytes().to_vec());
+    buffer.append(&mut sample_rate.to_le_bytes().to_vec());
+    buffer.append(&mut (sample_rate * sample_size as u32 / 8 * channels as u32).to_le_bytes().to_vec());
+    buffer.append(&mut (sample_size as u16 / 8 * channels).to_le_bytes().to_vec());
+    buffer.append(&mut (sample_size as u16).to_le_bytes().to_vec());
+    buffer.append(&mut b"data".to_vec());
+    buffer.append(&mut if rf64 { u32::MAX } else { data_size as u32 }.to_le_bytes().to_vec());
+
+    writer.write_all(&buffer)
+}
+
+
+fn sampler_chunk(options: &RenderOptions, (loop_start, loop_end): (usize, usize)) -> Vec<u8> {
+    let mut buffer = Vec::new();
+
+    buffer.append(&mut b"smpl".to_vec());
+    buffer.append(&mut 60_u32.to_le_bytes().to_vec());
+    buffer.append(&mut 0_u32.to_le_bytes().to_vec());
+    buffer.append(&mut 0_u32.to_le_bytes().to_vec());
+    buffer.append(&mut (1_000_000_000 / options.sample_rate).to_le_bytes().to_vec());
+    buffer.append(&mut 60_u32.to_le_bytes().to_vec());
+    buffer.append(&mut 0_u32.to_le_bytes().to_vec());
+    buffer.append(&mut 0_u32.to_le_bytes().to_vec());
+    buffer.append(&mut 0_u32.to_le_bytes().to_vec());
+    buffer.append(&mut 1_u32.to_le_bytes().to_vec());
+    buffer.append(&mut 0_u32.to_le_bytes().to_vec());
+
+    buffer.append(&mut 0_u32.to_le_bytes().to_vec());
+    buffer.append(&mut 0_u32.to_le_bytes().to_vec());
+    buffer.append(&mut (loop_start as u32).to_le_bytes().to_vec());
+    buffer.append(&mut (loop_end.saturating_sub(1) as u32).to_le_bytes().to_vec());
+    buffer.append(&mut 0_u32.to_le_bytes().to_vec());
+    buffer.append(&mut 0_u32.to_le_bytes().to_vec());
+
+    buffer
 }
 
 
-impl Sound {
-    pub fn get_sine_value_at(&self, seconds: f64) -> f64 {
-        (seconds * 2.0 * std::f64::consts::PI * self.frequency /* - self.started_at */).sin() * self.volume
+fn info_chunk(metadata: &Metadata) -> Vec<u8> {
+    let fields = [
+        (b"INAM", &metadata.title),
+        (b"IART", &metadata.artist),
+        (b"IMUS", &metadata.composer),
+        (b"ICMT", &metadata.comment),
+    ];
+
+    let mut entries = Vec::new();
+    for (id, text) in fields {
+        let Some(text) = text else {
+            continue;
+        };
+
+        let mut text = text.as_bytes().to_vec();
+        text.push(0);
+
+        entries.append(&mut id.to_vec());
+        entries.append(&mut (text.len() as u32).to_le_bytes().to_vec());
+        if text.len() % 2 == 1 {
+            text.push(0);
+        };
+        entries.append(&mut text);
+    };
+
+    let mut buffer = Vec::new();
+
+    buffer.append(&mut b"LIST".to_vec());
+    buffer.append(&mut (4 + entries.len() as u32).to_le_bytes().to_vec());
+    buffer.append(&mut b"INFO".to_vec());
+    buffer.append(&mut entries);
+
+    buffer
+}
+
+
+fn cue_chunks(cues: &[(usize, &str)]) -> Vec<u8> {
+    let mut points = Vec::new();
+    let mut labels = Vec::new();
+
+    for (id, (position, name)) in cues.iter().enumerate() {
+        let id = id as u32 + 1;
+
+        points.append(&mut id.to_le_bytes().to_vec());
+        points.append(&mut (*position as u32).to_le_bytes().to_vec());
+        points.append(&mut b"data".to_vec());
+        points.append(&mut 0_u32.to_le_bytes().to_vec());
+        points.append(&mut 0_u32.to_le_bytes().to_vec());
+        points.append(&mut (*position as u32).to_le_bytes().to_vec());
+
+        let mut text = name.as_bytes().to_vec();
+        text.push(0);
+
+        labels.append(&mut b"labl".to_vec());
+        labels.append(&mut (4 + text.len() as u32).to_le_bytes().to_vec());
+        labels.append(&mut id.to_le_bytes().to_vec());
+        if text.len() % 2 == 1 {
+            text.push(0);
+        };
+        labels.append(&mut text);
+    };
+
+    let mut buffer = Vec::new();
+
+    buffer.append(&mut b"cue ".to_vec());
+    buffer.append(&mut (4 + points.len() as u32).to_le_bytes().to_vec());
+    buffer.append(&mut (cues.len() as u32).to_le_bytes().to_vec());
+    buffer.append(&mut points);
+
+    buffer.append(&mut b"LIST".to_vec());
+    buffer.append(&mut (4 + labels.len() as u32).to_le_bytes().to_vec());
+    buffer.append(&mut b"adtl".to_vec());
+    buffer.append(&mut labels);
+
+    buffer
+}
+
+
+struct StreamWriter {
+    start: u64,
+    rf64: bool,
+    data_size: u64,
+    chunk: Vec<u8>,
+}
+
+
+impl StreamWriter {
+    pub fn begin<W: Write + Seek>(writer: &mut W, program: &Program, options: &RenderOptions) -> io::Result<Self> {
+        let start = writer.stream_position()?;
+
+        let (window_start, window_end) = render::sample_window(program, options);
+        let expected_size = (window_end - window_start) as u64 * (options.sample_size as u64 / 8) * options.channels as u64;
+        let rf64 = HEADER_SIZE - 8 + expected_size + 1 > u32::MAX as u64;
+
+        write_header(writer, options, 0, 0, rf64)?;
+
+        Ok(Self {
+            start,
+            rf64,
+            data_size: 0,
+            chunk: Vec::with_capacity(CHUNK_SAMPLES * options.sample_size as usize / 8),
+        })
+    }
+
+    pub fn push_block<W: Write>(&mut self, writer: &mut W, values: &[f32], options: &RenderOptions) -> io::Result<()> {
+        mixing::quantize_block(values, options.sample_size, &mut self.chunk);
+
+        if self.chunk.len() >= self.chunk.capacity() {
+            writer.write_all(&self.chunk)?;
+            self.data_size += self.chunk.len() as u64;
+            self.chunk.clear();
+        };
+
+        Ok(())
+    }
+
+    pub fn push<W: Write>(&mut self, writer: &mut W, value: f64, options: &RenderOptions) -> io::Result<()> {
+        self.chunk.append(&mut options.sample_size.quantize(value));
+
+        if self.chunk.len() >= self.chunk.capacity() {
+            writer.write_all(&self.chunk)?;
+            self.data_size += self.chunk.len() as u64;
+            self.chunk.clear();
+        };
+
+        Ok(())
+    }
+
+    pub fn finish<W: Write + Seek>(mut self, writer: &mut W, options: &RenderOptions, trailing_chunks: &[u8]) -> io::Result<()> {
+        writer.write_all(&self.chunk)?;
+        self.data_size += self.chunk.len() as u64;
+
+        if self.data_size % 2 == 1 {
+            writer.write_all(&[0])?;
+        };
+
+        writer.write_all(trailing_chunks)?;
+
+        let end = writer.stream_position()?;
+
+        writer.seek(SeekFrom::Start(self.start))?;
+        write_header(writer, options, self.data_size, trailing_chunks.len() as u64, self.rf64)?;
+        writer.seek(SeekFrom::Start(end))?;
+
+        Ok(())
     }
 }
 
 
-#[derive(Copy, Clone, Debug)]
-pub enum SampleSize {
-    Small = 8,
-    Large = 16,
+pub fn interpret_to_writer<W: Write + Seek>(program: &Program, options: &RenderOptions, writer: &mut W) -> io::Result<()> {
+    interpret_to_writer_with_progress(program, options, writer, |_| { })
 }
 
 
-pub fn interpret(program: &Program, sample_rate: u32, sample_size: SampleSize) -> Vec<u8> {
-    let mut samples = {
-        let mut samples = Vec::<u8>::new();
+pub fn interpret_to_writer_with_progress<W, F>(program: &Program, options: &RenderOptions, writer: &mut W, progress: F) -> io::Result<()>
+    where W: Write + Seek, F: FnMut(Progress)
+{
+    let mut samples = SampleIterator::new(program, options)?;
+    samples.on_progress(progress);
 
-        let mut sounds_pull = LinkedList::new();
-        let mut samples_stepped = 0_u32;
-        for instruction in program.get_instructions().iter() {
-            match instruction.data {
-                InstructionData::Play { frequency, duration } => {
-                    let seconds_passed = samples_stepped as f64 / sample_rate as f64;
-
-                    sounds_pull.push_back(Sound {
-                        frequency,
-                        started_at: seconds_passed,
-                        ends_at: seconds_passed + duration,
-                        volume: 1.0,
-                    });
-                },
-                InstructionData::Advance { duration } => {
-                    let samples_to_compute = (duration * sample_rate as f64).round() as u32;
-
-                    for _ in 0..samples_to_compute {
-                        samples_stepped += 1;
-
-                        let seconds_passed = samples_stepped as f64 / sample_rate as f64;
-
-                        for (i, sound) in sounds_pull.clone().iter().enumerate() {
-                            if sound.ends_at < seconds_passed {
-                                sounds_pull.remove(i);
-                            };
-                        };
-
-                        let values = sounds_pull.iter().map(|s| s.get_sine_value_at(seconds_passed)).collect::<Vec<_>>();
-                        let value = values.iter().sum::<f64>() / values.len() as f64;
-
-                        samples.append(&mut match sample_size {
-                            SampleSize::Small => ((i8::MAX as f64 * value).round() as u8 + i8::MAX as u8).to_le_bytes().to_vec(),
-                            SampleSize::Large => ((i16::MAX as f64 * value).round() as i16).to_le_bytes().to_vec(),
-                        });
-                    };
-                },
-            }
+    let mut stream = StreamWriter::begin(writer, program, options)?;
+
+    let mut block = vec![0.0; CHUNK_SAMPLES];
+    loop {
+        let filled = samples.fill(&mut block);
+        if filled == 0 {
+            break;
         };
 
-        samples
+        stream.push_block(writer, &block[..filled], options)?;
+    };
+
+    let mut trailing_chunks = Vec::new();
+
+    if !program.get_metadata().is_empty() {
+        trailing_chunks.append(&mut info_chunk(program.get_metadata()));
+    };
+
+    let (window_start, window_end) = render::sample_window(program, options);
+    let in_window = |instruction| {
+        let offset = render::sample_offset(program, instruction, options.sample_rate);
+
+        (window_start..=window_end).contains(&offset).then(|| offset - window_start)
     };
 
+    let in_render = |instruction| in_window(instruction).filter(|offset| *offset < window_end - window_start);
+
+    // marks are the chapters the script asks for, labels only stand in for them in scripts without any
+    let marks = program.get_instructions().iter().enumerate()
+        .filter(|(_, instruction)| matches!(instruction.data, InstructionData::Mark))
+        .collect::<Vec<_>>();
+    let cues = if marks.is_empty() {
+        program.get_label_regions().into_iter()
+            .filter_map(|region| Some((in_render(region.start_instruction)?, program.get_instruments()[region.instrument].name.as_deref()?)))
+            .collect::<Vec<_>>()
+    } else {
+        marks.into_iter()
+            .filter_map(|(i, instruction)| Some((in_render(i)?, program.get_mark(instruction.pos)?)))
+            .collect()
+    };
+
+    if !cues.is_empty() {
+        trailing_chunks.append(&mut cue_chunks(&cues));
+    };
+
+    if let Some(region) = program.get_loop_region()
+        && let Some(loop_start) = in_window(region.start_instruction)
+        && let Some(loop_end) = in_window(region.end_instruction)
     {
-        let mut buffer = Vec::new();
+        trailing_chunks.append(&mut sampler_chunk(options, (loop_start, loop_end)));
+    };
 
-        buffer.append(&mut b"RIFF".to_vec());
-        buffer.append(&mut (36 + samples.len() as u32).to_le_bytes().to_vec());
-        buffer.append(&mut b"WAVE".to_vec());
-        buffer.append(&mut b"fmt\x20".to_vec());
-        buffer.append(&mut 16_u32.to_le_bytes().to_vec());
-        buffer.append(&mut 1_u16.to_le_bytes().to_vec());
-        buffer.append(&mut 1_u16.to_le_bytes().to_vec());
-        buffer.append(&mut sample_rate.to_le_bytes().to_vec());
-        buffer.append(&mut (sample_rate * sample_size as u32 / 8).to_le_bytes().to_vec());
-        buffer.append(&mut (sample_size as u16 / 8).to_le_bytes().to_vec());
-        buffer.append(&mut (sample_size as u16).to_le_bytes().to_vec());
-        buffer.append(&mut b"data".to_vec());
-        buffer.append(&mut (samples.len() as u32).to_le_bytes().to_vec());
-        buffer.append(&mut samples);
-
-        buffer
+    stream.finish(writer, options, &trailing_chunks)
+}
+
+
+pub fn interpret_stems<W, F>(program: &Program, options: &RenderOptions, mut create_writer: F) -> io::Result<()>
+    where W: Write + Seek,
+          F: FnMut(&str) -> io::Result<W>
+{
+    let options = &RenderOptions { channels: 1, ..*options };
+    options.check_length(program)?;
+
+    let mut stems = {
+        let mut playing = vec![false; program.get_instruments().len()];
+        for instruction in program.get_instructions().iter() {
+            if let InstructionData::Play { instrument, .. } = instruction.data {
+                playing[instrument] = true;
+            };
+        };
+
+        // notes with overrides play on instruments of their own, named after their label and going into
+        // its stem
+        let mut stems: Vec<(Vec<usize>, W, StreamWriter)> = Vec::new();
+        for (instrument, settings) in program.get_instruments().iter().enumerate() {
+            if playing[instrument] {
+                let name = settings.name.as_deref().unwrap_or("global");
+                let same = stems.iter().position(|(instruments, ..)| program.get_instruments()[instruments[0]].name.as_deref().unwrap_or("global") == name);
+
+                match same {
+                    Some(stem) => stems[stem].0.push(instrument),
+                    None => {
+                        let mut writer = create_writer(name)?;
+                        let stream = StreamWriter::begin(&mut writer, program, options)?;
+
+                        stems.push((vec![instrument], writer, stream));
+                    },
+                };
+            };
+        };
+
+        stems
+    };
+
+    let fades = Fades::new(program, options);
+    let mut renderer = Renderer::new(program, options);
+    let mut position = renderer.position();
+    while let Some(values) = renderer.next_stems() {
+        for (instruments, writer, stream) in stems.iter_mut() {
+            let value = instruments.iter().map(|instrument| values[*instrument]).sum::<f64>();
+
+            stream.push(writer, options.master(value) * fades.gain(position), options)?;
+        };
+
+        position += 1;
+    };
+
+    for (_, mut writer, stream) in stems {
+        stream.finish(&mut writer, options, &[])?;
+    };
+
+    Ok(())
+}
+
+
+pub fn interpret_range(program: &Program, start_seconds: f64, end_seconds: f64, options: &RenderOptions) -> Result<Vec<u8>, TooLong> {
+    interpret(program, &RenderOptions { from: start_seconds, to: Some(end_seconds), ..*options })
+}
+
+
+pub struct Wav<W>(pub W);
+
+
+impl<W: Write + Seek> Backend for Wav<W> {
+    type Output = ();
+    type Error = io::Error;
+
+    fn render(&mut self, program: &Program, options: &RenderOptions) -> io::Result<()> {
+        interpret_to_writer(program, options, &mut self.0)
     }
 }
+
+
+pub fn interpret(program: &Program, options: &RenderOptions) -> Result<Vec<u8>, TooLong> {
+    options.check_length(program)?;
+
+    let mut buffer = io::Cursor::new(Vec::new());
+
+    interpret_to_writer(program, options, &mut buffer).expect("writing into memory doesn't fail");
+
+    Ok(buffer.into_inner())
+}
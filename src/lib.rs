@@ -0,0 +1,100 @@
+//! Compiler and renderer for roorle scripts.
+//!
+//! A script goes through three stages: [`syntax`] splits the source into statements, [`compiler`] turns
+//! those into a [`Program`] of timed instructions and [`interpreter`] renders the program into audio,
+//! MIDI or images. [`compile`] and [`render`] run the whole pipeline:
+//!
+//! ```no_run
+//! let program = roorle::compile("bpm: 120\n@main\nA 1/4")?;
+//!
+//! let file = std::fs::File::create("out.wav")?;
+//! roorle::render(&program, &roorle::interpreter::RenderOptions::default(), roorle::interpreter::Format::Wav, std::io::BufWriter::new(file))?;
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+#![feature(try_blocks)]
+#![cfg_attr(feature = "simd", feature(portable_simd))]
+
+use std::fmt;
+use std::io::{self, Seek, Write};
+use crate::compiler::{CompilingError, Program};
+use crate::interpreter::{Backend, Format, RenderOptions};
+use crate::interpreter::visual::{ImageFormat, PianoRoll, Spectrogram};
+use crate::syntax::parser::{ParsingError, Script};
+
+pub mod json;
+pub mod syntax;
+mod take;
+pub mod compiler;
+pub mod pitch;
+pub mod interpreter;
+pub mod score;
+pub mod sonify;
+pub mod import;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "python")]
+pub mod python;
+
+
+/// Why a script couldn't be compiled
+#[derive(Debug)]
+pub enum Error {
+    ParsingError(ParsingError),
+    CompilingError(CompilingError),
+}
+
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ParsingError(err) => write!(f, "{err:?}"),
+            Self::CompilingError(err) => write!(f, "{err:?}"),
+        }
+    }
+}
+
+
+impl std::error::Error for Error { }
+
+
+impl From<ParsingError> for Error {
+    fn from(err: ParsingError) -> Self {
+        Self::ParsingError(err)
+    }
+}
+
+
+impl From<CompilingError> for Error {
+    fn from(err: CompilingError) -> Self {
+        Self::CompilingError(err)
+    }
+}
+
+
+/// Parses and compiles the script in `source`
+pub fn compile(source: &str) -> Result<Program, Error> {
+    let script = Script::try_from(source)?;
+
+    Ok(Program::try_from(&script)?)
+}
+
+
+/// Renders `program` into `writer` in the given format
+pub fn render<W: Write + Seek>(program: &Program, options: &RenderOptions, format: Format, writer: W) -> io::Result<()> {
+    match format {
+        Format::Wav => interpreter::wav::Wav(writer).render(program, options),
+        Format::Flac => interpreter::flac::Flac(writer).render(program, options),
+        Format::Aiff => interpreter::aiff::Aiff(writer).render(program, options),
+        Format::Raw => interpreter::raw::Raw(writer).render(program, options),
+        Format::Midi => interpreter::midi::Midi(writer).render(program, options),
+        Format::Rtttl => interpreter::rtttl::Rtttl(writer).render(program, options),
+        Format::Svg => PianoRoll { writer, format: ImageFormat::Svg }.render(program, options),
+        Format::Png => PianoRoll { writer, format: ImageFormat::Png }.render(program, options),
+        Format::Spectrogram => Spectrogram(writer).render(program, options),
+        Format::Lrc => interpreter::lyrics::Lrc(writer).render(program, options),
+        Format::Srt => interpreter::lyrics::Srt(writer).render(program, options),
+    }
+}
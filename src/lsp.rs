@@ -0,0 +1,352 @@
+use std::collections::HashMap;
+use std::error::Error;
+use lsp_server::{Connection, ErrorCode, Message, Notification, Request, Response};
+use lsp_types::notification::{DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument, Notification as _, PublishDiagnostics};
+use lsp_types::request::{Completion, FoldingRangeRequest, GotoDefinition, HoverRequest, Request as _};
+use lsp_types::{
+    CompletionItem, CompletionItemKind, CompletionOptions, CompletionParams, Diagnostic, DiagnosticSeverity,
+    DidChangeTextDocumentParams, DidCloseTextDocumentParams, DidOpenTextDocumentParams, FoldingRange, FoldingRangeParams,
+    FoldingRangeProviderCapability, GotoDefinitionParams,
+    Hover, HoverContents, HoverParams, HoverProviderCapability, Location, MarkupContent, MarkupKind, OneOf, Position,
+    PositionEncodingKind, PublishDiagnosticsParams, Range, ServerCapabilities, TextDocumentSyncCapability, TextDocumentSyncKind, Uri,
+};
+use roorle::compiler::{self, notes, properties, Program};
+use roorle::compiler::lint::{self, Severity};
+use roorle::syntax::lexer::{SourceMap, Span, Token as LToken, TokenStream};
+use roorle::syntax::parser::Script;
+
+
+const COMMANDS: &[(&str, &str)] = &[
+    ("goto", "continue at a label, never coming back"),
+    ("repeat", "play a label the given amount of times"),
+    ("rest", "wait for the given length without playing"),
+    ("mark", "name the point of the piece it's at, which becomes a chapter"),
+    ("ghost", "play a note at a fraction of the volume, set with the `ghost` property"),
+    ("automate", "move volume, pan or cutoff through values, each reached after the duration before it"),
+];
+
+
+struct Word {
+    value: String,
+    start: usize,
+    statement: usize,
+}
+
+
+impl Word {
+    pub fn end(&self) -> usize {
+        self.start + self.value.chars().count()
+    }
+}
+
+
+fn words(source: &str) -> Vec<Word> {
+    let mut words = Vec::new();
+
+    let mut statement = 0;
+    let mut in_statement = false;
+    for token in TokenStream::from(source.chars()) {
+        match token {
+            LToken::Word { value, start, .. } => {
+                in_statement = true;
+                words.push(Word { value, start, statement });
+            },
+            LToken::SentenceEnd { .. } => if in_statement {
+                in_statement = false;
+                statement += 1;
+            },
+        };
+    };
+
+    words
+}
+
+
+fn statement(words: &[Word], statement: usize) -> impl Iterator<Item = &Word> {
+    words.iter().filter(move |word| word.statement == statement)
+}
+
+
+/// Char offset of a position, whose character counts UTF-16 code units into the line like clients do
+fn offset(source: &str, position: Position) -> usize {
+    let start = SourceMap::new(source).line_start(position.line as usize + 1);
+
+    let mut units = 0;
+    start + source.chars().skip(start)
+        .take_while(|c| {
+            units += c.len_utf16();
+            *c != '\n' && units <= position.character as usize
+        })
+        .count()
+}
+
+
+/// The position of char `offset` on its line and column, counted from 1
+fn located(source: &str, offset: usize, line: usize, column: usize) -> Position {
+    let character = source.chars().skip(offset + 1 - column).take(column - 1).map(char::len_utf16).sum::<usize>();
+
+    Position::new(line as u32 - 1, character as u32)
+}
+
+
+fn position(source: &str, offset: usize) -> Position {
+    let (line, column) = lint::line_and_column(source, offset);
+
+    located(source, offset, line, column)
+}
+
+
+fn span_range(source: &str, span: Span) -> Range {
+    let start = match span.line {
+        0 => position(source, span.start),
+        line => located(source, span.start, line, span.column),
+    };
+
+    Range::new(start, position(source, span.end))
+}
+
+
+fn diagnostics(source: &str) -> Vec<Diagnostic> {
+    let lints = match lint::lint(source) {
+        Ok(lints) => lints,
+        Err(_) => {
+            return Script::parse_partial(source).1.iter()
+                .map(|err| {
+                    let at = position(source, err.pos().unwrap_or(0));
+
+                    Diagnostic::new_simple(Range::new(at, at), format!("{err:?}"))
+                })
+                .collect();
+        },
+    };
+
+    let mut diagnostics = lints.iter()
+        .map(|lint| Diagnostic {
+            range: span_range(source, lint.span),
+            severity: Some(match lint.severity {
+                Severity::Warning => DiagnosticSeverity::WARNING,
+                Severity::Error => DiagnosticSeverity::ERROR,
+            }),
+            code: Some(lsp_types::NumberOrString::String(String::from(lint.rule))),
+            source: Some(String::from("roorle")),
+            message: lint.message.clone(),
+            ..Default::default()
+        })
+        .collect::<Vec<_>>();
+
+    // the linter already explains the errors it knows about better than the compiler does
+    if lints.iter().all(|lint| lint.severity != Severity::Error)
+        && let Ok(script) = Script::try_from(source)
+        && let Err(err) = Program::try_from(&script)
+    {
+        let span = err.pos().and_then(|pos| script.get_span(pos)).unwrap_or_default();
+
+        diagnostics.push(Diagnostic {
+            range: span_range(source, span),
+            severity: Some(DiagnosticSeverity::ERROR),
+            source: Some(String::from("roorle")),
+            message: format!("{err:?}"),
+            ..Default::default()
+        });
+    };
+
+    diagnostics
+}
+
+
+fn definition(source: &str, at: usize) -> Option<Range> {
+    let words = words(source);
+    let name = &words.iter().find(|word| word.start <= at && at <= word.end())?.value;
+
+    words.windows(2)
+        .find(|pair| pair[0].value == "@" && pair[0].statement == pair[1].statement && pair[1].value == *name)
+        .map(|pair| Range::new(position(source, pair[1].start), position(source, pair[1].end())))
+}
+
+
+/// Octave the given statement plays in, a label's own `octave` wins over the global one
+fn octave_at(words: &[Word], at: usize) -> u32 {
+    let scope_of = |statement: usize| words.iter()
+        .filter(|word| word.statement <= statement && word.value == "@")
+        .map(|word| word.statement)
+        .next_back();
+
+    let octave_in = |scope: Option<usize>| words.windows(3)
+        .filter(|triple| scope_of(triple[0].statement) == scope && triple[0].value == "octave" && triple[1].value == ":")
+        .find_map(|triple| triple[2].value.parse().ok());
+
+    octave_in(scope_of(at)).or_else(|| octave_in(None)).unwrap_or(4)
+}
+
+
+fn hover(source: &str, at: usize) -> Option<Hover> {
+    let words = words(source);
+    let word = words.iter().find(|word| word.start <= at && at <= word.end())?;
+
+    let mut current = statement(&words, word.statement);
+    let first = current.next()?;
+    if current.next().is_some_and(|second| second.value == ":") || compiler::note_frequency(&first.value, 4).is_none() {
+        return None;
+    };
+
+    let octave = octave_at(&words, word.statement);
+    let frequency = compiler::note_frequency(&word.value, octave)?;
+
+    Some(Hover {
+        contents: HoverContents::Markup(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value: format!("**{}{octave}**: {frequency:.2} Hz", word.value),
+        }),
+        range: Some(Range::new(position(source, word.start), position(source, word.end()))),
+    })
+}
+
+
+fn completion(source: &str, at: Position) -> Vec<CompletionItem> {
+    let start = SourceMap::new(source).line_start(at.line as usize + 1);
+    let before = source.chars().skip(start).take(offset(source, at) - start).collect::<String>();
+    let before = before.rsplit(';').next().unwrap_or("");
+
+    let mut typed = before.split(' ').filter(|word| !word.is_empty()).collect::<Vec<_>>();
+    if !before.ends_with(' ') {
+        typed.pop();
+    };
+
+    let item = |label: &str, kind, detail: Option<&str>| CompletionItem {
+        label: String::from(label),
+        kind: Some(kind),
+        detail: detail.map(String::from),
+        ..Default::default()
+    };
+
+    match typed.as_slice() {
+        [] => COMMANDS.iter().map(|(name, detail)| item(name, CompletionItemKind::KEYWORD, Some(detail)))
+            .chain(notes::DEFAULT_NOTES.iter().map(|(note, _)| item(note, CompletionItemKind::CONSTANT, Some("note"))))
+            .chain(properties::PROPERTIES.iter().map(|property| item(property.name, CompletionItemKind::PROPERTY, Some("property"))))
+            .collect(),
+        ["goto" | "repeat"] | ["loop:"] | ["loop", ":"] => words(source).windows(2)
+            .filter(|pair| pair[0].value == "@" && pair[0].statement == pair[1].statement)
+            .map(|pair| item(&pair[1].value, CompletionItemKind::REFERENCE, Some("label")))
+            .collect(),
+        [first, ..] if compiler::note_frequency(first, 4).is_some() => notes::DEFAULT_NOTES.iter()
+            .map(|(note, _)| item(note, CompletionItemKind::CONSTANT, Some("note")))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+
+/// Labels written with a block fold down to their first line
+fn folding_ranges(source: &str) -> Vec<FoldingRange> {
+    let (script, _) = Script::parse_partial(source);
+
+    script.get_blocks().iter()
+        .map(|block| FoldingRange {
+            start_line: position(source, block.start).line,
+            end_line: position(source, block.end).line,
+            ..Default::default()
+        })
+        .collect()
+}
+
+
+fn publish(connection: &Connection, uri: Uri, diagnostics: Vec<Diagnostic>) -> Result<(), Box<dyn Error + Sync + Send>> {
+    let params = PublishDiagnosticsParams::new(uri, diagnostics, None);
+    connection.sender.send(Message::Notification(Notification::new(String::from(PublishDiagnostics::METHOD), params)))?;
+
+    Ok(())
+}
+
+
+fn respond(documents: &HashMap<String, String>, request: Request) -> Result<Response, Box<dyn Error + Sync + Send>> {
+    let source = |uri: &Uri| documents.get(uri.as_str()).map(String::as_str).unwrap_or("");
+
+    Ok(match request.method.as_str() {
+        GotoDefinition::METHOD => {
+            let params: GotoDefinitionParams = serde_json::from_value(request.params)?;
+            let document = params.text_document_position_params.text_document.uri;
+            let source = source(&document);
+
+            let range = definition(source, offset(source, params.text_document_position_params.position));
+            Response::new_ok(request.id, range.map(|range| Location::new(document, range)))
+        },
+        HoverRequest::METHOD => {
+            let params: HoverParams = serde_json::from_value(request.params)?;
+            let source = source(&params.text_document_position_params.text_document.uri);
+
+            Response::new_ok(request.id, hover(source, offset(source, params.text_document_position_params.position)))
+        },
+        Completion::METHOD => {
+            let params: CompletionParams = serde_json::from_value(request.params)?;
+            let source = source(&params.text_document_position.text_document.uri);
+
+            Response::new_ok(request.id, completion(source, params.text_document_position.position))
+        },
+        FoldingRangeRequest::METHOD => {
+            let params: FoldingRangeParams = serde_json::from_value(request.params)?;
+
+            Response::new_ok(request.id, folding_ranges(source(&params.text_document.uri)))
+        },
+        method => Response::new_err(request.id, ErrorCode::MethodNotFound as i32, format!("unsupported method `{method}`")),
+    })
+}
+
+
+pub fn run() -> Result<(), Box<dyn Error + Sync + Send>> {
+    let (connection, threads) = Connection::stdio();
+
+    let capabilities = ServerCapabilities {
+        // what every client understands, positions are converted from chars to it
+        position_encoding: Some(PositionEncodingKind::UTF16),
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        definition_provider: Some(OneOf::Left(true)),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        completion_provider: Some(CompletionOptions { trigger_characters: Some(vec![String::from(" ")]), ..Default::default() }),
+        folding_range_provider: Some(FoldingRangeProviderCapability::Simple(true)),
+        ..Default::default()
+    };
+    connection.initialize(serde_json::to_value(capabilities)?)?;
+
+    // keyed by the uri text, `Uri` itself caches its parts behind interior mutability
+    let mut documents = HashMap::new();
+    for message in &connection.receiver {
+        match message {
+            Message::Request(request) => {
+                if connection.handle_shutdown(&request)? {
+                    break;
+                };
+
+                connection.sender.send(Message::Response(respond(&documents, request)?))?;
+            },
+            Message::Notification(notification) => match notification.method.as_str() {
+                DidOpenTextDocument::METHOD => {
+                    let params: DidOpenTextDocumentParams = serde_json::from_value(notification.params)?;
+
+                    publish(&connection, params.text_document.uri.clone(), diagnostics(&params.text_document.text))?;
+                    documents.insert(params.text_document.uri.to_string(), params.text_document.text);
+                },
+                DidChangeTextDocument::METHOD => {
+                    let mut params: DidChangeTextDocumentParams = serde_json::from_value(notification.params)?;
+
+                    if let Some(change) = params.content_changes.pop() {
+                        publish(&connection, params.text_document.uri.clone(), diagnostics(&change.text))?;
+                        documents.insert(params.text_document.uri.to_string(), change.text);
+                    };
+                },
+                DidCloseTextDocument::METHOD => {
+                    let params: DidCloseTextDocumentParams = serde_json::from_value(notification.params)?;
+
+                    documents.remove(params.text_document.uri.as_str());
+                    publish(&connection, params.text_document.uri, Vec::new())?;
+                },
+                _ => { },
+            },
+            Message::Response(_) => { },
+        };
+    };
+
+    drop(connection);
+    threads.join()?;
+
+    Ok(())
+}
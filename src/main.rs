@@ -1,16 +1,26 @@
-#![feature(try_blocks)]
-#![feature(let_chains)]
-#![feature(linked_list_remove)]
+use std::io::{self, Seek, Write};
+use std::process::ExitCode;
+use std::time::{Duration, Instant};
+use roorle::{compiler, interpreter, json, syntax};
+use roorle::compiler::incremental::Incremental;
+use roorle::interpreter::{Format, RenderOptions};
+use crate::cli::{Arguments, Command, Dump, ImportFormat};
 
-use crate::interpreter::wav::SampleSize;
+mod cli;
+mod repl;
+mod project;
+mod info;
+mod diff;
+#[cfg(feature = "lsp")]
+mod lsp;
 
-mod syntax;
-mod take;
-mod compiler;
-mod interpreter;
 
-
-const EXAMPLE_PROGRAM: &str = include_str!("../test.musical");
+const EXIT_USAGE: u8 = 64;
+const EXIT_SCRIPT: u8 = 65;
+const EXIT_NO_INPUT: u8 = 66;
+const EXIT_UNAVAILABLE: u8 = 69;
+const EXIT_IO: u8 = 74;
+const WATCH_INTERVAL: Duration = Duration::from_millis(250);
 
 
 fn test_value(s: &str) -> String {
@@ -24,18 +34,567 @@ fn test_value(s: &str) -> String {
 }
 
 
-fn main() {
-    syntax::lexer::TokenStream::from(EXAMPLE_PROGRAM.chars()).for_each(|token| println!("{token}"));
+/// Renders into `writer`, giving back the peak level of renders measured along the way
+fn render_to<W: Write + Seek>(program: &compiler::Program, options: &RenderOptions, format: Format, mut writer: W) -> io::Result<Option<f32>> {
+    match format {
+        Format::Wav => {
+            let mut peak = 0.0;
+            interpreter::wav::interpret_to_writer_with_progress(program, options, &mut writer, |progress| {
+                peak = progress.peak;
+
+                // the view redraws the same line of the terminal, and already shows how far the render is
+                if options.terminal_view {
+                    return;
+                };
+
+                eprint!("\rrendering: {:5.1}% (instruction {:?} at {:?})", progress.fraction * 100.0, progress.instruction, progress.pos);
+                if progress.fraction >= 1.0 {
+                    eprintln!();
+                };
+            })?;
+
+            Ok(Some(peak))
+        },
+        format => roorle::render(program, options, format, writer).map(|_| None),
+    }
+}
+
+
+fn write_output(program: &compiler::Program, arguments: &Arguments) -> io::Result<Option<f32>> {
+    let format = arguments.format();
+
+    if format == Format::Raw {
+        eprintln!("{}", interpreter::raw::format_report(&arguments.options));
+    };
+
+    match arguments.output_path() {
+        Some(path) => render_to(program, &arguments.options, format, io::BufWriter::new(std::fs::File::create(path)?)),
+        None => {
+            let mut buffer = io::Cursor::new(Vec::new());
+            let peak = render_to(program, &arguments.options, format, &mut buffer)?;
+
+            io::stdout().write_all(buffer.get_ref()).map(|_| peak)
+        },
+    }
+}
+
+
+#[cfg(not(all(feature = "playback", feature = "midi-output", feature = "lsp")))]
+fn missing_feature<T>(feature: &str) -> Result<T, u8> {
+    eprintln!("error: roorle was built without the `{feature}` feature");
+
+    Err(EXIT_UNAVAILABLE)
+}
+
+
+fn run(arguments: &Arguments) -> Result<(), u8> {
+    #[cfg(feature = "midi-output")]
+    if arguments.command == Command::PlayMidi && arguments.list_ports {
+        let ports = interpreter::midi_output::list_ports().map_err(|err| {
+            eprintln!("error: {err:?}");
+            EXIT_UNAVAILABLE
+        })?;
+        ports.iter().for_each(|port| println!("{port}"));
+
+        return Ok(());
+    };
+
+    match arguments.command {
+        Command::Watch => return watch(arguments),
+        Command::Repl => return repl::run(arguments),
+        #[cfg(feature = "lsp")]
+        Command::Lsp => return lsp::run().map_err(|err| {
+            eprintln!("error: {err}");
+            EXIT_IO
+        }),
+        #[cfg(not(feature = "lsp"))]
+        Command::Lsp => return missing_feature("lsp"),
+        _ => { },
+    };
+
+    if arguments.command == Command::Build {
+        return build(arguments);
+    };
+
+    if arguments.command == Command::Import {
+        return import(arguments);
+    };
+
+    let source = arguments.read_input().map_err(|err| {
+        eprintln!("error: can't read {}: {err}", arguments.input.as_deref().unwrap_or("-"));
+        EXIT_NO_INPUT
+    })?;
+
+    if arguments.command == Command::Fmt {
+        return format(&source, arguments);
+    };
+
+    if arguments.command == Command::Transpose {
+        return transpose(&source, arguments);
+    };
+
+    if arguments.command == Command::Lint {
+        return lint(&source, arguments);
+    };
+
+    if arguments.command == Command::Diff {
+        return diff(&source, arguments);
+    };
+
+    if arguments.command == Command::Highlight {
+        return highlight(&source, arguments);
+    };
+
+    if arguments.command == Command::Notes {
+        return notes(&source, arguments);
+    };
+
+    // the pipeline dumps can't share stdout with a render written there
+    let mut log: Box<dyn Write> = if arguments.command == Command::Render && arguments.output_path().is_none() {
+        Box::new(io::stderr())
+    } else {
+        Box::new(io::stdout())
+    };
+
+    let started = Instant::now();
+    let program = compile(&source, arguments.dump, arguments.json, arguments.strict, &mut log)?;
+    let compile_time = started.elapsed();
+
+    let started = Instant::now();
+    let peak = execute(&program, arguments)?;
+
+    if arguments.stats {
+        report_stats(&program, arguments, compile_time, started.elapsed(), peak);
+    };
+
+    Ok(())
+}
+
+
+fn build(arguments: &Arguments) -> Result<(), u8> {
+    let path = arguments.input.as_deref().unwrap_or(".");
+
+    let project = project::Project::load(std::path::Path::new(path)).map_err(|err| {
+        eprintln!("error: {path}: {err}");
+        EXIT_NO_INPUT
+    })?;
+    let source = project.source().map_err(|err| {
+        eprintln!("error: {err}");
+        EXIT_NO_INPUT
+    })?;
+
+    let mut options = arguments.options;
+    if let Some(sample_rate) = project.sample_rate {
+        options.sample_rate = sample_rate;
+    };
+
+    let render = Arguments {
+        command: Command::Render,
+        input: Some(project.entry.to_string_lossy().into_owned()),
+        output: arguments.output.clone().or_else(|| project.output.map(|output| output.to_string_lossy().into_owned())),
+        format: arguments.format.or(project.format),
+        options,
+        ..arguments.clone()
+    };
+
+    if let Some(output) = render.output_path() && let Some(directory) = std::path::Path::new(&output).parent() {
+        std::fs::create_dir_all(directory).map_err(|err| {
+            eprintln!("error: can't create {}: {err}", directory.display());
+            EXIT_IO
+        })?;
+    };
+
+    let started = Instant::now();
+    // like renders of a single script, a project's can be written to stdout
+    let program = compile(&source, render.dump, render.json, render.strict, &mut io::stderr())?;
+    let compile_time = started.elapsed();
+
+    let started = Instant::now();
+    let peak = execute(&program, &render)?;
+
+    if render.stats {
+        report_stats(&program, &render, compile_time, started.elapsed(), peak);
+    };
+
+    Ok(())
+}
+
+
+fn report_stats(program: &compiler::Program, arguments: &Arguments, compile_time: Duration, render_time: Duration, peak: Option<f32>) {
+    let options = &arguments.options;
+
+    let (start, end) = interpreter::render::sample_window(program, options);
+    let duration = (end - start) as f64 / options.sample_rate as f64;
+    let notes = program.get_instructions().iter()
+        .filter(|instruction| matches!(instruction.data, compiler::InstructionData::Play { .. }))
+        .count();
+
+    eprintln!("compile time:    {compile_time:.2?}");
+    eprintln!("render time:     {render_time:.2?}");
+    eprintln!("realtime factor: {:.1}x", duration / render_time.as_secs_f64());
+    eprintln!("duration:        {duration:.2}s");
+    eprintln!("notes:           {notes}");
+    // only measured by renders into WAV, the others would have to render again for it
+    if let Some(peak) = peak {
+        eprintln!("peak level:      {:.1} dBFS", 20.0 * (peak as f64).max(f64::MIN_POSITIVE).log10());
+    };
+
+    if arguments.command == Command::Render && let Some(path) = arguments.output_path() && let Ok(metadata) = std::fs::metadata(path) {
+        eprintln!("output size:     {:.1} KiB", metadata.len() as f64 / 1024.0);
+    };
+}
+
+
+fn format(source: &str, arguments: &Arguments) -> Result<(), u8> {
+    let formatted = syntax::formatter::format(source).map_err(|err| {
+        eprintln!("error: {err:?}");
+        EXIT_SCRIPT
+    })?;
+
+    if arguments.check {
+        return if formatted == source { Ok(()) } else {
+            eprintln!("{} isn't formatted", arguments.input.as_deref().unwrap_or("-"));
+
+            Err(1)
+        };
+    };
+
+    let result = match arguments.output.as_deref().or(arguments.input.as_deref()) {
+        None | Some("-") => io::stdout().write_all(formatted.as_bytes()),
+        Some(path) => std::fs::write(path, formatted),
+    };
+
+    result.map_err(|err| {
+        eprintln!("error: {err}");
+        EXIT_IO
+    })
+}
+
+
+fn transpose(source: &str, arguments: &Arguments) -> Result<(), u8> {
+    let transposed = compiler::transpose::transpose(source, arguments.semitones.unwrap_or(0)).map_err(|err| {
+        eprintln!("error: {err}");
+        EXIT_SCRIPT
+    })?;
+
+    let result = match arguments.output.as_deref() {
+        None | Some("-") => io::stdout().write_all(transposed.as_bytes()),
+        Some(path) => std::fs::write(path, transposed),
+    };
+
+    result.map_err(|err| {
+        eprintln!("error: {err}");
+        EXIT_IO
+    })
+}
+
+
+fn import(arguments: &Arguments) -> Result<(), u8> {
+    let bytes = arguments.read_input_bytes().map_err(|err| {
+        eprintln!("error: can't read {}: {err}", arguments.input.as_deref().unwrap_or("-"));
+        EXIT_NO_INPUT
+    })?;
+
+    let tune = match arguments.importer {
+        Some(ImportFormat::Midi) | None => roorle::import::midi::import(&bytes).map_err(|err| err.to_string()),
+        Some(ImportFormat::Abc) => roorle::import::abc::import(&String::from_utf8_lossy(&bytes)).map_err(|err| err.to_string()),
+        Some(ImportFormat::MusicXml) => roorle::import::musicxml::import(&String::from_utf8_lossy(&bytes)).map_err(|err| err.to_string()),
+        Some(ImportFormat::Rtttl) => roorle::import::rtttl::import(&String::from_utf8_lossy(&bytes)).map_err(|err| err.to_string()),
+        Some(ImportFormat::Mml) => roorle::import::mml::import(&String::from_utf8_lossy(&bytes)).map_err(|err| err.to_string()),
+        Some(ImportFormat::Wav) => roorle::import::wav::import(&bytes).map_err(|err| err.to_string()),
+    }.map_err(|err| {
+        eprintln!("error: {err}");
+        EXIT_SCRIPT
+    })?;
+
+    let script = format!("{}\n", tune.script());
+    let result = match arguments.output.as_deref() {
+        None | Some("-") => io::stdout().write_all(script.as_bytes()),
+        Some(path) => std::fs::write(path, script),
+    };
+
+    result.map_err(|err| {
+        eprintln!("error: {err}");
+        EXIT_IO
+    })
+}
+
+
+fn lint(source: &str, arguments: &Arguments) -> Result<(), u8> {
+    let lints = compiler::lint::lint(source).map_err(|_| {
+        for err in syntax::parser::Script::parse_partial(source).1 {
+            eprintln!("error: {err:?}");
+        };
+
+        EXIT_SCRIPT
+    })?;
+
+    let file = arguments.input.as_deref().unwrap_or("-");
+    for lint in lints.iter() {
+        if arguments.json {
+            println!("{}", lint.to_json(file));
+        } else {
+            println!("{file}:{lint}");
+        };
+    };
+
+    if lints.is_empty() { Ok(()) } else { Err(1) }
+}
+
+
+fn diff(source: &str, arguments: &Arguments) -> Result<(), u8> {
+    let other_path = arguments.other.as_deref().unwrap_or("-");
+    let other = match other_path {
+        "-" => io::read_to_string(io::stdin()),
+        path => std::fs::read_to_string(path),
+    }.map_err(|err| {
+        eprintln!("error: can't read {other_path}: {err}");
+        EXIT_NO_INPUT
+    })?;
+
+    let old = compile(source, Dump::default(), false, arguments.strict, &mut io::sink())?;
+    let new = compile(&other, Dump::default(), false, arguments.strict, &mut io::sink())?;
+
+    let changes = diff::changes(&old, &new);
+    for change in changes.iter() {
+        if arguments.json {
+            println!("{}", change.to_json());
+        } else {
+            println!("{change}");
+        };
+    };
+
+    if !arguments.json {
+        println!("{}", diff::summary(&changes));
+    };
+
+    if changes.is_empty() { Ok(()) } else { Err(1) }
+}
+
+
+fn highlight(source: &str, arguments: &Arguments) -> Result<(), u8> {
+    let output = arguments.output.as_deref().filter(|output| *output != "-");
+    let html = arguments.html || output.is_some_and(|output| output.ends_with(".html") || output.ends_with(".htm"));
+
+    let highlighted = if html { syntax::highlight::to_html(source) } else { syntax::highlight::to_ansi(source) };
+
+    let result = match output {
+        None => io::stdout().write_all(highlighted.as_bytes()),
+        Some(path) => std::fs::write(path, highlighted),
+    };
+
+    result.map_err(|err| {
+        eprintln!("error: {err}");
+        EXIT_IO
+    })
+}
+
+
+fn notes(source: &str, arguments: &Arguments) -> Result<(), u8> {
+    let program = compile(source, arguments.dump, arguments.json, arguments.strict, &mut io::stderr())?;
+    let csv = interpreter::csv::interpret(&program, Some(source));
+
+    let result = match arguments.output.as_deref() {
+        None | Some("-") => io::stdout().write_all(csv.as_bytes()),
+        Some(path) => std::fs::write(path, csv),
+    };
+
+    result.map_err(|err| {
+        eprintln!("error: {err}");
+        EXIT_IO
+    })
+}
+
+
+fn compile(source: &str, dump: Dump, json: bool, strict: bool, log: &mut dyn Write) -> Result<compiler::Program, u8> {
+    let mut separate = false;
+    let mut stage = |log: &mut dyn Write, name: &str, json_items: Vec<String>, text: String| {
+        if json {
+            let _ = writeln!(log, "{{{}:{}}}", json::string(name), json::array(json_items));
+        } else {
+            if separate {
+                let _ = writeln!(log, "--------------------------------------------");
+            };
+
+            let _ = writeln!(log, "{text}");
+        };
+
+        separate = true;
+    };
+
+    if dump.tokens {
+        let tokens = syntax::lexer::TokenStream::from(source.chars()).collect::<Vec<_>>();
+
+        stage(log, "tokens", tokens.iter().map(syntax::lexer::Token::to_json).collect(), tokens.iter().map(|token| format!("{token}")).collect::<Vec<_>>().join("\n"));
+    };
+
+    let script = syntax::parser::Script::try_from(source).map_err(|err| {
+        eprintln!("error: {err:?}");
+        EXIT_SCRIPT
+    })?;
+    if dump.ast {
+        stage(log, "ast", script.get_tokens().iter().map(syntax::parser::Token::to_json).collect(), format!("{script}"));
+    };
+
+    let program = compiler::Compiler::new().strict(strict).compile(&script).map_err(|err| {
+        eprintln!("error: {err:?}");
+        EXIT_SCRIPT
+    })?;
+    if dump.ir {
+        stage(log, "ir", program.get_instructions().iter().map(compiler::Instruction::to_json).collect(), format!("{program}"));
+    };
+
+    Ok(program)
+}
+
+
+fn watch(arguments: &Arguments) -> Result<(), u8> {
+    let Some(path) = arguments.input.as_deref().filter(|input| *input != "-") else {
+        eprintln!("error: watch needs a script file, not stdin");
+
+        return Err(EXIT_USAGE);
+    };
+
+    let render = Arguments { command: Command::Render, ..arguments.clone() };
+
+    // dumps need every stage run again, otherwise only what an edit touched is recompiled
+    let dumps = arguments.dump.tokens || arguments.dump.ast || arguments.dump.ir;
+    let mut incremental: Option<Incremental> = None;
+
+    let mut last_modified = None;
+    loop {
+        let modified = std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok();
+
+        if modified.is_some() && modified != last_modified {
+            last_modified = modified;
+
+            let started = Instant::now();
+            let result = arguments.read_input()
+                .map_err(|err| eprintln!("error: can't read {path}: {err}"))
+                .and_then(|source| {
+                    if dumps {
+                        let program = compile(&source, arguments.dump, arguments.json, arguments.strict, &mut io::stderr()).map_err(|_| ())?;
+
+                        return execute(&program, &render).map(|_| ()).map_err(|_| ());
+                    };
+
+                    let program = match incremental.as_mut() {
+                        Some(incremental) => incremental.set_source(&source),
+                        None => incremental.insert(Incremental::with_compiler(&source, compiler::Compiler::new().strict(arguments.strict))).program(),
+                    }.map_err(|err| eprintln!("error: {err}"))?;
+
+                    execute(program, &render).map(|_| ()).map_err(|_| ())
+                });
+
+            if result.is_ok() {
+                eprintln!("rendered {path} in {:.2?}", started.elapsed());
+            };
+            eprintln!("watching {path} for changes...");
+        };
+
+        std::thread::sleep(WATCH_INTERVAL);
+    };
+}
+
+
+/// Runs the command on the compiled program, giving back the peak level of renders that measured it
+fn execute(program: &compiler::Program, arguments: &Arguments) -> Result<Option<f32>, u8> {
+    let options = &arguments.options;
+
+    if matches!(arguments.command, Command::Render | Command::Build | Command::Stems | Command::Play | Command::PlayMidi) && let Err(err) = options.check_length(program) {
+        eprintln!("error: {err}, which --limit can raise");
+        return Err(EXIT_SCRIPT);
+    };
+
+    match arguments.command {
+        #[cfg(feature = "playback")]
+        Command::Play => interpreter::playback::Playback.render(program, options).map(|_| None).map_err(|err| {
+            eprintln!("error: {err:?}");
+            EXIT_UNAVAILABLE
+        }),
+        #[cfg(feature = "midi-output")]
+        Command::PlayMidi => interpreter::midi_output::LiveMidi { port: arguments.port.clone() }.render(program, options).map(|_| None).map_err(|err| {
+            eprintln!("error: {err:?}");
+            EXIT_UNAVAILABLE
+        }),
+        #[cfg(not(feature = "playback"))]
+        Command::Play => missing_feature("playback"),
+        #[cfg(not(feature = "midi-output"))]
+        Command::PlayMidi => missing_feature("midi-output"),
+        Command::Stems => {
+            let prefix = arguments.output.clone()
+                .or_else(|| arguments.input.as_deref().map(|input| std::path::Path::new(input).with_extension("").to_string_lossy().into_owned()))
+                .unwrap_or_else(|| String::from("stems"));
+
+            interpreter::wav::interpret_stems(program, options, |stem| std::fs::File::create(format!("{prefix}.{stem}.wav"))).map(|_| None).map_err(|err| {
+                eprintln!("error: {err}");
+                EXIT_IO
+            })
+        },
+        Command::Info => {
+            print!("{}", info::report(program));
+
+            Ok(None)
+        },
+        Command::Stats => {
+            if arguments.json {
+                println!("{}", info::statistics_json(program));
+            } else {
+                print!("{}", info::statistics(program));
+            };
+
+            Ok(None)
+        },
+        Command::TempoMap => {
+            let map = if arguments.json { format!("{}\n", info::tempo_map_json(program)) } else { info::tempo_map(program) };
+            let result = match arguments.output.as_deref() {
+                None | Some("-") => io::stdout().write_all(map.as_bytes()),
+                Some(path) => std::fs::write(path, map),
+            };
+
+            result.map(|_| None).map_err(|err| {
+                eprintln!("error: {err}");
+                EXIT_IO
+            })
+        },
+        Command::Chapters => {
+            let chapters = format!("{}\n", info::chapters_json(program));
+            let result = match arguments.output.as_deref() {
+                None | Some("-") => io::stdout().write_all(chapters.as_bytes()),
+                Some(path) => std::fs::write(path, chapters),
+            };
+
+            result.map(|_| None).map_err(|err| {
+                eprintln!("error: {err}");
+                EXIT_IO
+            })
+        },
+        Command::Render | Command::Build => write_output(program, arguments).map_err(|err| {
+            eprintln!("error: {err}");
+            EXIT_IO
+        }),
+        Command::Watch | Command::Repl | Command::Lsp | Command::Fmt | Command::Transpose | Command::Lint | Command::Diff | Command::Highlight | Command::Notes | Command::Import | Command::Help | Command::Version => Ok(None),
+    }
+}
 
-    println!("--------------------------------------------");
 
-    let script = syntax::parser::Script::try_from(EXAMPLE_PROGRAM).expect("Error");
-    println!("{script}");
+fn main() -> ExitCode {
+    let arguments = match cli::parse(std::env::args().skip(1)) {
+        Ok(arguments) => arguments,
+        Err(err) => {
+            eprintln!("error: {err}\n\n{}", cli::USAGE);
 
-    println!("--------------------------------------------");
+            return ExitCode::from(EXIT_USAGE);
+        },
+    };
 
-    let program = compiler::Program::try_from(&script).expect("Error");
-    println!("{program}");
+    match arguments.command {
+        Command::Help => println!("{}", cli::USAGE),
+        Command::Version => println!("roorle {}", env!("CARGO_PKG_VERSION")),
+        _ => if let Err(code) = run(&arguments) {
+            return ExitCode::from(code);
+        },
+    };
 
-    std::fs::write("test.wav", interpreter::wav::interpret(&program, 48000, SampleSize::Large)).expect("uga buga");
+    ExitCode::SUCCESS
 }
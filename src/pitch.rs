@@ -0,0 +1,78 @@
+//! Naming the note a frequency is closest to, the way back from the frequencies scripts compile to.
+//! Exporters, `info` and the visualizers all go through [`Pitch`] rather than working it out themselves:
+//!
+//! ```
+//! use roorle::pitch::Pitch;
+//!
+//! let pitch = Pitch::from_frequency(445.0);
+//! assert_eq!(pitch.to_string(), "A4");
+//! assert_eq!(pitch.midi_key(), 69);
+//! assert!((pitch.cents - 19.56).abs() < 0.01);
+//! ```
+
+use std::fmt;
+
+
+const A_4_FREQUENCY: f64 = 440.0;
+/// MIDI key of `A` in octave 4
+const A_4_KEY: i32 = 69;
+
+/// Names of the twelve notes of an octave as scripts write them, starting at `C`
+pub const NOTE_NAMES: [&str; 12] = ["C", "Cas", "D", "Das", "E", "F", "Fas", "G", "Gas", "A", "As", "B"];
+
+
+/// A key counted like MIDI's, 60 being `C` in octave 4, and how far off it a frequency is
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Pitch {
+    pub key: i32,
+    /// Hundredths of a semitone above the key, below it when negative, never more than 50 either way
+    pub cents: f64,
+}
+
+
+impl Pitch {
+    /// The key exactly, with no cents off it
+    pub fn from_key(key: i32) -> Self {
+        Self { key, cents: 0.0 }
+    }
+
+    /// The key closest to `frequency`, in equal temperament tuned to A at 440 Hz
+    pub fn from_frequency(frequency: f64) -> Self {
+        let semitones = A_4_KEY as f64 + 12.0 * (frequency / A_4_FREQUENCY).log2();
+        let key = semitones.round();
+
+        Self { key: key as i32, cents: (semitones - key) * 100.0 }
+    }
+
+    pub fn frequency(&self) -> f64 {
+        A_4_FREQUENCY * 2.0_f64.powf((self.key - A_4_KEY) as f64 / 12.0 + self.cents / 1200.0)
+    }
+
+    /// Name of the note as scripts write it, without its octave
+    pub fn name(&self) -> &'static str {
+        NOTE_NAMES[self.key.rem_euclid(12) as usize]
+    }
+
+    /// Octave as the `octave` property counts them, each starting at `C`
+    pub fn octave(&self) -> i32 {
+        self.key.div_euclid(12) - 1
+    }
+
+    /// Whether the note is one of the black keys of a piano
+    pub fn is_sharp(&self) -> bool {
+        matches!(self.key.rem_euclid(12), 1 | 3 | 6 | 8 | 10)
+    }
+
+    /// The key, brought into the ones MIDI has
+    pub fn midi_key(&self) -> u8 {
+        self.key.clamp(0, 127) as u8
+    }
+}
+
+
+/// The name and octave, like `Cas4`
+impl fmt::Display for Pitch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", self.name(), self.octave())
+    }
+}
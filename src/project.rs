@@ -0,0 +1,204 @@
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use roorle::interpreter::Format;
+use roorle::syntax::lexer::{Token, TokenStream};
+
+
+pub const FILE_NAME: &str = "roorle.toml";
+const SCRIPT_EXTENSION: &str = "musical";
+
+
+/// What a `roorle.toml` declares, every path already relative to the working directory
+#[derive(Debug)]
+pub struct Project {
+    pub entry: PathBuf,
+    pub output: Option<PathBuf>,
+    pub format: Option<Format>,
+    pub sample_rate: Option<u32>,
+    pub properties: Vec<(String, String)>,
+    pub include: Vec<PathBuf>,
+}
+
+
+#[derive(Debug)]
+pub enum ProjectError {
+    Io {
+        path: PathBuf,
+        err: io::Error,
+    },
+    Toml(toml::de::Error),
+    MissingKey(&'static str),
+    InvalidValue {
+        key: String,
+        expected: &'static str,
+    },
+}
+
+
+impl fmt::Display for ProjectError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io { path, err } => write!(f, "can't read {}: {err}", path.display()),
+            Self::Toml(err) => write!(f, "{err}"),
+            Self::MissingKey(key) => write!(f, "`{key}` is missing"),
+            Self::InvalidValue { key, expected } => write!(f, "`{key}` should be {expected}"),
+        }
+    }
+}
+
+
+fn property_value(key: &str, value: &toml::Value) -> Result<String, ProjectError> {
+    match value {
+        toml::Value::Integer(n) if *n >= 0 => Ok(n.to_string()),
+        toml::Value::String(s) => Ok(s.clone()),
+        toml::Value::Array(values) => Ok(values.iter()
+            .map(|value| property_value(key, value))
+            .collect::<Result<Vec<_>, _>>()?
+            .join(" ")),
+        _ => Err(ProjectError::InvalidValue {
+            key: format!("properties.{key}"),
+            expected: "a whole number, a string (like \"1/2\") or a list of those",
+        }),
+    }
+}
+
+
+/// Char offset of the first label, everything before it is global properties
+fn first_label(source: &str) -> usize {
+    let mut sentence_start = true;
+
+    for token in TokenStream::from(source.chars()) {
+        match token {
+            Token::Word { value, start, .. } => {
+                if sentence_start && value == "@" {
+                    return start;
+                };
+
+                sentence_start = false;
+            },
+            Token::SentenceEnd { .. } => sentence_start = true,
+        };
+    };
+
+    source.chars().count()
+}
+
+
+fn split_at_first_label(source: &str) -> (String, String) {
+    let at = first_label(source);
+
+    (source.chars().take(at).collect(), source.chars().skip(at).collect())
+}
+
+
+fn read(path: &Path) -> Result<String, ProjectError> {
+    std::fs::read_to_string(path).map_err(|err| ProjectError::Io { path: path.to_path_buf(), err })
+}
+
+
+impl Project {
+    /// Loads `roorle.toml` from `path`, or from inside it if it's a directory
+    pub fn load(path: &Path) -> Result<Self, ProjectError> {
+        let file = if path.is_dir() { path.join(FILE_NAME) } else { path.to_path_buf() };
+        let root = file.parent().unwrap_or(Path::new("")).to_path_buf();
+
+        let table: toml::Table = read(&file)?.parse().map_err(ProjectError::Toml)?;
+
+        let string = |key: &'static str| match table.get(key) {
+            None => Ok(None),
+            Some(toml::Value::String(s)) => Ok(Some(s.as_str())),
+            Some(_) => Err(ProjectError::InvalidValue { key: String::from(key), expected: "a string" }),
+        };
+
+        let format = match string("format")? {
+            None => None,
+            Some(name) => Some(Format::from_name(name).ok_or(ProjectError::InvalidValue { key: String::from("format"), expected: "an output format" })?),
+        };
+
+        let sample_rate = match table.get("sample_rate") {
+            None => None,
+            Some(toml::Value::Integer(rate)) if *rate > 0 && *rate <= u32::MAX as i64 => Some(*rate as u32),
+            Some(_) => return Err(ProjectError::InvalidValue { key: String::from("sample_rate"), expected: "a positive whole number" }),
+        };
+
+        let properties = match table.get("properties") {
+            None => Vec::new(),
+            Some(toml::Value::Table(properties)) => properties.iter()
+                .map(|(key, value)| Ok((key.clone(), property_value(key, value)?)))
+                .collect::<Result<_, ProjectError>>()?,
+            Some(_) => return Err(ProjectError::InvalidValue { key: String::from("properties"), expected: "a table" }),
+        };
+
+        let include = match table.get("include") {
+            None => Vec::new(),
+            Some(toml::Value::Array(directories)) => directories.iter()
+                .map(|directory| match directory {
+                    toml::Value::String(directory) => Ok(root.join(directory)),
+                    _ => Err(ProjectError::InvalidValue { key: String::from("include"), expected: "a list of directories" }),
+                })
+                .collect::<Result<_, _>>()?,
+            Some(_) => return Err(ProjectError::InvalidValue { key: String::from("include"), expected: "a list of directories" }),
+        };
+
+        Ok(Self {
+            entry: root.join(string("entry")?.ok_or(ProjectError::MissingKey("entry"))?),
+            output: string("output")?.map(|output| root.join(output)),
+            format,
+            sample_rate,
+            properties,
+            include,
+        })
+    }
+
+    /// Scripts from the included directories, in a stable order
+    pub fn included_scripts(&self) -> Result<Vec<PathBuf>, ProjectError> {
+        let mut scripts = Vec::new();
+
+        for directory in self.include.iter() {
+            let entries = std::fs::read_dir(directory).map_err(|err| ProjectError::Io { path: directory.clone(), err })?;
+
+            let mut found = entries
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|path| path.extension().is_some_and(|extension| extension == SCRIPT_EXTENSION) && *path != self.entry)
+                .collect::<Vec<_>>();
+            found.sort();
+
+            scripts.append(&mut found);
+        };
+
+        Ok(scripts)
+    }
+
+    /// Joins the entry and the included scripts into one, with the project properties as defaults.
+    ///
+    /// Global properties of every file go before any label, the entry's last so that it has the final say
+    /// over the included files, which in turn override the project. Labels of the entry come first, so
+    /// they win over included labels of the same name
+    pub fn source(&self) -> Result<String, ProjectError> {
+        let mut headers = Vec::new();
+        let mut bodies = Vec::new();
+
+        for path in std::iter::once(self.entry.clone()).chain(self.included_scripts()?) {
+            let (header, body) = split_at_first_label(&read(&path)?);
+
+            headers.push(header);
+            bodies.push(body);
+        };
+
+        let mut source = String::new();
+        for (name, value) in self.properties.iter() {
+            source.push_str(&format!("{name}: {value}\n"));
+        };
+
+        for part in headers.iter().skip(1).chain(headers.first()).chain(bodies.iter()) {
+            source.push_str(part);
+
+            if !part.is_empty() && !part.ends_with('\n') {
+                source.push('\n');
+            };
+        };
+
+        Ok(source)
+    }
+}
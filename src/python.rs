@@ -0,0 +1,133 @@
+//! Python module, built with the `python` feature (`maturin build` picks it up from `pyproject.toml`).
+//!
+//! ```python
+//! import roorle
+//!
+//! program = roorle.compile("bpm: 120\n@main\nA 1/4")
+//! for note in program:
+//!     print(note.start, note.frequency, note.label)
+//!
+//! open("out.wav", "wb").write(program.render_wav(sample_rate=44100))
+//! ```
+
+use pyo3::create_exception;
+use pyo3::exceptions::{PyException, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+use crate::compiler;
+use crate::interpreter::{self, RenderOptions, DEFAULT_MAX_RENDER_SECONDS};
+
+
+create_exception!(roorle, RoorleError, PyException, "A script that couldn't be parsed or compiled");
+
+
+impl From<crate::Error> for PyErr {
+    fn from(err: crate::Error) -> Self {
+        RoorleError::new_err(err.to_string())
+    }
+}
+
+
+/// A note the program plays, times in seconds
+#[pyclass(module = "roorle", frozen, get_all)]
+#[derive(Clone)]
+pub struct Note {
+    start: f64,
+    duration: f64,
+    frequency: f64,
+    label: Option<String>,
+}
+
+
+#[pymethods]
+impl Note {
+    fn __repr__(&self) -> String {
+        let label = self.label.as_ref().map(|label| format!("'{label}'")).unwrap_or_else(|| String::from("None"));
+
+        format!("Note(start={}, duration={}, frequency={:.2}, label={label})", self.start, self.duration, self.frequency)
+    }
+}
+
+
+/// A compiled script, iterating over it gives its notes in the order they start
+#[pyclass(module = "roorle", frozen)]
+pub struct Program(compiler::Program);
+
+
+#[pymethods]
+impl Program {
+    /// Length of the piece in seconds
+    #[getter]
+    fn duration(&self) -> f64 {
+        self.0.get_duration()
+    }
+
+    #[getter]
+    fn title(&self) -> Option<String> {
+        self.0.get_metadata().title.clone()
+    }
+
+    fn notes(&self) -> Vec<Note> {
+        let instruments = self.0.get_instruments();
+
+        self.0.get_note_events().into_iter()
+            .map(|event| Note {
+                start: event.start,
+                duration: event.duration,
+                frequency: event.frequency,
+                label: instruments[event.instrument].name.clone(),
+            })
+            .collect()
+    }
+
+    fn __iter__(&self, py: Python<'_>) -> PyResult<Py<PyAny>> {
+        Ok(self.notes().into_pyobject(py)?.try_iter()?.into_any().unbind())
+    }
+
+    /// Renders the piece into the bytes of a 16-bit WAV file, raising `ValueError` if `sample_rate` is 0 or
+    /// the piece lasts longer than an hour
+    #[pyo3(signature = (sample_rate = 48000, stereo = false))]
+    fn render_wav<'py>(&self, py: Python<'py>, sample_rate: u32, stereo: bool) -> PyResult<Bound<'py, PyBytes>> {
+        if sample_rate == 0 {
+            return Err(PyValueError::new_err("the sample rate has to be above 0"));
+        };
+
+        let options = RenderOptions {
+            sample_rate,
+            channels: if stereo { 2 } else { 1 },
+            max_render_seconds: Some(DEFAULT_MAX_RENDER_SECONDS),
+            ..RenderOptions::default()
+        };
+
+        let wav = py.detach(|| interpreter::wav::interpret(&self.0, &options)).map_err(|err| PyValueError::new_err(err.to_string()))?;
+
+        Ok(PyBytes::new(py, &wav))
+    }
+}
+
+
+/// Parses and compiles `source`, raising `RoorleError` if it isn't a valid script
+#[pyfunction]
+fn compile(source: &str) -> PyResult<Program> {
+    Ok(Program(crate::compile(source)?))
+}
+
+
+/// Compiles and renders `source` into the bytes of a WAV file in one go
+#[pyfunction]
+#[pyo3(signature = (source, sample_rate = 48000))]
+fn render_wav<'py>(py: Python<'py>, source: &str, sample_rate: u32) -> PyResult<Bound<'py, PyBytes>> {
+    compile(source)?.render_wav(py, sample_rate, false)
+}
+
+
+#[pymodule(name = "roorle")]
+fn python_module(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(compile, m)?)?;
+    m.add_function(wrap_pyfunction!(render_wav, m)?)?;
+    m.add_class::<Program>()?;
+    m.add_class::<Note>()?;
+    m.add("RoorleError", m.py().get_type::<RoorleError>())?;
+
+    Ok(())
+}
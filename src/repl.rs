@@ -0,0 +1,136 @@
+use std::io::{self, BufRead, Write};
+use roorle::compiler::Program;
+use crate::cli::{Arguments, Command, Dump};
+
+
+const HELP: &str = "\
+type notes and commands to hear them, e.g. `A C E 1/4` or `repeat chorus 2`
+  <property>: <value>  set a global property for the rest of the session
+  @<label>             start defining a label, every following line goes into it
+  @end                 stop defining the label
+  :show                print the script the session has built so far
+  :save <path>         write that script into a file
+  :clear               forget all properties and labels
+  :help                print this message
+  :quit                leave (so does end of input)";
+
+
+#[derive(Default)]
+struct Session {
+    properties: Vec<(String, String)>,
+    labels: Vec<(String, Vec<String>)>,
+    defining: Option<usize>,
+}
+
+
+impl Session {
+    pub fn script(&self, main: Option<&str>) -> String {
+        let mut script = String::new();
+
+        for (name, value) in self.properties.iter() {
+            script.push_str(&format!("{name}: {value}\n"));
+        };
+
+        if let Some(line) = main {
+            script.push_str(&format!("@main\n{line}\n"));
+        };
+
+        for (name, lines) in self.labels.iter() {
+            script.push_str(&format!("@{name}\n"));
+
+            for line in lines {
+                script.push_str(&format!("{line}\n"));
+            };
+        };
+
+        script
+    }
+
+    pub fn set_property(&mut self, name: &str, value: &str) {
+        match self.properties.iter_mut().find(|(property, _)| property == name) {
+            Some((_, old)) => *old = String::from(value),
+            None => self.properties.push((String::from(name), String::from(value))),
+        };
+    }
+
+    pub fn begin_label(&mut self, name: &str) {
+        self.defining = Some(match self.labels.iter().position(|(label, _)| label == name) {
+            Some(i) => {
+                self.labels[i].1.clear();
+                i
+            },
+            None => {
+                self.labels.push((String::from(name), Vec::new()));
+                self.labels.len() - 1
+            },
+        });
+    }
+}
+
+
+fn prompt(session: &Session) {
+    match session.defining {
+        Some(label) => eprint!("@{}> ", session.labels[label].0),
+        None => eprint!("> "),
+    };
+
+    let _ = io::stderr().flush();
+}
+
+
+fn play(program: &Program, arguments: &Arguments) -> Result<(), u8> {
+    #[cfg(feature = "playback")]
+    {
+        crate::execute(program, &Arguments { command: Command::Play, ..arguments.clone() }).map(|_| ())
+    }
+
+    #[cfg(not(feature = "playback"))]
+    {
+        let output = arguments.output.clone().unwrap_or_else(|| String::from("repl.wav"));
+        crate::execute(program, &Arguments { command: Command::Render, output: Some(output.clone()), ..arguments.clone() })?;
+
+        eprintln!("wrote {output}");
+
+        Ok(())
+    }
+}
+
+
+pub fn run(arguments: &Arguments) -> Result<(), u8> {
+    let mut session = Session::default();
+
+    eprintln!("roorle {}, :help for help", env!("CARGO_PKG_VERSION"));
+    prompt(&session);
+
+    for line in io::stdin().lock().lines() {
+        let line = line.map_err(|_| crate::EXIT_IO)?;
+        let line = line.trim();
+
+        match line.split_once(' ').map_or((line, ""), |(command, rest)| (command, rest.trim())) {
+            ("", _) => { },
+            (":quit" | ":q", _) => break,
+            (":help", _) => eprintln!("{HELP}"),
+            (":show", _) => eprint!("{}", session.script(None)),
+            (":clear", _) => session = Session::default(),
+            (":save", "") => eprintln!("error: usage: :save <path>"),
+            (":save", path) => if let Err(err) = std::fs::write(path, session.script(None)) {
+                eprintln!("error: can't write {path}: {err}");
+            },
+            ("@end", _) => session.defining = None,
+            (label, "") if label.len() > 1 && label.starts_with('@') => session.begin_label(&label[1..]),
+            _ => if let Some(label) = session.defining {
+                session.labels[label].1.push(String::from(line));
+            } else if let Some((name, value)) = line.split_once(':') && !name.contains(' ') {
+                session.set_property(name.trim(), value.trim());
+            } else if let Ok(program) = crate::compile(&session.script(Some(line)), Dump::default(), false, arguments.strict, &mut io::sink()) {
+                let _ = play(&program, arguments);
+            },
+        };
+
+        prompt(&session);
+    };
+
+    eprintln!();
+
+    Ok(())
+}
@@ -0,0 +1,228 @@
+//! Writing scripts from Rust instead of text, for music that's generated rather than composed:
+//!
+//! ```
+//! use roorle::score::{frac, ScoreBuilder};
+//!
+//! let program = ScoreBuilder::new()
+//!     .bpm(120)
+//!     .label("main", |l| l
+//!         .note("C", 4, frac(1, 4))
+//!         .chord("E", &["G"], 4, frac(1, 4))
+//!         .rest(frac(1, 8))
+//!         .note("C", 5, frac(1, 2))
+//!         .repeat("outro", 2))
+//!     .label("outro", |l| l.property("volume", frac(1, 2)).note("G", 3, 1))
+//!     .build()?;
+//! # Ok::<(), roorle::compiler::CompilingError>(())
+//! ```
+
+use crate::compiler::{CompilingError, Program};
+use crate::syntax::parser::{Script, Token, Value};
+
+
+/// A `numerator / denominator` value, mostly for note lengths
+pub fn frac(numerator: u32, denominator: u32) -> Value {
+    Value::Fraction { numerator, denominator }
+}
+
+
+enum Item {
+    Notes {
+        root: String,
+        others: Vec<String>,
+        octave: u32,
+        duration: Value,
+    },
+    Rest {
+        duration: Value,
+    },
+    Repeat {
+        label: String,
+        count: u32,
+    },
+    Goto {
+        label: String,
+    },
+}
+
+
+/// The properties and commands of one label, see [`ScoreBuilder::label`]
+pub struct LabelBuilder {
+    name: String,
+    properties: Vec<(String, Value)>,
+    items: Vec<Item>,
+}
+
+
+impl LabelBuilder {
+    fn new(name: &str) -> Self {
+        Self { name: String::from(name), properties: Vec::new(), items: Vec::new() }
+    }
+
+    /// Sets a property of this label only, like `volume` or `voice`
+    pub fn property(mut self, name: &str, value: impl Into<Value>) -> Self {
+        self.properties.push((String::from(name), value.into()));
+        self
+    }
+
+    pub fn bpm(self, bpm: u32) -> Self {
+        self.property("bpm", bpm)
+    }
+
+    /// Plays `name` (like `C` or `Fas`) in the given octave
+    pub fn note(self, name: &str, octave: u32, duration: impl Into<Value>) -> Self {
+        self.chord(name, &[], octave, duration)
+    }
+
+    /// Plays `root` and all of `others` at once in the given octave
+    pub fn chord(mut self, root: &str, others: &[&str], octave: u32, duration: impl Into<Value>) -> Self {
+        self.items.push(Item::Notes {
+            root: String::from(root),
+            others: others.iter().map(|name| String::from(*name)).collect(),
+            octave,
+            duration: duration.into(),
+        });
+        self
+    }
+
+    /// Plays nothing for `duration`
+    pub fn rest(mut self, duration: impl Into<Value>) -> Self {
+        self.items.push(Item::Rest { duration: duration.into() });
+        self
+    }
+
+    pub fn repeat(mut self, label: &str, count: u32) -> Self {
+        self.items.push(Item::Repeat { label: String::from(label), count });
+        self
+    }
+
+    /// Continues at `label` without coming back, anything added after it is never played
+    pub fn goto(mut self, label: &str) -> Self {
+        self.items.push(Item::Goto { label: String::from(label) });
+        self
+    }
+
+    fn label_tokens(name: &str, properties: &[(String, Value)], octave: Option<u32>) -> Vec<Token> {
+        std::iter::once(Token::Label { name: String::from(name) })
+            .chain(properties.iter()
+                .filter(|(property, _)| octave.is_none() || property != "octave")
+                .map(|(property, value)| Token::Property { name: property.clone(), value: value.clone() }))
+            .chain(octave.map(|octave| Token::Property { name: String::from("octave"), value: Value::Whole(octave) }))
+            .collect()
+    }
+
+    /// Labels only have one octave, so runs of notes in another one than the first note's move into
+    /// labels of their own (named `<label>~<n>`, with the same properties) that are repeated once
+    fn tokens(self) -> Vec<Token> {
+        let octave = self.items.iter().find_map(|item| match item {
+            Item::Notes { octave, .. } => Some(*octave),
+            _ => None,
+        });
+        let moved_name = |n: usize| format!("{}~{n}", self.name);
+
+        let mut tokens = Self::label_tokens(&self.name, &self.properties, octave);
+        let mut moved: Vec<(u32, Vec<Token>)> = Vec::new();
+        let mut in_run = false;
+
+        for item in self.items {
+            let token = match item {
+                Item::Notes { root, others, octave: note_octave, duration } => {
+                    let note = Token::Command {
+                        name: root,
+                        arguments: others.into_iter().map(Value::String).chain(std::iter::once(duration)).collect(),
+                        overrides: Vec::new(),
+                    };
+
+                    if Some(note_octave) != octave {
+                        if !in_run || moved.last().is_some_and(|(run_octave, _)| *run_octave != note_octave) {
+                            moved.push((note_octave, Vec::new()));
+                            tokens.push(Token::Command {
+                                name: String::from("repeat"),
+                                arguments: vec![Value::String(moved_name(moved.len())), Value::Whole(1)],
+                                overrides: Vec::new(),
+                            });
+                        };
+
+                        moved.last_mut().unwrap().1.push(note);
+                        in_run = true;
+
+                        continue;
+                    };
+
+                    note
+                },
+                Item::Rest { duration } => {
+                    let rest = Token::Command { name: String::from("rest"), arguments: vec![duration], overrides: Vec::new() };
+
+                    // rests have no octave, so they don't break up a run of notes in another one
+                    match moved.last_mut() {
+                        Some((_, run)) if in_run => run.push(rest),
+                        _ => tokens.push(rest),
+                    };
+
+                    continue;
+                },
+                Item::Repeat { label, count } => Token::Command {
+                    name: String::from("repeat"),
+                    arguments: vec![Value::String(label), Value::Whole(count)],
+                    overrides: Vec::new(),
+                },
+                Item::Goto { label } => Token::Command { name: String::from("goto"), arguments: vec![Value::String(label)], overrides: Vec::new() },
+            };
+
+            tokens.push(token);
+            in_run = false;
+        };
+
+        for (i, (octave, notes)) in moved.into_iter().enumerate() {
+            tokens.extend(Self::label_tokens(&moved_name(i + 1), &self.properties, Some(octave)));
+            tokens.extend(notes);
+        };
+
+        tokens
+    }
+}
+
+
+/// Builds a [`Script`] or a [`Program`] without going through text
+#[derive(Default)]
+pub struct ScoreBuilder {
+    properties: Vec<(String, Value)>,
+    labels: Vec<LabelBuilder>,
+}
+
+
+impl ScoreBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets a global property, like `title` or `volume`
+    pub fn property(mut self, name: &str, value: impl Into<Value>) -> Self {
+        self.properties.push((String::from(name), value.into()));
+        self
+    }
+
+    pub fn bpm(self, bpm: u32) -> Self {
+        self.property("bpm", bpm)
+    }
+
+    /// Adds a label, `main` is where the piece starts
+    pub fn label(mut self, name: &str, build: impl FnOnce(LabelBuilder) -> LabelBuilder) -> Self {
+        self.labels.push(build(LabelBuilder::new(name)));
+        self
+    }
+
+    pub fn script(self) -> Script {
+        let tokens = self.properties.into_iter()
+            .map(|(name, value)| Token::Property { name, value })
+            .chain(self.labels.into_iter().flat_map(LabelBuilder::tokens))
+            .collect::<Vec<_>>();
+
+        Script::from(tokens)
+    }
+
+    pub fn build(self) -> Result<Program, CompilingError> {
+        Program::try_from(&self.script())
+    }
+}
@@ -0,0 +1,168 @@
+//! Turning series of numbers into melodies, for hearing data rather than plotting it:
+//!
+//! ```
+//! use roorle::score::frac;
+//! use roorle::sonify::{Scale, Sonification};
+//!
+//! let temperatures = [3.1, 4.5, 8.2, 12.9, 17.4, 20.8, 22.5, 21.9, 18.0, 12.3, 7.1, 3.8];
+//! let program = Sonification::new()
+//!     .scale(Scale::Pentatonic)
+//!     .range(48, 84)
+//!     .duration(frac(1, 8))
+//!     .build(&temperatures)?;
+//! # Ok::<(), roorle::compiler::CompilingError>(())
+//! ```
+//!
+//! The smallest value of the series gets the lowest key of the range and the largest the highest, the
+//! rest falling in between on the keys of the scale. Values that aren't finite, like gaps read as NaN,
+//! are rests
+
+use crate::compiler::{CompilingError, Program};
+use crate::import::key_name;
+use crate::score::{frac, ScoreBuilder};
+use crate::syntax::parser::{Script, Value};
+
+
+/// Which keys of an octave values can land on
+#[derive(Clone, Debug, PartialEq)]
+pub enum Scale {
+    Chromatic,
+    Major,
+    Minor,
+    /// The major pentatonic, which has no clashing steps however the data jumps
+    Pentatonic,
+    /// Semitones above the lowest key of the range, repeated every octave
+    Custom(Vec<u8>),
+}
+
+
+impl Scale {
+    fn steps(&self) -> &[u8] {
+        match self {
+            Self::Chromatic => &[0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11],
+            Self::Major => &[0, 2, 4, 5, 7, 9, 11],
+            Self::Minor => &[0, 2, 3, 5, 7, 8, 10],
+            Self::Pentatonic => &[0, 2, 4, 7, 9],
+            Self::Custom(steps) => steps,
+        }
+    }
+
+    /// Keys of the scale from `low` to `high`, starting on `low`
+    fn keys(&self, low: u8, high: u8) -> Vec<u8> {
+        (low..=high)
+            .filter(|key| self.steps().iter().any(|step| step % 12 == (key - low) % 12))
+            .collect()
+    }
+}
+
+
+/// How a series is played, see the [module docs](self)
+#[derive(Clone, Debug)]
+pub struct Sonification {
+    scale: Scale,
+    low: u8,
+    high: u8,
+    duration: Value,
+    bpm: u32,
+    domain: Option<(f64, f64)>,
+}
+
+
+impl Default for Sonification {
+    fn default() -> Self {
+        Self {
+            scale: Scale::Major,
+            low: 48,
+            high: 84,
+            duration: frac(1, 8),
+            bpm: 120,
+            domain: None,
+        }
+    }
+}
+
+
+impl Sonification {
+    /// A C major scale from `C` in octave 3 to `C` in octave 6, an 8th note per value at `bpm: 120`
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn scale(mut self, scale: Scale) -> Self {
+        self.scale = scale;
+        self
+    }
+
+    /// Lowest and highest keys values are played at, counted like MIDI's with 60 being `C` in octave 4.
+    /// The scale starts on `low`
+    pub fn range(mut self, low: u8, high: u8) -> Self {
+        self.low = low.min(high).min(127);
+        self.high = high.max(low).min(127);
+        self
+    }
+
+    /// How long every value is played, as a fraction of a whole note
+    pub fn duration(mut self, duration: impl Into<Value>) -> Self {
+        self.duration = duration.into();
+        self
+    }
+
+    pub fn bpm(mut self, bpm: u32) -> Self {
+        self.bpm = bpm;
+        self
+    }
+
+    /// Values mapped to the lowest and the highest key, instead of the smallest and the largest of the
+    /// series. Values outside of it are played at the nearest end, so series can share one scale
+    pub fn domain(mut self, min: f64, max: f64) -> Self {
+        self.domain = Some((min, max));
+        self
+    }
+
+    /// The key every value is played at, as a MIDI key, or `None` for a rest
+    pub fn keys(&self, data: &[f64]) -> Vec<Option<u8>> {
+        let keys = self.scale.keys(self.low, self.high);
+        let (min, max) = self.domain.unwrap_or_else(|| {
+            data.iter()
+                .filter(|value| value.is_finite())
+                .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), value| (min.min(*value), max.max(*value)))
+        });
+
+        data.iter()
+            .map(|value| {
+                if !value.is_finite() || keys.is_empty() {
+                    return None;
+                };
+
+                // a flat series sits in the middle of the range
+                let position = if max > min { ((value - min) / (max - min)).clamp(0.0, 1.0) } else { 0.5 };
+
+                Some(keys[(position * (keys.len() - 1) as f64).round() as usize])
+            })
+            .collect()
+    }
+
+    /// A score playing `data` in its `main` label
+    pub fn score(&self, data: &[f64]) -> ScoreBuilder {
+        let keys = self.keys(data);
+
+        ScoreBuilder::new()
+            .bpm(self.bpm)
+            .label("main", |label| keys.into_iter().fold(label, |label, key| match key {
+                Some(key) => {
+                    let (name, octave) = key_name(key);
+
+                    label.note(name, octave, self.duration.clone())
+                },
+                None => label.rest(self.duration.clone()),
+            }))
+    }
+
+    pub fn script(&self, data: &[f64]) -> Script {
+        self.score(data).script()
+    }
+
+    pub fn build(&self, data: &[f64]) -> Result<Program, CompilingError> {
+        self.score(data).build()
+    }
+}
@@ -0,0 +1,130 @@
+use crate::syntax::lexer::{Comment, LexerConfig, Token, TokenStream};
+use crate::syntax::parser::{ParsingError, Script, Token as PToken};
+
+
+const INDENT: &str = "    ";
+
+
+struct Statement {
+    words: Vec<String>,
+    start: usize,
+    end: usize,
+}
+
+
+fn format_statement(config: &LexerConfig, words: &[String]) -> String {
+    match words {
+        [marker, name] if marker == "@" => format!("@{}", config.escape(name)),
+        [marker, name, block] if marker == "@" && block == PToken::BLOCK_START => format!("@{} {}", config.escape(name), PToken::BLOCK_START),
+        [name, separator, values @ ..] if separator == ":" => format!("{}: {}", config.escape(name), format_values(config, values)),
+        words => format_values(config, words),
+    }
+}
+
+
+fn format_values(config: &LexerConfig, words: &[String]) -> String {
+    let mut formatted = String::new();
+
+    for (i, word) in words.iter().enumerate() {
+        let joined = word == "/" || words.get(i.wrapping_sub(1)).is_some_and(|previous| previous == "/");
+
+        if i > 0 && !joined {
+            formatted.push(' ');
+        };
+
+        formatted.push_str(&config.escape(word));
+    };
+
+    formatted
+}
+
+
+fn push_comment(formatted: &mut String, comment: &Comment, indent: usize) {
+    formatted.push_str(&INDENT.repeat(indent));
+
+    formatted.push_str(comment.text.trim_end());
+    formatted.push('\n');
+}
+
+
+pub fn format(source: &str) -> Result<String, ParsingError> {
+    Script::try_from(source)?;
+
+    // words are written back for the version the script declares
+    let config = LexerConfig::of(source);
+    let mut stream = TokenStream::from(source.chars()).retaining_comments();
+
+    let mut statements = Vec::new();
+    let mut current: Option<Statement> = None;
+    for token in stream.by_ref() {
+        match token {
+            Token::Word { value, start, .. } => {
+                // a `}` goes on a line of its own, even when written right after a statement
+                let split = current.as_ref().is_some_and(|current| value == PToken::BLOCK_END || current.words == [PToken::BLOCK_END]);
+                if split && let Some(mut statement) = current.take() {
+                    statement.end = start;
+                    statements.push(statement);
+                };
+
+                current.get_or_insert(Statement { words: Vec::new(), start, end: start }).words.push(value);
+            },
+            Token::SentenceEnd { pos } => if let Some(mut statement) = current.take() {
+                statement.end = pos;
+                statements.push(statement);
+            },
+        };
+    };
+    if let Some(mut statement) = current.take() {
+        statement.end = source.chars().count();
+        statements.push(statement);
+    };
+
+    let mut comments = stream.take_comments().into_iter().peekable();
+    let mut formatted = String::new();
+    // how many blocks deep, and whether the statements are in a label written without one
+    let mut depth = 0;
+    let mut in_label = false;
+
+    for statement in statements.iter() {
+        let is_label = statement.words.first().is_some_and(|word| word == "@");
+        let opens = statement.words.last().is_some_and(|word| word == PToken::BLOCK_START);
+        let closes = statement.words == [PToken::BLOCK_END];
+
+        if closes {
+            depth -= 1;
+            in_label = false;
+        };
+        let indent = if is_label || opens || closes { depth } else { depth + in_label as usize };
+
+        if is_label && !formatted.is_empty() {
+            formatted.push('\n');
+        };
+
+        while let Some(comment) = comments.next_if(|comment| comment.start < statement.start) {
+            push_comment(&mut formatted, &comment, indent);
+        };
+
+        if opens {
+            depth += 1;
+            in_label = false;
+        } else if is_label {
+            in_label = true;
+        };
+
+        formatted.push_str(&INDENT.repeat(indent));
+        formatted.push_str(&format_statement(&config, &statement.words));
+
+        while let Some(comment) = comments.next_if(|comment| comment.start < statement.end) {
+            formatted.push_str("  ");
+            formatted.push_str(comment.text.trim_end());
+        };
+
+        formatted.push('\n');
+    };
+
+    for comment in comments {
+        push_comment(&mut formatted, &comment, depth + in_label as usize);
+    };
+
+    Ok(formatted)
+}
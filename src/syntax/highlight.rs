@@ -0,0 +1,228 @@
+use std::ops::Range;
+use crate::syntax::lexer::{LexerConfig, Token, TokenStream};
+use crate::syntax::parser::Token as PToken;
+
+
+const STYLE: &str = "\
+.roorle { color: #d4d4d4; background: #1e1e1e; padding: 1em; }
+.roorle .comment { color: #6a9955; font-style: italic; }
+.roorle .label { color: #dcdcaa; font-weight: bold; }
+.roorle .property { color: #9cdcfe; }
+.roorle .keyword { color: #c586c0; }
+.roorle .note { color: #4ec9b0; }
+.roorle .number { color: #b5cea8; }
+.roorle .punctuation { color: #808080; }
+.roorle .text { color: #ce9178; }";
+
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Class {
+    Plain,
+    Comment,
+    Label,
+    Property,
+    Keyword,
+    Note,
+    Number,
+    Punctuation,
+    Text,
+}
+
+
+impl Class {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Plain => "plain",
+            Self::Comment => "comment",
+            Self::Label => "label",
+            Self::Property => "property",
+            Self::Keyword => "keyword",
+            Self::Note => "note",
+            Self::Number => "number",
+            Self::Punctuation => "punctuation",
+            Self::Text => "text",
+        }
+    }
+
+    fn ansi(self) -> &'static str {
+        match self {
+            Self::Plain => "0",
+            Self::Comment => "3;32",
+            Self::Label => "1;33",
+            Self::Property => "36",
+            Self::Keyword => "35",
+            Self::Note => "1;36",
+            Self::Number => "32",
+            Self::Punctuation => "90",
+            Self::Text => "31",
+        }
+    }
+}
+
+
+/// Where a word that starts at `start` ends in the source, escapes included
+fn word_end(config: &LexerConfig, chars: &[char], start: usize) -> usize {
+    if chars.get(start).is_some_and(|c| config.independent_words.contains(c)) {
+        return start + 1;
+    };
+
+    let mut end = start;
+    let mut escaping = false;
+    let mut quoting = false;
+    let mut raw = false;
+    while let Some(c) = chars.get(end) {
+        let ends_word = config.word_separators.contains(c)
+            || config.independent_words.contains(c)
+            || config.line_separators.contains(c)
+            || [config.endline_comment, config.multiline_comment_start].contains(c);
+
+        if ends_word && !escaping && !quoting {
+            break;
+        };
+
+        if config.quote == Some(*c) && !escaping {
+            raw = !quoting && end == start + 1 && config.raw_prefix == Some(chars[start]);
+            quoting = !quoting;
+        };
+        escaping = !escaping && !raw && *c == config.escape_symbol;
+        end += 1;
+    };
+
+    end
+}
+
+
+fn classify_statement(words: &[String]) -> Vec<Class> {
+    let value = |word: &String| if word == "/" {
+        Class::Punctuation
+    } else if !word.is_empty() && word.chars().all(|c| c.is_ascii_digit()) {
+        Class::Number
+    } else {
+        Class::Text
+    };
+
+    match words {
+        [marker, _] if marker == "@" => vec![Class::Punctuation, Class::Label],
+        [marker, _, block] if marker == "@" && block == PToken::BLOCK_START => vec![Class::Punctuation, Class::Label, Class::Punctuation],
+        [block] if block == PToken::BLOCK_START || block == PToken::BLOCK_END => vec![Class::Punctuation],
+        [_, separator, values @ ..] if separator == ":" => [Class::Property, Class::Punctuation].into_iter()
+            .chain(values.iter().map(value))
+            .collect(),
+        [command, label, rest @ ..] if command == "goto" || command == "repeat" => [Class::Keyword, Class::Label].into_iter()
+            .chain(rest.iter().map(value))
+            .collect(),
+        [command, ..] if command == "goto" || command == "repeat" => vec![Class::Keyword],
+        [command, arguments @ ..] if command == "mark" => std::iter::once(Class::Keyword)
+            .chain(arguments.iter().map(|_| Class::Text))
+            .collect(),
+        [command, arguments @ ..] if command == "rest" => std::iter::once(Class::Keyword)
+            .chain(arguments.iter().map(value))
+            .collect(),
+        [command, _, points @ ..] if command == "automate" => [Class::Keyword, Class::Property].into_iter()
+            .chain(points.iter().map(value))
+            .collect(),
+        [command, arguments @ ..] if command == "ghost" => std::iter::once(Class::Keyword)
+            .chain(arguments.iter().map(|word| match value(word) {
+                Class::Text => Class::Note,
+                class => class,
+            }))
+            .collect(),
+        [_, arguments @ ..] => std::iter::once(Class::Note)
+            .chain(arguments.iter().map(|word| match value(word) {
+                Class::Text => Class::Note,
+                class => class,
+            }))
+            .collect(),
+        [] => Vec::new(),
+    }
+}
+
+
+/// Splits the source into runs of chars of the same class, covering all of it
+pub fn classify(source: &str) -> Vec<(Class, Range<usize>)> {
+    let chars = source.chars().collect::<Vec<_>>();
+    let mut classes = vec![Class::Plain; chars.len()];
+
+    let config = LexerConfig::of(source);
+    let mut stream = TokenStream::from(source.chars()).retaining_comments();
+
+    let mut statement: Vec<(String, usize)> = Vec::new();
+    let mut paint = |statement: &mut Vec<(String, usize)>| {
+        let words = statement.iter().map(|(word, _)| word.clone()).collect::<Vec<_>>();
+
+        for ((_, start), class) in statement.iter().zip(classify_statement(&words)) {
+            classes[*start..word_end(&config, &chars, *start)].fill(class);
+        };
+
+        statement.clear();
+    };
+
+    for token in stream.by_ref() {
+        match token {
+            // a `}` closes the block whatever it's written after
+            Token::Word { value, start, .. } if value == PToken::BLOCK_END => {
+                paint(&mut statement);
+                statement.push((value, start));
+                paint(&mut statement);
+            },
+            Token::Word { value, start, .. } => statement.push((value, start)),
+            Token::SentenceEnd { .. } => paint(&mut statement),
+        };
+    };
+    paint(&mut statement);
+
+    for comment in stream.take_comments() {
+        let end = (comment.start + comment.text.chars().count()).min(chars.len());
+        classes[comment.start..end].fill(Class::Comment);
+    };
+
+    let mut runs: Vec<(Class, Range<usize>)> = Vec::new();
+    for (i, class) in classes.into_iter().enumerate() {
+        match runs.last_mut() {
+            Some((last, range)) if *last == class => range.end = i + 1,
+            _ => runs.push((class, i..i + 1)),
+        };
+    };
+
+    runs
+}
+
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+
+pub fn to_html(source: &str) -> String {
+    let chars = source.chars().collect::<Vec<_>>();
+
+    let mut html = format!("<style>\n{STYLE}\n</style>\n<pre class=\"roorle\">");
+    for (class, range) in classify(source) {
+        let text = escape_html(&chars[range].iter().collect::<String>());
+
+        match class {
+            Class::Plain => html.push_str(&text),
+            class => html.push_str(&format!("<span class=\"{}\">{text}</span>", class.name())),
+        };
+    };
+    html.push_str("</pre>\n");
+
+    html
+}
+
+
+pub fn to_ansi(source: &str) -> String {
+    let chars = source.chars().collect::<Vec<_>>();
+
+    let mut ansi = String::new();
+    for (class, range) in classify(source) {
+        let text = chars[range].iter().collect::<String>();
+
+        match class {
+            Class::Plain => ansi.push_str(&text),
+            class => ansi.push_str(&format!("\x1b[{}m{text}\x1b[0m", class.ansi())),
+        };
+    };
+
+    ansi
+}
@@ -1,8 +1,129 @@
 use std::collections::VecDeque;
 use std::fmt;
+use std::io::{self, BufRead};
+use crate::json;
 use crate::take::Take;
 
 
+/// Chars `start..end` of the source something was written at, along with the bytes they are in UTF-8
+/// and the line and column they start at
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub byte_start: usize,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub byte_end: usize,
+    /// Counted from 1, `\r\n` ending a line just like `\n`. 0 for spans that weren't located in a source
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub line: usize,
+    /// Chars into the line, counted from 1
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub column: usize,
+}
+
+
+impl Span {
+    /// A span of chars that isn't located in a source, see [`SourceMap::span`] for one that is
+    pub fn new(start: usize, end: usize) -> Self {
+        Self { start, end, ..Self::default() }
+    }
+
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    pub fn contains(&self, offset: usize) -> bool {
+        (self.start..self.end).contains(&offset)
+    }
+
+    /// The smallest span covering both
+    pub fn to(self, other: Span) -> Self {
+        let first = if self.start <= other.start { self } else { other };
+        let last = if self.end >= other.end { self } else { other };
+
+        Self { end: last.end, byte_end: last.byte_end, ..first }
+    }
+}
+
+
+/// Where the chars of a source are in bytes and lines, filled in a char at a time as it's read
+#[derive(Clone, Debug, Default)]
+pub struct SourceMap {
+    /// Chars the lines after the first start at
+    lines: Vec<usize>,
+    /// Chars taking more than a byte, each with how many bytes more than chars there are up to its end
+    wide: Vec<(usize, usize)>,
+    chars: usize,
+}
+
+
+impl SourceMap {
+    pub fn new(source: &str) -> Self {
+        let mut map = Self::default();
+        source.chars().for_each(|c| map.push(c));
+
+        map
+    }
+
+    /// Records the next char of the source
+    pub fn push(&mut self, c: char) {
+        if c.len_utf8() > 1 {
+            let extra = self.wide.last().map_or(0, |(_, extra)| *extra) + c.len_utf8() - 1;
+            self.wide.push((self.chars, extra));
+        };
+
+        self.chars += 1;
+
+        // the `\r` of a `\r\n` is the last char of its line, like a space before the `\n` would be
+        if c == '\n' {
+            self.lines.push(self.chars);
+        };
+    }
+
+    /// How many chars of the source were recorded
+    pub fn chars(&self) -> usize {
+        self.chars
+    }
+
+    /// Bytes before char `offset`
+    pub fn byte(&self, offset: usize) -> usize {
+        let wide = self.wide.partition_point(|(at, _)| *at < offset);
+
+        offset + wide.checked_sub(1).map_or(0, |last| self.wide[last].1)
+    }
+
+    /// Line and column of char `offset`, both counted from 1
+    pub fn line_and_column(&self, offset: usize) -> (usize, usize) {
+        let line = self.lines.partition_point(|start| *start <= offset);
+        let start = line.checked_sub(1).map_or(0, |last| self.lines[last]);
+
+        (line + 1, offset - start + 1)
+    }
+
+    /// Char line `line`, counted from 1, starts at, the end of the source for lines past its last
+    pub fn line_start(&self, line: usize) -> usize {
+        match line {
+            0 | 1 => 0,
+            line => self.lines.get(line - 2).copied().unwrap_or(self.chars),
+        }
+    }
+
+    /// The span of chars `start..end`, located in the source
+    pub fn span(&self, start: usize, end: usize) -> Span {
+        let (line, column) = self.line_and_column(start);
+
+        Span { start, end, byte_start: self.byte(start), byte_end: self.byte(end), line, column }
+    }
+}
+
+
 #[derive(Clone, Debug)]
 pub enum Token {
     SentenceEnd {
@@ -10,19 +131,47 @@ pub enum Token {
     },
     Word {
         start: usize,
+        /// Where the word stops, which is after `value.len()` chars unless it has escapes
+        end: usize,
         value: String,
     },
 }
 
 
 impl Token {
-    pub const WORD_SEPARATORS: &'static [char] = &[' '];
+    // the chars of version 1 of the syntax
+
+    /// `\r` only ever comes before a `\n` in sources written on Windows
+    pub const WORD_SEPARATORS: &'static [char] = &[' ', '\r'];
     pub const INDEPENDENT_WORDS: &'static [char] = &['@', ':', '/'];
     pub const LINE_SEPARATORS: &'static [char] = &['\n', ';'];
     pub const ESCAPE_SYMBOL: char = '\\';
     pub const ENDLINE_COMMENT: char = '#';
     pub const MULTILINE_COMMENT_START: char = '<';
     pub const MULTILINE_COMMENT_END: char = '>';
+    /// From version 2 of the syntax on
+    pub const QUOTE: char = '"';
+    /// From version 2 of the syntax on, right before a quote
+    pub const RAW_PREFIX: char = 'r';
+
+    /// `word` written so the lexer reads it back as one word, see [`LexerConfig::escape`]
+    pub fn escape(word: &str) -> String {
+        LexerConfig::default().escape(word)
+    }
+
+    pub fn span(&self) -> Span {
+        match self {
+            Self::SentenceEnd { pos } => Span::new(*pos, pos + 1),
+            Self::Word { start, end, .. } => Span::new(*start, *end),
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        match self {
+            Self::SentenceEnd { pos } => format!("{{\"kind\":\"end\",\"pos\":{pos}}}"),
+            Self::Word { start, end, value } => format!("{{\"kind\":\"word\",\"value\":{},\"start\":{start},\"end\":{end}}}", json::string(value)),
+        }
+    }
 }
 
 
@@ -30,12 +179,145 @@ impl fmt::Display for Token {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Self::SentenceEnd { pos } => write!(f, "separator (at {pos})"),
-            Token::Word { start, value } => write!(f, "'{value}' (at {start})"),
+            Token::Word { start, value, .. } => write!(f, "'{value}' (at {start})"),
+        }
+    }
+}
+
+
+/// Chars the lexer gives a meaning to, which differ between versions of the syntax. A script is read
+/// with the version it was written for, so chars taken for new syntax don't change what old scripts mean:
+/// scripts declare a version after the first with a `# syntax: 2` comment as their first line, which
+/// every version reads as a comment
+#[derive(Clone, Debug, PartialEq)]
+pub struct LexerConfig {
+    pub word_separators: Vec<char>,
+    /// Chars that are words on their own even when written right next to others
+    pub independent_words: Vec<char>,
+    pub line_separators: Vec<char>,
+    pub escape_symbol: char,
+    pub endline_comment: char,
+    pub multiline_comment_start: char,
+    pub multiline_comment_end: char,
+    /// Whether `\n` and `\t` are a newline and a tab rather than `n` and `t`, any other escaped char
+    /// being itself either way
+    pub escape_sequences: bool,
+    /// Chars between two of these are part of the word, separators and comments included. Escapes are
+    /// still read, so a quote can be written in them as `\"`
+    pub quote: Option<char>,
+    /// Written right before a quote, makes the chars up to the next quote taken exactly as they are,
+    /// escapes included
+    pub raw_prefix: Option<char>,
+}
+
+
+/// The chars of version 1, which scripts that don't declare a version are written for
+impl Default for LexerConfig {
+    fn default() -> Self {
+        Self::version(1).unwrap()
+    }
+}
+
+
+impl LexerConfig {
+    pub const LATEST_VERSION: u32 = 2;
+    /// What comes after the comment char in the comment declaring a script's version
+    const DECLARATION: &'static str = "syntax:";
+
+    /// The chars of version `version` of the syntax, [`None`] if there's no such version
+    pub fn version(version: u32) -> Option<Self> {
+        match version {
+            1 => Some(Self {
+                word_separators: Token::WORD_SEPARATORS.to_vec(),
+                independent_words: Token::INDEPENDENT_WORDS.to_vec(),
+                line_separators: Token::LINE_SEPARATORS.to_vec(),
+                escape_symbol: Token::ESCAPE_SYMBOL,
+                endline_comment: Token::ENDLINE_COMMENT,
+                multiline_comment_start: Token::MULTILINE_COMMENT_START,
+                multiline_comment_end: Token::MULTILINE_COMMENT_END,
+                escape_sequences: false,
+                quote: None,
+                raw_prefix: None,
+            }),
+            2 => Some(Self {
+                escape_sequences: true,
+                quote: Some(Token::QUOTE),
+                raw_prefix: Some(Token::RAW_PREFIX),
+                ..Self::version(1)?
+            }),
+            _ => None,
+        }
+    }
+
+    /// The chars of the version `line` declares, if it's a `# syntax: 2` comment for one there is
+    pub fn declared(line: &str) -> Option<Self> {
+        let declaration = line.strip_prefix(Token::ENDLINE_COMMENT)?.trim().strip_prefix(Self::DECLARATION)?;
+
+        Self::version(declaration.trim().parse().ok()?)
+    }
+
+    /// The comment declaring `version` as the first line of a script
+    pub fn declaration(version: u32) -> String {
+        format!("{} {} {version}", Token::ENDLINE_COMMENT, Self::DECLARATION)
+    }
+
+    /// The chars `source` is read with, those of the version it declares on its first line or version 1's
+    pub fn of(source: &str) -> Self {
+        source.lines().next().and_then(Self::declared).unwrap_or_default()
+    }
+
+    /// Whether `c` has to be escaped to be part of a word
+    pub fn is_special(&self, c: char) -> bool {
+        self.word_separators.contains(&c)
+            || self.independent_words.contains(&c)
+            || self.line_separators.contains(&c)
+            || [self.escape_symbol, self.endline_comment, self.multiline_comment_start, self.multiline_comment_end].contains(&c)
+            || self.quote == Some(c)
+    }
+
+    /// `word` written so the lexer reads it back as one word, in quotes if it's empty or has spaces. The
+    /// independent words on their own can't be told apart from their escaped versions, so they're left as
+    /// they are
+    pub fn escape(&self, word: &str) -> String {
+        if word.chars().count() == 1 && self.independent_words.contains(&word.chars().next().unwrap()) {
+            return String::from(word);
+        };
+
+        let quote = self.quote.filter(|_| word.is_empty() || word.contains(' '));
+
+        let mut escaped = String::from_iter(quote);
+        for c in word.chars() {
+            match c {
+                '\n' if self.escape_sequences => escaped.extend([self.escape_symbol, 'n']),
+                '\t' if self.escape_sequences => escaped.extend([self.escape_symbol, 't']),
+                c if quote.is_some() && (Some(c) == quote || c == self.escape_symbol) => escaped.extend([self.escape_symbol, c]),
+                c if quote.is_none() && self.is_special(c) => escaped.extend([self.escape_symbol, c]),
+                c => escaped.push(c),
+            };
+        };
+        escaped.extend(quote);
+
+        escaped
+    }
+
+    /// The char `c` stands for after an escape symbol
+    fn unescape(&self, c: char) -> char {
+        match c {
+            'n' if self.escape_sequences => '\n',
+            't' if self.escape_sequences => '\t',
+            c => c,
         }
     }
 }
 
 
+#[derive(Clone, Debug)]
+pub struct Comment {
+    pub start: usize,
+    pub text: String,
+}
+
+
 #[derive(Debug)]
 pub struct TokenStream<C>
     where C: Iterator<Item = char>
@@ -44,8 +326,21 @@ pub struct TokenStream<C>
     pos: usize,
     token_queue: VecDeque<Token>,
     escaping: bool,
+    quoting: bool,
+    /// Whether the quote being read was opened after the raw prefix
+    raw: bool,
     last_was_separator: bool,
     commenting: CommentingMode,
+    comments: Option<Vec<Comment>>,
+    comment: Option<Comment>,
+    /// The comment the script starts with while it's read, which can declare the version it's written for
+    declaration: Option<String>,
+    last_word: Option<Span>,
+    word_before: Option<Span>,
+    map: SourceMap,
+    config: LexerConfig,
+    /// Takes the error the chars ended early at from a stream that can fail, like [`ReaderChars`]
+    read_error: Option<fn(&mut C) -> Option<io::Error>>,
 }
 
 
@@ -54,7 +349,8 @@ enum CommentingMode {
     #[default]
     Disabled,
     Endline,
-    Multiline
+    /// How many comments deep, each `<` opening one inside the one it's in
+    Multiline(usize),
 }
 
 
@@ -62,9 +358,170 @@ impl<C> TokenStream<C>
     where C: Iterator<Item = char>
 {
     pub fn schedule(&mut self, token: Token) {
+        if let Token::Word { .. } = token {
+            self.last_word = self.word_before.take();
+        };
+
         self.token_queue.push_front(token);
         // self.last_was_separator = false;
     }
+
+    /// Span of the last word handed out and not scheduled back, so a parser knows where what it read ends
+    pub fn last_word(&self) -> Option<Span> {
+        self.last_word
+    }
+
+    /// Reads the script with the chars of another version of the syntax than the one it declares
+    pub fn with_config(mut self, config: LexerConfig) -> Self {
+        self.config = config;
+
+        self
+    }
+
+    pub fn config(&self) -> &LexerConfig {
+        &self.config
+    }
+
+    pub fn retaining_comments(mut self) -> Self {
+        self.comments = Some(Vec::new());
+
+        self
+    }
+
+    /// Where the chars read so far are
+    pub fn source_map(&self) -> &SourceMap {
+        &self.map
+    }
+
+    /// Why reading stopped early, [`None`] if the whole script was read
+    pub fn take_error(&mut self) -> Option<io::Error> {
+        self.read_error.and_then(|take| take(&mut self.char_stream))
+    }
+
+    pub fn take_comments(&mut self) -> Vec<Comment> {
+        self.comments.as_mut().map(std::mem::take).unwrap_or_default()
+    }
+
+    fn comment_char(&mut self, c: char) {
+        if c == '\r' {
+            return;
+        };
+
+        if let Some(declaration) = self.declaration.as_mut() {
+            declaration.push(c);
+        };
+        if self.comments.is_none() {
+            return;
+        };
+
+        match self.comment.as_mut() {
+            Some(comment) => comment.text.push(c),
+            None => self.comment = Some(Comment { start: self.pos - 1, text: String::from(c) }),
+        };
+    }
+
+    fn finish_comment(&mut self) {
+        if let Some(declaration) = self.declaration.take() && let Some(config) = LexerConfig::declared(&declaration) {
+            self.config = config;
+        };
+
+        if let Some(comment) = self.comment.take() && let Some(comments) = self.comments.as_mut() {
+            comments.push(comment);
+        };
+    }
+}
+
+
+/// Chars decoded from UTF-8 as they're read, without reading the whole source first.
+///
+/// Stops at the first read error or invalid byte sequence, which [`ReaderChars::take_error`] returns
+#[derive(Debug)]
+pub struct ReaderChars<R>
+    where R: BufRead
+{
+    reader: R,
+    error: Option<io::Error>,
+}
+
+
+impl<R> ReaderChars<R>
+    where R: BufRead
+{
+    pub fn new(reader: R) -> Self {
+        Self { reader, error: None }
+    }
+
+    /// Why the chars ended early, [`None`] if the reader was read to its end
+    pub fn take_error(&mut self) -> Option<io::Error> {
+        self.error.take()
+    }
+
+    fn fail(&mut self, error: io::Error) -> Option<char> {
+        self.error = Some(error);
+
+        None
+    }
+}
+
+
+impl<R> Iterator for ReaderChars<R>
+    where R: BufRead
+{
+    type Item = char;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.error.is_some() {
+            return None;
+        };
+
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, "stream did not contain valid UTF-8");
+
+        // a char can be split between two reads, so its bytes are gathered until there are enough
+        let mut bytes = [0; 4];
+        let mut length = 0;
+        let mut width = 1;
+        while length < width {
+            let buffer = match self.reader.fill_buf() {
+                Ok(buffer) => buffer,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return self.fail(err),
+            };
+
+            if buffer.is_empty() {
+                return if length == 0 { None } else { self.fail(invalid()) };
+            };
+
+            if length == 0 {
+                width = match buffer[0] {
+                    0x00..=0x7F => 1,
+                    0xC2..=0xDF => 2,
+                    0xE0..=0xEF => 3,
+                    0xF0..=0xF4 => 4,
+                    _ => return self.fail(invalid()),
+                };
+            };
+
+            let read = (width - length).min(buffer.len());
+            bytes[length..length + read].copy_from_slice(&buffer[..read]);
+            self.reader.consume(read);
+            length += read;
+        };
+
+        match std::str::from_utf8(&bytes[..width]) {
+            Ok(decoded) => decoded.chars().next(),
+            Err(_) => self.fail(invalid()),
+        }
+    }
+}
+
+
+impl<R> TokenStream<ReaderChars<R>>
+    where R: BufRead
+{
+    /// Lexes a script while it's being read, e.g. from a large file or a socket
+    pub fn from_reader(reader: R) -> Self {
+        Self { read_error: Some(ReaderChars::take_error), ..Self::from(ReaderChars::new(reader)) }
+    }
 }
 
 
@@ -77,8 +534,18 @@ impl<C> From<C> for TokenStream<C>
             pos: 0,
             token_queue: VecDeque::new(),
             escaping: false,
+            quoting: false,
+            raw: false,
             last_was_separator: true,
             commenting: CommentingMode::Disabled,
+            comments: None,
+            comment: None,
+            declaration: None,
+            last_word: None,
+            word_before: None,
+            map: SourceMap::default(),
+            config: LexerConfig::default(),
+            read_error: None,
         }
     }
 }
@@ -90,6 +557,21 @@ impl<C> Iterator for TokenStream<C>
     type Item = Token;
 
     fn next(&mut self) -> Option<Self::Item> {
+        let token = self.lex();
+
+        if let Some(word @ Token::Word { .. }) = token.as_ref() {
+            self.word_before = self.last_word.replace(word.span());
+        };
+
+        token
+    }
+}
+
+
+impl<C> TokenStream<C>
+    where C: Iterator<Item = char>
+{
+    fn lex(&mut self) -> Option<Token> {
         if let Some(token) = self.token_queue.pop_front() {
             if let Token::SentenceEnd { .. } = token {
                 self.last_was_separator = true;
@@ -102,6 +584,9 @@ impl<C> Iterator for TokenStream<C>
 
         let mut token_value = String::new();
         let mut initial_position;
+        let mut end = self.pos;
+        // `""` is a word even though it's empty
+        let mut quoted = false;
 
         loop {
             initial_position = self.pos;
@@ -109,7 +594,9 @@ impl<C> Iterator for TokenStream<C>
             loop {
                 match self.char_stream.next() {
                     None => {
-                        if token_value.is_empty() && self.token_queue.is_empty() {
+                        self.finish_comment();
+
+                        if token_value.is_empty() && !quoted && self.token_queue.is_empty() {
                             return if self.last_was_separator {
                                 None
                             } else {
@@ -123,60 +610,119 @@ impl<C> Iterator for TokenStream<C>
                     },
                     Some(c) => {
                         self.pos += 1;
+                        self.map.push(c);
+
+                        if self.quoting {
+                            if self.escaping.take() {
+                                token_value.push(self.config.unescape(c));
+                            } else if c == self.config.escape_symbol && !self.raw {
+                                self.escaping = true;
+                            } else if Some(c) == self.config.quote {
+                                self.quoting = false;
+                            } else {
+                                token_value.push(c);
+                            };
+
+                            end = self.pos;
+                            continue;
+                        };
 
                         let escaping = self.escaping.take();
 
                         if escaping && self.commenting == CommentingMode::Disabled {
-                            token_value.push(c);
+                            token_value.push(self.config.unescape(c));
+                            end = self.pos;
                             continue;
-                        }
+                        } else if Some(c) == self.config.quote && self.commenting == CommentingMode::Disabled {
+                            // the prefix is only one when it's written on its own right before the quote
+                            self.raw = self.config.raw_prefix.is_some_and(|prefix| token_value == prefix.to_string())
+                                && !quoted && self.pos - initial_position == 2;
+                            if self.raw {
+                                token_value.clear();
+                            };
+
+                            self.quoting = true;
+                            quoted = true;
+                            continue;
+                        };
 
-                        if c == Token::ESCAPE_SYMBOL {
+                        if c == self.config.escape_symbol {
                             self.escaping = true;
-                        } else if c == Token::ENDLINE_COMMENT /* && token_value.is_empty() */ {
+
+                            if self.commenting != CommentingMode::Disabled {
+                                self.comment_char(c);
+                            };
+                        } else if c == self.config.endline_comment /* && token_value.is_empty() */ {
+                            if self.pos == 1 {
+                                self.declaration = Some(String::new());
+                            };
+                            self.comment_char(c);
+
                             if self.commenting == CommentingMode::Disabled {
                                 self.commenting = CommentingMode::Endline;
                             };
-                        } else if c == Token::MULTILINE_COMMENT_START {
-                            self.commenting = CommentingMode::Multiline;
-                        } else if c == Token::MULTILINE_COMMENT_END {
-                            if self.commenting == CommentingMode::Multiline {
-                                self.commenting = CommentingMode::Disabled;
+                        } else if c == self.config.multiline_comment_start {
+                            self.comment_char(c);
+
+                            self.commenting = match self.commenting {
+                                CommentingMode::Multiline(depth) => CommentingMode::Multiline(depth + 1),
+                                _ => CommentingMode::Multiline(1),
+                            };
+                        } else if c == self.config.multiline_comment_end {
+                            if self.commenting != CommentingMode::Disabled {
+                                self.comment_char(c);
                             };
-                        } else if Token::LINE_SEPARATORS.contains(&c) {
-                            if self.commenting != CommentingMode::Multiline {
+
+                            match self.commenting {
+                                CommentingMode::Multiline(1) => {
+                                    self.commenting = CommentingMode::Disabled;
+                                    self.finish_comment();
+                                },
+                                CommentingMode::Multiline(depth) => self.commenting = CommentingMode::Multiline(depth - 1),
+                                _ => { },
+                            };
+                        } else if self.config.line_separators.contains(&c) {
+                            if !matches!(self.commenting, CommentingMode::Multiline(_)) {
                                 self.token_queue.push_back(Token::SentenceEnd { pos: self.pos - 1 });
                             };
 
                             if self.commenting == CommentingMode::Endline && !escaping {
                                 self.commenting = CommentingMode::Disabled;
+                                self.finish_comment();
+                            } else if self.commenting != CommentingMode::Disabled {
+                                self.comment_char(c);
                             };
 
                             break;
-                        } else if self.commenting == CommentingMode::Disabled {
-                            if Token::WORD_SEPARATORS.contains(&c) {
+                        } else if self.commenting != CommentingMode::Disabled {
+                            self.comment_char(c);
+                        } else {
+                            if self.config.word_separators.contains(&c) {
                                 break;
-                            } else if Token::INDEPENDENT_WORDS.contains(&c) {
+                            } else if self.config.independent_words.contains(&c) {
                                 self.token_queue.push_back(Token::Word {
                                     value: String::from(c),
                                     start: self.pos - 1,
+                                    end: self.pos,
                                 });
 
                                 break;
                             } else {
                                 token_value.push(c);
+                                end = self.pos;
                             };
                         };
                     }
                 };
             };
 
-            if !token_value.is_empty() {
+            if !token_value.is_empty() || quoted {
                 self.last_was_separator = false;
 
                 return Some(Token::Word {
                     value: token_value,
-                    start: initial_position
+                    start: initial_position,
+                    end,
                 })
             } else {
                 match self.token_queue.pop_front() {
@@ -1,2 +1,5 @@
 pub mod lexer;
 pub mod parser;
+pub mod formatter;
+pub mod highlight;
+pub mod visit;
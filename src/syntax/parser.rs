@@ -1,6 +1,7 @@
-use std::fmt;
+use std::{fmt, io};
 use std::str::FromStr;
-use crate::syntax::lexer::{TokenStream, Token as LToken};
+use crate::json;
+use crate::syntax::lexer::{LexerConfig, Span, TokenStream, Token as LToken};
 
 mod helper {
     use crate::syntax::lexer::{Token as LToken, Token, TokenStream};
@@ -12,16 +13,36 @@ mod helper {
             Some(Token::SentenceEnd { pos }) => Err(
                 ParsingError::EndOfSentence { parsing_as, pos }
             ),
-            Some(Token::Word { value, start }) => Ok((value, start))
+            Some(Token::Word { value, start, .. }) => Ok((value, start))
         }
     }
 
+    /// Drops what's left of the sentence, up to and including its end, or up to the end of the block
+    pub fn skip_sentence<C>(stream: &mut TokenStream<C>)
+        where C: Iterator<Item = char>
+    {
+        while let Some(token) = stream.next() {
+            match token {
+                LToken::SentenceEnd { .. } => break,
+                word @ LToken::Word { .. } if is_word(&word, super::Token::BLOCK_END) => {
+                    stream.schedule(word);
+                    break;
+                },
+                LToken::Word { .. } => { },
+            };
+        };
+    }
+
+    pub fn is_word(token: &LToken, word: &str) -> bool {
+        matches!(token, LToken::Word { value, .. } if value == word)
+    }
+
     pub fn consume_eos_token<C>(stream: &mut TokenStream<C>)
         where C: Iterator<Item = char>
     {
         if let Some(eos_token) = stream.next() {
             match eos_token {
-                LToken::Word { value, start } => stream.schedule(LToken::Word { value, start }),
+                word @ LToken::Word { .. } => stream.schedule(word),
                 LToken::SentenceEnd { .. } => { },
             };
         };
@@ -29,12 +50,208 @@ mod helper {
 }
 
 #[derive(Debug)]
-pub struct Script(Vec<Token>);
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Script {
+    tokens: Vec<Token>,
+    /// Where each token was written, empty for scripts that weren't parsed from a source
+    #[cfg_attr(feature = "serde", serde(default))]
+    spans: Vec<Span>,
+    /// Where each label written with a block is, from its `@` to the `}` closing it
+    #[cfg_attr(feature = "serde", serde(default))]
+    blocks: Vec<Span>,
+}
 
 
 impl Script {
     pub fn get_tokens(&self) -> &[Token] {
-        &self.0
+        &self.tokens
+    }
+
+    /// Labels written with a block, in the order their blocks close, for folding them away in editors
+    pub fn get_blocks(&self) -> &[Span] {
+        &self.blocks
+    }
+
+    /// Tokens to rewrite in place, there can't be more or fewer of them since each has its span
+    pub fn get_tokens_mut(&mut self) -> &mut [Token] {
+        &mut self.tokens
+    }
+
+    pub fn get_spans(&self) -> &[Span] {
+        &self.spans
+    }
+
+    /// Where the `pos`-th token was written, if the script was parsed
+    pub fn get_span(&self, pos: usize) -> Option<Span> {
+        self.spans.get(pos).copied()
+    }
+
+    pub fn into_tokens(self) -> Vec<Token> {
+        self.tokens
+    }
+
+    pub fn into_parts(self) -> (Vec<Token>, Vec<Span>) {
+        (self.tokens, self.spans)
+    }
+
+    /// Whether writing the script out and parsing that gives back the same tokens, which is always the
+    /// case for parsed scripts but not for built ones with strings that can't be written
+    pub fn round_trips(&self) -> bool {
+        let writable = self.tokens.iter().all(|token| match token {
+            Token::Property { value, .. } => value.is_writable(),
+            // a string argument with a `=` would be read back as an override
+            Token::Command { arguments, overrides, .. } => arguments.iter().all(|argument| match argument {
+                Value::List(_) => false,
+                Value::String(s) => !s.contains(Token::OVERRIDE_SEPARATOR) && argument.is_writable(),
+                _ => argument.is_writable(),
+            }) && overrides.iter().all(|(_, value)| !matches!(value, Value::List(_)) && value.is_writable()),
+            Token::Label { .. } => true,
+        });
+
+        writable && Script::try_from(self.to_string().as_str()).is_ok_and(|written| written.tokens == self.tokens)
+    }
+
+    /// Parses `source` with the chars of another version of the syntax than the one it declares
+    pub fn parse_with_config(source: &str, config: LexerConfig) -> Result<Self, ParsingError> {
+        Self::try_from(&mut TokenStream::from(source.chars()).with_config(config))
+    }
+
+    /// Parses what it can of `source`, skipping every sentence that doesn't parse and returning why
+    pub fn parse_partial(source: &str) -> (Self, Vec<ParsingError>) {
+        Self::parse_partial_stream(&mut TokenStream::from(source.chars()))
+    }
+
+    pub fn parse_partial_stream<C>(token_stream: &mut TokenStream<C>) -> (Self, Vec<ParsingError>)
+        where C: Iterator<Item = char>
+    {
+        Self::parse(token_stream, true)
+    }
+
+    fn parse<C>(token_stream: &mut TokenStream<C>, recover: bool) -> (Self, Vec<ParsingError>)
+        where C: Iterator<Item = char>
+    {
+        // a label nested in another's block goes after it, so its statements don't look like the other's
+        // label's to the compiler. Each group is a run of labels written one after another
+        let mut groups: Vec<(Vec<Token>, Vec<Span>)> = vec![(Vec::new(), Vec::new())];
+        // blocks still open, by the group their statements go in and where their label starts
+        let mut open: Vec<(usize, usize)> = Vec::new();
+        let mut blocks = Vec::new();
+        let mut current = 0;
+        let mut after_block = false;
+        let mut errors = Vec::new();
+        while let Some(first) = token_stream.next() {
+            let start = first.span().start;
+
+            if helper::is_word(&first, Token::BLOCK_END) {
+                match open.pop() {
+                    Some((_, label_start)) => {
+                        blocks.push(token_stream.source_map().span(label_start, start + 1));
+                        current = open.last().map_or_else(|| {
+                            groups.push((Vec::new(), Vec::new()));
+                            after_block = true;
+
+                            groups.len() - 1
+                        }, |(group, _)| *group);
+                    },
+                    None => {
+                        errors.push(ParsingError::UnmatchedBlockEnd { pos: start });
+
+                        if !recover {
+                            break;
+                        };
+                    },
+                };
+
+                helper::consume_eos_token(token_stream);
+                continue;
+            };
+            token_stream.schedule(first);
+
+            match Token::try_from(&mut *token_stream) {
+                Err(ParsingError::StreamTokenDepleted) => { break; },
+                Ok(token) => {
+                    let span = token_stream.source_map().span(start, token_stream.last_word().map(|word| word.end).unwrap_or(start));
+                    let is_label = matches!(token, Token::Label { .. });
+
+                    let opens = is_label && match token_stream.next() {
+                        Some(next) if helper::is_word(&next, Token::BLOCK_START) => {
+                            helper::consume_eos_token(token_stream);
+                            true
+                        },
+                        Some(next) => {
+                            token_stream.schedule(next);
+                            false
+                        },
+                        None => false,
+                    };
+
+                    let misplaced = if opens {
+                        None
+                    } else if is_label && !open.is_empty() {
+                        Some(ParsingError::LabelInBlock { pos: start })
+                    } else if !is_label && after_block {
+                        Some(ParsingError::OutsideBlock { pos: start })
+                    } else {
+                        None
+                    };
+                    if let Some(err) = misplaced {
+                        errors.push(err);
+
+                        if !recover {
+                            break;
+                        };
+                        continue;
+                    };
+
+                    if opens {
+                        groups.push((Vec::new(), Vec::new()));
+                        current = groups.len() - 1;
+                        open.push((current, start));
+                    };
+                    after_block &= !is_label;
+
+                    groups[current].0.push(token);
+                    groups[current].1.push(span);
+                },
+                Err(err) => {
+                    // running into the end of the sentence already went past it
+                    let skip = !matches!(err, ParsingError::EndOfSentence { .. });
+                    errors.push(err);
+
+                    if !recover {
+                        break;
+                    };
+
+                    if skip {
+                        helper::skip_sentence(token_stream);
+                    };
+                },
+            };
+        };
+
+        errors.extend(open.into_iter().map(|(_, start)| ParsingError::UnclosedBlock { pos: start }));
+
+        // what's left unread would've changed the rest, so the read error goes before what it caused
+        if let Some(err) = token_stream.take_error() {
+            errors.insert(0, ParsingError::ReadError { err, pos: token_stream.source_map().chars() });
+        };
+
+        let (tokens, spans) = groups.into_iter()
+            .fold((Vec::new(), Vec::new()), |(mut tokens, mut spans), (group_tokens, group_spans)| {
+                tokens.extend(group_tokens);
+                spans.extend(group_spans);
+
+                (tokens, spans)
+            });
+
+        (Self { tokens, spans, blocks }, errors)
+    }
+}
+
+
+impl From<Vec<Token>> for Script {
+    fn from(tokens: Vec<Token>) -> Self {
+        Self { tokens, spans: Vec::new(), blocks: Vec::new() }
     }
 }
 
@@ -54,31 +271,52 @@ impl<C> TryFrom<&mut TokenStream<C>> for Script
     type Error = ParsingError;
 
     fn try_from(token_stream: &mut TokenStream<C>) -> Result<Self, Self::Error> {
-        let mut tokens = Vec::new();
-        loop {
-            match Token::try_from(&mut *token_stream) {
-                Err(ParsingError::StreamTokenDepleted) => { break; },
-                token => tokens.push(token?),
-            };
-        };
+        let (script, errors) = Self::parse(token_stream, false);
 
-        Ok(Self(tokens))
+        match errors.into_iter().next() {
+            None => Ok(script),
+            Some(err) => Err(err),
+        }
     }
+
 }
 
 
+/// One token per line, written so that parsing it again gives back the same tokens. Written for version 1
+/// of the syntax unless it has empty strings, which only quotes can write
 impl fmt::Display for Script {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        for token in self.0[..self.0.len() - 1].iter() {
-            writeln!(f, "{token}")?;
+        let empty = self.tokens.iter().any(|token| match token {
+            Token::Property { name, value } => name.is_empty() || value.has_empty_string(),
+            Token::Label { name } => name.is_empty(),
+            Token::Command { name, arguments, overrides } => name.is_empty()
+                || arguments.iter().any(Value::has_empty_string)
+                || overrides.iter().any(|(name, value)| name.is_empty() || value.has_empty_string()),
+        });
+
+        let config = if empty {
+            write!(f, "{}", LexerConfig::declaration(LexerConfig::LATEST_VERSION))?;
+            LexerConfig::version(LexerConfig::LATEST_VERSION).unwrap()
+        } else {
+            LexerConfig::default()
+        };
+
+        for (i, token) in self.tokens.iter().enumerate() {
+            if i > 0 || empty {
+                writeln!(f)?;
+            };
+
+            token.write(f, &config)?;
         };
 
-        write!(f, "{}", self.0.last().unwrap())
+        Ok(())
     }
 }
 
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(tag = "kind", rename_all = "snake_case"))]
 pub enum Token {
     Property {
         name: String,
@@ -90,12 +328,36 @@ pub enum Token {
     Command {
         name: String,
         arguments: Vec<Value>,
+        /// Properties set for this command only, written as `name=value` after its arguments
+        #[cfg_attr(feature = "serde", serde(default))]
+        overrides: Vec<(String, Value)>,
     }
 }
 
 impl Token {
     const PROPERTY_SEPARATOR: &'static str = ":";
     const LABEL_MARKER: &'static str = "@";
+    const OVERRIDE_SEPARATOR: char = '=';
+    /// Written after a label's name, `{` makes the label end at the `}` closing it rather than at the next
+    /// label, which lets labels be written inside others
+    pub const BLOCK_START: &'static str = "{";
+    pub const BLOCK_END: &'static str = "}";
+
+    pub fn to_json(&self) -> String {
+        match self {
+            Token::Property { name, value } => format!("{{\"kind\":\"property\",\"name\":{},\"value\":{}}}", json::string(name), value.to_json()),
+            Token::Label { name } => format!("{{\"kind\":\"label\",\"name\":{}}}", json::string(name)),
+            Token::Command { name, arguments, overrides } if overrides.is_empty() => format!(
+                "{{\"kind\":\"command\",\"name\":{},\"arguments\":{}}}",
+                json::string(name), json::array(arguments.iter().map(Value::to_json)),
+            ),
+            Token::Command { name, arguments, overrides } => format!(
+                "{{\"kind\":\"command\",\"name\":{},\"arguments\":{},\"overrides\":{{{}}}}}",
+                json::string(name), json::array(arguments.iter().map(Value::to_json)),
+                overrides.iter().map(|(name, value)| format!("{}:{}", json::string(name), value.to_json())).collect::<Vec<_>>().join(","),
+            ),
+        }
+    }
 }
 
 
@@ -117,22 +379,50 @@ impl<C> TryFrom<&mut TokenStream<C>> for Token
                 name => {
                     let property_sep = stream.next();
                     if let Some(LToken::Word { value, ..}) = property_sep.clone() && value == Self::PROPERTY_SEPARATOR {
-                        let property_token = Self::Property { name: String::from(name), value: Value::try_from(&mut *stream)? };
+                        let mut values = vec![Value::try_from(&mut *stream)?];
+                        loop {
+                            match stream.next() {
+                                None | Some(LToken::SentenceEnd { .. }) => break,
+                                Some(token) if helper::is_word(&token, Self::BLOCK_END) => {
+                                    stream.schedule(token);
+                                    break;
+                                },
+                                Some(token) => {
+                                    stream.schedule(token);
+                                    values.push(Value::try_from(&mut *stream)?);
+                                },
+                            };
+                        };
 
-                        helper::consume_eos_token(&mut *stream);
+                        let value = if values.len() == 1 { values.pop().unwrap() } else { Value::List(values) };
 
-                        Ok(property_token)
+                        Ok(Self::Property { name: String::from(name), value })
                     } else {
                         if let Some(property_sep_token) = property_sep {
                             stream.schedule(property_sep_token);
                         };
 
                         let mut arguments = Vec::new();
+                        let mut overrides = Vec::new();
                         loop {
                             let next_token = stream.next();
 
                             if let Some(LToken::SentenceEnd { .. }) = next_token {
                                 break;
+                            } else if let Some(token) = next_token.clone() && helper::is_word(&token, Self::BLOCK_END) {
+                                stream.schedule(token);
+                                break;
+                            } else if let Some(LToken::Word { start, end, value }) = next_token.clone()
+                                && let Some((property, written)) = value.split_once(Self::OVERRIDE_SEPARATOR)
+                                && !property.is_empty() && !written.is_empty()
+                            {
+                                // the value goes back as a word of its own, so a fraction still takes the
+                                // `/` and denominator after it
+                                let offset = property.chars().count() + 1;
+                                stream.schedule(LToken::Word { start: start + offset, end, value: String::from(written) });
+
+                                overrides.push((String::from(property), Value::try_from(&mut *stream)?));
+                                continue;
                             } else if let Some(token) = next_token {
                                 stream.schedule(token);
                             };
@@ -145,7 +435,7 @@ impl<C> TryFrom<&mut TokenStream<C>> for Token
                             };
                         };
 
-                        Ok(Self::Command { name: String::from(name), arguments })
+                        Ok(Self::Command { name: String::from(name), arguments, overrides })
                     }
                 }
             }
@@ -156,16 +446,25 @@ impl<C> TryFrom<&mut TokenStream<C>> for Token
 }
 
 
-impl fmt::Display for Token {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+impl Token {
+    fn write(&self, f: &mut fmt::Formatter<'_>, config: &LexerConfig) -> fmt::Result {
         match self {
-            Token::Property { name, value } => write!(f, "{name}: {value}"),
-            Token::Label { name } => write!(f, "@{name}"),
-            Token::Command { name, arguments } => {
-                write!(f, "{name}")?;
+            Token::Property { name, value } => {
+                write!(f, "{}: ", config.escape(name))?;
+                value.write(f, config)
+            },
+            Token::Label { name } => write!(f, "@{}", config.escape(name)),
+            Token::Command { name, arguments, overrides } => {
+                write!(f, "{}", config.escape(name))?;
 
                 for argument in arguments.iter() {
-                    write!(f, " {argument}")?;
+                    write!(f, " ")?;
+                    argument.write(f, config)?;
+                };
+
+                for (name, value) in overrides.iter() {
+                    write!(f, " {}{}", config.escape(name), Self::OVERRIDE_SEPARATOR)?;
+                    value.write(f, config)?;
                 };
 
                 Ok(())
@@ -174,7 +473,19 @@ impl fmt::Display for Token {
     }
 }
 
-#[derive(Clone, Debug)]
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write(f, &LexerConfig::default())
+    }
+}
+
+/// A value as written in a script. Every parsed value is written back the same way, but a string made
+/// by hand can't be when it looks like a number, or is a lone `:` or `/` where that would be
+/// read as a separator
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(untagged))]
 pub enum Value {
     Whole(u32),
     Fraction {
@@ -182,16 +493,27 @@ pub enum Value {
         denominator: u32,
     },
     String(String),
+    List(Vec<Value>),
 }
 
 
 impl fmt::Display for Value {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            Self::Whole(n) => write!(f, "{n}"),
-            Self::Fraction {numerator: num, denominator: don} => write!(f, "{num} / {don}"),
-            Self::String(s) => write!(f, "{s}"),
-        }
+        self.write(f, &LexerConfig::default())
+    }
+}
+
+
+impl From<u32> for Value {
+    fn from(n: u32) -> Self {
+        Self::Whole(n)
+    }
+}
+
+
+impl From<&str> for Value {
+    fn from(s: &str) -> Self {
+        Self::String(String::from(s))
     }
 }
 
@@ -199,6 +521,61 @@ impl fmt::Display for Value {
 impl Value {
     const FRACTION_SEPARATOR: &'static str = "/";
 
+    pub fn to_json(&self) -> String {
+        match self {
+            Self::Whole(n) => format!("{n}"),
+            Self::Fraction { numerator, denominator } => format!("{{\"numerator\":{numerator},\"denominator\":{denominator}}}"),
+            Self::String(s) => json::string(s),
+            Self::List(values) => json::array(values.iter().map(Value::to_json)),
+        }
+    }
+
+    /// The value as it reads, without the escapes [`Display`](fmt::Display) adds to keep it one word
+    pub fn to_text(&self) -> String {
+        match self {
+            Self::String(s) => s.clone(),
+            Self::List(values) => values.iter().map(Value::to_text).collect::<Vec<_>>().join(" "),
+            value => value.to_string(),
+        }
+    }
+
+    fn write(&self, f: &mut fmt::Formatter<'_>, config: &LexerConfig) -> fmt::Result {
+        match self {
+            Self::Whole(n) => write!(f, "{n}"),
+            Self::Fraction {numerator: num, denominator: don} => write!(f, "{num}/{don}"),
+            Self::String(s) => write!(f, "{}", config.escape(s)),
+            Self::List(values) => {
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " ")?;
+                    };
+
+                    value.write(f, config)?;
+                };
+
+                Ok(())
+            },
+        }
+    }
+
+    fn has_empty_string(&self) -> bool {
+        match self {
+            Self::String(s) => s.is_empty(),
+            Self::List(values) => values.iter().any(Self::has_empty_string),
+            _ => false,
+        }
+    }
+
+    /// Strings that look like numbers would be read back as them or not at all, and a list needs more
+    /// than one value, none of them lists, to be read back as one
+    fn is_writable(&self) -> bool {
+        match self {
+            Self::String(s) => s.trim().parse::<f64>().is_err(),
+            Self::List(values) => values.len() > 1 && values.iter().all(|value| !matches!(value, Self::List(_)) && value.is_writable()),
+            _ => true,
+        }
+    }
+
     fn parse_num<N: FromStr>(s: &str, pos: usize) -> Result<N, ParsingError>
         where <N as FromStr>::Err: fmt::Display
     {
@@ -210,6 +587,29 @@ impl Value {
         })
     }
 
+    /// A decimal like `0.5`, read as the fraction it's a shorter way to write
+    fn parse_decimal(s: &str, pos: usize) -> Result<Self, ParsingError> {
+        let error = |err_msg: &str| ParsingError::ValueError {
+            tried_parsing: Some(String::from(s)),
+            parsing_as: "decimal",
+            err_msg: Some(String::from(err_msg)),
+            pos: Some(pos),
+        };
+
+        let (whole, decimals) = s.trim().split_once('.')
+            .filter(|(whole, decimals)| !decimals.is_empty() && whole.chars().chain(decimals.chars()).all(|c| c.is_ascii_digit()))
+            .ok_or_else(|| error("only positive numbers with a decimal point can be written as decimals"))?;
+
+        let denominator = 10_u32.checked_pow(decimals.len() as u32).ok_or_else(|| error("too many decimals"))?;
+        let numerator = format!("{whole}{decimals}").parse::<u32>().map_err(|err| error(&err.to_string()))?;
+
+        let divisor = gcd(numerator, denominator);
+        Ok(match (numerator / divisor, denominator / divisor) {
+            (numerator, 1) => Self::Whole(numerator),
+            (numerator, denominator) => Self::Fraction { numerator, denominator },
+        })
+    }
+
     #[inline]
     fn parse_wrapped_num(num_token: Option<LToken>) -> Result<u32, ParsingError> {
         let word = helper::unwrap_word(num_token, "value")?;
@@ -219,6 +619,11 @@ impl Value {
 }
 
 
+fn gcd(a: u32, b: u32) -> u32 {
+    if b == 0 { a } else { gcd(b, a % b) }
+}
+
+
 impl<C> TryFrom<&mut TokenStream<C>> for Value
     where C: Iterator<Item = char>
 {
@@ -228,11 +633,11 @@ impl<C> TryFrom<&mut TokenStream<C>> for Value
         let token_a = helper::unwrap_word(stream.next(), "value")?;
 
         match Self::parse_num(&token_a.0, token_a.1) {
+            // words like `inf` read as numbers too, but only ones with digits are written as them. Signed ones
+            // like `-0.3` stay strings for the properties taking them, as numbers with a unit do
             Err(_) => match Self::parse_num::<f64>(&token_a.0, token_a.1) {
-                Err(_) => Ok(Self::String(token_a.0)),
-                Ok(num_a) => {
-                    todo!("floats inputted with tenth fractions (float to fraction)")
-                }
+                Ok(_) if token_a.0.contains(|c: char| c.is_ascii_digit()) && !token_a.0.starts_with(['-', '+']) => Self::parse_decimal(&token_a.0, token_a.1),
+                _ => Ok(Self::String(token_a.0)),
             },
             Ok(num_a) => {
                 let separator = stream.next();
@@ -242,9 +647,9 @@ impl<C> TryFrom<&mut TokenStream<C>> for Value
                         stream.schedule(LToken::SentenceEnd { pos });
                         Ok(Self::Whole(num_a))
                     }
-                    Some(LToken::Word { value, start }) => {
-                        if value != Self::FRACTION_SEPARATOR {
-                            stream.schedule(LToken::Word { value, start });
+                    Some(word @ LToken::Word { .. }) => {
+                        if !matches!(&word, LToken::Word { value, .. } if value == Self::FRACTION_SEPARATOR) {
+                            stream.schedule(word);
 
                             Ok(Self::Whole(num_a))
                         } else {
@@ -273,4 +678,39 @@ pub enum ParsingError {
         pos: usize,
     },
     StreamTokenDepleted,
+    /// A `}` without a block to close
+    UnmatchedBlockEnd {
+        pos: usize,
+    },
+    /// A label whose block is never closed, at where the label starts
+    UnclosedBlock {
+        pos: usize,
+    },
+    /// A label without a block of its own inside another's block
+    LabelInBlock {
+        pos: usize,
+    },
+    /// A statement after a block that's in no label
+    OutsideBlock {
+        pos: usize,
+    },
+    /// The source stopped being readable, at the char reading stopped at
+    ReadError {
+        err: io::Error,
+        pos: usize,
+    },
+}
+
+
+impl ParsingError {
+    /// Char offset in the source the error happened at, if it's known
+    pub fn pos(&self) -> Option<usize> {
+        match self {
+            Self::ValueError { pos, .. } => *pos,
+            Self::EndOfSentence { pos, .. } => Some(*pos),
+            Self::StreamTokenDepleted => None,
+            Self::UnmatchedBlockEnd { pos } | Self::UnclosedBlock { pos } | Self::LabelInBlock { pos } | Self::OutsideBlock { pos } => Some(*pos),
+            Self::ReadError { pos, .. } => Some(*pos),
+        }
+    }
 }
@@ -0,0 +1,146 @@
+//! Walking a parsed [`Script`] without matching on every kind of token by hand.
+//!
+//! A visitor overrides the `visit_*` methods it cares about. The default ones keep walking down through
+//! the `walk_*` functions, which an override can call itself to also visit what's inside. `pos` is always
+//! the index of the token being walked, the same one [`Script::get_span`] and the compiler use
+
+use crate::syntax::parser::{Script, Token, Value};
+
+
+pub trait Visitor<'s> {
+    fn visit_script(&mut self, script: &'s Script) {
+        walk_tokens(self, script.get_tokens());
+    }
+
+    fn visit_token(&mut self, pos: usize, token: &'s Token) {
+        walk_token(self, pos, token);
+    }
+
+    fn visit_label(&mut self, _pos: usize, _name: &'s str) { }
+
+    fn visit_property(&mut self, pos: usize, _name: &'s str, value: &'s Value) {
+        self.visit_value(pos, value);
+    }
+
+    fn visit_command(&mut self, pos: usize, _name: &'s str, arguments: &'s [Value]) {
+        for argument in arguments.iter() {
+            self.visit_value(pos, argument);
+        };
+    }
+
+    /// A `name=value` set for the command at `pos` only, visited after the command
+    fn visit_override(&mut self, pos: usize, _name: &'s str, value: &'s Value) {
+        self.visit_value(pos, value);
+    }
+
+    fn visit_value(&mut self, pos: usize, value: &'s Value) {
+        walk_value(self, pos, value);
+    }
+}
+
+
+pub fn walk_tokens<'s, V>(visitor: &mut V, tokens: &'s [Token])
+    where V: Visitor<'s> + ?Sized
+{
+    for (pos, token) in tokens.iter().enumerate() {
+        visitor.visit_token(pos, token);
+    };
+}
+
+
+pub fn walk_token<'s, V>(visitor: &mut V, pos: usize, token: &'s Token)
+    where V: Visitor<'s> + ?Sized
+{
+    match token {
+        Token::Label { name } => visitor.visit_label(pos, name),
+        Token::Property { name, value } => visitor.visit_property(pos, name, value),
+        Token::Command { name, arguments, overrides } => {
+            visitor.visit_command(pos, name, arguments);
+
+            for (name, value) in overrides.iter() {
+                visitor.visit_override(pos, name, value);
+            };
+        },
+    };
+}
+
+
+pub fn walk_value<'s, V>(visitor: &mut V, pos: usize, value: &'s Value)
+    where V: Visitor<'s> + ?Sized
+{
+    if let Value::List(values) = value {
+        for value in values.iter() {
+            visitor.visit_value(pos, value);
+        };
+    };
+}
+
+
+/// [`Visitor`] that can change what it walks over. Tokens can be rewritten but not added or removed,
+/// so the spans of the script still match them
+pub trait VisitorMut {
+    fn visit_script(&mut self, script: &mut Script) {
+        walk_tokens_mut(self, script.get_tokens_mut());
+    }
+
+    fn visit_token(&mut self, pos: usize, token: &mut Token) {
+        walk_token_mut(self, pos, token);
+    }
+
+    fn visit_label(&mut self, _pos: usize, _name: &mut String) { }
+
+    fn visit_property(&mut self, pos: usize, _name: &mut String, value: &mut Value) {
+        self.visit_value(pos, value);
+    }
+
+    fn visit_command(&mut self, pos: usize, _name: &mut String, arguments: &mut Vec<Value>) {
+        for argument in arguments.iter_mut() {
+            self.visit_value(pos, argument);
+        };
+    }
+
+    fn visit_override(&mut self, pos: usize, _name: &mut String, value: &mut Value) {
+        self.visit_value(pos, value);
+    }
+
+    fn visit_value(&mut self, pos: usize, value: &mut Value) {
+        walk_value_mut(self, pos, value);
+    }
+}
+
+
+pub fn walk_tokens_mut<V>(visitor: &mut V, tokens: &mut [Token])
+    where V: VisitorMut + ?Sized
+{
+    for (pos, token) in tokens.iter_mut().enumerate() {
+        visitor.visit_token(pos, token);
+    };
+}
+
+
+pub fn walk_token_mut<V>(visitor: &mut V, pos: usize, token: &mut Token)
+    where V: VisitorMut + ?Sized
+{
+    match token {
+        Token::Label { name } => visitor.visit_label(pos, name),
+        Token::Property { name, value } => visitor.visit_property(pos, name, value),
+        Token::Command { name, arguments, overrides } => {
+            visitor.visit_command(pos, name, arguments);
+
+            for (name, value) in overrides.iter_mut() {
+                visitor.visit_override(pos, name, value);
+            };
+        },
+    };
+}
+
+
+pub fn walk_value_mut<V>(visitor: &mut V, pos: usize, value: &mut Value)
+    where V: VisitorMut + ?Sized
+{
+    if let Value::List(values) = value {
+        for value in values.iter_mut() {
+            visitor.visit_value(pos, value);
+        };
+    };
+}
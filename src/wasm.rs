@@ -0,0 +1,69 @@
+//! Bindings for running roorle in a browser, built with the `wasm` feature for `wasm32-unknown-unknown`.
+//!
+//! Nothing here touches the filesystem, scripts come in as strings and renders go out as bytes:
+//!
+//! ```js
+//! import init, { Program } from "./roorle.js";
+//!
+//! await init();
+//! const program = new Program("bpm: 120\n@main\nA 1/4");
+//! const wav = program.renderWav(48000, false);
+//! new Audio(URL.createObjectURL(new Blob([wav], { type: "audio/wav" }))).play();
+//! ```
+
+use wasm_bindgen::prelude::*;
+use crate::compiler;
+use crate::interpreter::{self, RenderOptions, DEFAULT_MAX_RENDER_SECONDS};
+
+
+/// A compiled script
+#[wasm_bindgen]
+pub struct Program(compiler::Program);
+
+
+#[wasm_bindgen]
+impl Program {
+    /// Compiles `source`, throwing the parsing or compiling error if it isn't a valid script
+    #[wasm_bindgen(constructor)]
+    pub fn new(source: &str) -> Result<Program, JsError> {
+        Ok(Self(crate::compile(source)?))
+    }
+
+    /// Length of the piece in seconds
+    #[wasm_bindgen(getter)]
+    pub fn duration(&self) -> f64 {
+        self.0.get_duration()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn title(&self) -> Option<String> {
+        self.0.get_metadata().title.clone()
+    }
+
+    /// Renders the piece into the bytes of a 16-bit WAV file, throwing if `sample_rate` is 0 or the piece
+    /// lasts longer than an hour
+    #[wasm_bindgen(js_name = renderWav)]
+    pub fn render_wav(&self, sample_rate: u32, stereo: bool) -> Result<Vec<u8>, JsError> {
+        if sample_rate == 0 {
+            return Err(JsError::new("the sample rate has to be above 0"));
+        };
+
+        let options = RenderOptions {
+            sample_rate,
+            channels: if stereo { 2 } else { 1 },
+            max_render_seconds: Some(DEFAULT_MAX_RENDER_SECONDS),
+            ..RenderOptions::default()
+        };
+
+        Ok(interpreter::wav::interpret(&self.0, &options)?)
+    }
+}
+
+
+/// Compiles and renders `source` with the default options in one go
+#[wasm_bindgen(js_name = renderWav)]
+pub fn render_wav(source: &str) -> Result<Vec<u8>, JsError> {
+    let options = RenderOptions { max_render_seconds: Some(DEFAULT_MAX_RENDER_SECONDS), ..RenderOptions::default() };
+
+    Ok(interpreter::wav::interpret(&crate::compile(source)?, &options)?)
+}
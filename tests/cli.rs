@@ -0,0 +1,50 @@
+//! The command line tool describes note lengths the way a script writes them
+
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Output, Stdio};
+
+
+fn roorle(arguments: &[&str], stdin: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_roorle"))
+        .args(arguments)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("roorle doesn't start");
+
+    child.stdin.take().unwrap().write_all(stdin.as_bytes()).unwrap();
+    child.wait_with_output().unwrap()
+}
+
+
+fn script(name: &str, source: &str) -> PathBuf {
+    let path = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(name);
+    std::fs::write(&path, source).unwrap();
+
+    path
+}
+
+
+#[test]
+fn stats_counts_lengths_as_written() {
+    let output = roorle(&["stats", "-", "--json"], "bpm: 60\n@main\nA 1/4\nB 1/8\nC 1/8\n");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(stdout.contains("{\"duration\":\"1/8\",\"count\":2},{\"duration\":\"1/4\",\"count\":1}"), "{stdout}");
+}
+
+
+#[test]
+fn diff_shows_lengths_as_written() {
+    let old = script("diff_old.musical", "bpm: 60\n@main\nE 1/2\n");
+    let new = script("diff_new.musical", "bpm: 60\n@main\nE 1/4\n");
+
+    let output = roorle(&["diff", old.to_str().unwrap(), new.to_str().unwrap()], "");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert_eq!(output.status.code(), Some(1), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(stdout.contains("E4 1/2 -> 1/4"), "{stdout}");
+}
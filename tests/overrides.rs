@@ -0,0 +1,84 @@
+//! Commands setting properties for themselves with `name=value`
+
+use roorle::compiler::{Compiler, CompilingError, Instruction, Instrument};
+use roorle::compiler::commands::{CommandContext, NoteCommand};
+use roorle::syntax::parser::{Script, Value};
+
+
+/// The instrument the first note of `source` plays on
+fn first_instrument(source: &str) -> Instrument {
+    let program = roorle::compile(source).unwrap_or_else(|err| panic!("{source:?} doesn't compile: {err}"));
+    let note = program.get_note_events()[0];
+
+    program.get_instruments()[note.instrument].clone()
+}
+
+
+#[test]
+fn volume_and_signed_pan() {
+    let instrument = first_instrument("bpm: 120\n@main\nC 1/4 vol=0.6 pan=-0.3\n");
+
+    assert_eq!(instrument.volume, 0.6);
+    assert_eq!(instrument.pan, -0.3);
+}
+
+
+#[test]
+fn pan_spellings_agree() {
+    for (source, pan) in [("pan=L30", -0.3), ("pan=R50", 0.5), ("pan=1/2", 0.5), ("pan=+0.25", 0.25), ("pan=C", 0.0)] {
+        let instrument = first_instrument(&format!("bpm: 120\n@main\nC 1/4 {source}\n"));
+
+        assert_eq!(instrument.pan, pan, "{source}");
+    };
+
+    assert!(roorle::compile("bpm: 120\n@main\nC 1/4 pan=-1.5\n").is_err());
+}
+
+
+/// `twice C 1/8` plays the note two times
+fn twice(context: &mut CommandContext, arguments: &[Value]) -> Result<Vec<Instruction>, CompilingError> {
+    let Some((Value::String(note), arguments)) = arguments.split_first() else {
+        return Err(CompilingError::WrongAmountArguments { pos: context.pos(), expected: 2, got: arguments.len() });
+    };
+
+    let mut instructions = context.note(note, arguments)?;
+    instructions.extend(context.note(note, &[])?);
+
+    Ok(instructions)
+}
+
+
+/// Compiles to no instructions at all
+fn nothing(_: &mut CommandContext, _: &[Value]) -> Result<Vec<Instruction>, CompilingError> {
+    Ok(Vec::new())
+}
+
+
+#[test]
+fn only_commands_playing_notes_take_overrides() {
+    let compile = |compiler: Compiler, source: &str| compiler.compile(&Script::try_from(source).unwrap());
+
+    for source in ["bpm: 120\n@main\nrest 1/4 volume=1/2\n", "bpm: 120\n@main\nmark intro lyric=la\n", "bpm: 120\n@main\nnothing volume=1/2\n"] {
+        let result = compile(Compiler::new().command("nothing", nothing), source);
+
+        assert!(matches!(result, Err(CompilingError::InvalidOverride { .. })), "{source:?} gave {:?}", result.err());
+    };
+
+    let program = compile(Compiler::new().command("twice", NoteCommand(twice)), "bpm: 120\n@main\ntwice C 1/8 vol=1/2\n").unwrap();
+    let notes = program.get_note_events();
+    assert_eq!(program.get_instruments()[notes[0].instrument].volume, 0.5);
+}
+
+
+#[test]
+fn only_commands_playing_notes_are_sung_to() {
+    let source = "bpm: 120\n@main\nlyrics: one two\nnothing\nC 1/4\ntwice D 1/4\n";
+    let program = Compiler::new()
+        .command("nothing", nothing)
+        .command("twice", NoteCommand(twice))
+        .compile(&Script::try_from(source).unwrap())
+        .unwrap();
+
+    let lyrics = program.get_note_events().iter().map(|note| program.get_lyric(note.pos)).collect::<Vec<_>>();
+    assert_eq!(lyrics, [Some("one"), Some("two"), Some("two")]);
+}
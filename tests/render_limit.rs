@@ -0,0 +1,45 @@
+//! Renders longer than `max_render_seconds` fail before anything is rendered, whatever they render into
+
+use std::io;
+use roorle::interpreter::{self, Format, RenderOptions};
+use roorle::interpreter::render::{self, SampleIterator};
+
+
+/// Two whole notes at 60 quarter notes a minute, 8 seconds
+const SOURCE: &str = "bpm: 60\n@main\nA 1\nA 1\n";
+
+
+fn options(limit: f64) -> RenderOptions {
+    RenderOptions { sample_rate: 8000, max_render_seconds: Some(limit), ..RenderOptions::default() }
+}
+
+
+#[test]
+fn too_long_renders_fail() {
+    let program = roorle::compile(SOURCE).unwrap();
+    let options = options(5.0);
+
+    assert!(SampleIterator::new(&program, &options).is_err());
+    assert!(render::render(&program, &options).is_err());
+    assert!(interpreter::wav::interpret(&program, &options).is_err());
+    assert!(interpreter::raw::interpret(&program, &options).is_err());
+
+    for format in [Format::Wav, Format::Flac, Format::Aiff, Format::Raw, Format::Midi, Format::Rtttl, Format::Svg, Format::Spectrogram, Format::Lrc] {
+        let mut written = io::Cursor::new(Vec::new());
+        let err = roorle::render(&program, &options, format, &mut written).expect_err(&format!("{format:?} renders"));
+
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput, "{format:?}");
+        assert!(written.get_ref().is_empty(), "{format:?} wrote before failing");
+    };
+}
+
+
+#[test]
+fn renders_within_the_limit_succeed() {
+    let program = roorle::compile(SOURCE).unwrap();
+    let options = options(10.0);
+
+    assert_eq!(render::render(&program, &options).unwrap().len(), 8 * 8000);
+    assert!(interpreter::wav::interpret(&program, &options).is_ok());
+    assert!(roorle::render(&program, &options, Format::Midi, io::Cursor::new(Vec::new())).is_ok());
+}
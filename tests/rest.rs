@@ -0,0 +1,47 @@
+//! `rest` waits for its duration without playing, the way importers write the gaps between notes
+
+use roorle::compiler::CompilingError;
+use roorle::score::{frac, ScoreBuilder};
+
+
+fn starts(source: &str) -> Vec<f64> {
+    let program = roorle::compile(source).unwrap_or_else(|err| panic!("{source:?} doesn't compile: {err}"));
+
+    program.get_note_events().iter().map(|note| note.start).collect()
+}
+
+
+#[test]
+fn rests_wait_without_playing() {
+    assert_eq!(starts("bpm: 60\n@main\nA 1/4\nrest 1/2\nB 1/4\n"), [0.0, 3.0]);
+    assert_eq!(roorle::compile("bpm: 60\n@main\nrest 1\n").unwrap().get_duration(), 4.0);
+}
+
+
+#[test]
+fn rests_share_the_length_of_notes() {
+    assert_eq!(starts("bpm: 60\n@main\nlength: 1/4\nA\nrest\nB\n"), [0.0, 2.0]);
+    assert_eq!(starts("bpm: 60\n@main\nA 1/2\nrest\nB 1/4\nrest\nC\n"), [0.0, 4.0, 6.0]);
+}
+
+
+#[test]
+fn rests_take_one_duration() {
+    assert!(matches!(
+        roorle::compile("bpm: 60\n@main\nrest 1/4 1/4\n"),
+        Err(roorle::Error::CompilingError(CompilingError::WrongAmountArguments { expected: 1, got: 2, .. })),
+    ));
+    assert!(roorle::compile("bpm: 60\n@main\nrest\n").is_err());
+}
+
+
+#[test]
+fn scores_write_rests() {
+    let score = || ScoreBuilder::new()
+        .bpm(60)
+        .label("main", |l| l.note("A", 4, frac(1, 4)).rest(frac(1, 2)).note("B", 4, frac(1, 4)));
+
+    let script = score().script().to_string();
+    assert!(script.contains("\nrest 1/2\n"), "{script}");
+    assert_eq!(score().build().unwrap().get_note_events()[1].start, 3.0);
+}
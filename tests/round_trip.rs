@@ -0,0 +1,45 @@
+//! Scripts written back by `Display` and by the formatter parse to the tokens they were written from
+
+use roorle::syntax::formatter;
+use roorle::syntax::parser::Script;
+
+
+const CORPUS: &[&str] = &[
+    // escapes of version 1
+    "title: Night\\ and\\ Day\ncomposer: Some\\;one\nbpm: 120\n@main\nA 1/4\n",
+    // quotes, their escapes and raw strings of version 2
+    "# syntax: 2\ntitle: \"Night and Day\"\ncomposer: \"a \\\"quoted\\\" \\\\ name\"\nbpm: 120\n@main\nmark r\"C:\\ intro\"\nA 1/4\n",
+    "# syntax: 2\ntitle: \"\"\nbpm: 120\n@main\nA 1/4\n",
+    // fractions, decimals and tempos given per beat
+    "bpm: 3/8 = 90\n@main\nlength: 1/8\nvolume: 0.5\nA 3/8\nB 1/16\nC\nrest 1/2\n",
+    // overrides and chords
+    "bpm: 120\n@main\nA C E 1/4 volume=1/2 octave=5\nghost B 1/8\nC 1/4 vol=0.6 pan=-0.3\nrepeat outro 2\n@outro {\n    bpm: 60\n    G 1\n}\n",
+    // lyrics, from the label and from overrides
+    "bpm: 120\n@main\nlyrics: twin kle _ lit tle\nC 1/4\nC 1/4\nG 1/4 lyric=star\nG 1/4\nA 1/2\n",
+    "# syntax: 2\nbpm: 120\n@main\nlyrics: \"how I won der\"\nC 1/4 lyric=\"what you\"\nD 1/4\n",
+];
+
+
+#[test]
+fn display_round_trips() {
+    for source in CORPUS {
+        let script = Script::try_from(*source).unwrap_or_else(|err| panic!("{source:?} doesn't parse: {err:?}"));
+        let written = script.to_string();
+        let reparsed = Script::try_from(written.as_str()).unwrap_or_else(|err| panic!("{written:?} doesn't parse: {err:?}"));
+
+        assert_eq!(script.get_tokens(), reparsed.get_tokens(), "{source:?} was written as {written:?}");
+    };
+}
+
+
+#[test]
+fn formatter_round_trips() {
+    for source in CORPUS {
+        let script = Script::try_from(*source).unwrap_or_else(|err| panic!("{source:?} doesn't parse: {err:?}"));
+        let formatted = formatter::format(source).unwrap_or_else(|err| panic!("{source:?} doesn't format: {err:?}"));
+        let reparsed = Script::try_from(formatted.as_str()).unwrap_or_else(|err| panic!("{formatted:?} doesn't parse: {err:?}"));
+
+        assert_eq!(script.get_tokens(), reparsed.get_tokens(), "{source:?} was formatted as {formatted:?}");
+        assert_eq!(formatter::format(&formatted).ok().as_deref(), Some(formatted.as_str()), "formatting {source:?} twice changes it");
+    };
+}